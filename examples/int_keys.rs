@@ -1,9 +1,9 @@
-use redb::{Database, Error, ReadableTable, TableDefinition};
+use redb::{Database, Error, InMemoryBackend, ReadableTable, TableDefinition};
 
 const TABLE: TableDefinition<u64, u64> = TableDefinition::new("my_data");
 
 fn main() -> Result<(), Error> {
-    let db = Database::create("int_keys.redb")?;
+    let db = Database::builder().create_with_backend(InMemoryBackend::new())?;
     let write_txn = db.begin_write()?;
     {
         let mut table = write_txn.open_table(TABLE)?;
@@ -1,6 +1,6 @@
 use redb::{
-    Database, Error, ReadableTable, RedbKey, RedbValue, Table, TableDefinition, TableHandle,
-    WriteTransaction,
+    Database, Error, InMemoryBackend, ReadableTable, RedbKey, RedbValue, Table, TableDefinition,
+    TableHandle, WriteTransaction,
 };
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
@@ -16,7 +16,9 @@ struct SpecialValuesDb {
 impl SpecialValuesDb {
     fn new() -> Self {
         SpecialValuesDb {
-            database: Database::create("index.redb").unwrap(),
+            database: Database::builder()
+                .create_with_backend(InMemoryBackend::new())
+                .unwrap(),
             file: OpenOptions::new()
                 .write(true)
                 .create(true)
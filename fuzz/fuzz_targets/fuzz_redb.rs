@@ -1,15 +1,14 @@
 #![no_main]
 
 use libfuzzer_sys::fuzz_target;
-use redb::{AccessGuard, Database, Durability, Error, MultimapTable, MultimapTableDefinition, MultimapValue, ReadableMultimapTable, ReadableTable, Savepoint, Table, TableDefinition, WriteTransaction};
+use redb::{AccessGuard, Database, Durability, Error, MockVfs, MultimapTable, MultimapTableDefinition, MultimapValue, ReadableMultimapTable, ReadableTable, Savepoint, Table, TableDefinition, WriteTransaction};
 use std::collections::{BTreeMap, BTreeSet, HashSet};
-use std::io::{Read, Seek, SeekFrom};
-use tempfile::NamedTempFile;
 
 mod common;
 use common::*;
 use crate::FuzzerSavepoint::{Ephemeral, NotYetDurablePersistent, Persistent};
 
+const DB_PATH: &str = "/fuzz.redb";
 // These slow down the fuzzer, so don't create too many
 const MAX_PERSISTENT_SAVEPOINTS: usize = 20;
 const TABLE_DEF: TableDefinition<u64, &[u8]> = TableDefinition::new("fuzz_table");
@@ -391,13 +390,13 @@ fn handle_table_op(op: &FuzzOperation, reference: &mut BTreeMap<u64, usize>, tab
 }
 
 fn exec_table_crash_support<T: Clone>(config: &FuzzConfig, apply: fn(&Database, &mut BTreeMap<u64, T>, &FuzzTransaction, &mut SavepointManager<T>) -> Result<(), redb::Error>) -> Result<(), redb::Error> {
-    let mut redb_file: NamedTempFile = NamedTempFile::new().unwrap();
+    let vfs = MockVfs::new();
 
     let mut db = Database::builder()
         .set_page_size(config.page_size.value)
         .set_cache_size(config.cache_size.value)
         .set_region_size(config.region_size.value as u64)
-        .create(redb_file.path())
+        .create(DB_PATH, vfs.config(), vfs.clone())
         .unwrap();
     db.set_crash_countdown(config.crash_after_ops.value);
 
@@ -420,17 +419,15 @@ fn exec_table_crash_support<T: Clone>(config: &FuzzConfig, apply: fn(&Database,
                     non_durable_reference = reference.clone();
 
                     // Check that recovery flag is set
-                    redb_file.seek(SeekFrom::Start(9)).unwrap();
-                    let mut god_byte = vec![0u8];
-                    assert_eq!(redb_file.read(&mut god_byte).unwrap(), 1);
-                    assert_ne!(god_byte[0] & 2, 0);
+                    let god_byte = vfs.file(DB_PATH).unwrap()[9];
+                    assert_ne!(god_byte & 2, 0);
 
                     // Repair the database
                     db = Database::builder()
                         .set_page_size(config.page_size.value)
                         .set_cache_size(config.cache_size.value)
                         .set_region_size(config.region_size.value as u64)
-                        .create(redb_file.path())
+                        .create(DB_PATH, vfs.config(), vfs.clone())
                         .unwrap();
                 } else {
                     return Err(err);
@@ -446,17 +443,15 @@ fn exec_table_crash_support<T: Clone>(config: &FuzzConfig, apply: fn(&Database,
                 non_durable_reference = reference.clone();
 
                 // Check that recovery flag is set
-                redb_file.seek(SeekFrom::Start(9)).unwrap();
-                let mut god_byte = vec![0u8];
-                assert_eq!(redb_file.read(&mut god_byte).unwrap(), 1);
-                assert_ne!(god_byte[0] & 2, 0);
+                let god_byte = vfs.file(DB_PATH).unwrap()[9];
+                assert_ne!(god_byte & 2, 0);
 
                 // Repair the database
                 db = Database::builder()
                     .set_page_size(config.page_size.value)
                     .set_cache_size(config.cache_size.value)
                     .set_region_size(config.region_size.value as u64)
-                    .create(redb_file.path())
+                    .create(DB_PATH, vfs.config(), vfs.clone())
                     .unwrap();
             } else {
                 return result;
@@ -0,0 +1,503 @@
+//! Derive macros for the `RedbValue` and `RedbKey` traits of [redb](https://docs.rs/redb)
+//!
+//! Fields are encoded in the same way as a tuple of the field types, so every field type must
+//! implement `RedbValue` (and `RedbKey` to derive `RedbKey`). Enums are encoded as a one byte
+//! variant index followed by the fields of the variant. The encoding is fixed width, if the
+//! fields of every variant are.
+//!
+//! Keys are ordered in the same way as a derived `Ord` implementation: by variant, and then by
+//! each field in order.
+//!
+//! The derived type name is made of the name of the type, and the names of its fields and their
+//! types. It can be overridden with `#[redb(type_name = "...")]`.
+//!
+//! ```ignore
+//! use redb_derive::{RedbKey, RedbValue};
+//!
+//! #[derive(Debug, RedbValue, RedbKey)]
+//! struct Version<'a> {
+//!     name: &'a str,
+//!     major: u32,
+//!     minor: u32,
+//! }
+//! ```
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::visit_mut::VisitMut;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, Fields, GenericParam, Ident, Lifetime, LitStr,
+    Result, Type,
+};
+
+#[proc_macro_derive(RedbValue, attributes(redb))]
+pub fn derive_redb_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    Definition::parse(&input)
+        .map(|definition| definition.value_impl())
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(RedbKey, attributes(redb))]
+pub fn derive_redb_key(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    Definition::parse(&input)
+        .map(|definition| definition.key_impl())
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+// A struct, or a variant of an enum
+struct Variant {
+    // Path used to construct and match the variant
+    path: TokenStream2,
+    // Name of the variant, used in the type name
+    name: Option<Ident>,
+    fields: Fields,
+}
+
+impl Variant {
+    fn types(&self) -> Vec<&Type> {
+        self.fields.iter().map(|field| &field.ty).collect()
+    }
+
+    fn bindings(&self) -> Vec<Ident> {
+        (0..self.fields.len())
+            .map(|i| format_ident!("__field{}", i))
+            .collect()
+    }
+
+    // Pattern which binds each field to its binding, or constructs the variant from them
+    fn pattern(&self, values: &[TokenStream2]) -> TokenStream2 {
+        let path = &self.path;
+        match &self.fields {
+            Fields::Named(fields) => {
+                let names = fields.named.iter().map(|field| &field.ident);
+                quote!(#path { #(#names: #values),* })
+            }
+            Fields::Unnamed(_) => quote!(#path(#(#values),*)),
+            Fields::Unit => quote!(#path),
+        }
+    }
+
+    fn widths(&self) -> TokenStream2 {
+        let types = self.types();
+        quote!([#(<#types as ::redb::RedbValue>::fixed_width()),*])
+    }
+
+    // Sum of the fixed widths of the fields, returning None from the enclosing function if any is
+    // variable width
+    fn fixed_width_impl(&self) -> TokenStream2 {
+        let types = self.types();
+        quote!(0 #(+ <#types as ::redb::RedbValue>::fixed_width()?)*)
+    }
+
+    // Encodes the fields, which must already be bound
+    fn encode_impl(&self, lifetimes: &Lifetimes) -> TokenStream2 {
+        let widths = self.widths();
+        let types: Vec<Type> = self
+            .types()
+            .into_iter()
+            .map(|ty| lifetimes.replace(ty, "__b"))
+            .collect();
+        let bindings = self.bindings();
+        quote! {
+            {
+                #(
+                    let #bindings = <#types as ::redb::RedbValue>::as_bytes(#bindings);
+                )*
+                ::redb::__private::encode_fields(
+                    #widths,
+                    [#(::core::convert::AsRef::<[u8]>::as_ref(&#bindings)),*],
+                )
+            }
+        }
+    }
+
+    fn decode_impl(&self, lifetimes: &Lifetimes, data: TokenStream2) -> TokenStream2 {
+        let widths = self.widths();
+        let bindings = self.bindings();
+        let values: Vec<TokenStream2> = self
+            .types()
+            .into_iter()
+            .zip(&bindings)
+            .map(|(ty, binding)| {
+                let ty = lifetimes.replace(ty, "__a");
+                quote!(<#ty as ::redb::RedbValue>::from_bytes(#binding))
+            })
+            .collect();
+        let constructor = self.pattern(&values);
+        quote! {
+            {
+                let [#(#bindings),*] = ::redb::__private::split_fields(#widths, #data);
+                #constructor
+            }
+        }
+    }
+
+    fn compare_impl(&self, data1: TokenStream2, data2: TokenStream2) -> TokenStream2 {
+        let widths = self.widths();
+        let types = self.types();
+        let indices = 0..types.len();
+        quote! {
+            {
+                let __fields1 = ::redb::__private::split_fields(#widths, #data1);
+                let __fields2 = ::redb::__private::split_fields(#widths, #data2);
+                #(
+                    match <#types as ::redb::RedbKey>::compare(
+                        __fields1[#indices],
+                        __fields2[#indices],
+                    ) {
+                        ::core::cmp::Ordering::Equal => {}
+                        other => return other,
+                    }
+                )*
+                ::core::cmp::Ordering::Equal
+            }
+        }
+    }
+
+    // Appends the name of the variant and its fields to the format string of the type name
+    fn type_name_impl(&self, format: &mut String, arguments: &mut Vec<TokenStream2>) {
+        if let Some(name) = &self.name {
+            format.push_str(&name.to_string());
+        }
+        let (open, close) = match &self.fields {
+            Fields::Named(_) => ("{{", "}}"),
+            Fields::Unnamed(_) => ("(", ")"),
+            Fields::Unit => return,
+        };
+        format.push_str(open);
+        for (i, field) in self.fields.iter().enumerate() {
+            if i > 0 {
+                format.push_str(", ");
+            }
+            if let Some(ident) = &field.ident {
+                format.push_str(&format!("{ident}: "));
+            }
+            format.push_str("{}");
+            let ty = &field.ty;
+            arguments.push(quote!(<#ty as ::redb::RedbValue>::type_name().name()));
+        }
+        format.push_str(close);
+    }
+}
+
+// Replaces the lifetime parameter of the type in field types
+struct Lifetimes {
+    lifetime: Option<Lifetime>,
+}
+
+impl Lifetimes {
+    fn replace(&self, ty: &Type, name: &str) -> Type {
+        let mut ty = ty.clone();
+        if let Some(lifetime) = &self.lifetime {
+            ReplaceLifetime {
+                from: lifetime,
+                to: Lifetime::new(&format!("'{name}"), lifetime.span()),
+            }
+            .visit_type_mut(&mut ty);
+        }
+        ty
+    }
+}
+
+struct ReplaceLifetime<'a> {
+    from: &'a Lifetime,
+    to: Lifetime,
+}
+
+impl VisitMut for ReplaceLifetime<'_> {
+    fn visit_lifetime_mut(&mut self, lifetime: &mut Lifetime) {
+        if lifetime.ident == self.from.ident {
+            *lifetime = self.to.clone();
+        }
+    }
+}
+
+struct Definition {
+    name: Ident,
+    lifetimes: Lifetimes,
+    // A single variant for a struct
+    variants: Vec<Variant>,
+    is_enum: bool,
+    type_name: Option<LitStr>,
+}
+
+impl Definition {
+    fn parse(input: &DeriveInput) -> Result<Self> {
+        let mut lifetime = None;
+        for param in &input.generics.params {
+            match param {
+                GenericParam::Lifetime(param) if lifetime.is_none() => {
+                    lifetime = Some(param.lifetime.clone());
+                }
+                GenericParam::Lifetime(param) => {
+                    return Err(Error::new_spanned(
+                        param,
+                        "only a single lifetime parameter is supported",
+                    ));
+                }
+                _ => {
+                    return Err(Error::new_spanned(
+                        param,
+                        "generic type parameters are not supported",
+                    ));
+                }
+            }
+        }
+
+        let mut type_name = None;
+        for attr in &input.attrs {
+            if attr.path().is_ident("redb") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("type_name") {
+                        type_name = Some(meta.value()?.parse()?);
+                        Ok(())
+                    } else {
+                        Err(meta.error("unsupported redb attribute"))
+                    }
+                })?;
+            }
+        }
+
+        let name = input.ident.clone();
+        let (variants, is_enum) = match &input.data {
+            Data::Struct(data) => (
+                vec![Variant {
+                    path: quote!(#name),
+                    name: None,
+                    fields: data.fields.clone(),
+                }],
+                false,
+            ),
+            Data::Enum(data) => {
+                if data.variants.is_empty() || data.variants.len() > 256 {
+                    return Err(Error::new_spanned(
+                        input,
+                        "enums must have between 1 and 256 variants",
+                    ));
+                }
+                let variants = data
+                    .variants
+                    .iter()
+                    .map(|variant| {
+                        let ident = &variant.ident;
+                        Variant {
+                            path: quote!(#name::#ident),
+                            name: Some(ident.clone()),
+                            fields: variant.fields.clone(),
+                        }
+                    })
+                    .collect();
+                (variants, true)
+            }
+            Data::Union(_) => {
+                return Err(Error::new_spanned(input, "unions are not supported"));
+            }
+        };
+
+        Ok(Self {
+            name,
+            lifetimes: Lifetimes { lifetime },
+            variants,
+            is_enum,
+            type_name,
+        })
+    }
+
+    fn impl_header(&self, trait_name: TokenStream2) -> TokenStream2 {
+        let name = &self.name;
+        match &self.lifetimes.lifetime {
+            Some(lifetime) => quote!(impl<#lifetime> #trait_name for #name<#lifetime>),
+            None => quote!(impl #trait_name for #name),
+        }
+    }
+
+    fn self_type(&self, lifetime: &str) -> TokenStream2 {
+        let name = &self.name;
+        if self.lifetimes.lifetime.is_some() {
+            let lifetime = Lifetime::new(&format!("'{lifetime}"), name.span());
+            quote!(#name<#lifetime>)
+        } else {
+            quote!(#name)
+        }
+    }
+
+    // Variant index, and the variant, of each variant of an enum
+    fn tagged_variants(&self) -> impl Iterator<Item = (u8, &Variant)> {
+        self.variants
+            .iter()
+            .enumerate()
+            .map(|(i, variant)| (u8::try_from(i).unwrap(), variant))
+    }
+
+    fn fixed_width_impl(&self) -> TokenStream2 {
+        if self.is_enum {
+            let widths = self.variants.iter().map(Variant::fixed_width_impl);
+            quote! {
+                let mut width = 0;
+                #(
+                    width = ::core::cmp::max(width, #widths);
+                )*
+                Some(1 + width)
+            }
+        } else {
+            let width = self.variants[0].fixed_width_impl();
+            quote!(Some(#width))
+        }
+    }
+
+    fn encode_impl(&self) -> TokenStream2 {
+        if self.is_enum {
+            let arms = self.tagged_variants().map(|(tag, variant)| {
+                let bindings: Vec<TokenStream2> =
+                    variant.bindings().iter().map(|x| quote!(#x)).collect();
+                let pattern = variant.pattern(&bindings);
+                let encoded = variant.encode_impl(&self.lifetimes);
+                quote! {
+                    #pattern => {
+                        let mut result = vec![#tag];
+                        result.extend_from_slice(&#encoded);
+                        result
+                    }
+                }
+            });
+            quote! {
+                let mut result = match value {
+                    #(#arms)*
+                };
+                if let Some(width) = <Self as ::redb::RedbValue>::fixed_width() {
+                    result.resize(width, 0);
+                }
+                result
+            }
+        } else {
+            let variant = &self.variants[0];
+            let bindings: Vec<TokenStream2> =
+                variant.bindings().iter().map(|x| quote!(#x)).collect();
+            let pattern = variant.pattern(&bindings);
+            let encoded = variant.encode_impl(&self.lifetimes);
+            quote! {
+                let #pattern = value;
+                #encoded
+            }
+        }
+    }
+
+    fn decode_impl(&self) -> TokenStream2 {
+        if self.is_enum {
+            let arms = self.tagged_variants().map(|(tag, variant)| {
+                let value = variant.decode_impl(&self.lifetimes, quote!(&data[1..]));
+                quote!(#tag => #value,)
+            });
+            quote! {
+                match data[0] {
+                    #(#arms)*
+                    _ => unreachable!(),
+                }
+            }
+        } else {
+            self.variants[0].decode_impl(&self.lifetimes, quote!(data))
+        }
+    }
+
+    fn compare_impl(&self) -> TokenStream2 {
+        if self.is_enum {
+            let arms = self.tagged_variants().map(|(tag, variant)| {
+                let compare = variant.compare_impl(quote!(&data1[1..]), quote!(&data2[1..]));
+                quote!(#tag => #compare,)
+            });
+            quote! {
+                match data1[0].cmp(&data2[0]) {
+                    ::core::cmp::Ordering::Equal => {}
+                    other => return other,
+                }
+                match data1[0] {
+                    #(#arms)*
+                    _ => unreachable!(),
+                }
+            }
+        } else {
+            self.variants[0].compare_impl(quote!(data1), quote!(data2))
+        }
+    }
+
+    fn type_name_impl(&self) -> TokenStream2 {
+        if let Some(type_name) = &self.type_name {
+            return quote!(::redb::TypeName::new(#type_name));
+        }
+        let mut format = self.name.to_string();
+        let mut arguments = vec![];
+        if self.is_enum {
+            format.push_str("{{");
+            for (i, variant) in self.variants.iter().enumerate() {
+                if i > 0 {
+                    format.push_str(", ");
+                }
+                variant.type_name_impl(&mut format, &mut arguments);
+            }
+            format.push_str("}}");
+        } else {
+            self.variants[0].type_name_impl(&mut format, &mut arguments);
+        }
+        quote!(::redb::TypeName::new(&format!(#format, #(#arguments),*)))
+    }
+
+    fn value_impl(&self) -> TokenStream2 {
+        let header = self.impl_header(quote!(::redb::RedbValue));
+        let self_type = self.self_type("__a");
+        let fixed_width = self.fixed_width_impl();
+        let as_bytes = self.encode_impl();
+        let from_bytes = self.decode_impl();
+        let type_name = self.type_name_impl();
+        let b_type = self.self_type("__b");
+        quote! {
+            #header {
+                type SelfType<'__a> = #self_type
+                where
+                    Self: '__a;
+                type AsBytes<'__a> = ::std::vec::Vec<u8>
+                where
+                    Self: '__a;
+
+                fn fixed_width() -> Option<usize> {
+                    #fixed_width
+                }
+
+                fn from_bytes<'__a>(data: &'__a [u8]) -> #self_type
+                where
+                    Self: '__a,
+                {
+                    #from_bytes
+                }
+
+                fn as_bytes<'__a, '__b: '__a>(value: &'__a #b_type) -> ::std::vec::Vec<u8>
+                where
+                    Self: '__a,
+                    Self: '__b,
+                {
+                    #as_bytes
+                }
+
+                fn type_name() -> ::redb::TypeName {
+                    #type_name
+                }
+            }
+        }
+    }
+
+    fn key_impl(&self) -> TokenStream2 {
+        let header = self.impl_header(quote!(::redb::RedbKey));
+        let compare = self.compare_impl();
+        quote! {
+            #header {
+                fn compare(data1: &[u8], data2: &[u8]) -> ::core::cmp::Ordering {
+                    #compare
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::table::Range;
+use crate::types::{RedbKey, RedbValue};
+use crate::{
+    AccessGuard, CommitError, Database, Durability, ReadTransaction, Savepoint, SavepointError,
+    StorageError, TransactionError, WriteTransaction,
+};
+use futures_core::Stream;
+use std::ops::Deref;
+use std::panic;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// Number of entries a RangeStream returns before yielding to the executor
+const STREAM_YIELD_INTERVAL: usize = 64;
+
+/// A wrapper around [`Database`] for use from async code
+///
+/// Waiting for the single write slot, in [`AsyncDatabase::begin_write`], yields to the executor
+/// instead of parking the thread. All write transactions must be started through this wrapper:
+/// one started with [`Database::begin_write`] on the inner database will still block.
+///
+/// Commits and aborts run on tokio's blocking thread pool. Other reads and writes are performed on
+/// the calling task, so VFS requests made while executing them block it for the duration of the
+/// request
+pub struct AsyncDatabase {
+    // Shared with the write transactions, which may outlive the borrow of this wrapper while they
+    // are committed on another thread
+    database: Arc<Database>,
+    write_slot: Arc<Semaphore>,
+}
+
+impl AsyncDatabase {
+    pub fn new(database: Database) -> Self {
+        Self {
+            database: Arc::new(database),
+            write_slot: Arc::new(Semaphore::new(1)),
+        }
+    }
+
+    /// Returns the wrapped [`Database`]
+    pub fn database(&self) -> &Database {
+        &self.database
+    }
+
+    /// Returns the wrapped [`Database`], which is shared with any commit that is still running
+    pub fn into_inner(self) -> Arc<Database> {
+        self.database
+    }
+
+    /// Begins a write transaction
+    ///
+    /// If another write, started through this wrapper, is in progress the returned future waits
+    /// until it is committed or aborted
+    pub async fn begin_write(&self) -> Result<AsyncWriteTransaction, TransactionError> {
+        // The semaphore is never closed
+        let permit = self.write_slot.clone().acquire_owned().await.unwrap();
+        let transaction = self.database.begin_write()?;
+        // Safety: the transaction is always dropped before `database`, which keeps the database
+        // alive, and it is only ever exposed through a shared reference, so it can't be swapped
+        // with a transaction which borrows another database
+        let transaction: WriteTransaction<'static> = unsafe { std::mem::transmute(transaction) };
+        Ok(AsyncWriteTransaction {
+            transaction,
+            database: self.database.clone(),
+            _permit: permit,
+        })
+    }
+
+    /// Begins a read transaction. See [`Database::begin_read`]
+    ///
+    /// Read transactions never wait for other transactions
+    pub fn begin_read(&self) -> Result<ReadTransaction<'_>, TransactionError> {
+        self.database.begin_read()
+    }
+}
+
+impl From<Database> for AsyncDatabase {
+    fn from(database: Database) -> Self {
+        Self::new(database)
+    }
+}
+
+/// A [`WriteTransaction`] started by [`AsyncDatabase::begin_write`]
+///
+/// Dereferences to the underlying transaction, which may be used to open tables. The write slot
+/// is released when the transaction is committed, aborted, or dropped
+pub struct AsyncWriteTransaction {
+    // Must be dropped before the database, which it borrows, and before the permit, so that the
+    // next writer finds the slot free
+    transaction: WriteTransaction<'static>,
+    database: Arc<Database>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl AsyncWriteTransaction {
+    /// Set the desired durability level for writes made in this transaction. See
+    /// [`WriteTransaction::set_durability`]
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.transaction.set_durability(durability);
+    }
+
+    /// Restore the state of the database to the given [`Savepoint`]. See
+    /// [`WriteTransaction::restore_savepoint`]
+    pub fn restore_savepoint(&mut self, savepoint: &Savepoint) -> Result<(), SavepointError> {
+        self.transaction.restore_savepoint(savepoint)
+    }
+
+    // Runs `f` on the blocking thread pool, holding the write slot until it finishes, even if the
+    // returned future is dropped
+    async fn run_blocking<T: Send + 'static>(
+        self,
+        f: impl FnOnce(WriteTransaction<'static>) -> T + Send + 'static,
+    ) -> T {
+        let Self {
+            transaction,
+            database,
+            _permit: permit,
+        } = self;
+        let result = tokio::task::spawn_blocking(move || {
+            let result = f(transaction);
+            drop(database);
+            drop(permit);
+            result
+        })
+        .await;
+        match result {
+            Ok(result) => result,
+            Err(err) => panic::resume_unwind(err.into_panic()),
+        }
+    }
+
+    /// Commit the transaction. See [`WriteTransaction::commit`]
+    ///
+    /// The pages are written and synced on tokio's blocking thread pool, so the calling task
+    /// yields until they are durable
+    pub async fn commit(self) -> Result<(), CommitError> {
+        self.run_blocking(|transaction| transaction.commit()).await
+    }
+
+    /// Abort the transaction. See [`WriteTransaction::abort`]
+    pub async fn abort(self) -> Result<(), StorageError> {
+        self.run_blocking(|transaction| transaction.abort()).await
+    }
+}
+
+impl Deref for AsyncWriteTransaction {
+    type Target = WriteTransaction<'static>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.transaction
+    }
+}
+
+/// A [`Stream`] over the entries of a [`Range`]
+///
+/// The range borrows its table, so its pages can't be fetched on another thread: each call to
+/// `poll_next()` which needs a page that isn't cached blocks the executor thread while the page is
+/// read. The stream yields to the executor every 64 entries, so that iterating a large range does
+/// not starve other tasks, but over a slow VFS other tasks still wait for up to that many reads at
+/// a time
+pub struct RangeStream<'a, K: RedbKey + 'static, V: RedbValue + 'static> {
+    range: Range<'a, K, V>,
+    since_yield: usize,
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> RangeStream<'a, K, V> {
+    pub fn new(range: Range<'a, K, V>) -> Self {
+        Self {
+            range,
+            since_yield: 0,
+        }
+    }
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> From<Range<'a, K, V>>
+    for RangeStream<'a, K, V>
+{
+    fn from(range: Range<'a, K, V>) -> Self {
+        Self::new(range)
+    }
+}
+
+// The stream is never pinned structurally, so it doesn't matter whether K and V are Unpin
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Unpin for RangeStream<'a, K, V> {}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Stream for RangeStream<'a, K, V> {
+    type Item = Result<(AccessGuard<'a, K>, AccessGuard<'a, V>), StorageError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.since_yield == STREAM_YIELD_INTERVAL {
+            self.since_yield = 0;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        self.since_yield += 1;
+        Poll::Ready(self.range.next())
+    }
+}
@@ -0,0 +1,528 @@
+use crate::multimap_table::parse_subtree_roots;
+use crate::transaction_tracker::TransactionId;
+use crate::tree_store::{
+    BranchAccessor, Checksum, InternalTableDefinition, LeafAccessor, Page, PageNumber, TableType,
+    TransactionalMemory, BRANCH, LEAF, LEGACY_BRANCH,
+};
+use crate::types::RedbValue;
+use crate::{BackupError, StorageBackend, StorageError};
+use std::collections::HashMap;
+use std::io;
+use std::mem::size_of;
+
+// Backup layout:
+//
+// Header (first 64 bytes):
+// 16 bytes: magic number. Written last, so that an incomplete backup is rejected
+// 1 byte: version
+// 1 byte: != 0 if the backup is incremental
+// 2 bytes: padding
+// 4 bytes: length of the database header
+// 8 bytes: id of the backed up transaction
+// 8 bytes: id of the transaction of the backup this one is based on, if incremental
+// 8 bytes: length of the database file
+// 8 bytes: offset of the manifest
+// 8 bytes: number of entries in the manifest
+//
+// Database header (next n bytes):
+// The header of the restored database. It requires recovery, so that the allocator state is
+// rebuilt when the database is first opened
+//
+// Pages (up to the manifest), each of which is:
+// 8 bytes: offset in the database file
+// 4 bytes: length
+// n bytes: data
+//
+// Manifest (the rest of the backup):
+// Every page of the backed up transaction, each of which is:
+// 8 bytes: page number
+// 16 bytes: checksum
+// 4 bytes: number of children
+// 24 bytes per child: page number and checksum
+
+const MAGICNUMBER: [u8; 16] = [
+    b'r', b'e', b'd', b'b', b'-', b'b', b'a', b'c', b'k', b'u', b'p', 0x1A, 0x0A, 0xA9, 0x0D, 0x0A,
+];
+const VERSION_OFFSET: usize = MAGICNUMBER.len();
+const INCREMENTAL_OFFSET: usize = VERSION_OFFSET + size_of::<u8>();
+const DATABASE_HEADER_LENGTH_OFFSET: usize = INCREMENTAL_OFFSET + size_of::<u8>() + 2; // +2 for padding
+const TRANSACTION_ID_OFFSET: usize = DATABASE_HEADER_LENGTH_OFFSET + size_of::<u32>();
+const BASE_TRANSACTION_ID_OFFSET: usize = TRANSACTION_ID_OFFSET + size_of::<u64>();
+const FILE_LENGTH_OFFSET: usize = BASE_TRANSACTION_ID_OFFSET + size_of::<u64>();
+const MANIFEST_OFFSET_OFFSET: usize = FILE_LENGTH_OFFSET + size_of::<u64>();
+const MANIFEST_ENTRIES_OFFSET: usize = MANIFEST_OFFSET_OFFSET + size_of::<u64>();
+const HEADER_SIZE: usize = MANIFEST_ENTRIES_OFFSET + size_of::<u64>();
+
+const BACKUP_FORMAT_VERSION: u8 = 1;
+
+const POINTER_SIZE: usize = PageNumber::serialized_size() + size_of::<Checksum>();
+
+// Size of the reads and writes made while streaming a backup
+const IO_BUFFER_SIZE: usize = 1024 * 1024;
+
+fn get_u32(data: &[u8]) -> u32 {
+    u32::from_le_bytes(data[..size_of::<u32>()].try_into().unwrap())
+}
+
+fn get_u64(data: &[u8]) -> u64 {
+    u64::from_le_bytes(data[..size_of::<u64>()].try_into().unwrap())
+}
+
+fn sync(backend: &dyn StorageBackend) -> io::Result<()> {
+    match backend.sync_data(false) {
+        Err(err) if err.kind() != io::ErrorKind::Unsupported => Err(err),
+        _ => Ok(()),
+    }
+}
+
+type Pointer = (PageNumber, Checksum);
+
+// The pages of a backed up transaction, and the pages each of them points to.
+// Checksums cover the checksums of child pages, so a page with the same number and checksum as one
+// in the manifest is the root of a subtree which is identical to the one in the backup
+#[derive(Default)]
+struct Manifest {
+    children: HashMap<Pointer, Vec<Pointer>>,
+}
+
+impl Manifest {
+    fn contains(&self, pointer: &Pointer) -> bool {
+        self.children.contains_key(pointer)
+    }
+
+    // Copies the subtree at `pointer` from `other`
+    fn copy_subtree(&mut self, other: &Manifest, pointer: Pointer) {
+        let mut pending = vec![pointer];
+        while let Some(pointer) = pending.pop() {
+            let children = &other.children[&pointer];
+            pending.extend_from_slice(children);
+            self.children.insert(pointer, children.clone());
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut result = vec![];
+        for (pointer, children) in self.children.iter() {
+            push_pointer(&mut result, pointer);
+            let len: u32 = children.len().try_into().unwrap();
+            result.extend_from_slice(&len.to_le_bytes());
+            for child in children {
+                push_pointer(&mut result, child);
+            }
+        }
+
+        result
+    }
+
+    fn read(reader: &mut BackupReader, entries: u64) -> io::Result<Self> {
+        let mut children = HashMap::new();
+        for _ in 0..entries {
+            let pointer = parse_pointer(&reader.read(POINTER_SIZE)?);
+            let len = get_u32(&reader.read(size_of::<u32>())?) as usize;
+            let data = reader.read(len * POINTER_SIZE)?;
+            let pointers = data.chunks_exact(POINTER_SIZE).map(parse_pointer).collect();
+            children.insert(pointer, pointers);
+        }
+        Ok(Self { children })
+    }
+}
+
+fn push_pointer(data: &mut Vec<u8>, (page, checksum): &Pointer) {
+    data.extend_from_slice(&page.to_le_bytes());
+    data.extend_from_slice(&checksum.to_le_bytes());
+}
+
+fn parse_pointer(data: &[u8]) -> Pointer {
+    let page = PageNumber::from_le_bytes(data[..PageNumber::serialized_size()].try_into().unwrap());
+    let checksum = Checksum::from_le_bytes(
+        data[PageNumber::serialized_size()..POINTER_SIZE]
+            .try_into()
+            .unwrap(),
+    );
+    (page, checksum)
+}
+
+// The tree a page belongs to, which determines where its children are stored
+#[derive(Clone, Copy)]
+enum TreeKind {
+    // The table tree, whose values are table definitions
+    Tables,
+    Table {
+        fixed_key_size: Option<usize>,
+    },
+    MultimapTable {
+        fixed_key_size: Option<usize>,
+        fixed_value_size: Option<usize>,
+    },
+    // The values of a single key of a multimap table
+    MultimapValues {
+        fixed_value_size: Option<usize>,
+    },
+}
+
+impl TreeKind {
+    fn fixed_key_size(self) -> Option<usize> {
+        match self {
+            TreeKind::Tables => <&str>::fixed_width(),
+            TreeKind::Table { fixed_key_size, .. }
+            | TreeKind::MultimapTable { fixed_key_size, .. } => fixed_key_size,
+            TreeKind::MultimapValues { fixed_value_size } => fixed_value_size,
+        }
+    }
+
+    // Returns the pages pointed to by `page`, in key order, and the tree each of them belongs to
+    fn children(self, page: &impl Page) -> Result<Vec<(Pointer, TreeKind)>, StorageError> {
+        let children = match page.memory()[0] {
+            BRANCH | LEGACY_BRANCH => {
+                let accessor = BranchAccessor::new(page, self.fixed_key_size());
+                (0..accessor.count_children())
+                    .map(|i| {
+                        let child = accessor.child_page(i).unwrap();
+                        let checksum = accessor.child_checksum(i).unwrap();
+                        ((child, checksum), self)
+                    })
+                    .collect()
+            }
+            LEAF => match self {
+                TreeKind::Tables => {
+                    let accessor = LeafAccessor::new(
+                        page.memory(),
+                        <&str>::fixed_width(),
+                        InternalTableDefinition::fixed_width(),
+                    );
+                    let mut result = vec![];
+                    for i in 0..accessor.num_pairs() {
+                        let entry = accessor.entry(i).unwrap();
+                        let definition = InternalTableDefinition::from_bytes(entry.value());
+                        let fixed_key_size = definition.get_fixed_key_size();
+                        let fixed_value_size = definition.get_fixed_value_size();
+                        let kind = match definition.get_type() {
+                            TableType::Normal => TreeKind::Table { fixed_key_size },
+                            TableType::Multimap => TreeKind::MultimapTable {
+                                fixed_key_size,
+                                fixed_value_size,
+                            },
+                        };
+                        if let Some(root) = definition.get_root() {
+                            result.push((root, kind));
+                        }
+                    }
+                    result
+                }
+                TreeKind::MultimapTable {
+                    fixed_key_size,
+                    fixed_value_size,
+                } => parse_subtree_roots(page, fixed_key_size, fixed_value_size)
+                    .into_iter()
+                    .map(|root| (root, TreeKind::MultimapValues { fixed_value_size }))
+                    .collect(),
+                TreeKind::Table { .. } | TreeKind::MultimapValues { .. } => vec![],
+            },
+            page_type => {
+                return Err(StorageError::Corrupted(format!(
+                    "Page {:?} has invalid type {page_type}",
+                    page.get_page_number()
+                )));
+            }
+        };
+        Ok(children)
+    }
+}
+
+struct BackupWriter<'a> {
+    backend: &'a dyn StorageBackend,
+    offset: u64,
+    buffer: Vec<u8>,
+}
+
+impl<'a> BackupWriter<'a> {
+    fn new(backend: &'a dyn StorageBackend, offset: u64) -> Self {
+        Self {
+            backend,
+            offset,
+            buffer: vec![],
+        }
+    }
+
+    fn position(&self) -> u64 {
+        self.offset + self.buffer.len() as u64
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() >= IO_BUFFER_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.backend.write_all_at(&self.buffer, self.offset)?;
+            self.offset += self.buffer.len() as u64;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+}
+
+struct BackupReader<'a> {
+    backend: &'a dyn StorageBackend,
+    // Offset of the end of the buffer
+    offset: u64,
+    end: u64,
+    buffer: Vec<u8>,
+    consumed: usize,
+}
+
+impl<'a> BackupReader<'a> {
+    fn new(backend: &'a dyn StorageBackend, start: u64, end: u64) -> Self {
+        Self {
+            backend,
+            offset: start,
+            end,
+            buffer: vec![],
+            consumed: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.consumed == self.buffer.len() && self.offset == self.end
+    }
+
+    fn read(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        let buffered = self.buffer.len() - self.consumed;
+        if buffered < len {
+            let remaining = self.end - self.offset;
+            let wanted = (len - buffered).max(IO_BUFFER_SIZE) as u64;
+            let chunk_len: usize = remaining.min(wanted).try_into().unwrap();
+            if buffered + chunk_len < len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.buffer.drain(..self.consumed);
+            self.consumed = 0;
+            self.buffer.resize(buffered + chunk_len, 0);
+            self.backend
+                .read_exact_at(&mut self.buffer[buffered..], self.offset)?;
+            self.offset += chunk_len as u64;
+        }
+        let result = self.buffer[self.consumed..(self.consumed + len)].to_vec();
+        self.consumed += len;
+        Ok(result)
+    }
+}
+
+struct BackupHeader {
+    transaction_id: u64,
+    // The transaction id of the backup this one is based on, if it is incremental
+    base: Option<u64>,
+    file_length: u64,
+    manifest_offset: u64,
+    manifest_entries: u64,
+    database_header: Vec<u8>,
+}
+
+impl BackupHeader {
+    fn read(backend: &dyn StorageBackend) -> Result<Self, BackupError> {
+        if backend.len()? < HEADER_SIZE as u64 {
+            return Err(BackupError::InvalidBackup);
+        }
+        let mut data = [0; HEADER_SIZE];
+        backend.read_exact_at(&mut data, 0)?;
+        if data[..MAGICNUMBER.len()] != MAGICNUMBER || data[VERSION_OFFSET] != BACKUP_FORMAT_VERSION
+        {
+            return Err(BackupError::InvalidBackup);
+        }
+        let base = if data[INCREMENTAL_OFFSET] != 0 {
+            Some(get_u64(&data[BASE_TRANSACTION_ID_OFFSET..]))
+        } else {
+            None
+        };
+        let mut database_header = vec![0; get_u32(&data[DATABASE_HEADER_LENGTH_OFFSET..]) as usize];
+        backend.read_exact_at(&mut database_header, HEADER_SIZE as u64)?;
+
+        Ok(Self {
+            transaction_id: get_u64(&data[TRANSACTION_ID_OFFSET..]),
+            base,
+            file_length: get_u64(&data[FILE_LENGTH_OFFSET..]),
+            manifest_offset: get_u64(&data[MANIFEST_OFFSET_OFFSET..]),
+            manifest_entries: get_u64(&data[MANIFEST_ENTRIES_OFFSET..]),
+            database_header,
+        })
+    }
+
+    fn pages_offset(&self) -> u64 {
+        (HEADER_SIZE + self.database_header.len()) as u64
+    }
+
+    // Serializes the header, without the magic number
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut result = vec![0; HEADER_SIZE];
+        result[VERSION_OFFSET] = BACKUP_FORMAT_VERSION;
+        result[INCREMENTAL_OFFSET] = self.base.is_some().into();
+        let database_header_length: u32 = self.database_header.len().try_into().unwrap();
+        result[DATABASE_HEADER_LENGTH_OFFSET..TRANSACTION_ID_OFFSET]
+            .copy_from_slice(&database_header_length.to_le_bytes());
+        result[TRANSACTION_ID_OFFSET..BASE_TRANSACTION_ID_OFFSET]
+            .copy_from_slice(&self.transaction_id.to_le_bytes());
+        result[BASE_TRANSACTION_ID_OFFSET..FILE_LENGTH_OFFSET]
+            .copy_from_slice(&self.base.unwrap_or_default().to_le_bytes());
+        result[FILE_LENGTH_OFFSET..MANIFEST_OFFSET_OFFSET]
+            .copy_from_slice(&self.file_length.to_le_bytes());
+        result[MANIFEST_OFFSET_OFFSET..MANIFEST_ENTRIES_OFFSET]
+            .copy_from_slice(&self.manifest_offset.to_le_bytes());
+        result[MANIFEST_ENTRIES_OFFSET..HEADER_SIZE]
+            .copy_from_slice(&self.manifest_entries.to_le_bytes());
+        result.extend_from_slice(&self.database_header);
+
+        result
+    }
+}
+
+// A backup which an incremental backup is based on
+pub(crate) struct BackupBase {
+    transaction_id: u64,
+    manifest: Manifest,
+}
+
+impl BackupBase {
+    pub(crate) fn read(backend: &dyn StorageBackend) -> Result<Self, BackupError> {
+        let header = BackupHeader::read(backend)?;
+        let mut reader = BackupReader::new(backend, header.manifest_offset, backend.len()?);
+        let manifest = Manifest::read(&mut reader, header.manifest_entries)?;
+        Ok(Self {
+            transaction_id: header.transaction_id,
+            manifest,
+        })
+    }
+}
+
+// Writes a backup of the user tree at `root`, which was committed by `transaction_id`. Pages which
+// are part of `base` are skipped
+pub(crate) fn write_backup(
+    mem: &TransactionalMemory,
+    root: Option<(PageNumber, Checksum)>,
+    transaction_id: TransactionId,
+    base: Option<&BackupBase>,
+    backend: &dyn StorageBackend,
+) -> Result<(), BackupError> {
+    let (database_header, file_length) = mem.backup_header(root, transaction_id);
+    // Remove any existing backup first, so that its magic number is never paired with this one
+    backend.set_len(0)?;
+    let mut writer = BackupWriter::new(backend, (HEADER_SIZE + database_header.len()) as u64);
+
+    let mut manifest = Manifest::default();
+    let mut pending: Vec<(Pointer, TreeKind)> = root
+        .map(|root| (root, TreeKind::Tables))
+        .into_iter()
+        .collect();
+    while let Some((pointer, kind)) = pending.pop() {
+        if let Some(base) = base {
+            if base.manifest.contains(&pointer) {
+                manifest.copy_subtree(&base.manifest, pointer);
+                continue;
+            }
+        }
+        let page = mem.get_page(pointer.0)?;
+        let children = kind.children(&page)?;
+        if mem.read_ahead_pages() > 0 {
+            let unchanged = |child: &Pointer| base.map_or(false, |x| x.manifest.contains(child));
+            let changed: Vec<PageNumber> = children
+                .iter()
+                .filter(|(child, _)| !unchanged(child))
+                .map(|(child, _)| child.0)
+                .collect();
+            mem.read_ahead(&changed)?;
+        }
+
+        let data = page.memory();
+        let len: u32 = data.len().try_into().unwrap();
+        writer.write(&mem.page_offset(pointer.0).to_le_bytes())?;
+        writer.write(&len.to_le_bytes())?;
+        writer.write(data)?;
+
+        manifest
+            .children
+            .insert(pointer, children.iter().map(|(child, _)| *child).collect());
+        // Visit the children in order
+        pending.extend(children.into_iter().rev());
+    }
+
+    let header = BackupHeader {
+        transaction_id: transaction_id.0,
+        base: base.map(|base| base.transaction_id),
+        file_length,
+        manifest_offset: writer.position(),
+        manifest_entries: manifest.children.len() as u64,
+        database_header,
+    };
+    writer.write(&manifest.to_bytes())?;
+    writer.flush()?;
+    backend.write_all_at(&header.to_bytes(), 0)?;
+    sync(backend)?;
+    backend.write_all_at(&MAGICNUMBER, 0)?;
+    sync(backend)?;
+
+    Ok(())
+}
+
+// Rebuilds a database in `target` from a full backup and the incremental backups which follow it.
+// Returns the id of the restored transaction
+pub(crate) fn restore(
+    backups: &[&dyn StorageBackend],
+    target: &dyn StorageBackend,
+) -> Result<u64, BackupError> {
+    let mut headers = vec![];
+    for backup in backups {
+        headers.push(BackupHeader::read(*backup)?);
+    }
+    if headers.is_empty() {
+        return Err(BackupError::InvalidBackup);
+    }
+    // Backups before the last full backup are not needed
+    let start = headers
+        .iter()
+        .rposition(|header| header.base.is_none())
+        .ok_or_else(|| BackupError::MissingBase(headers[0].base.unwrap()))?;
+    for i in (start + 1)..headers.len() {
+        let base = headers[i].base.unwrap();
+        if base != headers[i - 1].transaction_id {
+            return Err(BackupError::MissingBase(base));
+        }
+    }
+
+    let last = headers.last().unwrap();
+    target.set_len(0)?;
+    target.set_len(last.file_length)?;
+    // Later backups overwrite pages which were reused since the earlier ones
+    for (backup, header) in backups[start..].iter().zip(&headers[start..]) {
+        let mut reader = BackupReader::new(*backup, header.pages_offset(), header.manifest_offset);
+        let mut pages = vec![];
+        let mut buffered = 0;
+        while !reader.is_empty() {
+            let offset = get_u64(&reader.read(size_of::<u64>())?);
+            let len = get_u32(&reader.read(size_of::<u32>())?) as usize;
+            let data = reader.read(len)?;
+            // Pages which were freed by the time of the last backup may lie beyond its end
+            if offset < last.file_length {
+                pages.push((offset, data));
+            }
+            buffered += len;
+            if buffered >= IO_BUFFER_SIZE || reader.is_empty() {
+                pages.sort_by_key(|(offset, _)| *offset);
+                let writes: Vec<(u64, &[u8])> = pages
+                    .iter()
+                    .map(|(offset, data)| (*offset, data.as_slice()))
+                    .collect();
+                target.write_batch(&writes)?;
+                pages.clear();
+                buffered = 0;
+            }
+        }
+    }
+    sync(target)?;
+    target.write_all_at(&last.database_header, 0)?;
+    sync(target)?;
+
+    Ok(last.transaction_id)
+}
@@ -1,32 +1,38 @@
+use crate::backup::{restore, write_backup, BackupBase};
 use crate::transaction_tracker::{SavepointId, TransactionId, TransactionTracker};
 use crate::tree_store::{
-    AllPageNumbersBtreeIter, BtreeRangeIter, Checksum, FreedPageList, FreedTableKey,
-    InternalTableDefinition, PageHint, PageNumber, RawBtree, SerializedSavepoint, TableTree,
-    TableType, TransactionalMemory, PAGE_SIZE,
+    AllPageNumbersBtreeIter, BtreeRangeIter, Checksum, CompactionCursor, FreedPageList,
+    FreedTableKey, InternalTableDefinition, PageHint, PageNumber, RawBtree, SerializedSavepoint,
+    TableTree, TableType, TransactionalMemory, PAGE_SIZE,
 };
 use crate::types::{RedbKey, RedbValue};
 use crate::{
-    CompactionError, DatabaseError, Durability, ReadOnlyTable, ReadableTable, SavepointError,
-    StorageError,
+    BackupError, ChangeEvent, CompactionError, DatabaseError, Durability, ReadOnlyTable,
+    ReadableTable, Savepoint, SavepointError, StorageBackend, StorageError,
 };
 use crate::{ReadTransaction, Result, WriteTransaction};
 use std::fmt::{Display, Formatter};
 // use std::fs::{File, OpenOptions};
+use std::borrow::Borrow;
 use std::io::ErrorKind;
 use std::marker::PhantomData;
-use std::ops::RangeFull;
+use std::ops::{Bound, RangeBounds, RangeFull};
 use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
+use tokio::sync::mpsc::UnboundedReceiver;
 
 use crate::error::TransactionError;
+use crate::index::{IndexDefinition, Indexes};
+use crate::merge::MergeOperator;
 use crate::multimap_table::{parse_subtree_roots, DynamicCollection};
 use crate::sealed::Sealed;
+use crate::subscriptions::Subscriptions;
 use crate::transactions::SAVEPOINT_TABLE;
 #[cfg(feature = "logging")]
 use log::{info, warn};
 
-use crate::{File, OpenOptions};
+use crate::{File, OpenOptions, VfsConfig, VfsTransport};
 
 struct AtomicTransactionId {
     inner: AtomicU64,
@@ -101,6 +107,7 @@ impl Sealed for UntypedMultimapTableHandle {}
 /// that is stored or retreived from the table
 pub struct TableDefinition<'a, K: RedbKey + 'static, V: RedbValue + 'static> {
     name: &'a str,
+    merge_operator: Option<MergeOperator<V>>,
     _key_type: PhantomData<K>,
     _value_type: PhantomData<V>,
 }
@@ -115,10 +122,23 @@ impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> TableDefinition<'a, K, V>
         assert!(!name.is_empty());
         Self {
             name,
+            merge_operator: None,
             _key_type: PhantomData,
             _value_type: PhantomData,
         }
     }
+
+    /// Sets the operator used by [`Table::merge`](crate::Table::merge) to combine values
+    pub const fn with_merge_operator(self, merge_operator: MergeOperator<V>) -> Self {
+        Self {
+            merge_operator: Some(merge_operator),
+            ..self
+        }
+    }
+
+    pub(crate) fn merge_operator(&self) -> Option<MergeOperator<V>> {
+        self.merge_operator
+    }
 }
 
 impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> TableHandle for TableDefinition<'a, K, V> {
@@ -218,13 +238,10 @@ impl<'a, K: RedbKey + 'static, V: RedbKey + 'static> Display for MultimapTableDe
 ///
 /// ```rust
 /// use redb::*;
-/// # use tempfile::NamedTempFile;
 /// const TABLE: TableDefinition<u64, u64> = TableDefinition::new("my_data");
 ///
 /// # fn main() -> Result<(), Error> {
-/// # let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
-/// # let filename = tmpfile.path();
-/// let db = Database::create(filename)?;
+/// let db = Database::builder().create_with_backend(InMemoryBackend::new())?;
 /// let write_txn = db.begin_write()?;
 /// {
 ///     let mut table = write_txn.open_table(TABLE)?;
@@ -240,6 +257,10 @@ pub struct Database {
     transaction_tracker: Arc<Mutex<TransactionTracker>>,
     live_write_transaction: Mutex<Option<TransactionId>>,
     live_write_transaction_available: Condvar,
+    subscriptions: Subscriptions,
+    indexes: Indexes,
+    // Where the previous call to compact_step() stopped
+    compaction_cursor: Mutex<CompactionCursor>,
 }
 
 impl Database {
@@ -247,35 +268,52 @@ impl Database {
     /// * if the file does not exist, or is an empty file, a new database will be initialized in it
     /// * if the file is a valid redb database, it will be opened
     /// * otherwise this function will return an error
+    ///
+    /// The database is leased while it is open. Returns [`DatabaseError::DatabaseAlreadyOpen`] if
+    /// another process holds an unexpired lease on it
     pub fn create(
         path: impl AsRef<Path>,
-        our_node: String,
-        drive: String,
-        get_payload: fn() -> Option<(Option<String>, Vec<u8>)>,
-        send_and_await_response: fn(String, String, String, String, Vec<u8>, Option<String>, Option<(Option<String>, Vec<u8>)>, u64) -> (Vec<u8>, Option<String>),
+        config: VfsConfig,
+        transport: impl VfsTransport,
     ) -> Result<Database, DatabaseError> {
-        Self::builder().create(
-            path,
-            our_node,
-            drive,
-            get_payload,
-            send_and_await_response,
-        )
+        Self::builder().create(path, config, transport)
     }
 
     /// Opens an existing redb database.
     pub fn open(
         path: impl AsRef<Path>,
-        drive: String,
-        get_payload: fn() -> Option<(Option<String>, Vec<u8>)>,
-        send_and_await_response: fn(String, String, String, String, Vec<u8>, Option<String>, Option<(Option<String>, Vec<u8>)>, u64) -> (Vec<u8>, Option<String>),
+        config: VfsConfig,
+        transport: impl VfsTransport,
     ) -> Result<Database, DatabaseError> {
-        Self::builder().open(
-            path,
-            drive,
-            get_payload,
-            send_and_await_response,
-        )
+        Self::builder().open(path, config, transport)
+    }
+
+    /// Breaks the lease held on the database at `path`, so that it can be opened again.
+    ///
+    /// Only call this when the process which held the database open is known to have exited
+    /// without closing it, for example after a crash, to avoid waiting for its lease to expire.
+    /// Breaking the lease of a database which is still in use allows it to be opened twice, which
+    /// may corrupt it.
+    pub fn break_lease(
+        path: impl AsRef<Path>,
+        config: VfsConfig,
+        transport: impl VfsTransport,
+    ) -> Result<(), DatabaseError> {
+        let path = vfs_path(path.as_ref())?;
+        crate::uqfile::break_lease(&path, &config, &transport)?;
+        Ok(())
+    }
+
+    /// Renews the lease held on the database, if half of it has elapsed.
+    ///
+    /// Any access to the database renews its lease, but the lease of a database which may be idle
+    /// for longer than half of [`VfsConfig::lease_duration`] must be renewed by calling this
+    /// periodically, for example from a timer. Otherwise, another process may open the database
+    /// once the lease has expired.
+    ///
+    /// Returns an error if the lease has been taken by another process.
+    pub fn renew_lease(&self) -> Result {
+        self.mem.renew_lock()
     }
 
     pub(crate) fn start_write_transaction(&self) -> TransactionId {
@@ -391,7 +429,10 @@ impl Database {
             let mut progress = false;
 
             let mut txn = self.begin_write().map_err(|e| e.into_storage_error())?;
-            if txn.compact_pages()? {
+            if txn
+                .compact_pages(usize::MAX, &mut CompactionCursor::default())?
+                .0
+            {
                 progress = true;
                 txn.commit().map_err(|e| e.into_storage_error())?;
             } else {
@@ -414,6 +455,32 @@ impl Database {
         Ok(compacted)
     }
 
+    /// Performs one step of compaction in a single write transaction, and shrinks the file if
+    /// trailing regions have become free
+    ///
+    /// Each step reads at most `max_pages` pages, not counting the few pages needed to get back to
+    /// where the previous step stopped, and relocates those which can be moved to lower pages.
+    ///
+    /// Unlike [`Database::compact`], this can be called while the database is in use, and while
+    /// savepoints exist. Pages are only freed once no read transaction or savepoint references
+    /// them, so the file shrinks as older snapshots are released. Call it repeatedly, for example
+    /// between other writes, until it returns `false`
+    ///
+    /// Returns `true` if any pages were relocated, the file was shrunk, or the current pass over
+    /// the database isn't complete
+    pub fn compact_step(&self, max_pages: usize) -> Result<bool, CompactionError> {
+        let file_len = self.mem.get_file_len();
+        let mut txn = self.begin_write().map_err(|e| e.into_storage_error())?;
+        let (relocated, finished) = {
+            let mut cursor = self.compaction_cursor.lock().unwrap();
+            txn.compact_pages(max_pages, &mut cursor)?
+        };
+        // Always commit, to free the pages relocated by the previous step
+        txn.commit().map_err(|e| e.into_storage_error())?;
+
+        Ok(relocated || !finished || self.mem.get_file_len() < file_len)
+    }
+
     fn mark_persistent_savepoints(
         system_root: Option<(PageNumber, Checksum)>,
         mem: &TransactionalMemory,
@@ -619,11 +686,13 @@ impl Database {
     }
 
     fn new(
-        file: File,
+        file: Box<dyn StorageBackend>,
         page_size: usize,
         region_size: Option<u64>,
         read_cache_size_bytes: usize,
         write_cache_size_bytes: usize,
+        read_ahead_pages: usize,
+        allow_unsynced: bool,
     ) -> Result<Self, DatabaseError> {
         #[cfg(feature = "logging")]
         let file_path = format!("{:?}", &file);
@@ -635,6 +704,8 @@ impl Database {
             region_size,
             read_cache_size_bytes,
             write_cache_size_bytes,
+            read_ahead_pages,
+            allow_unsynced,
         )?;
         if mem.needs_repair()? {
             #[cfg(feature = "logging")]
@@ -651,6 +722,9 @@ impl Database {
             transaction_tracker: Arc::new(Mutex::new(TransactionTracker::new())),
             live_write_transaction: Mutex::new(None),
             live_write_transaction_available: Condvar::new(),
+            subscriptions: Default::default(),
+            indexes: Default::default(),
+            compaction_cursor: Default::default(),
         };
 
         // Restore the tracker state for any persistent savepoints
@@ -727,8 +801,200 @@ impl Database {
             self.get_memory(),
             self.transaction_tracker.clone(),
             id,
+            self.mem.get_data_root(),
+        ))
+    }
+
+    /// Begins a read transaction over the state of the database when the given persistent
+    /// savepoint was created. See [`WriteTransaction::persistent_savepoint`]
+    ///
+    /// The savepoint is not restored, so later writes are not affected. It may be deleted while the
+    /// transaction is in use, in which case its pages are kept until the transaction is dropped
+    ///
+    /// Returns [`SavepointError::InvalidSavepoint`] if the savepoint does not exist
+    pub fn begin_read_at(&self, savepoint_id: u64) -> Result<ReadTransaction<'_>, SavepointError> {
+        // Keep the current system tree alive, while the savepoint is looked up in it
+        let current = self.allocate_read_transaction()?;
+        let savepoint = self.read_persistent_savepoint(SavepointId(savepoint_id));
+        let mut tracker = self.transaction_tracker.lock().unwrap();
+        tracker.deallocate_read_transaction(current);
+        let savepoint = savepoint?.ok_or(SavepointError::InvalidSavepoint)?;
+        // The savepoint may have been deleted since it was looked up
+        if !tracker.is_valid_savepoint(savepoint.get_id()) {
+            return Err(SavepointError::InvalidSavepoint);
+        }
+        let id = savepoint.get_transaction_id();
+        tracker.register_read_transaction(id);
+        drop(tracker);
+        #[cfg(feature = "logging")]
+        info!(
+            "Beginning read transaction id={:?} at savepoint id={:?}",
+            id,
+            savepoint.get_id()
+        );
+
+        Ok(ReadTransaction::new(
+            self.get_memory(),
+            self.transaction_tracker.clone(),
+            id,
+            savepoint.get_user_root(),
         ))
     }
+
+    /// Writes a backup of the database, as of the last committed transaction, to `backend`.
+    /// Returns the id of that transaction
+    ///
+    /// The backup is made from a read snapshot, so writes may continue while it is in progress. It
+    /// holds the contents of all tables, but not persistent savepoints. Any existing contents of
+    /// `backend` are replaced. Use [`Database::restore`] to rebuild a database from it
+    pub fn backup_to(&self, backend: &dyn StorageBackend) -> Result<u64, BackupError> {
+        self.backup(None, backend)
+    }
+
+    /// Writes an incremental backup to `backend`, holding only the pages written since the backup
+    /// in `previous` was made. `previous` must hold a full or incremental backup of this database
+    ///
+    /// Returns the id of the backed up transaction. See [`Database::backup_to`]
+    pub fn backup_incremental_to(
+        &self,
+        previous: &dyn StorageBackend,
+        backend: &dyn StorageBackend,
+    ) -> Result<u64, BackupError> {
+        let base = BackupBase::read(previous)?;
+        self.backup(Some(&base), backend)
+    }
+
+    fn backup(
+        &self,
+        base: Option<&BackupBase>,
+        backend: &dyn StorageBackend,
+    ) -> Result<u64, BackupError> {
+        // Keeps the pages being copied from being freed
+        let snapshot = self.begin_read().map_err(|e| e.into_storage_error())?;
+        let transaction_id = snapshot.transaction_id();
+        #[cfg(feature = "logging")]
+        info!("Backing up transaction id={:?}", transaction_id);
+        write_backup(&self.mem, snapshot.root(), transaction_id, base, backend)?;
+
+        Ok(transaction_id.0)
+    }
+
+    /// Rebuilds a database in `target` from `backups`: a full backup, followed by the incremental
+    /// backups made from it in the order they were made. Any existing contents of `target` are
+    /// replaced. Returns the id of the restored transaction
+    ///
+    /// The restored database must be opened with the page size of the backed up one
+    pub fn restore(
+        backups: &[&dyn StorageBackend],
+        target: &dyn StorageBackend,
+    ) -> Result<u64, BackupError> {
+        restore(backups, target)
+    }
+
+    fn read_persistent_savepoint(&self, id: SavepointId) -> Result<Option<Savepoint>> {
+        let table_tree = TableTree::new(self.mem.get_system_root(), &self.mem, Default::default());
+        let savepoint_table_def = match table_tree
+            .get_table::<SavepointId, SerializedSavepoint>(
+                SAVEPOINT_TABLE.name(),
+                TableType::Normal,
+            )
+            .map_err(|e| {
+                e.into_storage_error_or_corrupted("Persistent savepoint table corrupted")
+            })? {
+            Some(definition) => definition,
+            None => return Ok(None),
+        };
+        let savepoint_table: ReadOnlyTable<SavepointId, SerializedSavepoint> =
+            ReadOnlyTable::new(savepoint_table_def.get_root(), PageHint::None, &self.mem)?;
+        let savepoint = savepoint_table.get(id)?.map(|serialized| {
+            serialized
+                .value()
+                .to_savepoint(self.transaction_tracker.clone())
+        });
+        Ok(savepoint)
+    }
+
+    /// Subscribes to changes made to `range` of the given table
+    ///
+    /// After each successful [`WriteTransaction::commit`] which inserted, updated, or removed keys in
+    /// the range, the returned channel receives a [`ChangeEvent`] listing them. Aborted transactions
+    /// are never reported. Changes made by restoring a savepoint, or deleting the table, are not
+    /// reported either
+    ///
+    /// The channel is unbounded, so events accumulate until they are received. Drop the receiver to
+    /// unsubscribe
+    pub fn subscribe<'a, K: RedbKey + 'static, V: RedbValue + 'static, KR>(
+        &self,
+        definition: TableDefinition<K, V>,
+        range: impl RangeBounds<KR> + 'a,
+    ) -> UnboundedReceiver<ChangeEvent>
+    where
+        KR: Borrow<K::SelfType<'a>> + 'a,
+    {
+        let (start, end) = range_bytes::<K, KR>(&range);
+        self.subscriptions
+            .subscribe::<K>(definition.name(), start, end)
+    }
+
+    /// Subscribes to changes made to `range` of the given multimap table. See [`Database::subscribe`]
+    ///
+    /// A key is reported as [`ChangeKind::Updated`](crate::ChangeKind::Updated) when values are
+    /// added to, or removed from, its existing set of values
+    pub fn subscribe_multimap<'a, K: RedbKey + 'static, V: RedbKey + 'static, KR>(
+        &self,
+        definition: MultimapTableDefinition<K, V>,
+        range: impl RangeBounds<KR> + 'a,
+    ) -> UnboundedReceiver<ChangeEvent>
+    where
+        KR: Borrow<K::SelfType<'a>> + 'a,
+    {
+        let (start, end) = range_bytes::<K, KR>(&range);
+        self.subscriptions
+            .subscribe::<K>(definition.name(), start, end)
+    }
+
+    pub(crate) fn subscriptions(&self) -> &Subscriptions {
+        &self.subscriptions
+    }
+
+    /// Registers a secondary index of a table
+    ///
+    /// Write transactions begun afterwards keep the index up to date: each time its table is
+    /// opened, the multimap table of the index is opened along with it, and updated by every
+    /// insert or removal. Entries written before the index was registered, or while it was not,
+    /// are not indexed until [`Table::check_index`](crate::Table::check_index) rebuilds it.
+    /// Registering another index with the same name replaces it
+    pub fn register_index<K, V, I>(&self, index: IndexDefinition<K, V, I>)
+    where
+        K: RedbKey + Send + Sync + 'static,
+        V: RedbValue + 'static,
+        I: RedbKey + Send + Sync + 'static,
+    {
+        self.indexes.register(index);
+    }
+
+    pub(crate) fn indexes(&self) -> &Indexes {
+        &self.indexes
+    }
+}
+
+pub(crate) fn range_bytes<'a, K: RedbKey + 'a, KR: Borrow<K::SelfType<'a>>>(
+    range: &impl RangeBounds<KR>,
+) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+    let to_bytes = |bound: Bound<&KR>| match bound {
+        Bound::Included(key) => Bound::Included(K::as_bytes(key.borrow()).as_ref().to_vec()),
+        Bound::Excluded(key) => Bound::Excluded(K::as_bytes(key.borrow()).as_ref().to_vec()),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    (to_bytes(range.start_bound()), to_bytes(range.end_bound()))
+}
+
+// VFS paths are strings, so reject paths which are not valid UTF-8
+fn vfs_path(path: &Path) -> Result<String, DatabaseError> {
+    match path.to_str() {
+        Some(path) => Ok(path.to_string()),
+        None => Err(StorageError::Io(ErrorKind::InvalidInput.into()).into()),
+    }
 }
 
 /// Configuration builder of a redb [Database].
@@ -737,6 +1003,8 @@ pub struct Builder {
     region_size: Option<u64>,
     read_cache_size_bytes: usize,
     write_cache_size_bytes: usize,
+    read_ahead_pages: usize,
+    allow_unsynced: bool,
 }
 
 impl Builder {
@@ -745,6 +1013,7 @@ impl Builder {
     /// ## Defaults
     ///
     /// - `cache_size_bytes`: 1GiB
+    /// - `read_ahead`: 16 pages
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         let mut result = Self {
@@ -757,6 +1026,8 @@ impl Builder {
             read_cache_size_bytes: 0,
             // TODO: Default should probably take into account the total system memory
             write_cache_size_bytes: 0,
+            read_ahead_pages: 16,
+            allow_unsynced: false,
         };
 
         result.set_cache_size(1024 * 1024 * 1024);
@@ -785,6 +1056,34 @@ impl Builder {
         self
     }
 
+    /// Set the number of pages which range iterators read ahead
+    ///
+    /// Once an iterator has consumed a leaf, and so is scanning sequentially, the upcoming sibling
+    /// pages are fetched together, merging pages which are close together in the file into a single
+    /// read. This reduces the number of round trips to slow backends. Set to 0 to disable
+    ///
+    /// ## Defaults
+    ///
+    /// Default to 16 pages.
+    pub fn set_read_ahead(&mut self, pages: usize) -> &mut Self {
+        self.read_ahead_pages = pages;
+        self
+    }
+
+    /// Allow the database to be used with a storage backend which cannot sync
+    ///
+    /// When enabled, commits to a backend whose [`StorageBackend::sync_data`] returns an error of
+    /// kind [`ErrorKind::Unsupported`] succeed without any durability guarantee, and a warning is
+    /// logged. Otherwise, such commits return an error, as does creating a new database.
+    ///
+    /// ## Defaults
+    ///
+    /// Default to `false`.
+    pub fn set_allow_unsynced(&mut self, allow: bool) -> &mut Self {
+        self.allow_unsynced = allow;
+        self
+    }
+
     #[cfg(any(test, fuzzing))]
     pub fn set_region_size(&mut self, size: u64) -> &mut Self {
         assert!(size.is_power_of_two());
@@ -799,27 +1098,22 @@ impl Builder {
     pub fn create(
         &self,
         path: impl AsRef<Path>,
-        our_node: String,
-        drive: String,
-        get_payload: fn() -> Option<(Option<String>, Vec<u8>)>,
-        send_and_await_response: fn(String, String, String, String, Vec<u8>, Option<String>, Option<(Option<String>, Vec<u8>)>, u64) -> (Vec<u8>, Option<String>),
+        config: VfsConfig,
+        transport: impl VfsTransport,
     ) -> Result<Database, DatabaseError> {
+        let path = vfs_path(path.as_ref())?;
         let file = OpenOptions::new()
-            .read(true)
-            .write(true)
             .create(true)
-            .our_node(our_node)
-            .drive(drive)
-            .get_payload(get_payload)
-            .send_and_await_response(send_and_await_response)
-            .open(path.as_ref().to_str().unwrap().into())?;
+            .open(path, config, transport)?;
 
         Database::new(
-            file,
+            Box::new(file),
             self.page_size,
             self.region_size,
             self.read_cache_size_bytes,
             self.write_cache_size_bytes,
+            self.read_ahead_pages,
+            self.allow_unsynced,
         )
     }
 
@@ -827,28 +1121,24 @@ impl Builder {
     pub fn open(
         &self,
         path: impl AsRef<Path>,
-        drive: String,
-        get_payload: fn() -> Option<(Option<String>, Vec<u8>)>,
-        send_and_await_response: fn(String, String, String, String, Vec<u8>, Option<String>, Option<(Option<String>, Vec<u8>)>, u64) -> (Vec<u8>, Option<String>),
+        config: VfsConfig,
+        transport: impl VfsTransport,
     ) -> Result<Database, DatabaseError> {
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .drive(drive)
-            .get_payload(get_payload)
-            .send_and_await_response(send_and_await_response)
-            .open(path.as_ref().to_str().unwrap().into())?;
-
-        if file.metadata()?.len() == 0 {
+        let path = vfs_path(path.as_ref())?;
+        let file = OpenOptions::new().open(path, config, transport)?;
+
+        if file.len()? == 0 {
             return Err(StorageError::Io(ErrorKind::InvalidData.into()).into());
         }
 
         Database::new(
-            file,
+            Box::new(file),
             self.page_size,
             None,
             self.read_cache_size_bytes,
             self.write_cache_size_bytes,
+            self.read_ahead_pages,
+            self.allow_unsynced,
         )
     }
 
@@ -856,12 +1146,24 @@ impl Builder {
     ///
     /// The file must be empty or contain a valid database.
     pub fn create_file(&self, file: File) -> Result<Database, DatabaseError> {
+        self.create_with_backend(file)
+    }
+
+    /// Open an existing or create a new database with the given backend.
+    ///
+    /// The backend must be empty or contain a valid database.
+    pub fn create_with_backend(
+        &self,
+        backend: impl StorageBackend,
+    ) -> Result<Database, DatabaseError> {
         Database::new(
-            file,
+            Box::new(backend),
             self.page_size,
             self.region_size,
             self.read_cache_size_bytes,
             self.write_cache_size_bytes,
+            self.read_ahead_pages,
+            self.allow_unsynced,
         )
     }
 }
@@ -875,17 +1177,18 @@ impl std::fmt::Debug for Database {
 #[cfg(test)]
 mod test {
     use crate::{
-        Database, DatabaseError, Durability, ReadableTable, StorageError, TableDefinition,
+        Database, DatabaseError, Durability, OpenOptions, ReadableTable, StorageError,
+        TableDefinition,
     };
     use std::io::ErrorKind;
 
     #[test]
     fn small_pages() {
-        let tmpfile = crate::create_tempfile();
+        let vfs = crate::MockVfs::new();
 
         let db = Database::builder()
             .set_page_size(512)
-            .create(tmpfile.path())
+            .create(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
             .unwrap();
 
         let table_definition: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
@@ -898,11 +1201,11 @@ mod test {
 
     #[test]
     fn small_pages2() {
-        let tmpfile = crate::create_tempfile();
+        let vfs = crate::MockVfs::new();
 
         let db = Database::builder()
             .set_page_size(512)
-            .create(tmpfile.path())
+            .create(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
             .unwrap();
 
         let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
@@ -992,11 +1295,11 @@ mod test {
 
     #[test]
     fn small_pages3() {
-        let tmpfile = crate::create_tempfile();
+        let vfs = crate::MockVfs::new();
 
         let db = Database::builder()
             .set_page_size(1024)
-            .create(tmpfile.path())
+            .create(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
             .unwrap();
 
         let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
@@ -1025,12 +1328,12 @@ mod test {
 
     #[test]
     fn small_pages4() {
-        let tmpfile = crate::create_tempfile();
+        let vfs = crate::MockVfs::new();
 
         let db = Database::builder()
             .set_cache_size(1024 * 1024)
             .set_page_size(1024)
-            .create(tmpfile.path())
+            .create(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
             .unwrap();
 
         let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
@@ -1062,13 +1365,13 @@ mod test {
 
     #[test]
     fn crash_regression3() {
-        let tmpfile = crate::create_tempfile();
+        let vfs = crate::MockVfs::new();
 
         let db = Database::builder()
             .set_cache_size(1024 * 1024)
             .set_page_size(16 * 1024)
             .set_region_size(32 * 4096)
-            .create(tmpfile.path())
+            .create(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
             .unwrap();
 
         let tx = db.begin_write().unwrap();
@@ -1082,13 +1385,13 @@ mod test {
 
     #[test]
     fn crash_regression4() {
-        let tmpfile = crate::create_tempfile();
+        let vfs = crate::MockVfs::new();
 
         let db = Database::builder()
             .set_cache_size(12686)
             .set_page_size(8 * 1024)
             .set_region_size(32 * 4096)
-            .create(tmpfile.path())
+            .create(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
             .unwrap();
         db.set_crash_countdown(10);
 
@@ -1110,19 +1413,19 @@ mod test {
             .set_cache_size(1024 * 1024)
             .set_page_size(8 * 1024)
             .set_region_size(32 * 4096)
-            .create(tmpfile.path())
+            .create(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
             .unwrap();
     }
 
     #[test]
     fn dynamic_shrink() {
-        let tmpfile = crate::create_tempfile();
+        let vfs = crate::MockVfs::new();
         let table_definition: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
         let big_value = vec![0u8; 1024];
 
         let db = Database::builder()
             .set_region_size(1024 * 1024)
-            .create(tmpfile.path())
+            .create(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
             .unwrap();
 
         let txn = db.begin_write().unwrap();
@@ -1134,7 +1437,7 @@ mod test {
         }
         txn.commit().unwrap();
 
-        let file_size = tmpfile.as_file().metadata().unwrap().len();
+        let file_size = vfs.file(crate::TEST_DB_PATH).unwrap().len();
 
         let txn = db.begin_write().unwrap();
         {
@@ -1161,25 +1464,27 @@ mod test {
         let txn = db.begin_write().unwrap();
         txn.commit().unwrap();
 
-        let final_file_size = tmpfile.as_file().metadata().unwrap().len();
+        let final_file_size = vfs.file(crate::TEST_DB_PATH).unwrap().len();
         assert!(final_file_size < file_size);
     }
 
     #[test]
     fn create_new_db_in_empty_file() {
-        let tmpfile = crate::create_tempfile();
-
-        let _db = Database::builder()
-            .create_file(tmpfile.into_file())
+        let vfs = crate::MockVfs::new();
+        vfs.insert_file(crate::TEST_DB_PATH, vec![]);
+        let file = OpenOptions::new()
+            .open(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
             .unwrap();
+
+        let _db = Database::builder().create_file(file).unwrap();
     }
 
     #[test]
     fn open_missing_file() {
-        let tmpfile = crate::create_tempfile();
+        let vfs = crate::MockVfs::new();
 
         let err = Database::builder()
-            .open(tmpfile.path().with_extension("missing"))
+            .open(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
             .unwrap_err();
 
         match err {
@@ -1190,9 +1495,12 @@ mod test {
 
     #[test]
     fn open_empty_file() {
-        let tmpfile = crate::create_tempfile();
+        let vfs = crate::MockVfs::new();
+        vfs.insert_file(crate::TEST_DB_PATH, vec![]);
 
-        let err = Database::builder().open(tmpfile.path()).unwrap_err();
+        let err = Database::builder()
+            .open(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
+            .unwrap_err();
 
         match err {
             DatabaseError::Storage(StorageError::Io(err))
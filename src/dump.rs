@@ -0,0 +1,380 @@
+use crate::multimap_table::multimap_visit_entries;
+use crate::tree_store::{
+    btree_visit_entries, InternalTableDefinition, TableType, TransactionalMemory,
+};
+use crate::types::{RedbKey, RedbValue};
+use crate::{
+    ImportError, MultimapTable, MultimapTableDefinition, MultimapTableHandle, Result, StorageError,
+    Table, TableDefinition, TableError, TableHandle, WriteTransaction,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, Write};
+
+// Dump format:
+//
+// JSON lines. Each table is a header line, followed by a line for each entry, or for each value
+// of a multimap table, in ascending order:
+// {"table":"users","multimap":false,"key_type":"u64","value_type":"&str"}
+// {"key":1,"value":"alice"}
+//
+// The type names are those returned by `RedbValue::type_name()`. Keys and values of the built in
+// types are rendered as JSON: integers and floats as numbers, `&str` as a string, and `()` as
+// null. 128 bit integers are written as strings of their decimal value, since many JSON parsers
+// read numbers as doubles. Values of any other type, and floats which are not finite, are written
+// as a hex string of their `RedbValue` encoding
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct DumpHeader {
+    table: String,
+    multimap: bool,
+    key_type: String,
+    value_type: String,
+}
+
+impl DumpHeader {
+    // Checks that the dumped table can be imported into a table with the given types
+    pub(crate) fn check<K: RedbKey, V: RedbValue>(
+        &self,
+        multimap: bool,
+    ) -> Result<(), ImportError> {
+        if self.multimap != multimap {
+            return Err(if self.multimap {
+                TableError::TableIsMultimap(self.table.clone()).into()
+            } else {
+                TableError::TableIsNotMultimap(self.table.clone()).into()
+            });
+        }
+        if self.key_type != K::type_name().name() || self.value_type != V::type_name().name() {
+            return Err(ImportError::DumpTypeMismatch {
+                table: self.table.clone(),
+                key: self.key_type.clone(),
+                value: self.value_type.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct DumpEntry {
+    key: Value,
+    value: Value,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DumpLine {
+    Header(DumpHeader),
+    Entry(DumpEntry),
+}
+
+fn to_hex(data: &[u8]) -> String {
+    let mut result = String::with_capacity(2 * data.len());
+    for byte in data {
+        write!(result, "{byte:02x}").unwrap();
+    }
+    result
+}
+
+fn from_hex(data: &str) -> Option<Vec<u8>> {
+    if data.len() % 2 != 0 {
+        return None;
+    }
+    (0..data.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(data.get(i..(i + 2))?, 16).ok())
+        .collect()
+}
+
+fn fixed<const N: usize>(type_name: &str, data: &[u8]) -> Result<[u8; N]> {
+    data.try_into()
+        .map_err(|_| StorageError::Corrupted(format!("{type_name} has length {}", data.len())))
+}
+
+// Renders a key or value of the given type
+fn to_json(type_name: &str, data: &[u8]) -> Result<Value> {
+    let value = match type_name {
+        "()" => Value::Null,
+        "&str" => Value::from(
+            std::str::from_utf8(data)
+                .map_err(|_| StorageError::Corrupted("&str is not valid UTF-8".to_string()))?,
+        ),
+        "u8" => Value::from(u8::from_le_bytes(fixed(type_name, data)?)),
+        "u16" => Value::from(u16::from_le_bytes(fixed(type_name, data)?)),
+        "u32" => Value::from(u32::from_le_bytes(fixed(type_name, data)?)),
+        "u64" => Value::from(u64::from_le_bytes(fixed(type_name, data)?)),
+        "u128" => Value::from(u128::from_le_bytes(fixed(type_name, data)?).to_string()),
+        "i8" => Value::from(i8::from_le_bytes(fixed(type_name, data)?)),
+        "i16" => Value::from(i16::from_le_bytes(fixed(type_name, data)?)),
+        "i32" => Value::from(i32::from_le_bytes(fixed(type_name, data)?)),
+        "i64" => Value::from(i64::from_le_bytes(fixed(type_name, data)?)),
+        "i128" => Value::from(i128::from_le_bytes(fixed(type_name, data)?).to_string()),
+        "f32" => Number::from_f64(f32::from_le_bytes(fixed(type_name, data)?).into())
+            .map_or_else(|| Value::from(to_hex(data)), Value::Number),
+        "f64" => Number::from_f64(f64::from_le_bytes(fixed(type_name, data)?))
+            .map_or_else(|| Value::from(to_hex(data)), Value::Number),
+        _ => Value::from(to_hex(data)),
+    };
+    Ok(value)
+}
+
+// Parses a key or value of the given type, rendered by `to_json()`
+fn from_json(type_name: &str, value: &Value) -> Option<Vec<u8>> {
+    // Floats which are not finite, and types which are not rendered, are hex encoded
+    let hex = || from_hex(value.as_str()?);
+    let bytes = match type_name {
+        "()" => value.as_null().map(|_| vec![])?,
+        "&str" => value.as_str()?.as_bytes().to_vec(),
+        "u8" => u8::try_from(value.as_u64()?).ok()?.to_le_bytes().to_vec(),
+        "u16" => u16::try_from(value.as_u64()?).ok()?.to_le_bytes().to_vec(),
+        "u32" => u32::try_from(value.as_u64()?).ok()?.to_le_bytes().to_vec(),
+        "u64" => value.as_u64()?.to_le_bytes().to_vec(),
+        "u128" => value.as_str()?.parse::<u128>().ok()?.to_le_bytes().to_vec(),
+        "i8" => i8::try_from(value.as_i64()?).ok()?.to_le_bytes().to_vec(),
+        "i16" => i16::try_from(value.as_i64()?).ok()?.to_le_bytes().to_vec(),
+        "i32" => i32::try_from(value.as_i64()?).ok()?.to_le_bytes().to_vec(),
+        "i64" => value.as_i64()?.to_le_bytes().to_vec(),
+        "i128" => value.as_str()?.parse::<i128>().ok()?.to_le_bytes().to_vec(),
+        // f32 values are written as the f64 with the same value, so converting back is exact
+        #[allow(clippy::cast_possible_truncation)]
+        "f32" if value.is_number() => (value.as_f64()? as f32).to_le_bytes().to_vec(),
+        "f64" if value.is_number() => value.as_f64()?.to_le_bytes().to_vec(),
+        _ => hex()?,
+    };
+    Some(bytes)
+}
+
+fn write_line(writer: &mut dyn Write, line: &impl Serialize) -> Result {
+    serde_json::to_writer(&mut *writer, line).map_err(std::io::Error::from)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+// Writes the table to `writer`, and returns the number of entries written
+pub(crate) fn export_table(
+    mem: &TransactionalMemory,
+    name: &str,
+    definition: &InternalTableDefinition,
+    writer: &mut dyn Write,
+) -> Result<u64> {
+    let multimap = definition.get_type() == TableType::Multimap;
+    let header = DumpHeader {
+        table: name.to_string(),
+        multimap,
+        key_type: definition.get_key_type().name().to_string(),
+        value_type: definition.get_value_type().name().to_string(),
+    };
+    write_line(writer, &header)?;
+
+    let mut entries = 0;
+    let mut visitor = |key: &[u8], value: &[u8]| {
+        entries += 1;
+        write_line(
+            writer,
+            &DumpEntry {
+                key: to_json(&header.key_type, key)?,
+                value: to_json(&header.value_type, value)?,
+            },
+        )
+    };
+    let root = definition.get_root().map(|(root, _)| root);
+    let fixed_key_size = definition.get_fixed_key_size();
+    let fixed_value_size = definition.get_fixed_value_size();
+    if multimap {
+        multimap_visit_entries(root, mem, fixed_key_size, fixed_value_size, &mut visitor)?;
+    } else {
+        btree_visit_entries(root, mem, fixed_key_size, fixed_value_size, &mut visitor)?;
+    }
+
+    Ok(entries)
+}
+
+type RawEntry = (Vec<u8>, Vec<u8>);
+
+pub(crate) struct DumpReader<'a> {
+    reader: &'a mut dyn BufRead,
+    line_number: u64,
+    // The header of the next table, if it was read while reading the entries of the previous one
+    next_header: Option<DumpHeader>,
+}
+
+impl<'a> DumpReader<'a> {
+    pub(crate) fn new(reader: &'a mut dyn BufRead) -> Self {
+        Self {
+            reader,
+            line_number: 0,
+            next_header: None,
+        }
+    }
+
+    fn invalid(&self, reason: &str) -> ImportError {
+        ImportError::InvalidDump(format!("line {}: {reason}", self.line_number))
+    }
+
+    fn read_line(&mut self) -> Result<Option<DumpLine>, ImportError> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            self.line_number += 1;
+            if !line.trim().is_empty() {
+                break;
+            }
+        }
+        serde_json::from_str(&line)
+            .map(Some)
+            .map_err(|err| self.invalid(&err.to_string()))
+    }
+
+    // Skips the rest of the current table, and returns the header of the next one
+    pub(crate) fn next_table(&mut self) -> Result<Option<DumpHeader>, ImportError> {
+        if let Some(header) = self.next_header.take() {
+            return Ok(Some(header));
+        }
+        let first_line = self.line_number == 0;
+        loop {
+            match self.read_line()? {
+                None => return Ok(None),
+                Some(DumpLine::Header(header)) => return Ok(Some(header)),
+                Some(DumpLine::Entry(_)) if first_line => {
+                    return Err(self.invalid("expected a table header"));
+                }
+                Some(DumpLine::Entry(_)) => {}
+            }
+        }
+    }
+
+    // Returns the header of the table with the given name
+    pub(crate) fn find_table(&mut self, name: &str) -> Result<DumpHeader, ImportError> {
+        while let Some(header) = self.next_table()? {
+            if header.table == name {
+                return Ok(header);
+            }
+        }
+        Err(TableError::TableDoesNotExist(name.to_string()).into())
+    }
+
+    // Returns the next entry of the current table, whose key and value types are `K` and `V`.
+    // `key_type` and `value_type` are their names
+    fn next_entry<K: RedbValue, V: RedbValue>(
+        &mut self,
+        key_type: &str,
+        value_type: &str,
+    ) -> Result<Option<RawEntry>, ImportError> {
+        if self.next_header.is_some() {
+            return Ok(None);
+        }
+        match self.read_line()? {
+            None => Ok(None),
+            Some(DumpLine::Header(header)) => {
+                self.next_header = Some(header);
+                Ok(None)
+            }
+            Some(DumpLine::Entry(entry)) => {
+                let key =
+                    from_json(key_type, &entry.key).ok_or_else(|| self.invalid("invalid key"))?;
+                let value = from_json(value_type, &entry.value)
+                    .ok_or_else(|| self.invalid("invalid value"))?;
+                if K::fixed_width().map_or(false, |width| width != key.len()) {
+                    return Err(self.invalid("key has the wrong length"));
+                }
+                if V::fixed_width().map_or(false, |width| width != value.len()) {
+                    return Err(self.invalid("value has the wrong length"));
+                }
+                Ok(Some((key, value)))
+            }
+        }
+    }
+}
+
+// Inserts the entries of the current table into `table`, and returns their number
+pub(crate) fn import_table<K: RedbKey + 'static, V: RedbValue + 'static>(
+    table: &mut Table<K, V>,
+    reader: &mut DumpReader,
+) -> Result<u64, ImportError> {
+    let (key_type, value_type) = (K::type_name(), V::type_name());
+    let mut entries = 0;
+    while let Some((key, value)) = reader.next_entry::<K, V>(key_type.name(), value_type.name())? {
+        table.insert(K::from_bytes(&key), V::from_bytes(&value))?;
+        entries += 1;
+    }
+    Ok(entries)
+}
+
+// Inserts the values of the current multimap table into `table`, and returns their number
+pub(crate) fn import_multimap_table<K: RedbKey + 'static, V: RedbKey + 'static>(
+    table: &mut MultimapTable<K, V>,
+    reader: &mut DumpReader,
+) -> Result<u64, ImportError> {
+    let (key_type, value_type) = (K::type_name(), V::type_name());
+    let mut entries = 0;
+    while let Some((key, value)) = reader.next_entry::<K, V>(key_type.name(), value_type.name())? {
+        table.insert(K::from_bytes(&key), V::from_bytes(&value))?;
+        entries += 1;
+    }
+    Ok(entries)
+}
+
+type ImportFn<'a> =
+    Box<dyn Fn(&WriteTransaction, &DumpHeader, &mut DumpReader) -> Result<u64, ImportError> + 'a>;
+
+/// The tables to import from a dump of a database. See [`WriteTransaction::import`]
+#[derive(Default)]
+pub struct ImportSchema<'a> {
+    tables: HashMap<String, ImportFn<'a>>,
+}
+
+impl<'a> ImportSchema<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Import the table with the name of `definition` into it
+    pub fn table<K: RedbKey + 'static, V: RedbValue + 'static>(
+        &mut self,
+        definition: TableDefinition<'a, K, V>,
+    ) -> &mut Self {
+        self.tables.insert(
+            definition.name().to_string(),
+            Box::new(move |txn, header, reader| {
+                header.check::<K, V>(false)?;
+                import_table(&mut txn.open_table(definition)?, reader)
+            }),
+        );
+        self
+    }
+
+    /// Import the multimap table with the name of `definition` into it
+    pub fn multimap_table<K: RedbKey + 'static, V: RedbKey + 'static>(
+        &mut self,
+        definition: MultimapTableDefinition<'a, K, V>,
+    ) -> &mut Self {
+        self.tables.insert(
+            definition.name().to_string(),
+            Box::new(move |txn, header, reader| {
+                header.check::<K, V>(true)?;
+                import_multimap_table(&mut txn.open_multimap_table(definition)?, reader)
+            }),
+        );
+        self
+    }
+
+    // Imports the table with the given header, which must be part of the schema
+    pub(crate) fn import(
+        &self,
+        txn: &WriteTransaction,
+        header: &DumpHeader,
+        reader: &mut DumpReader,
+    ) -> Result<u64, ImportError> {
+        let import = self
+            .tables
+            .get(&header.table)
+            .ok_or_else(|| TableError::TableDoesNotExist(header.table.clone()))?;
+        import(txn, header, reader)
+    }
+}
@@ -1,4 +1,5 @@
 use crate::tree_store::{FILE_FORMAT_VERSION, MAX_VALUE_LENGTH};
+use crate::uqfile::VfsFailure;
 use crate::TypeName;
 use std::fmt::{Display, Formatter};
 use std::sync::PoisonError;
@@ -15,6 +16,15 @@ pub enum StorageError {
     Corrupted(String),
     /// The value being inserted exceeds the maximum of 3GiB
     ValueTooLarge(usize),
+    /// The table was merged into, but its definition has no merge operator
+    NoMergeOperator(String),
+    /// Space was reserved for a value in a table which has registered indexes
+    IndexedTable(String),
+    /// The VFS returned an error, or an unexpected response, to `action`
+    Vfs {
+        kind: String,
+        action: String,
+    },
     Io(io::Error),
     LockPoisoned(&'static panic::Location<'static>),
 }
@@ -27,7 +37,18 @@ impl<T> From<PoisonError<T>> for StorageError {
 
 impl From<io::Error> for StorageError {
     fn from(err: io::Error) -> StorageError {
-        StorageError::Io(err)
+        if err
+            .get_ref()
+            .map_or(false, |inner| inner.is::<VfsFailure>())
+        {
+            let failure = err.into_inner().unwrap().downcast::<VfsFailure>().unwrap();
+            StorageError::Vfs {
+                kind: failure.kind,
+                action: failure.action.to_string(),
+            }
+        } else {
+            StorageError::Io(err)
+        }
     }
 }
 
@@ -38,6 +59,9 @@ impl From<StorageError> for Error {
             StorageError::SimulatedIOFailure => Error::SimulatedIOFailure,
             StorageError::Corrupted(msg) => Error::Corrupted(msg),
             StorageError::ValueTooLarge(x) => Error::ValueTooLarge(x),
+            StorageError::NoMergeOperator(table) => Error::NoMergeOperator(table),
+            StorageError::IndexedTable(table) => Error::IndexedTable(table),
+            StorageError::Vfs { kind, action } => Error::Vfs { kind, action },
             StorageError::Io(x) => Error::Io(x),
             StorageError::LockPoisoned(location) => Error::LockPoisoned(location),
         }
@@ -61,6 +85,18 @@ impl Display for StorageError {
                     MAX_VALUE_LENGTH / 1024 / 1024 / 1024
                 )
             }
+            StorageError::NoMergeOperator(table) => {
+                write!(f, "Table '{table}' has no merge operator")
+            }
+            StorageError::IndexedTable(table) => {
+                write!(
+                    f,
+                    "Table '{table}' has indexes, so values cannot be reserved"
+                )
+            }
+            StorageError::Vfs { kind, action } => {
+                write!(f, "VFS error: {kind} in response to {action}")
+            }
             StorageError::Io(err) => {
                 write!(f, "I/O error: {err}")
             }
@@ -214,7 +250,7 @@ impl From<DatabaseError> for Error {
 
 impl From<io::Error> for DatabaseError {
     fn from(err: io::Error) -> DatabaseError {
-        DatabaseError::Storage(StorageError::Io(err))
+        DatabaseError::Storage(err.into())
     }
 }
 
@@ -331,6 +367,189 @@ impl Display for CompactionError {
 
 impl std::error::Error for CompactionError {}
 
+/// Errors related to backups
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BackupError {
+    /// The storage does not hold a complete backup
+    InvalidBackup,
+    /// An incremental backup was restored without the backup it was made from, which backed up
+    /// the transaction with the given id
+    MissingBase(u64),
+    /// Error from underlying storage
+    Storage(StorageError),
+}
+
+impl From<BackupError> for Error {
+    fn from(err: BackupError) -> Error {
+        match err {
+            BackupError::InvalidBackup => Error::InvalidBackup,
+            BackupError::MissingBase(id) => Error::MissingBackup(id),
+            BackupError::Storage(storage) => storage.into(),
+        }
+    }
+}
+
+impl From<StorageError> for BackupError {
+    fn from(err: StorageError) -> BackupError {
+        BackupError::Storage(err)
+    }
+}
+
+impl From<io::Error> for BackupError {
+    fn from(err: io::Error) -> BackupError {
+        BackupError::Storage(err.into())
+    }
+}
+
+impl Display for BackupError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackupError::InvalidBackup => {
+                write!(f, "Storage does not hold a complete backup")
+            }
+            BackupError::MissingBase(id) => {
+                write!(f, "Missing the backup of transaction {id}, which an incremental backup was made from")
+            }
+            BackupError::Storage(storage) => storage.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+/// Errors related to importing a dump
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ImportError {
+    /// The dump is malformed
+    InvalidDump(String),
+    /// The dumped table has different key or value types than the table it is imported into
+    DumpTypeMismatch {
+        table: String,
+        key: String,
+        value: String,
+    },
+    Table(TableError),
+    /// Error from underlying storage
+    Storage(StorageError),
+}
+
+impl From<ImportError> for Error {
+    fn from(err: ImportError) -> Error {
+        match err {
+            ImportError::InvalidDump(reason) => Error::InvalidDump(reason),
+            ImportError::DumpTypeMismatch { table, key, value } => {
+                Error::DumpTypeMismatch { table, key, value }
+            }
+            ImportError::Table(table) => table.into(),
+            ImportError::Storage(storage) => storage.into(),
+        }
+    }
+}
+
+impl From<TableError> for ImportError {
+    fn from(err: TableError) -> ImportError {
+        ImportError::Table(err)
+    }
+}
+
+impl From<StorageError> for ImportError {
+    fn from(err: StorageError) -> ImportError {
+        ImportError::Storage(err)
+    }
+}
+
+impl From<io::Error> for ImportError {
+    fn from(err: io::Error) -> ImportError {
+        ImportError::Storage(err.into())
+    }
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::InvalidDump(reason) => {
+                write!(f, "Invalid dump: {reason}")
+            }
+            ImportError::DumpTypeMismatch { table, key, value } => {
+                write!(f, "{table} was dumped with type Table<{key}, {value}>")
+            }
+            ImportError::Table(table) => table.fmt(f),
+            ImportError::Storage(storage) => storage.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Errors related to bulk loading a table
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BulkLoadError {
+    /// The table being loaded already contains entries
+    TableNotEmpty(String),
+    /// The key at `position` in the input is not greater than the key before it
+    UnsortedInput {
+        table: String,
+        position: u64,
+    },
+    /// The fill factor is not greater than 0 and at most 1
+    InvalidFillFactor(f32),
+    Table(TableError),
+    /// Error from underlying storage
+    Storage(StorageError),
+}
+
+impl From<BulkLoadError> for Error {
+    fn from(err: BulkLoadError) -> Error {
+        match err {
+            BulkLoadError::TableNotEmpty(table) => Error::TableNotEmpty(table),
+            BulkLoadError::UnsortedInput { table, position } => {
+                Error::UnsortedInput { table, position }
+            }
+            BulkLoadError::InvalidFillFactor(fill_factor) => Error::InvalidFillFactor(fill_factor),
+            BulkLoadError::Table(table) => table.into(),
+            BulkLoadError::Storage(storage) => storage.into(),
+        }
+    }
+}
+
+impl From<TableError> for BulkLoadError {
+    fn from(err: TableError) -> BulkLoadError {
+        BulkLoadError::Table(err)
+    }
+}
+
+impl From<StorageError> for BulkLoadError {
+    fn from(err: StorageError) -> BulkLoadError {
+        BulkLoadError::Storage(err)
+    }
+}
+
+impl Display for BulkLoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BulkLoadError::TableNotEmpty(table) => {
+                write!(f, "Table '{table}' is not empty")
+            }
+            BulkLoadError::UnsortedInput { table, position } => {
+                write!(
+                    f,
+                    "Input for table '{table}' is not sorted at position {position}"
+                )
+            }
+            BulkLoadError::InvalidFillFactor(fill_factor) => {
+                write!(f, "Fill factor must be in (0, 1], but was {fill_factor}")
+            }
+            BulkLoadError::Table(table) => table.fmt(f),
+            BulkLoadError::Storage(storage) => storage.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for BulkLoadError {}
+
 /// Errors related to transactions
 #[derive(Debug)]
 #[non_exhaustive]
@@ -426,12 +645,37 @@ pub enum Error {
     PersistentSavepointExists,
     /// An Ephemeral savepoint exists
     EphemeralSavepointExists,
+    /// The storage does not hold a complete backup
+    InvalidBackup,
+    /// An incremental backup was restored without the backup it was made from
+    MissingBackup(u64),
+    /// The dump being imported is malformed
+    InvalidDump(String),
+    /// The dumped table has different key or value types than the table it is imported into
+    DumpTypeMismatch {
+        table: String,
+        key: String,
+        value: String,
+    },
+    /// The table being bulk loaded already contains entries
+    TableNotEmpty(String),
+    /// The key at `position` in the input to a bulk load is not greater than the key before it
+    UnsortedInput {
+        table: String,
+        position: u64,
+    },
+    /// The fill factor of a bulk load is not greater than 0 and at most 1
+    InvalidFillFactor(f32),
     /// The Database is corrupted
     Corrupted(String),
     /// The database file is in an old file format and must be manually upgraded
     UpgradeRequired(u8),
     /// The value being inserted exceeds the maximum of 3GiB
     ValueTooLarge(usize),
+    /// The table was merged into, but its definition has no merge operator
+    NoMergeOperator(String),
+    /// Space was reserved for a value in a table which has registered indexes
+    IndexedTable(String),
     /// Table types didn't match.
     TableTypeMismatch {
         table: String,
@@ -452,6 +696,11 @@ pub enum Error {
     // Tables cannot be opened for writing multiple times, since they could retrieve immutable &
     // mutable references to the same dirty pages, or multiple mutable references via insert_reserve()
     TableAlreadyOpen(String, &'static panic::Location<'static>),
+    /// The VFS returned an error, or an unexpected response, to `action`
+    Vfs {
+        kind: String,
+        action: String,
+    },
     Io(io::Error),
     LockPoisoned(&'static panic::Location<'static>),
 }
@@ -464,7 +713,7 @@ impl<T> From<PoisonError<T>> for Error {
 
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
-        Error::Io(err)
+        StorageError::from(err).into()
     }
 }
 
@@ -488,6 +737,15 @@ impl Display for Error {
                     MAX_VALUE_LENGTH / 1024 / 1024 / 1024
                 )
             }
+            Error::NoMergeOperator(table) => {
+                write!(f, "Table '{table}' has no merge operator")
+            }
+            Error::IndexedTable(table) => {
+                write!(
+                    f,
+                    "Table '{table}' has indexes, so values cannot be reserved"
+                )
+            }
             Error::TypeDefinitionChanged {
                 name,
                 alignment,
@@ -521,6 +779,9 @@ impl Display for Error {
             Error::TableAlreadyOpen(name, location) => {
                 write!(f, "Table '{name}' already opened at: {location}")
             }
+            Error::Vfs { kind, action } => {
+                write!(f, "VFS error: {kind} in response to {action}")
+            }
             Error::Io(err) => {
                 write!(f, "I/O error: {err}")
             }
@@ -548,6 +809,30 @@ impl Display for Error {
                     "Savepoint is invalid because an older savepoint was already restored."
                 )
             }
+            Error::InvalidBackup => {
+                write!(f, "Storage does not hold a complete backup")
+            }
+            Error::MissingBackup(id) => {
+                write!(f, "Missing the backup of transaction {id}, which an incremental backup was made from")
+            }
+            Error::InvalidDump(reason) => {
+                write!(f, "Invalid dump: {reason}")
+            }
+            Error::DumpTypeMismatch { table, key, value } => {
+                write!(f, "{table} was dumped with type Table<{key}, {value}>")
+            }
+            Error::TableNotEmpty(table) => {
+                write!(f, "Table '{table}' is not empty")
+            }
+            Error::UnsortedInput { table, position } => {
+                write!(
+                    f,
+                    "Input for table '{table}' is not sorted at position {position}"
+                )
+            }
+            Error::InvalidFillFactor(fill_factor) => {
+                write!(f, "Fill factor must be in (0, 1], but was {fill_factor}")
+            }
         }
     }
 }
@@ -0,0 +1,353 @@
+use crate::tree_store::BtreeMut;
+use crate::types::{RedbKey, RedbValue, TypeName};
+use crate::{
+    AccessGuard, MultimapTable, MultimapTableDefinition, ReadableMultimapTable, Result,
+    TableDefinition, TableError, TableHandle, WriteTransaction,
+};
+use std::any::Any;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeFull};
+use std::sync::{Arc, Mutex};
+
+type Extractor<V, I> =
+    for<'a, 'v> fn(&'a <V as RedbValue>::SelfType<'v>) -> <I as RedbValue>::SelfType<'a>;
+
+/// Defines a secondary index over the values of a table
+///
+/// The index is a multimap table named `name`, from the key extracted from each value by
+/// `extractor`, to the keys of the entries with that value. Once registered with
+/// [`Database::register_index`](crate::Database::register_index), it is updated by every write
+/// to the table in the same transaction
+pub struct IndexDefinition<'a, K: RedbKey + 'static, V: RedbValue + 'static, I: RedbKey + 'static> {
+    name: &'a str,
+    table: TableDefinition<'a, K, V>,
+    extractor: Extractor<V, I>,
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static, I: RedbKey + 'static>
+    IndexDefinition<'a, K, V, I>
+{
+    /// Construct a new index named `name`, over the values of `table`
+    ///
+    /// ## Invariant
+    ///
+    /// `name` must not be empty, and must not be the name of another table.
+    pub const fn new(
+        name: &'a str,
+        table: TableDefinition<'a, K, V>,
+        extractor: for<'e, 'v> fn(&'e V::SelfType<'v>) -> I::SelfType<'e>,
+    ) -> Self {
+        assert!(!name.is_empty());
+        Self {
+            name,
+            table,
+            extractor,
+        }
+    }
+
+    /// Returns the name of the index
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    /// Returns the definition of the indexed table
+    pub fn table(&self) -> TableDefinition<'a, K, V> {
+        self.table
+    }
+
+    /// Returns the definition of the multimap table which stores the index
+    pub fn multimap_table(&self) -> MultimapTableDefinition<'a, I, K> {
+        MultimapTableDefinition::new(self.name)
+    }
+
+    pub(crate) fn extractor(&self) -> Extractor<V, I> {
+        self.extractor
+    }
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static, I: RedbKey + 'static> Clone
+    for IndexDefinition<'a, K, V, I>
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static, I: RedbKey + 'static> Copy
+    for IndexDefinition<'a, K, V, I>
+{
+}
+
+// An index of a table opened in a write transaction
+pub(crate) trait OpenIndex<K: RedbKey + 'static, V: RedbValue + 'static>: Send {
+    fn name(&self) -> &str;
+
+    // Adds the entry with the given serialized key to the index
+    fn insert(&mut self, key: &[u8], value: &V::SelfType<'_>) -> Result;
+
+    // Removes the entry with the given serialized key from the index
+    fn remove(&mut self, key: &[u8], value: &V::SelfType<'_>) -> Result;
+
+    // Returns the serialized keys of the entries with an index key in the given range
+    fn primary_keys(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<Vec<Vec<u8>>>;
+
+    // Rebuilds the index from `table`, if it is stale. Returns true if it was rebuilt
+    fn check(&mut self, table: &BtreeMut<K, V>) -> Result<bool>;
+}
+
+struct IndexTable<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static, I: RedbKey + 'static> {
+    name: String,
+    table: MultimapTable<'db, 'txn, I, K>,
+    extractor: Extractor<V, I>,
+}
+
+impl<'db, 'txn, K, V, I> OpenIndex<K, V> for IndexTable<'db, 'txn, K, V, I>
+where
+    K: RedbKey + Send + Sync + 'static,
+    V: RedbValue + 'static,
+    I: RedbKey + Send + Sync + 'static,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn insert(&mut self, key: &[u8], value: &V::SelfType<'_>) -> Result {
+        let index_key = (self.extractor)(value);
+        let index_bytes = I::as_bytes(&index_key);
+        self.table
+            .insert(I::from_bytes(index_bytes.as_ref()), K::from_bytes(key))?;
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &[u8], value: &V::SelfType<'_>) -> Result {
+        let index_key = (self.extractor)(value);
+        let index_bytes = I::as_bytes(&index_key);
+        self.table
+            .remove(I::from_bytes(index_bytes.as_ref()), K::from_bytes(key))?;
+        Ok(())
+    }
+
+    fn primary_keys(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<Vec<Vec<u8>>> {
+        index_primary_keys::<K, I>(&self.table, start, end)
+    }
+
+    fn check(&mut self, table: &BtreeMut<K, V>) -> Result<bool> {
+        check_index(&mut self.table, self.extractor, table)
+    }
+}
+
+pub(crate) fn index_primary_keys<'a, K: RedbKey + 'static, I: RedbKey + 'static>(
+    index: &impl ReadableMultimapTable<I, K>,
+    start: Bound<&'a [u8]>,
+    end: Bound<&'a [u8]>,
+) -> Result<Vec<Vec<u8>>> {
+    let decode = |bound: Bound<&'a [u8]>| match bound {
+        Bound::Included(key) => Bound::Included(I::from_bytes(key)),
+        Bound::Excluded(key) => Bound::Excluded(I::from_bytes(key)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    let (start, end) = (decode(start), decode(end));
+    let mut keys = vec![];
+    for entry in index.range::<I::SelfType<'_>>((start, end))? {
+        for key in entry?.1 {
+            keys.push(K::as_bytes(&key?.value()).as_ref().to_vec());
+        }
+    }
+    Ok(keys)
+}
+
+pub(crate) fn check_index<K: RedbKey + 'static, V: RedbValue + 'static, I: RedbKey + 'static>(
+    index: &mut MultimapTable<I, K>,
+    extractor: Extractor<V, I>,
+    table: &BtreeMut<K, V>,
+) -> Result<bool> {
+    let mut expected = vec![];
+    for entry in table.range::<RangeFull, K::SelfType<'_>>(&(..))? {
+        let entry = entry?;
+        let value = entry.value();
+        let index_key = I::as_bytes(&extractor(&value)).as_ref().to_vec();
+        expected.push((index_key, entry.key_data()));
+    }
+    expected.sort_by(|a, b| I::compare(&a.0, &b.0).then_with(|| K::compare(&a.1, &b.1)));
+
+    let mut actual = vec![];
+    for entry in index.iter()? {
+        let (index_key, keys) = entry?;
+        let index_key = I::as_bytes(&index_key.value()).as_ref().to_vec();
+        for key in keys {
+            actual.push((
+                index_key.clone(),
+                K::as_bytes(&key?.value()).as_ref().to_vec(),
+            ));
+        }
+    }
+    if actual == expected {
+        return Ok(false);
+    }
+
+    for (index_key, key) in actual {
+        index.remove(I::from_bytes(&index_key), K::from_bytes(&key))?;
+    }
+    for (index_key, key) in expected {
+        index.insert(I::from_bytes(&index_key), K::from_bytes(&key))?;
+    }
+    Ok(true)
+}
+
+// Opens an index in a write transaction
+trait IndexFactory<K: RedbKey + 'static, V: RedbValue + 'static>: Send + Sync {
+    fn open<'db, 'txn>(
+        &self,
+        transaction: &'txn WriteTransaction<'db>,
+    ) -> Result<Box<dyn OpenIndex<K, V> + 'txn>, TableError>;
+}
+
+struct RegisteredIndex<K: RedbKey + 'static, V: RedbValue + 'static, I: RedbKey + 'static> {
+    name: String,
+    extractor: Extractor<V, I>,
+    _types: PhantomData<fn() -> (K, I)>,
+}
+
+impl<K, V, I> IndexFactory<K, V> for RegisteredIndex<K, V, I>
+where
+    K: RedbKey + Send + Sync + 'static,
+    V: RedbValue + 'static,
+    I: RedbKey + Send + Sync + 'static,
+{
+    fn open<'db, 'txn>(
+        &self,
+        transaction: &'txn WriteTransaction<'db>,
+    ) -> Result<Box<dyn OpenIndex<K, V> + 'txn>, TableError> {
+        let definition: MultimapTableDefinition<I, K> = MultimapTableDefinition::new(&self.name);
+        Ok(Box::new(IndexTable {
+            name: self.name.clone(),
+            table: transaction.open_multimap_table(definition)?,
+            extractor: self.extractor,
+        }))
+    }
+}
+
+struct IndexEntry {
+    name: String,
+    key_type: TypeName,
+    value_type: TypeName,
+    // An Arc<dyn IndexFactory<K, V>>, for the key and value types above
+    factory: Box<dyn Any + Send + Sync>,
+}
+
+// The indexes registered with a database, by the name of the table they index
+#[derive(Default)]
+pub(crate) struct Indexes {
+    tables: Mutex<HashMap<String, Vec<IndexEntry>>>,
+}
+
+impl Indexes {
+    pub(crate) fn register<K, V, I>(&self, index: IndexDefinition<K, V, I>)
+    where
+        K: RedbKey + Send + Sync + 'static,
+        V: RedbValue + 'static,
+        I: RedbKey + Send + Sync + 'static,
+    {
+        let factory: Arc<dyn IndexFactory<K, V>> = Arc::new(RegisteredIndex {
+            name: index.name().to_string(),
+            extractor: index.extractor(),
+            _types: PhantomData,
+        });
+        let mut tables = self.tables.lock().unwrap();
+        let entries = tables.entry(index.table().name().to_string()).or_default();
+        entries.retain(|entry| entry.name != index.name());
+        entries.push(IndexEntry {
+            name: index.name().to_string(),
+            key_type: K::type_name(),
+            value_type: V::type_name(),
+            factory: Box::new(factory),
+        });
+    }
+
+    // Opens the indexes of the given table in `transaction`
+    pub(crate) fn open<'txn, K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        table: &str,
+        transaction: &'txn WriteTransaction,
+    ) -> Result<Vec<Box<dyn OpenIndex<K, V> + 'txn>>, TableError> {
+        let mut factories = vec![];
+        if let Some(entries) = self.tables.lock().unwrap().get(table) {
+            for entry in entries {
+                match entry.factory.downcast_ref::<Arc<dyn IndexFactory<K, V>>>() {
+                    Some(factory) => factories.push(factory.clone()),
+                    None => {
+                        return Err(TableError::TableTypeMismatch {
+                            table: table.to_string(),
+                            key: entry.key_type.clone(),
+                            value: entry.value_type.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        // The registry lock must not be held while opening tables
+        factories
+            .iter()
+            .map(|factory| factory.open(transaction))
+            .collect()
+    }
+}
+
+type Lookup<'a, V> = Box<dyn Fn(&[u8]) -> Result<Option<AccessGuard<'a, V>>> + 'a>;
+
+/// An iterator over the entries found by an index lookup, in the order of their index keys
+pub struct IndexRange<'a, K: RedbKey + 'static, V: RedbValue + 'static> {
+    keys: std::vec::IntoIter<Vec<u8>>,
+    lookup: Lookup<'a, V>,
+    _key_type: PhantomData<K>,
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> IndexRange<'a, K, V> {
+    pub(crate) fn new(
+        keys: Vec<Vec<u8>>,
+        lookup: impl Fn(&[u8]) -> Result<Option<AccessGuard<'a, V>>> + 'a,
+    ) -> Self {
+        Self {
+            keys: keys.into_iter(),
+            lookup: Box::new(lookup),
+            _key_type: PhantomData,
+        }
+    }
+
+    // Looks up the entry with the given key. Keys which are missing from the table, because the
+    // index is stale, are skipped
+    fn entry(&self, key: Vec<u8>) -> Option<Result<(AccessGuard<'a, K>, AccessGuard<'a, V>)>> {
+        match (self.lookup)(&key) {
+            Ok(Some(value)) => Some(Ok((AccessGuard::with_owned_value(key), value))),
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Iterator for IndexRange<'a, K, V> {
+    type Item = Result<(AccessGuard<'a, K>, AccessGuard<'a, V>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(key) = self.keys.next() {
+            if let Some(entry) = self.entry(key) {
+                return Some(entry);
+            }
+        }
+        None
+    }
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> DoubleEndedIterator
+    for IndexRange<'a, K, V>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while let Some(key) = self.keys.next_back() {
+            if let Some(entry) = self.entry(key) {
+                return Some(entry);
+            }
+        }
+        None
+    }
+}
@@ -6,7 +6,7 @@ pub struct VfsRequest {
     pub action: VfsAction,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum VfsAction {
     New,
     Add {
@@ -22,10 +22,17 @@ pub enum VfsAction {
         full_path: String,
         offset: u64,
     },
+    //  the payload is the concatenation of the writes, each given as (offset, length)
+    WriteOffsets {
+        full_path: String,
+        writes: Vec<(u64, u64)>,
+    },
     SetSize {
         full_path: String,
         size: u64,
     },
+    //  flush all writes to the file to persistent storage before responding
+    SyncData(String),
     GetPath(u128),
     GetHash(String),
     GetEntry(String),
@@ -37,7 +44,7 @@ pub enum VfsAction {
     GetEntryLength(String),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum AddEntryType {
     Dir,
     NewFile,                     //  add a new file to fs and add name in vfs
@@ -73,9 +80,8 @@ pub enum VfsError {
     NoCap,
 }
 
-#[allow(dead_code)]
 impl VfsError {
-    pub fn kind(&self) -> &str {
+    pub fn kind(&self) -> &'static str {
         match *self {
             VfsError::BadDriveName => "BadDriveName",
             VfsError::BadDescriptor => "BadDescriptor",
@@ -27,14 +27,15 @@
 //! # Example
 //!
 //! ```
-//! use redb::{Database, Error, ReadableTable, TableDefinition};
+//! use redb::{Database, Error, InMemoryBackend, ReadableTable, TableDefinition};
 //!
 //! const TABLE: TableDefinition<&str, u64> = TableDefinition::new("my_data");
 //!
 //! #[cfg(not(target_os = "wasi"))]
 //! fn main() -> Result<(), Error> {
-//!     let file = tempfile::NamedTempFile::new().unwrap();
-//!     let db = Database::create(file.path())?;
+//!     // In a uqbar process, use Database::create() with the VfsConfig of your drive, and a
+//!     // VfsTransport which sends requests through the process runtime
+//!     let db = Database::builder().create_with_backend(InMemoryBackend::new())?;
 //!     let write_txn = db.begin_write()?;
 //!     {
 //!         let mut table = write_txn.open_table(TABLE)?;
@@ -53,50 +54,73 @@
 //! [lmdb]: https://www.lmdb.tech/doc/
 //! [design]: https://github.com/cberner/redb/blob/master/docs/design.md
 
+pub use async_db::{AsyncDatabase, AsyncWriteTransaction, RangeStream};
 pub use db::{
     Builder, Database, MultimapTableDefinition, MultimapTableHandle, TableDefinition, TableHandle,
     UntypedMultimapTableHandle, UntypedTableHandle,
 };
+pub use dump::ImportSchema;
 pub use error::{
-    CommitError, CompactionError, DatabaseError, Error, SavepointError, StorageError, TableError,
-    TransactionError,
+    BackupError, BulkLoadError, CommitError, CompactionError, DatabaseError, Error, ImportError,
+    SavepointError, StorageError, TableError, TransactionError,
 };
+pub use index::{IndexDefinition, IndexRange};
+pub use merge::MergeOperator;
 pub use multimap_table::{
     MultimapRange, MultimapTable, MultimapValue, ReadOnlyMultimapTable, ReadableMultimapTable,
 };
-pub use table::{Drain, DrainFilter, Range, ReadOnlyTable, ReadableTable, Table};
+pub use serde_types::{Bincode, Json, SerdeKey, StableName};
+pub use subscriptions::{ChangeEvent, ChangeKind, KeyChange};
+pub use table::{Cursor, Drain, DrainFilter, Range, ReadOnlyTable, ReadableTable, Table};
 pub use transactions::{DatabaseStats, Durability, ReadTransaction, WriteTransaction};
-pub use tree_store::{AccessGuard, AccessGuardMut, Savepoint};
-pub use types::{RedbKey, RedbValue, TypeName};
+pub use tree_store::{AccessGuard, AccessGuardMut, InMemoryBackend, Savepoint, StorageBackend};
+pub use ttl_table::{ReadOnlyTtlTable, ReadableTtlTable, TtlRange, TtlTable, TtlTableDefinition};
+pub use types::{RedbKey, RedbKeyPrefix, RedbValue, TypeName};
+
+#[cfg(feature = "derive")]
+pub use redb_derive::{RedbKey, RedbValue};
+
+#[doc(hidden)]
+pub mod __private {
+    // Used by the code generated by redb-derive
+    pub use crate::tuple_types::{encode_fields, split_fields};
+}
 
 type Result<T = (), E = StorageError> = std::result::Result<T, E>;
 
 #[cfg(feature = "python")]
 pub use crate::python::redb;
 
+mod async_db;
+mod backup;
 mod db;
+mod dump;
 mod error;
+mod index;
+mod merge;
 mod multimap_table;
 #[cfg(feature = "python")]
 mod python;
 mod sealed;
+mod serde_types;
+mod subscriptions;
 mod table;
 mod transaction_tracker;
 mod transactions;
 mod tree_store;
+mod ttl_table;
 mod tuple_types;
 mod types;
 
 #[cfg(test)]
-fn create_tempfile() -> tempfile::NamedTempFile {
-    if cfg!(target_os = "wasi") {
-        tempfile::NamedTempFile::new_in("/").unwrap()
-    } else {
-        tempfile::NamedTempFile::new().unwrap()
-    }
-}
+const TEST_DB_PATH: &str = "/test.redb";
 
 mod uqfile;
-pub use uqfile::{File, OpenOptions};
+pub use uqfile::{File, OpenOptions, VfsConfig, VfsTransport};
+
+#[cfg(any(test, feature = "mock_vfs"))]
+mod mock_vfs;
+#[cfg(any(test, feature = "mock_vfs"))]
+pub use mock_vfs::MockVfs;
 
 mod kernel_types;
@@ -0,0 +1,79 @@
+use crate::types::RedbValue;
+
+type MergeFn<V> = for<'a, 'b, 'o> fn(
+    Option<<V as RedbValue>::SelfType<'a>>,
+    &'b <V as RedbValue>::SelfType<'o>,
+) -> Vec<u8>;
+
+/// Combines the value stored for a key with an operand, for use by
+/// [`Table::merge`](crate::Table::merge)
+///
+/// A merge operator is registered with [`TableDefinition::with_merge_operator`](crate::TableDefinition::with_merge_operator).
+/// Operators are expected to be associative, like the provided `ADD` and `APPEND` operators
+pub struct MergeOperator<V: RedbValue + 'static> {
+    merge: MergeFn<V>,
+}
+
+impl<V: RedbValue + 'static> MergeOperator<V> {
+    /// Construct a new operator from `merge`, which is passed the existing value, if there is one,
+    /// and the operand, and returns the serialized result
+    pub const fn new(
+        merge: for<'a, 'b, 'o> fn(Option<V::SelfType<'a>>, &'b V::SelfType<'o>) -> Vec<u8>,
+    ) -> Self {
+        Self { merge }
+    }
+
+    pub(crate) fn apply(&self, existing: Option<&[u8]>, operand: &V::SelfType<'_>) -> Vec<u8> {
+        (self.merge)(existing.map(V::from_bytes), operand)
+    }
+}
+
+impl<V: RedbValue + 'static> Clone for MergeOperator<V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<V: RedbValue + 'static> Copy for MergeOperator<V> {}
+
+macro_rules! add_operator {
+    ($t:ty) => {
+        impl MergeOperator<$t> {
+            /// Adds the operand to the existing value, or to zero if there is none. Overflow wraps
+            /// around
+            pub const ADD: Self = Self::new(|existing, operand| {
+                let sum = existing.unwrap_or(0).wrapping_add(*operand);
+                <$t>::as_bytes(&sum).to_vec()
+            });
+        }
+    };
+}
+
+add_operator!(u8);
+add_operator!(u16);
+add_operator!(u32);
+add_operator!(u64);
+add_operator!(u128);
+add_operator!(i8);
+add_operator!(i16);
+add_operator!(i32);
+add_operator!(i64);
+add_operator!(i128);
+
+impl MergeOperator<&'static [u8]> {
+    /// Appends the operand to the existing value
+    pub const APPEND: Self = Self::new(|existing, operand| {
+        let mut result = existing.unwrap_or_default().to_vec();
+        result.extend_from_slice(operand);
+        result
+    });
+}
+
+impl MergeOperator<&'static str> {
+    /// Appends the operand to the existing value
+    pub const APPEND: Self = Self::new(|existing, operand| {
+        let mut result = existing.unwrap_or_default().as_bytes().to_vec();
+        result.extend_from_slice(operand.as_bytes());
+        result
+    });
+}
@@ -0,0 +1,235 @@
+use crate::kernel_types::{AddEntryType, VfsAction, VfsError, VfsRequest, VfsResponse};
+use crate::uqfile::action_name;
+use crate::{VfsConfig, VfsTransport};
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+const NODE: &str = "mock";
+const DRIVE: &str = "mock";
+const PROCESS: &str = "vfs:sys:uqbar";
+
+/// An in-process implementation of the uqbar VFS, which stores files in memory
+///
+/// Serves a single drive, which can be reached using [`MockVfs::config`]. Clones share the same
+/// files, so a clone can be passed to [`Database::create`](crate::Database::create) while the
+/// original is used to inspect the files and inject failures.
+///
+/// Intended for testing code which runs on top of the VFS, without a running uqbar kernel. Only
+/// available with the `mock_vfs` feature
+#[derive(Clone, Default)]
+pub struct MockVfs {
+    state: Arc<Mutex<MockVfsState>>,
+}
+
+#[derive(Default)]
+struct MockVfsState {
+    files: HashMap<String, Vec<u8>>,
+    latency: Duration,
+    // Keyed by action name, the number of upcoming requests which should fail
+    failures: HashMap<String, u64>,
+    // Keyed by action name, the number of upcoming responses which should be dropped
+    drops: HashMap<String, u64>,
+    requests: HashMap<String, u64>,
+}
+
+impl MockVfs {
+    /// Creates a new VFS, with no files
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the configuration of the drive served by this VFS
+    pub fn config(&self) -> VfsConfig {
+        let mut config = VfsConfig::new(NODE, DRIVE);
+        config.set_process(PROCESS);
+        config
+    }
+
+    /// Delay every response by `latency`
+    pub fn set_latency(&self, latency: Duration) {
+        self.state().latency = latency;
+    }
+
+    /// Respond to the next `count` requests for `action`, for example `"WriteOffset"`, with an
+    /// error instead of performing them
+    pub fn fail_next(&self, action: &str, count: u64) {
+        self.state().failures.insert(action.to_string(), count);
+    }
+
+    /// Perform the next `count` requests for `action`, but drop their responses. The sender sees
+    /// an error of kind [`io::ErrorKind::TimedOut`]
+    pub fn drop_next(&self, action: &str, count: u64) {
+        self.state().drops.insert(action.to_string(), count);
+    }
+
+    /// Returns the number of requests received for `action`
+    pub fn request_count(&self, action: &str) -> u64 {
+        self.state().requests.get(action).copied().unwrap_or(0)
+    }
+
+    /// Returns the contents of the file at `path`, if it exists
+    pub fn file(&self, path: &str) -> Option<Vec<u8>> {
+        self.state().files.get(path).cloned()
+    }
+
+    /// Creates, or replaces, the file at `path`
+    pub fn insert_file(&self, path: &str, contents: Vec<u8>) {
+        self.state().files.insert(path.to_string(), contents);
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockVfsState> {
+        self.state.lock().unwrap()
+    }
+}
+
+// Decrements the counter for `action`, and returns true if it was non-zero
+fn take(counters: &mut HashMap<String, u64>, action: &str) -> bool {
+    match counters.get_mut(action) {
+        Some(remaining) if *remaining > 0 => {
+            *remaining -= 1;
+            true
+        }
+        _ => false,
+    }
+}
+
+impl MockVfsState {
+    fn handle(
+        &mut self,
+        action: VfsAction,
+        payload: Option<Vec<u8>>,
+    ) -> (VfsResponse, Option<Vec<u8>>) {
+        match action {
+            VfsAction::Add {
+                full_path,
+                entry_type: AddEntryType::NewFile,
+            } => {
+                self.files.insert(full_path, payload.unwrap_or_default());
+                (VfsResponse::Ok, None)
+            }
+            VfsAction::GetEntry(full_path) => match self.files.get(&full_path) {
+                Some(contents) => (
+                    VfsResponse::GetEntry {
+                        is_file: true,
+                        children: vec![],
+                    },
+                    Some(contents.clone()),
+                ),
+                None => (VfsResponse::Err(VfsError::BadDescriptor), None),
+            },
+            VfsAction::GetEntryLength(full_path) => match self.files.get(&full_path) {
+                Some(contents) => (VfsResponse::GetEntryLength(contents.len() as u64), None),
+                None => (VfsResponse::Err(VfsError::BadDescriptor), None),
+            },
+            VfsAction::GetFileChunk {
+                full_path,
+                offset,
+                length,
+            } => match self.files.get(&full_path) {
+                Some(contents) => {
+                    let start = contents.len().min(offset.try_into().unwrap());
+                    let end = contents.len().min((offset + length).try_into().unwrap());
+                    (
+                        VfsResponse::GetFileChunk,
+                        Some(contents[start..end].to_vec()),
+                    )
+                }
+                None => (VfsResponse::Err(VfsError::BadDescriptor), None),
+            },
+            VfsAction::WriteOffset { full_path, offset } => {
+                let data = payload.unwrap_or_default();
+                self.write(&full_path, &[(offset, data.len() as u64)], &data)
+            }
+            VfsAction::WriteOffsets { full_path, writes } => {
+                let data = payload.unwrap_or_default();
+                self.write(&full_path, &writes, &data)
+            }
+            VfsAction::SetSize { full_path, size } => match self.files.get_mut(&full_path) {
+                Some(contents) => {
+                    contents.resize(size.try_into().unwrap(), 0);
+                    (VfsResponse::Ok, None)
+                }
+                None => (VfsResponse::Err(VfsError::BadDescriptor), None),
+            },
+            VfsAction::Delete(full_path) => match self.files.remove(&full_path) {
+                Some(_) => (VfsResponse::Ok, None),
+                None => (VfsResponse::Err(VfsError::BadDescriptor), None),
+            },
+            VfsAction::SyncData(full_path) => {
+                if self.files.contains_key(&full_path) {
+                    (VfsResponse::Ok, None)
+                } else {
+                    (VfsResponse::Err(VfsError::BadDescriptor), None)
+                }
+            }
+            _ => (VfsResponse::Err(VfsError::BadDescriptor), None),
+        }
+    }
+
+    fn write(
+        &mut self,
+        full_path: &str,
+        writes: &[(u64, u64)],
+        data: &[u8],
+    ) -> (VfsResponse, Option<Vec<u8>>) {
+        let contents = match self.files.get_mut(full_path) {
+            Some(contents) => contents,
+            None => return (VfsResponse::Err(VfsError::BadDescriptor), None),
+        };
+        let mut data = data;
+        for &(offset, length) in writes {
+            let offset: usize = offset.try_into().unwrap();
+            let length: usize = length.try_into().unwrap();
+            if contents.len() < offset + length {
+                contents.resize(offset + length, 0);
+            }
+            contents[offset..offset + length].copy_from_slice(&data[..length]);
+            data = &data[length..];
+        }
+        (VfsResponse::Ok, None)
+    }
+}
+
+impl VfsTransport for MockVfs {
+    fn send_and_await_response(
+        &self,
+        node: &str,
+        process: &str,
+        ipc: Vec<u8>,
+        payload: Option<Vec<u8>>,
+        timeout: Duration,
+    ) -> io::Result<(Vec<u8>, Option<Vec<u8>>)> {
+        if node != NODE || process != PROCESS {
+            // There is nothing to respond to the request
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        let request: VfsRequest = serde_json::from_slice(&ipc)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let name = action_name(&request.action);
+
+        let (latency, response, dropped) = {
+            let mut state = self.state();
+            *state.requests.entry(name.to_string()).or_default() += 1;
+            let response = if request.drive != DRIVE {
+                (VfsResponse::Err(VfsError::BadDriveName), None)
+            } else if take(&mut state.failures, name) {
+                (VfsResponse::Err(VfsError::NoCap), None)
+            } else {
+                state.handle(request.action, payload)
+            };
+            let dropped = take(&mut state.drops, name);
+            (state.latency, response, dropped)
+        };
+
+        if dropped || latency > timeout {
+            thread::sleep(latency.min(timeout));
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        thread::sleep(latency);
+        let (response, payload) = response;
+        Ok((serde_json::to_vec(&response)?, payload))
+    }
+}
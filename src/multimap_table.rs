@@ -2,9 +2,10 @@ use crate::multimap_table::DynamicCollectionType::{Inline, Subtree};
 use crate::sealed::Sealed;
 use crate::table::TableStats;
 use crate::tree_store::{
-    btree_stats, AllPageNumbersBtreeIter, BranchAccessor, Btree, BtreeMut, BtreeRangeIter,
-    BtreeStats, CachePriority, Checksum, LeafAccessor, LeafMutator, Page, PageHint, PageNumber,
-    RawBtree, RawLeafBuilder, TransactionalMemory, UntypedBtreeMut, BRANCH, LEAF, MAX_VALUE_LENGTH,
+    btree_diff, btree_stats, btree_visit_entries, subtree_len, AllPageNumbersBtreeIter,
+    BranchAccessor, Btree, BtreeMut, BtreeRangeIter, BtreeStats, CachePriority, Checksum,
+    LeafAccessor, LeafMutator, Page, PageHint, PageNumber, RawBtree, RawLeafBuilder,
+    TransactionalMemory, UntypedBtreeMut, BRANCH, LEAF, LEGACY_BRANCH, MAX_VALUE_LENGTH,
 };
 use crate::types::{RedbKey, RedbValue, TypeName};
 use crate::{AccessGuard, Result, StorageError, WriteTransaction};
@@ -37,6 +38,83 @@ pub(crate) fn multimap_btree_stats(
     }
 }
 
+// Returns whether two serialized collections hold the same values
+// Counts the values in the multimap table rooted at `root`, by visiting each of its keys
+pub(crate) fn count_values<K: RedbKey + 'static, V: RedbKey + 'static>(
+    root: Option<(PageNumber, Checksum)>,
+    mem: &TransactionalMemory,
+) -> Result<u64> {
+    let tree: Btree<K, &'static DynamicCollection<V>> = Btree::new(root, PageHint::None, mem)?;
+    let mut len = 0;
+    for entry in tree.range::<RangeFull, K::SelfType<'_>>(&(..))? {
+        len += entry?.value().len(mem)?;
+    }
+    Ok(len)
+}
+
+pub(crate) fn collections_equal<V: RedbKey>(
+    a: &[u8],
+    b: &[u8],
+    mem: &TransactionalMemory,
+) -> Result<bool> {
+    if a == b {
+        return Ok(true);
+    }
+    let a = DynamicCollection::<V>::new(a);
+    let b = DynamicCollection::<V>::new(b);
+    if let (Subtree, Subtree) = (a.collection_type(), b.collection_type()) {
+        let changes = btree_diff::<V>(
+            Some(a.as_subtree().0),
+            Some(b.as_subtree().0),
+            mem,
+            V::fixed_width(),
+            <() as RedbValue>::fixed_width(),
+            |_, _| Ok(true),
+        )?;
+        return Ok(changes.is_empty());
+    }
+    Ok(a.values(mem)? == b.values(mem)?)
+}
+
+// Calls `visitor` with each key and value of a multimap table, in ascending order
+pub(crate) fn multimap_visit_entries<F: FnMut(&[u8], &[u8]) -> Result>(
+    root: Option<PageNumber>,
+    mem: &TransactionalMemory,
+    fixed_key_size: Option<usize>,
+    fixed_value_size: Option<usize>,
+    visitor: &mut F,
+) -> Result {
+    btree_visit_entries(
+        root,
+        mem,
+        fixed_key_size,
+        DynamicCollection::<()>::fixed_width_with(fixed_value_size),
+        &mut |key, value| {
+            let collection = <&DynamicCollection<()>>::from_bytes(value);
+            match collection.collection_type() {
+                Inline => {
+                    let accessor = LeafAccessor::new(
+                        collection.as_inline(),
+                        fixed_value_size,
+                        <() as RedbValue>::fixed_width(),
+                    );
+                    for i in 0..accessor.num_pairs() {
+                        visitor(key, accessor.entry(i).unwrap().key())?;
+                    }
+                    Ok(())
+                }
+                Subtree => btree_visit_entries(
+                    Some(collection.as_subtree().0),
+                    mem,
+                    fixed_value_size,
+                    <() as RedbValue>::fixed_width(),
+                    &mut |value, _| visitor(key, value),
+                ),
+            }
+        },
+    )
+}
+
 fn multimap_stats_helper(
     page_number: PageNumber,
     mem: &TransactionalMemory,
@@ -113,7 +191,7 @@ fn multimap_stats_helper(
                 fragmented_bytes,
             })
         }
-        BRANCH => {
+        BRANCH | LEGACY_BRANCH => {
             let accessor = BranchAccessor::new(&page, fixed_key_size);
             let mut max_child_height = 0;
             let mut leaf_pages = 0;
@@ -261,7 +339,7 @@ pub(crate) fn parse_subtree_roots<T: Page>(
     fixed_value_size: Option<usize>,
 ) -> Vec<(PageNumber, Checksum)> {
     match page.memory()[0] {
-        BRANCH => {
+        BRANCH | LEGACY_BRANCH => {
             vec![]
         }
         LEAF => {
@@ -475,6 +553,43 @@ impl<V> DynamicCollection<V> {
 }
 
 impl<V: RedbKey> DynamicCollection<V> {
+    fn len(&self, mem: &TransactionalMemory) -> Result<u64> {
+        match self.collection_type() {
+            Inline => {
+                let accessor = LeafAccessor::new(
+                    self.as_inline(),
+                    V::fixed_width(),
+                    <() as RedbValue>::fixed_width(),
+                );
+                Ok(accessor.num_pairs().try_into().unwrap())
+            }
+            Subtree => subtree_len(mem, self.as_subtree().0),
+        }
+    }
+
+    // Returns the serialized values, in ascending order
+    fn values(&self, mem: &TransactionalMemory) -> Result<Vec<Vec<u8>>> {
+        match self.collection_type() {
+            Inline => {
+                let accessor = LeafAccessor::new(
+                    self.as_inline(),
+                    V::fixed_width(),
+                    <() as RedbValue>::fixed_width(),
+                );
+                Ok((0..accessor.num_pairs())
+                    .map(|i| accessor.entry(i).unwrap().key().to_vec())
+                    .collect())
+            }
+            Subtree => {
+                let root = self.as_subtree().0;
+                let iter: BtreeRangeIter<V, ()> =
+                    BtreeRangeIter::new::<RangeFull, &V::SelfType<'_>>(&(..), Some(root), mem)?;
+                iter.map(|entry| entry.map(|entry| entry.key_data()))
+                    .collect()
+            }
+        }
+    }
+
     fn iter<'a>(
         collection: AccessGuard<'a, &'static DynamicCollection<V>>,
         mem: &'a TransactionalMemory,
@@ -717,6 +832,9 @@ pub struct MultimapTable<'db, 'txn, K: RedbKey + 'static, V: RedbKey + 'static>
     freed_pages: Arc<Mutex<Vec<PageNumber>>>,
     tree: BtreeMut<'txn, K, &'static DynamicCollection<V>>,
     mem: &'db TransactionalMemory,
+    num_values: u64,
+    // True if the table has subscribers, which should be notified of changes
+    record_changes: bool,
     _value_type: PhantomData<V>,
 }
 
@@ -724,6 +842,7 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbKey + 'static> MultimapTable<'db, '
     pub(crate) fn new(
         name: &str,
         table_root: Option<(PageNumber, Checksum)>,
+        num_values: u64,
         freed_pages: Arc<Mutex<Vec<PageNumber>>>,
         mem: &'db TransactionalMemory,
         transaction: &'txn WriteTransaction<'db>,
@@ -734,10 +853,19 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbKey + 'static> MultimapTable<'db, '
             freed_pages: freed_pages.clone(),
             tree: BtreeMut::new(table_root, mem, freed_pages),
             mem,
+            num_values,
+            record_changes: transaction.records_changes(name),
             _value_type: Default::default(),
         }
     }
 
+    fn record_change(&self, key: &K::SelfType<'_>, existed: bool, exists: bool) {
+        if self.record_changes {
+            self.transaction
+                .record_change(&self.name, K::as_bytes(key).as_ref(), existed, exists);
+        }
+    }
+
     #[allow(dead_code)]
     pub(crate) fn print_debug(&self, include_values: bool) -> Result {
         self.tree.print_debug(include_values)
@@ -761,6 +889,7 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbKey + 'static> MultimapTable<'db, '
             return Err(StorageError::ValueTooLarge(key_bytes.as_ref().len()));
         }
         let get_result = self.tree.get(key.borrow())?;
+        let key_existed = get_result.is_some();
         let existed = if get_result.is_some() {
             #[allow(clippy::unnecessary_unwrap)]
             let guard = get_result.unwrap();
@@ -885,6 +1014,10 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbKey + 'static> MultimapTable<'db, '
             }
             false
         };
+        if !existed {
+            self.num_values += 1;
+            self.record_change(key.borrow(), key_existed, true);
+        }
 
         Ok(existed)
     }
@@ -991,7 +1124,7 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbKey + 'static> MultimapTable<'db, '
                                     .insert(key.borrow(), &DynamicCollection::new(&subtree_data))?;
                             }
                         }
-                        BRANCH => {
+                        BRANCH | LEGACY_BRANCH => {
                             let subtree_data =
                                 DynamicCollection::<V>::make_subtree_data(new_root, new_checksum);
                             self.tree
@@ -1006,6 +1139,13 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbKey + 'static> MultimapTable<'db, '
                 existed
             }
         };
+        if existed {
+            self.num_values -= 1;
+        }
+        if existed && self.record_changes {
+            let exists = self.tree.get(key.borrow())?.is_some();
+            self.record_change(key.borrow(), true, exists);
+        }
 
         Ok(existed)
     }
@@ -1018,6 +1158,12 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbKey + 'static> MultimapTable<'db, '
         K: 'a,
     {
         let iter = if let Some(collection) = self.tree.remove(key.borrow())? {
+            self.num_values -= collection.value().len(self.mem)?;
+            if self.record_changes {
+                let key_bytes = K::as_bytes(key.borrow());
+                self.transaction
+                    .record_change(&self.name, key_bytes.as_ref(), true, false);
+            }
             let mut pages = vec![];
             if matches!(
                 collection.value().collection_type(),
@@ -1103,15 +1249,7 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbKey + 'static> ReadableMultimapTabl
 
     /// Returns the number of key-value pairs in the table
     fn len(&self) -> Result<u64> {
-        let mut count = 0;
-        for item in self.iter()? {
-            let (_, values) = item?;
-            for v in values {
-                v?;
-                count += 1;
-            }
-        }
-        Ok(count)
+        Ok(self.num_values)
     }
 
     /// Returns `true` if the table is empty
@@ -1126,7 +1264,8 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbKey + 'static> Drop
     for MultimapTable<'db, 'txn, K, V>
 {
     fn drop(&mut self) {
-        self.transaction.close_table(&self.name, &self.tree);
+        self.transaction
+            .close_multimap_table(&self.name, &self.tree, self.num_values);
     }
 }
 
@@ -1159,18 +1298,22 @@ pub trait ReadableMultimapTable<K: RedbKey + 'static, V: RedbKey + 'static>: Sea
 pub struct ReadOnlyMultimapTable<'txn, K: RedbKey + 'static, V: RedbKey + 'static> {
     tree: Btree<'txn, K, &'static DynamicCollection<V>>,
     mem: &'txn TransactionalMemory,
+    // None if the table was written by file format version 1, and its values must be counted
+    num_values: Option<u64>,
     _value_type: PhantomData<V>,
 }
 
 impl<'txn, K: RedbKey + 'static, V: RedbKey + 'static> ReadOnlyMultimapTable<'txn, K, V> {
     pub(crate) fn new(
         root_page: Option<(PageNumber, Checksum)>,
+        num_values: Option<u64>,
         hint: PageHint,
         mem: &'txn TransactionalMemory,
     ) -> Result<ReadOnlyMultimapTable<'txn, K, V>> {
         Ok(ReadOnlyMultimapTable {
             tree: Btree::new(root_page, hint, mem)?,
             mem,
+            num_values,
             _value_type: Default::default(),
         })
     }
@@ -1225,15 +1368,10 @@ impl<'txn, K: RedbKey + 'static, V: RedbKey + 'static> ReadableMultimapTable<K,
     }
 
     fn len(&self) -> Result<u64> {
-        let mut count = 0;
-        for item in self.iter()? {
-            let (_, values) = item?;
-            for v in values {
-                v?;
-                count += 1;
-            }
+        match self.num_values {
+            Some(num_values) => Ok(num_values),
+            None => count_values::<K, V>(self.tree.get_root(), self.mem),
         }
-        Ok(count)
     }
 
     fn is_empty(&self) -> Result<bool> {
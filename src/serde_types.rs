@@ -0,0 +1,957 @@
+use crate::types::{RedbKey, RedbValue, TypeName};
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::ser::{self, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::marker::PhantomData;
+
+/// A stable name for a type stored with [`Json`], [`Bincode`], or [`SerdeKey`]
+///
+/// The name is stored as part of the type of each table which contains the type, and is checked
+/// when the table is opened. It must not change for as long as such tables exist, even if the
+/// type is renamed or moved to another module
+pub trait StableName {
+    /// Returns the name of the type
+    fn stable_name() -> String;
+}
+
+macro_rules! stable_name {
+    ($($t:ty),*) => {
+        $(
+            impl StableName for $t {
+                fn stable_name() -> String {
+                    stringify!($t).to_string()
+                }
+            }
+        )*
+    };
+}
+
+stable_name!(
+    (),
+    bool,
+    char,
+    String,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    f32,
+    f64
+);
+
+impl<T: StableName> StableName for Option<T> {
+    fn stable_name() -> String {
+        format!("Option<{}>", T::stable_name())
+    }
+}
+
+impl<T: StableName> StableName for Vec<T> {
+    fn stable_name() -> String {
+        format!("Vec<{}>", T::stable_name())
+    }
+}
+
+impl<K: StableName, V: StableName> StableName for BTreeMap<K, V> {
+    fn stable_name() -> String {
+        format!("BTreeMap<{}, {}>", K::stable_name(), V::stable_name())
+    }
+}
+
+macro_rules! stable_name_tuple {
+    ($($t:ident),+) => {
+        impl<$($t: StableName),+> StableName for ($($t,)+) {
+            fn stable_name() -> String {
+                let names: Vec<String> = vec![$($t::stable_name()),+];
+                if names.len() == 1 {
+                    format!("({},)", names[0])
+                } else {
+                    format!("({})", names.join(", "))
+                }
+            }
+        }
+    };
+}
+
+stable_name_tuple!(T0);
+stable_name_tuple!(T0, T1);
+stable_name_tuple!(T0, T1, T2);
+stable_name_tuple!(T0, T1, T2, T3);
+stable_name_tuple!(T0, T1, T2, T3, T4);
+stable_name_tuple!(T0, T1, T2, T3, T4, T5);
+
+/// Stores values of type `T` encoded as JSON
+///
+/// The type name is derived from the [`StableName`] of `T`, so opening a table with a different
+/// type fails with [`TableError::TableTypeMismatch`](crate::TableError::TableTypeMismatch)
+///
+/// # Panics
+///
+/// Inserting a value which can't be encoded as JSON, such as a map with non-string keys, panics
+#[derive(Debug)]
+pub struct Json<T>(PhantomData<T>);
+
+impl<T: Debug + Serialize + DeserializeOwned + StableName> RedbValue for Json<T> {
+    type SelfType<'a> = T
+    where
+        Self: 'a;
+    type AsBytes<'a> = Vec<u8>
+    where
+        Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        None
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> T
+    where
+        Self: 'a,
+    {
+        serde_json::from_slice(data).unwrap()
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Vec<u8>
+    where
+        Self: 'a,
+        Self: 'b,
+    {
+        serde_json::to_vec(value).unwrap()
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::internal(&format!("Json<{}>", T::stable_name()))
+    }
+}
+
+/// Stores values of type `T` in the compact binary encoding of [bincode](https://docs.rs/bincode)
+///
+/// The type name is derived from the [`StableName`] of `T`, in the same way as [`Json`]
+///
+/// # Panics
+///
+/// Inserting a value which bincode can't encode, such as a sequence whose `Serialize`
+/// implementation doesn't report its length, panics
+#[derive(Debug)]
+pub struct Bincode<T>(PhantomData<T>);
+
+impl<T: Debug + Serialize + DeserializeOwned + StableName> RedbValue for Bincode<T> {
+    type SelfType<'a> = T
+    where
+        Self: 'a;
+    type AsBytes<'a> = Vec<u8>
+    where
+        Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        None
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> T
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).unwrap()
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Vec<u8>
+    where
+        Self: 'a,
+        Self: 'b,
+    {
+        bincode::serialize(value).unwrap()
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::internal(&format!("Bincode<{}>", T::stable_name()))
+    }
+}
+
+/// Stores keys of type `T` in an order preserving encoding, so that they can be used as the key
+/// of a table
+///
+/// Keys are ordered in the same way as a derived `Ord` implementation: structs and tuples by
+/// their fields in order, enums by variant and then by their fields, and sequences, strings, and
+/// maps lexicographically. Floats are ordered by `total_cmp()`.
+///
+/// The type name is derived from the [`StableName`] of `T`, in the same way as [`Json`]
+///
+/// # Panics
+///
+/// Inserting a key whose `Serialize` implementation returns an error panics
+#[derive(Debug)]
+pub struct SerdeKey<T>(PhantomData<T>);
+
+impl<T: Debug + Serialize + DeserializeOwned + StableName> RedbValue for SerdeKey<T> {
+    type SelfType<'a> = T
+    where
+        Self: 'a;
+    type AsBytes<'a> = Vec<u8>
+    where
+        Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        None
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> T
+    where
+        Self: 'a,
+    {
+        let mut deserializer = OrderedDeserializer { input: data };
+        let value = T::deserialize(&mut deserializer).unwrap();
+        assert!(deserializer.input.is_empty());
+        value
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Vec<u8>
+    where
+        Self: 'a,
+        Self: 'b,
+    {
+        let mut serializer = OrderedSerializer { output: vec![] };
+        value.serialize(&mut serializer).unwrap();
+        serializer.output
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::internal(&format!("SerdeKey<{}>", T::stable_name()))
+    }
+}
+
+impl<T: Debug + Serialize + DeserializeOwned + StableName> RedbKey for SerdeKey<T> {
+    fn compare(data1: &[u8], data2: &[u8]) -> Ordering {
+        data1.cmp(data2)
+    }
+}
+
+// Order preserving encoding:
+// * integers are big endian, with the sign bit flipped for signed integers
+// * floats are big endian, with the sign bit flipped for positive values and all bits flipped
+//   for negative values
+// * strings and byte arrays have each 0 byte escaped as [0, 0xFF], and are terminated by [0, 0]
+// * options, sequences, and maps are prefixed (for each element) by 1, and terminated by 0
+// * enums are prefixed by the big endian variant index
+// * structs and tuples are the concatenation of their fields
+const ESCAPE: u8 = 0;
+const ESCAPED_ZERO: u8 = 0xFF;
+const TERMINATOR: u8 = 0;
+const ELEMENT: u8 = 1;
+const END: u8 = 0;
+
+#[derive(Debug)]
+struct EncodingError(String);
+
+impl Display for EncodingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+impl ser::Error for EncodingError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+impl de::Error for EncodingError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+struct OrderedSerializer {
+    output: Vec<u8>,
+}
+
+impl OrderedSerializer {
+    fn write_signed<const N: usize>(&mut self, mut bytes: [u8; N]) {
+        bytes[0] ^= 0x80;
+        self.output.extend_from_slice(&bytes);
+    }
+
+    fn write_escaped(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.output.push(byte);
+            if byte == ESCAPE {
+                self.output.push(ESCAPED_ZERO);
+            }
+        }
+        self.output.extend_from_slice(&[ESCAPE, TERMINATOR]);
+    }
+}
+
+impl ser::Serializer for &mut OrderedSerializer {
+    type Ok = ();
+    type Error = EncodingError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<(), EncodingError> {
+        self.output.push(u8::from(v));
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), EncodingError> {
+        self.write_signed(v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), EncodingError> {
+        self.write_signed(v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), EncodingError> {
+        self.write_signed(v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), EncodingError> {
+        self.write_signed(v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<(), EncodingError> {
+        self.write_signed(v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), EncodingError> {
+        self.output.push(v);
+        Ok(())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), EncodingError> {
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), EncodingError> {
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), EncodingError> {
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<(), EncodingError> {
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), EncodingError> {
+        let bits = v.to_bits();
+        let bits = if v.is_sign_negative() {
+            !bits
+        } else {
+            bits | (1 << 31)
+        };
+        self.serialize_u32(bits)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), EncodingError> {
+        let bits = v.to_bits();
+        let bits = if v.is_sign_negative() {
+            !bits
+        } else {
+            bits | (1 << 63)
+        };
+        self.serialize_u64(bits)
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), EncodingError> {
+        self.serialize_u32(u32::from(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), EncodingError> {
+        self.write_escaped(v.as_bytes());
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), EncodingError> {
+        self.write_escaped(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), EncodingError> {
+        self.output.push(END);
+        Ok(())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), EncodingError> {
+        self.output.push(ELEMENT);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), EncodingError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), EncodingError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), EncodingError> {
+        self.serialize_u32(variant_index)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), EncodingError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), EncodingError> {
+        self.output.extend_from_slice(&variant_index.to_be_bytes());
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self, EncodingError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self, EncodingError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self, EncodingError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, EncodingError> {
+        self.output.extend_from_slice(&variant_index.to_be_bytes());
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self, EncodingError> {
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, EncodingError> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, EncodingError> {
+        self.output.extend_from_slice(&variant_index.to_be_bytes());
+        Ok(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+impl ser::SerializeSeq for &mut OrderedSerializer {
+    type Ok = ();
+    type Error = EncodingError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), EncodingError> {
+        self.output.push(ELEMENT);
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), EncodingError> {
+        self.output.push(END);
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for &mut OrderedSerializer {
+    type Ok = ();
+    type Error = EncodingError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), EncodingError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), EncodingError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for &mut OrderedSerializer {
+    type Ok = ();
+    type Error = EncodingError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), EncodingError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), EncodingError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for &mut OrderedSerializer {
+    type Ok = ();
+    type Error = EncodingError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), EncodingError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), EncodingError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for &mut OrderedSerializer {
+    type Ok = ();
+    type Error = EncodingError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), EncodingError> {
+        self.output.push(ELEMENT);
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), EncodingError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), EncodingError> {
+        self.output.push(END);
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut OrderedSerializer {
+    type Ok = ();
+    type Error = EncodingError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), EncodingError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), EncodingError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for &mut OrderedSerializer {
+    type Ok = ();
+    type Error = EncodingError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), EncodingError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), EncodingError> {
+        Ok(())
+    }
+}
+
+struct OrderedDeserializer<'de> {
+    input: &'de [u8],
+}
+
+impl<'de> OrderedDeserializer<'de> {
+    fn read<const N: usize>(&mut self) -> Result<[u8; N], EncodingError> {
+        if self.input.len() < N {
+            return Err(EncodingError("unexpected end of key".to_string()));
+        }
+        let (bytes, rest) = self.input.split_at(N);
+        self.input = rest;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn read_byte(&mut self) -> Result<u8, EncodingError> {
+        Ok(self.read::<1>()?[0])
+    }
+
+    fn read_signed<const N: usize>(&mut self) -> Result<[u8; N], EncodingError> {
+        let mut bytes = self.read::<N>()?;
+        bytes[0] ^= 0x80;
+        Ok(bytes)
+    }
+
+    fn read_escaped(&mut self) -> Result<Vec<u8>, EncodingError> {
+        let mut result = vec![];
+        loop {
+            let byte = self.read_byte()?;
+            if byte != ESCAPE {
+                result.push(byte);
+                continue;
+            }
+            match self.read_byte()? {
+                ESCAPED_ZERO => result.push(0),
+                TERMINATOR => return Ok(result),
+                _ => return Err(EncodingError("invalid escape sequence".to_string())),
+            }
+        }
+    }
+
+    // Returns true if another element follows
+    fn read_element_marker(&mut self) -> Result<bool, EncodingError> {
+        match self.read_byte()? {
+            ELEMENT => Ok(true),
+            END => Ok(false),
+            _ => Err(EncodingError("invalid element marker".to_string())),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut OrderedDeserializer<'de> {
+    type Error = EncodingError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, EncodingError> {
+        Err(EncodingError(
+            "the key encoding is not self-describing".to_string(),
+        ))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        match self.read_byte()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            _ => Err(EncodingError("invalid bool".to_string())),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        visitor.visit_i8(i8::from_be_bytes(self.read_signed()?))
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        visitor.visit_i16(i16::from_be_bytes(self.read_signed()?))
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        visitor.visit_i32(i32::from_be_bytes(self.read_signed()?))
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        visitor.visit_i64(i64::from_be_bytes(self.read_signed()?))
+    }
+
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        visitor.visit_i128(i128::from_be_bytes(self.read_signed()?))
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        visitor.visit_u8(self.read_byte()?)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        visitor.visit_u16(u16::from_be_bytes(self.read()?))
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        visitor.visit_u32(u32::from_be_bytes(self.read()?))
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        visitor.visit_u64(u64::from_be_bytes(self.read()?))
+    }
+
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        visitor.visit_u128(u128::from_be_bytes(self.read()?))
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        let bits = u32::from_be_bytes(self.read()?);
+        let bits = if bits & (1 << 31) == 0 {
+            !bits
+        } else {
+            bits ^ (1 << 31)
+        };
+        visitor.visit_f32(f32::from_bits(bits))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        let bits = u64::from_be_bytes(self.read()?);
+        let bits = if bits & (1 << 63) == 0 {
+            !bits
+        } else {
+            bits ^ (1 << 63)
+        };
+        visitor.visit_f64(f64::from_bits(bits))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        let value = u32::from_be_bytes(self.read()?);
+        visitor.visit_char(
+            char::from_u32(value).ok_or_else(|| EncodingError("invalid char".to_string()))?,
+        )
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        let data = self.read_escaped()?;
+        visitor.visit_string(String::from_utf8(data).map_err(|err| EncodingError(err.to_string()))?)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        visitor.visit_byte_buf(self.read_escaped()?)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        if self.read_element_marker()? {
+            visitor.visit_some(self)
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, EncodingError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, EncodingError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        visitor.visit_seq(Elements { de: self })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, EncodingError> {
+        visitor.visit_seq(Fields { de: self, len })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, EncodingError> {
+        visitor.visit_seq(Fields { de: self, len })
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EncodingError> {
+        visitor.visit_map(Elements { de: self })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, EncodingError> {
+        visitor.visit_seq(Fields {
+            de: self,
+            len: fields.len(),
+        })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, EncodingError> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, EncodingError> {
+        self.deserialize_u32(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, EncodingError> {
+        self.deserialize_any(visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+// The elements of a sequence or map, each prefixed by a marker
+struct Elements<'a, 'de> {
+    de: &'a mut OrderedDeserializer<'de>,
+}
+
+impl<'de> SeqAccess<'de> for Elements<'_, 'de> {
+    type Error = EncodingError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, EncodingError> {
+        if self.de.read_element_marker()? {
+            seed.deserialize(&mut *self.de).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<'de> MapAccess<'de> for Elements<'_, 'de> {
+    type Error = EncodingError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, EncodingError> {
+        if self.de.read_element_marker()? {
+            seed.deserialize(&mut *self.de).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, EncodingError> {
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+// The fields of a struct or tuple
+struct Fields<'a, 'de> {
+    de: &'a mut OrderedDeserializer<'de>,
+    len: usize,
+}
+
+impl<'de> SeqAccess<'de> for Fields<'_, 'de> {
+    type Error = EncodingError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, EncodingError> {
+        if self.len == 0 {
+            return Ok(None);
+        }
+        self.len -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+impl<'de> EnumAccess<'de> for &mut OrderedDeserializer<'de> {
+    type Error = EncodingError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self), EncodingError> {
+        let index = u32::from_be_bytes(self.read()?);
+        let variant =
+            seed.deserialize(IntoDeserializer::<EncodingError>::into_deserializer(index))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for &mut OrderedDeserializer<'de> {
+    type Error = EncodingError;
+
+    fn unit_variant(self) -> Result<(), EncodingError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, EncodingError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, EncodingError> {
+        visitor.visit_seq(Fields { de: self, len })
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, EncodingError> {
+        visitor.visit_seq(Fields {
+            de: self,
+            len: fields.len(),
+        })
+    }
+}
@@ -0,0 +1,188 @@
+use crate::types::RedbKey;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+use std::sync::Mutex;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// The kind of change made to a key by a transaction
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChangeKind {
+    /// The key was not present before the transaction
+    Inserted,
+    /// The key was present before and after the transaction. In a [`ChangeEvent`] its value may be
+    /// unchanged, if it was overwritten with an equal value
+    Updated,
+    /// The key was present before the transaction, and was removed by it
+    Removed,
+}
+
+/// A key changed by a committed transaction, or between two snapshots
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct KeyChange {
+    kind: ChangeKind,
+    key: Vec<u8>,
+}
+
+impl KeyChange {
+    pub(crate) fn new(kind: ChangeKind, key: Vec<u8>) -> Self {
+        Self { kind, key }
+    }
+
+    pub fn kind(&self) -> ChangeKind {
+        self.kind
+    }
+
+    /// Returns the key, which must be decoded with the key type of its table
+    pub fn key<'a, K: RedbKey + 'a>(&'a self) -> K::SelfType<'a> {
+        K::from_bytes(&self.key)
+    }
+
+    /// Returns the serialized key
+    pub fn key_bytes(&self) -> &[u8] {
+        &self.key
+    }
+}
+
+/// The changes made to a subscribed range by a committed transaction
+#[derive(Clone, Debug)]
+pub struct ChangeEvent {
+    transaction_id: u64,
+    changes: Vec<KeyChange>,
+}
+
+impl ChangeEvent {
+    /// Returns the id of the transaction which made the changes. Ids increase with each
+    /// transaction
+    pub fn transaction_id(&self) -> u64 {
+        self.transaction_id
+    }
+
+    /// Returns the changed keys, in ascending order
+    pub fn changes(&self) -> &[KeyChange] {
+        &self.changes
+    }
+}
+
+// Whether a key was present before a transaction, and whether it is present now
+#[derive(Clone, Copy)]
+struct PendingChange {
+    existed: bool,
+    exists: bool,
+}
+
+// The keys changed by an uncommitted transaction, for tables which have subscribers
+#[derive(Default)]
+pub(crate) struct ChangeLog {
+    tables: HashMap<String, BTreeMap<Vec<u8>, PendingChange>>,
+}
+
+impl ChangeLog {
+    pub(crate) fn record(&mut self, table: &str, key: &[u8], existed: bool, exists: bool) {
+        let keys = match self.tables.get_mut(table) {
+            Some(keys) => keys,
+            None => self.tables.entry(table.to_string()).or_default(),
+        };
+        keys.entry(key.to_vec())
+            .or_insert(PendingChange { existed, exists })
+            .exists = exists;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.tables.clear();
+    }
+}
+
+struct Subscription {
+    table: String,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    compare: fn(&[u8], &[u8]) -> Ordering,
+    sender: UnboundedSender<ChangeEvent>,
+}
+
+impl Subscription {
+    fn contains(&self, key: &[u8]) -> bool {
+        let after_start = match &self.start {
+            Bound::Included(start) => (self.compare)(key, start).is_ge(),
+            Bound::Excluded(start) => (self.compare)(key, start).is_gt(),
+            Bound::Unbounded => true,
+        };
+        let before_end = match &self.end {
+            Bound::Included(end) => (self.compare)(key, end).is_le(),
+            Bound::Excluded(end) => (self.compare)(key, end).is_lt(),
+            Bound::Unbounded => true,
+        };
+        after_start && before_end
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Subscriptions {
+    subscriptions: Mutex<Vec<Subscription>>,
+}
+
+impl Subscriptions {
+    pub(crate) fn subscribe<K: RedbKey>(
+        &self,
+        table: &str,
+        start: Bound<Vec<u8>>,
+        end: Bound<Vec<u8>>,
+    ) -> UnboundedReceiver<ChangeEvent> {
+        let (sender, receiver) = unbounded_channel();
+        self.subscriptions.lock().unwrap().push(Subscription {
+            table: table.to_string(),
+            start,
+            end,
+            compare: K::compare,
+            sender,
+        });
+        receiver
+    }
+
+    pub(crate) fn is_subscribed(&self, table: &str) -> bool {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.retain(|subscription| !subscription.sender.is_closed());
+        subscriptions
+            .iter()
+            .any(|subscription| subscription.table == table)
+    }
+
+    pub(crate) fn notify(&self, transaction_id: u64, log: ChangeLog) {
+        if log.tables.is_empty() {
+            return;
+        }
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.retain(|subscription| {
+            let keys = match log.tables.get(&subscription.table) {
+                Some(keys) => keys,
+                None => return true,
+            };
+            let mut changes: Vec<KeyChange> = keys
+                .iter()
+                .filter(|(key, _)| subscription.contains(key))
+                .filter_map(|(key, change)| {
+                    let kind = match (change.existed, change.exists) {
+                        (false, true) => ChangeKind::Inserted,
+                        (true, true) => ChangeKind::Updated,
+                        (true, false) => ChangeKind::Removed,
+                        (false, false) => return None,
+                    };
+                    Some(KeyChange::new(kind, key.clone()))
+                })
+                .collect();
+            if changes.is_empty() {
+                return true;
+            }
+            changes.sort_by(|a, b| (subscription.compare)(&a.key, &b.key));
+            // The receiver has been dropped
+            subscription
+                .sender
+                .send(ChangeEvent {
+                    transaction_id,
+                    changes,
+                })
+                .is_ok()
+        });
+    }
+}
@@ -1,13 +1,19 @@
+use crate::db::range_bytes;
+use crate::index::{check_index, index_primary_keys, IndexDefinition, IndexRange, OpenIndex};
+use crate::merge::MergeOperator;
 use crate::sealed::Sealed;
 use crate::tree_store::{
-    AccessGuardMut, Btree, BtreeDrain, BtreeDrainFilter, BtreeMut, BtreeRangeIter, Checksum,
-    PageHint, PageNumber, TransactionalMemory, MAX_VALUE_LENGTH,
+    AccessGuardMut, Btree, BtreeCursor, BtreeDrain, BtreeDrainFilter, BtreeLoader, BtreeMut,
+    BtreeRangeIter, Checksum, PageHint, PageNumber, TransactionalMemory, MAX_VALUE_LENGTH,
 };
-use crate::types::{RedbKey, RedbValue, RedbValueMutInPlace};
+use crate::types::{RedbKey, RedbKeyPrefix, RedbValue, RedbValueMutInPlace};
 use crate::Result;
-use crate::{AccessGuard, StorageError, WriteTransaction};
+use crate::{
+    AccessGuard, BulkLoadError, ReadTransaction, StorageError, TableError, WriteTransaction,
+};
 use std::borrow::Borrow;
-use std::ops::RangeBounds;
+use std::cmp::Ordering;
+use std::ops::{Bound, RangeBounds};
 use std::sync::{Arc, Mutex};
 
 /// Informational storage stats about a table
@@ -59,6 +65,11 @@ pub struct Table<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> {
     name: String,
     transaction: &'txn WriteTransaction<'db>,
     tree: BtreeMut<'txn, K, V>,
+    // True if the table has subscribers, which should be notified of changes
+    record_changes: bool,
+    // The registered indexes of the table
+    indexes: Vec<Box<dyn OpenIndex<K, V> + 'txn>>,
+    merge_operator: Option<MergeOperator<V>>,
 }
 
 impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> Table<'db, 'txn, K, V> {
@@ -73,7 +84,69 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> Table<'db, 'txn, K
             name: name.to_string(),
             transaction,
             tree: BtreeMut::new(table_root, mem, freed_pages),
+            record_changes: transaction.records_changes(name),
+            indexes: vec![],
+            merge_operator: None,
+        }
+    }
+
+    pub(crate) fn with_merge_operator(mut self, merge_operator: Option<MergeOperator<V>>) -> Self {
+        self.merge_operator = merge_operator;
+        self
+    }
+
+    pub(crate) fn add_index(&mut self, index: Box<dyn OpenIndex<K, V> + 'txn>) {
+        self.indexes.push(index);
+    }
+
+    // Records the removal of the entries in `range` which match `predicate`, and removes them
+    // from the indexes
+    fn record_removed<'a, KR>(
+        &mut self,
+        range: &(impl RangeBounds<KR> + 'a),
+        predicate: impl for<'f> Fn(K::SelfType<'f>, V::SelfType<'f>) -> bool,
+    ) -> Result
+    where
+        K: 'a,
+        KR: Borrow<K::SelfType<'a>> + 'a,
+    {
+        if !self.record_changes && self.indexes.is_empty() {
+            return Ok(());
+        }
+        for entry in self.tree.range(range)? {
+            let entry = entry?;
+            if predicate(entry.key(), entry.value()) {
+                let key = entry.key_data();
+                if self.record_changes {
+                    self.transaction
+                        .record_change(&self.name, &key, true, false);
+                }
+                for index in self.indexes.iter_mut() {
+                    index.remove(&key, &entry.value())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `index` contains exactly the entries of the table, and rebuilds it if not
+    ///
+    /// Returns `true` if the index was stale and has been rebuilt
+    pub fn check_index<I: RedbKey + 'static>(
+        &mut self,
+        index: IndexDefinition<K, V, I>,
+    ) -> Result<bool, TableError> {
+        if let Some(open) = self
+            .indexes
+            .iter_mut()
+            .find(|open| open.name() == index.name())
+        {
+            return Ok(open.check(&self.tree)?);
         }
+        let mut table = self
+            .transaction
+            .open_multimap_table(index.multimap_table())?;
+        Ok(check_index(&mut table, index.extractor(), &self.tree)?)
     }
 
     #[allow(dead_code)]
@@ -125,6 +198,7 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> Table<'db, 'txn, K
         K: 'a,
         KR: Borrow<K::SelfType<'a>> + 'a,
     {
+        self.record_removed(&range, |_, _| true)?;
         self.tree.drain(&range).map(Drain::new)
     }
 
@@ -141,11 +215,27 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> Table<'db, 'txn, K
         K: 'a,
         KR: Borrow<K::SelfType<'a>> + 'a,
     {
+        self.record_removed(&range, &predicate)?;
         self.tree
             .drain_filter(&range, predicate)
             .map(DrainFilter::new)
     }
 
+    /// Removes all entries in the specified range, and returns the number removed
+    ///
+    /// Unlike [`Table::drain`], the removed entries are not returned. Subtrees which lie entirely
+    /// within the range are freed without reading their entries, unless the table has indexes or
+    /// change subscribers which must be told of each removal
+    pub fn remove_range<'a, KR>(&mut self, range: impl RangeBounds<KR> + 'a) -> Result<u64>
+    where
+        K: 'a,
+        KR: Borrow<K::SelfType<'a>> + 'a,
+    {
+        self.record_removed(&range, |_, _| true)?;
+        let (start, end) = range_bytes::<K, KR>(&range);
+        self.tree.remove_range(as_slice(&start), as_slice(&end))
+    }
+
     /// Insert mapping of the given key to the given value
     ///
     /// Returns the old value, if the key was present in the table
@@ -162,7 +252,138 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> Table<'db, 'txn, K
         if key_len > MAX_VALUE_LENGTH {
             return Err(StorageError::ValueTooLarge(key_len));
         }
-        self.tree.insert(key.borrow(), value.borrow())
+        let old = self.tree.insert(key.borrow(), value.borrow())?;
+        if self.record_changes {
+            let key_bytes = K::as_bytes(key.borrow());
+            self.transaction
+                .record_change(&self.name, key_bytes.as_ref(), old.is_some(), true);
+        }
+        if !self.indexes.is_empty() {
+            let key_bytes = K::as_bytes(key.borrow());
+            for index in self.indexes.iter_mut() {
+                if let Some(ref old) = old {
+                    index.remove(key_bytes.as_ref(), &old.value())?;
+                }
+                index.insert(key_bytes.as_ref(), value.borrow())?;
+            }
+        }
+        Ok(old)
+    }
+
+    /// Inserts all the given entries
+    ///
+    /// The entries are sorted by key first, so that consecutive keys which land in the same page
+    /// are inserted without walking the tree again. If a key is given more than once, the last
+    /// value is kept
+    pub fn insert_many<'k, 'v>(
+        &mut self,
+        entries: impl IntoIterator<Item = (impl Borrow<K::SelfType<'k>>, impl Borrow<V::SelfType<'v>>)>,
+    ) -> Result
+    where
+        K: 'k,
+        V: 'v,
+    {
+        let mut entries: Vec<_> = entries.into_iter().collect();
+        // Changes and index updates must be recorded for each insertion
+        if self.record_changes || !self.indexes.is_empty() {
+            entries.sort_by(|(a, _), (b, _)| {
+                K::compare(
+                    K::as_bytes(a.borrow()).as_ref(),
+                    K::as_bytes(b.borrow()).as_ref(),
+                )
+            });
+            for (key, value) in entries {
+                self.insert(key, value)?;
+            }
+            return Ok(());
+        }
+
+        let mut encoded = Vec::with_capacity(entries.len());
+        for (key, value) in entries.iter() {
+            let key_bytes = K::as_bytes(key.borrow());
+            let value_bytes = V::as_bytes(value.borrow());
+            if value_bytes.as_ref().len() > MAX_VALUE_LENGTH {
+                return Err(StorageError::ValueTooLarge(value_bytes.as_ref().len()));
+            }
+            if key_bytes.as_ref().len() > MAX_VALUE_LENGTH {
+                return Err(StorageError::ValueTooLarge(key_bytes.as_ref().len()));
+            }
+            encoded.push((key_bytes, value_bytes));
+        }
+        // The sort is stable, so the last of any duplicate keys is inserted last
+        encoded.sort_by(|(a, _), (b, _)| K::compare(a.as_ref(), b.as_ref()));
+        self.tree.insert_many(
+            encoded
+                .iter()
+                .map(|(key, value)| (key.as_ref(), value.as_ref())),
+        )
+    }
+
+    // Loads `entries`, which must be in ascending key order, into the table, which must be empty.
+    // If an entry can't be loaded, the entries before it are kept
+    pub(crate) fn bulk_load<'k, 'v>(
+        &mut self,
+        fill_factor: f32,
+        entries: impl IntoIterator<Item = (impl Borrow<K::SelfType<'k>>, impl Borrow<V::SelfType<'v>>)>,
+    ) -> Result<u64, BulkLoadError>
+    where
+        K: 'k,
+        V: 'v,
+    {
+        if !(fill_factor > 0.0 && fill_factor <= 1.0) {
+            return Err(BulkLoadError::InvalidFillFactor(fill_factor));
+        }
+        if !self.is_empty()? {
+            return Err(BulkLoadError::TableNotEmpty(self.name.clone()));
+        }
+        let mut loader = self.tree.bulk_loader(fill_factor);
+        let mut loaded = 0;
+        let mut result = Ok(());
+        for (key, value) in entries {
+            let key_bytes = K::as_bytes(key.borrow());
+            let value_bytes = V::as_bytes(value.borrow());
+            let (key_bytes, value_bytes) = (key_bytes.as_ref(), value_bytes.as_ref());
+            if let Some(last) = loader.last_key() {
+                if K::compare(last, key_bytes) != Ordering::Less {
+                    result = Err(BulkLoadError::UnsortedInput {
+                        table: self.name.clone(),
+                        position: loaded,
+                    });
+                    break;
+                }
+            }
+            if let Err(err) = self.load_entry(&mut loader, key_bytes, value_bytes, value.borrow()) {
+                result = Err(err.into());
+                break;
+            }
+            loaded += 1;
+        }
+        // Always install the loaded pages, so that they're not leaked
+        self.tree.finish_bulk_load(loader)?;
+        result.map(|_| loaded)
+    }
+
+    fn load_entry(
+        &mut self,
+        loader: &mut BtreeLoader<'txn, K, V>,
+        key: &[u8],
+        value_bytes: &[u8],
+        value: &V::SelfType<'_>,
+    ) -> Result {
+        if value_bytes.len() > MAX_VALUE_LENGTH {
+            return Err(StorageError::ValueTooLarge(value_bytes.len()));
+        }
+        if key.len() > MAX_VALUE_LENGTH {
+            return Err(StorageError::ValueTooLarge(key.len()));
+        }
+        loader.push(key, value_bytes)?;
+        if self.record_changes {
+            self.transaction.record_change(&self.name, key, false, true);
+        }
+        for index in self.indexes.iter_mut() {
+            index.insert(key, value)?;
+        }
+        Ok(())
     }
 
     /// Removes the given key
@@ -175,13 +396,116 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> Table<'db, 'txn, K
     where
         K: 'a,
     {
-        self.tree.remove(key.borrow())
+        let old = self.tree.remove(key.borrow())?;
+        if self.record_changes && old.is_some() {
+            let key_bytes = K::as_bytes(key.borrow());
+            self.transaction
+                .record_change(&self.name, key_bytes.as_ref(), true, false);
+        }
+        if let Some(ref old) = old {
+            let key_bytes = K::as_bytes(key.borrow());
+            for index in self.indexes.iter_mut() {
+                index.remove(key_bytes.as_ref(), &old.value())?;
+            }
+        }
+        Ok(old)
+    }
+
+    /// Replaces the value of `key` with the result of `f`, which is passed the current value, if
+    /// there is one. If `f` returns `None`, the key is removed
+    ///
+    /// The tree is descended once: the pages read to find the current value are reused when they
+    /// have to be copied, and values in pages written by this transaction are updated in place
+    /// when they fit. Returns the old value, if the key was present in the table
+    pub fn update<'k, 'v, R: Borrow<V::SelfType<'v>>>(
+        &mut self,
+        key: impl Borrow<K::SelfType<'k>>,
+        f: impl for<'f> FnOnce(Option<V::SelfType<'f>>) -> Option<R>,
+    ) -> Result<Option<AccessGuard<'_, V>>>
+    where
+        V: 'v,
+    {
+        self.update_bytes(key.borrow(), |old| {
+            f(old.map(V::from_bytes)).map(|new| V::as_bytes(new.borrow()).as_ref().to_vec())
+        })
+    }
+
+    /// Inserts `value` if `key` is not present, and otherwise replaces the existing value with the
+    /// result of `f`
+    ///
+    /// Returns the old value, if the key was present in the table
+    pub fn upsert<'k, 'v, R: Borrow<V::SelfType<'v>>>(
+        &mut self,
+        key: impl Borrow<K::SelfType<'k>>,
+        value: R,
+        f: impl for<'f> FnOnce(V::SelfType<'f>) -> R,
+    ) -> Result<Option<AccessGuard<'_, V>>>
+    where
+        V: 'v,
+    {
+        self.update(key, |old| match old {
+            Some(old) => Some(f(old)),
+            None => Some(value),
+        })
+    }
+
+    /// Combines the value of `key` with `operand`, using the merge operator of the table
+    /// definition. If the key is not present, the operator is passed `None`
+    ///
+    /// Returns the old value, if the key was present in the table, or
+    /// [`StorageError::NoMergeOperator`] if the table definition has no merge operator
+    pub fn merge<'k, 'v>(
+        &mut self,
+        key: impl Borrow<K::SelfType<'k>>,
+        operand: impl Borrow<V::SelfType<'v>>,
+    ) -> Result<Option<AccessGuard<'_, V>>>
+    where
+        V: 'v,
+    {
+        let merge_operator = self
+            .merge_operator
+            .ok_or_else(|| StorageError::NoMergeOperator(self.name.clone()))?;
+        self.update_bytes(key.borrow(), |old| {
+            Some(merge_operator.apply(old, operand.borrow()))
+        })
+    }
+
+    fn update_bytes(
+        &mut self,
+        key: &K::SelfType<'_>,
+        f: impl FnOnce(Option<&[u8]>) -> Option<Vec<u8>>,
+    ) -> Result<Option<AccessGuard<'_, V>>> {
+        let key_bytes = K::as_bytes(key);
+        let key_bytes = key_bytes.as_ref();
+        if key_bytes.len() > MAX_VALUE_LENGTH {
+            return Err(StorageError::ValueTooLarge(key_bytes.len()));
+        }
+        let (old, new) = self.tree.update(key, f)?;
+        if old.is_none() && new.is_none() {
+            return Ok(None);
+        }
+        if self.record_changes {
+            self.transaction
+                .record_change(&self.name, key_bytes, old.is_some(), new.is_some());
+        }
+        for index in self.indexes.iter_mut() {
+            if let Some(ref old) = old {
+                index.remove(key_bytes, &old.value())?;
+            }
+            if let Some(ref new) = new {
+                index.insert(key_bytes, &V::from_bytes(new))?;
+            }
+        }
+        Ok(old)
     }
 }
 
 impl<'db, 'txn, K: RedbKey + 'static, V: RedbValueMutInPlace + 'static> Table<'db, 'txn, K, V> {
     /// Reserve space to insert a key-value pair
     /// The returned reference will have length equal to value_length
+    ///
+    /// Returns [`StorageError::IndexedTable`] if the table has registered indexes, since they cannot
+    /// be updated with a value which has not been written yet
     pub fn insert_reserve<'a>(
         &mut self,
         key: impl Borrow<K::SelfType<'a>>,
@@ -190,6 +514,9 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValueMutInPlace + 'static> Table<'d
     where
         K: 'a,
     {
+        if !self.indexes.is_empty() {
+            return Err(StorageError::IndexedTable(self.name.clone()));
+        }
         if value_length as usize > MAX_VALUE_LENGTH {
             return Err(StorageError::ValueTooLarge(value_length as usize));
         }
@@ -197,6 +524,12 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValueMutInPlace + 'static> Table<'d
         if key_len > MAX_VALUE_LENGTH {
             return Err(StorageError::ValueTooLarge(key_len));
         }
+        if self.record_changes {
+            let existed = self.tree.get(key.borrow())?.is_some();
+            let key_bytes = K::as_bytes(key.borrow());
+            self.transaction
+                .record_change(&self.name, key_bytes.as_ref(), existed, true);
+        }
         self.tree.insert_reserve(key.borrow(), value_length)
     }
 }
@@ -211,6 +544,18 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadableTable<K, V
         self.tree.get(key.borrow())
     }
 
+    fn get_many<'a>(
+        &self,
+        keys: impl IntoIterator<Item = impl Borrow<K::SelfType<'a>>>,
+    ) -> Result<Vec<Option<AccessGuard<'_, V>>>>
+    where
+        K: 'a,
+    {
+        let keys: Vec<_> = keys.into_iter().collect();
+        let keys: Vec<_> = keys.iter().map(|key| K::as_bytes(key.borrow())).collect();
+        self.tree.get_many(&keys)
+    }
+
     fn range<'a, KR>(&self, range: impl RangeBounds<KR> + 'a) -> Result<Range<K, V>>
     where
         K: 'a,
@@ -219,6 +564,42 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadableTable<K, V
         self.tree.range(&range).map(Range::new)
     }
 
+    fn prefix<'a, P: RedbValue + 'a>(
+        &self,
+        prefix: impl Borrow<P::SelfType<'a>>,
+    ) -> Result<Range<'_, K, V>>
+    where
+        K: RedbKeyPrefix<P>,
+    {
+        self.tree.prefix(prefix.borrow()).map(Range::new)
+    }
+
+    fn cursor(&self) -> Result<Cursor<'_, K, V>> {
+        self.tree.cursor().map(Cursor::new)
+    }
+
+    fn get_by_index<'a, I: RedbKey + 'static>(
+        &self,
+        index: IndexDefinition<K, V, I>,
+        key: impl Borrow<I::SelfType<'a>>,
+    ) -> Result<IndexRange<'_, K, V>, TableError> {
+        let key = I::as_bytes(key.borrow());
+        let bound = Bound::Included(key.as_ref());
+        self.index_range_bytes(index, bound, bound)
+    }
+
+    fn index_range<'a, I: RedbKey + 'static, KR>(
+        &self,
+        index: IndexDefinition<K, V, I>,
+        range: impl RangeBounds<KR> + 'a,
+    ) -> Result<IndexRange<'_, K, V>, TableError>
+    where
+        KR: Borrow<I::SelfType<'a>> + 'a,
+    {
+        let (start, end) = range_bytes::<I, KR>(&range);
+        self.index_range_bytes(index, as_slice(&start), as_slice(&end))
+    }
+
     fn stats(&self) -> Result<TableStats> {
         let tree_stats = self.tree.stats()?;
 
@@ -239,6 +620,48 @@ impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadableTable<K, V
     fn is_empty(&self) -> Result<bool> {
         self.len().map(|x| x == 0)
     }
+
+    fn range_len<'a, KR>(&self, range: impl RangeBounds<KR> + 'a) -> Result<u64>
+    where
+        K: 'a,
+        KR: Borrow<K::SelfType<'a>> + 'a,
+    {
+        self.tree.range_len(&range)
+    }
+
+    fn nth(&self, n: u64) -> Result<Option<(AccessGuard<'_, K>, AccessGuard<'_, V>)>> {
+        self.tree.nth(n)
+    }
+
+    fn rank<'a>(&self, key: impl Borrow<K::SelfType<'a>>) -> Result<u64>
+    where
+        K: 'a,
+    {
+        self.tree.rank(key.borrow())
+    }
+}
+
+impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> Table<'db, 'txn, K, V> {
+    fn index_range_bytes<I: RedbKey + 'static>(
+        &self,
+        index: IndexDefinition<K, V, I>,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Result<IndexRange<'_, K, V>, TableError> {
+        let keys = match self.indexes.iter().find(|open| open.name() == index.name()) {
+            Some(open) => open.primary_keys(start, end)?,
+            None => {
+                let table = self
+                    .transaction
+                    .open_multimap_table(index.multimap_table())?;
+                index_primary_keys::<K, I>(&table, start, end)?
+            }
+        };
+        let tree = &self.tree;
+        Ok(IndexRange::new(keys, move |key| {
+            tree.get(&K::from_bytes(key))
+        }))
+    }
 }
 
 impl<K: RedbKey, V: RedbValue> Sealed for Table<'_, '_, K, V> {}
@@ -255,6 +678,17 @@ pub trait ReadableTable<K: RedbKey + 'static, V: RedbValue + 'static>: Sealed {
     where
         K: 'a;
 
+    /// Returns the values corresponding to the given keys, in the same order
+    ///
+    /// The keys are looked up in sorted order, so that pages shared by consecutive keys are only
+    /// read once
+    fn get_many<'a>(
+        &self,
+        keys: impl IntoIterator<Item = impl Borrow<K::SelfType<'a>>>,
+    ) -> Result<Vec<Option<AccessGuard<'_, V>>>>
+    where
+        K: 'a;
+
     /// Returns a double-ended iterator over a range of elements in the table
     ///
     /// # Examples
@@ -262,13 +696,10 @@ pub trait ReadableTable<K: RedbKey + 'static, V: RedbValue + 'static>: Sealed {
     /// Usage:
     /// ```rust
     /// use redb::*;
-    /// # use tempfile::NamedTempFile;
     /// const TABLE: TableDefinition<&str, u64> = TableDefinition::new("my_data");
     ///
     /// # fn main() -> Result<(), Error> {
-    /// # let tmpfile: NamedTempFile = NamedTempFile::new().unwrap();
-    /// # let filename = tmpfile.path();
-    /// let db = Database::create(filename)?;
+    /// let db = Database::builder().create_with_backend(InMemoryBackend::new())?;
     /// let write_txn = db.begin_write()?;
     /// {
     ///     let mut table = write_txn.open_table(TABLE)?;
@@ -292,6 +723,49 @@ pub trait ReadableTable<K: RedbKey + 'static, V: RedbValue + 'static>: Sealed {
         K: 'a,
         KR: Borrow<K::SelfType<'a>> + 'a;
 
+    /// Returns a double-ended iterator over the elements whose keys start with `prefix`.
+    /// For tuple keys, `prefix` is the first element of the tuple
+    fn prefix<'a, P: RedbValue + 'a>(
+        &self,
+        prefix: impl Borrow<P::SelfType<'a>>,
+    ) -> Result<Range<'_, K, V>>
+    where
+        K: RedbKeyPrefix<P>;
+
+    /// Returns a cursor over the table, which can be moved to any key and then stepped in either
+    /// direction. The cursor is initially not positioned on any entry
+    ///
+    /// # Examples
+    ///
+    /// Usage:
+    /// ```rust
+    /// use redb::*;
+    /// const TABLE: TableDefinition<u64, u64> = TableDefinition::new("my_data");
+    ///
+    /// # fn main() -> Result<(), Error> {
+    /// let db = Database::builder().create_with_backend(InMemoryBackend::new())?;
+    /// let write_txn = db.begin_write()?;
+    /// {
+    ///     let mut table = write_txn.open_table(TABLE)?;
+    ///     table.insert(1, 10)?;
+    ///     table.insert(3, 30)?;
+    ///     table.insert(5, 50)?;
+    /// }
+    /// write_txn.commit()?;
+    ///
+    /// let read_txn = db.begin_read()?;
+    /// let table = read_txn.open_table(TABLE)?;
+    /// let mut cursor = table.cursor()?;
+    /// let (key, _) = cursor.seek(2)?.unwrap();
+    /// assert_eq!(3, key.value());
+    /// let (key, _) = cursor.prev()?.unwrap();
+    /// assert_eq!(1, key.value());
+    /// assert!(cursor.prev()?.is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn cursor(&self) -> Result<Cursor<'_, K, V>>;
+
     /// Retrieves information about storage usage for the table
     fn stats(&self) -> Result<TableStats>;
 
@@ -301,15 +775,59 @@ pub trait ReadableTable<K: RedbKey + 'static, V: RedbValue + 'static>: Sealed {
     /// Returns `true` if the table is empty
     fn is_empty(&self) -> Result<bool>;
 
+    /// Returns the number of entries in `range`
+    fn range_len<'a, KR>(&self, range: impl RangeBounds<KR> + 'a) -> Result<u64>
+    where
+        K: 'a,
+        KR: Borrow<K::SelfType<'a>> + 'a;
+
+    /// Returns the entry at position `n` in key order, or `None` if the table has `n` or fewer
+    /// entries
+    fn nth(&self, n: u64) -> Result<Option<(AccessGuard<'_, K>, AccessGuard<'_, V>)>>;
+
+    /// Returns the number of entries with keys less than `key`, which is the position of `key` if
+    /// it's in the table
+    fn rank<'a>(&self, key: impl Borrow<K::SelfType<'a>>) -> Result<u64>
+    where
+        K: 'a;
+
     /// Returns a double-ended iterator over all elements in the table
     fn iter(&self) -> Result<Range<K, V>> {
         self.range::<K::SelfType<'_>>(..)
     }
+
+    /// Returns a double-ended iterator over the entries whose index key in `index` is `key`, in
+    /// key order
+    fn get_by_index<'a, I: RedbKey + 'static>(
+        &self,
+        index: IndexDefinition<K, V, I>,
+        key: impl Borrow<I::SelfType<'a>>,
+    ) -> Result<IndexRange<'_, K, V>, TableError>;
+
+    /// Returns a double-ended iterator over the entries whose index key in `index` is in
+    /// `range`, ordered by index key and then by key
+    fn index_range<'a, I: RedbKey + 'static, KR>(
+        &self,
+        index: IndexDefinition<K, V, I>,
+        range: impl RangeBounds<KR> + 'a,
+    ) -> Result<IndexRange<'_, K, V>, TableError>
+    where
+        KR: Borrow<I::SelfType<'a>> + 'a;
+}
+
+fn as_slice(bound: &Bound<Vec<u8>>) -> Bound<&[u8]> {
+    match bound {
+        Bound::Included(data) => Bound::Included(data.as_slice()),
+        Bound::Excluded(data) => Bound::Excluded(data.as_slice()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
 }
 
 /// A read-only table
 pub struct ReadOnlyTable<'txn, K: RedbKey + 'static, V: RedbValue + 'static> {
     tree: Btree<'txn, K, V>,
+    // The transaction the table was opened in, which is used to open its indexes
+    transaction: Option<&'txn ReadTransaction<'txn>>,
 }
 
 impl<'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadOnlyTable<'txn, K, V> {
@@ -320,8 +838,31 @@ impl<'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadOnlyTable<'txn, K,
     ) -> Result<ReadOnlyTable<'txn, K, V>> {
         Ok(ReadOnlyTable {
             tree: Btree::new(root_page, hint, mem)?,
+            transaction: None,
         })
     }
+
+    pub(crate) fn with_transaction(mut self, transaction: &'txn ReadTransaction<'txn>) -> Self {
+        self.transaction = Some(transaction);
+        self
+    }
+
+    fn index_range_bytes<I: RedbKey + 'static>(
+        &self,
+        index: IndexDefinition<K, V, I>,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Result<IndexRange<'_, K, V>, TableError> {
+        let transaction = self
+            .transaction
+            .ok_or_else(|| TableError::TableDoesNotExist(index.name().to_string()))?;
+        let table = transaction.open_multimap_table(index.multimap_table())?;
+        let keys = index_primary_keys::<K, I>(&table, start, end)?;
+        let tree = &self.tree;
+        Ok(IndexRange::new(keys, move |key| {
+            tree.get(&K::from_bytes(key))
+        }))
+    }
 }
 
 impl<'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadableTable<K, V>
@@ -334,6 +875,18 @@ impl<'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadableTable<K, V>
         self.tree.get(key.borrow())
     }
 
+    fn get_many<'a>(
+        &self,
+        keys: impl IntoIterator<Item = impl Borrow<K::SelfType<'a>>>,
+    ) -> Result<Vec<Option<AccessGuard<'_, V>>>>
+    where
+        K: 'a,
+    {
+        let keys: Vec<_> = keys.into_iter().collect();
+        let keys: Vec<_> = keys.iter().map(|key| K::as_bytes(key.borrow())).collect();
+        self.tree.get_many(&keys)
+    }
+
     fn range<'a, KR>(&self, range: impl RangeBounds<KR> + 'a) -> Result<Range<K, V>>
     where
         K: 'a,
@@ -342,6 +895,42 @@ impl<'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadableTable<K, V>
         self.tree.range(&range).map(Range::new)
     }
 
+    fn prefix<'a, P: RedbValue + 'a>(
+        &self,
+        prefix: impl Borrow<P::SelfType<'a>>,
+    ) -> Result<Range<'_, K, V>>
+    where
+        K: RedbKeyPrefix<P>,
+    {
+        self.tree.prefix(prefix.borrow()).map(Range::new)
+    }
+
+    fn cursor(&self) -> Result<Cursor<'_, K, V>> {
+        self.tree.cursor().map(Cursor::new)
+    }
+
+    fn get_by_index<'a, I: RedbKey + 'static>(
+        &self,
+        index: IndexDefinition<K, V, I>,
+        key: impl Borrow<I::SelfType<'a>>,
+    ) -> Result<IndexRange<'_, K, V>, TableError> {
+        let key = I::as_bytes(key.borrow());
+        let bound = Bound::Included(key.as_ref());
+        self.index_range_bytes(index, bound, bound)
+    }
+
+    fn index_range<'a, I: RedbKey + 'static, KR>(
+        &self,
+        index: IndexDefinition<K, V, I>,
+        range: impl RangeBounds<KR> + 'a,
+    ) -> Result<IndexRange<'_, K, V>, TableError>
+    where
+        KR: Borrow<I::SelfType<'a>> + 'a,
+    {
+        let (start, end) = range_bytes::<I, KR>(&range);
+        self.index_range_bytes(index, as_slice(&start), as_slice(&end))
+    }
+
     fn stats(&self) -> Result<TableStats> {
         let tree_stats = self.tree.stats()?;
 
@@ -362,6 +951,25 @@ impl<'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadableTable<K, V>
     fn is_empty(&self) -> Result<bool> {
         self.len().map(|x| x == 0)
     }
+
+    fn range_len<'a, KR>(&self, range: impl RangeBounds<KR> + 'a) -> Result<u64>
+    where
+        K: 'a,
+        KR: Borrow<K::SelfType<'a>> + 'a,
+    {
+        self.tree.range_len(&range)
+    }
+
+    fn nth(&self, n: u64) -> Result<Option<(AccessGuard<'_, K>, AccessGuard<'_, V>)>> {
+        self.tree.nth(n)
+    }
+
+    fn rank<'a>(&self, key: impl Borrow<K::SelfType<'a>>) -> Result<u64>
+    where
+        K: 'a,
+    {
+        self.tree.rank(key.borrow())
+    }
 }
 
 impl<K: RedbKey, V: RedbValue> Sealed for ReadOnlyTable<'_, K, V> {}
@@ -498,3 +1106,76 @@ impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> DoubleEndedIterator for R
         })
     }
 }
+
+/// A position in a table, which can be moved to any key and then stepped forwards or backwards.
+/// Methods which move the cursor return the entry it was moved to, or `None` if there is no such
+/// entry, in which case the cursor is no longer positioned
+pub struct Cursor<'a, K: RedbKey + 'static, V: RedbValue + 'static> {
+    inner: BtreeCursor<'a, K, V>,
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Cursor<'a, K, V> {
+    fn new(inner: BtreeCursor<'a, K, V>) -> Self {
+        Self { inner }
+    }
+
+    /// Moves to the first entry with a key greater than or equal to `key`
+    pub fn seek<'k>(
+        &mut self,
+        key: impl Borrow<K::SelfType<'k>>,
+    ) -> Result<Option<(AccessGuard<'a, K>, AccessGuard<'a, V>)>>
+    where
+        K: 'k,
+    {
+        self.inner.seek(K::as_bytes(key.borrow()).as_ref())?;
+        Ok(self.current())
+    }
+
+    /// Moves to the last entry with a key less than or equal to `key`
+    pub fn seek_for_prev<'k>(
+        &mut self,
+        key: impl Borrow<K::SelfType<'k>>,
+    ) -> Result<Option<(AccessGuard<'a, K>, AccessGuard<'a, V>)>>
+    where
+        K: 'k,
+    {
+        self.inner
+            .seek_for_prev(K::as_bytes(key.borrow()).as_ref())?;
+        Ok(self.current())
+    }
+
+    /// Moves to the first entry in the table
+    pub fn seek_first(&mut self) -> Result<Option<(AccessGuard<'a, K>, AccessGuard<'a, V>)>> {
+        self.inner.seek_first()?;
+        Ok(self.current())
+    }
+
+    /// Moves to the last entry in the table
+    pub fn seek_last(&mut self) -> Result<Option<(AccessGuard<'a, K>, AccessGuard<'a, V>)>> {
+        self.inner.seek_last()?;
+        Ok(self.current())
+    }
+
+    /// Moves to the next entry. Does nothing if the cursor is not positioned
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<(AccessGuard<'a, K>, AccessGuard<'a, V>)>> {
+        self.inner.next()?;
+        Ok(self.current())
+    }
+
+    /// Moves to the previous entry. Does nothing if the cursor is not positioned
+    pub fn prev(&mut self) -> Result<Option<(AccessGuard<'a, K>, AccessGuard<'a, V>)>> {
+        self.inner.prev()?;
+        Ok(self.current())
+    }
+
+    /// Returns the entry at the current position, or `None` if the cursor is not positioned
+    pub fn current(&self) -> Option<(AccessGuard<'a, K>, AccessGuard<'a, V>)> {
+        self.inner.current().map(|entry| {
+            let (page, key_range, value_range) = entry.into_raw();
+            let key = AccessGuard::with_page(page.clone(), key_range);
+            let value = AccessGuard::with_page(page, value_range);
+            (key, value)
+        })
+    }
+}
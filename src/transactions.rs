@@ -1,15 +1,22 @@
+use crate::dump::{export_table, import_multimap_table, import_table, DumpReader};
 use crate::error::CommitError;
+use crate::multimap_table::{collections_equal, count_values, DynamicCollection};
 use crate::sealed::Sealed;
+use crate::subscriptions::ChangeLog;
 use crate::transaction_tracker::{SavepointId, TransactionId, TransactionTracker};
 use crate::tree_store::{
-    Btree, BtreeMut, Checksum, FreedPageList, FreedTableKey, InternalTableDefinition, PageHint,
-    PageNumber, SerializedSavepoint, TableTree, TableType, TransactionalMemory, MAX_VALUE_LENGTH,
+    btree_diff, Btree, BtreeMut, Checksum, CompactionCursor, FreedPageList, FreedTableKey,
+    InternalTableDefinition, PageHint, PageNumber, SerializedSavepoint, TableTree, TableType,
+    TransactionalMemory, MAX_VALUE_LENGTH,
 };
+use crate::ttl_table::expiry_table_name;
 use crate::types::{RedbKey, RedbValue};
 use crate::{
-    AccessGuard, Database, MultimapTable, MultimapTableDefinition, MultimapTableHandle, Range,
-    ReadOnlyMultimapTable, ReadOnlyTable, Result, Savepoint, SavepointError, StorageError, Table,
-    TableDefinition, TableError, TableHandle, UntypedMultimapTableHandle, UntypedTableHandle,
+    AccessGuard, BulkLoadError, Database, ImportError, ImportSchema, KeyChange, MultimapTable,
+    MultimapTableDefinition, MultimapTableHandle, Range, ReadOnlyMultimapTable, ReadOnlyTable,
+    ReadOnlyTtlTable, Result, Savepoint, SavepointError, StorageError, Table, TableDefinition,
+    TableError, TableHandle, TtlTable, TtlTableDefinition, UntypedMultimapTableHandle,
+    UntypedTableHandle,
 };
 #[cfg(feature = "logging")]
 use log::{info, warn};
@@ -17,11 +24,12 @@ use std::borrow::Borrow;
 use std::cmp::min;
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufWriter, Write};
 use std::marker::PhantomData;
 use std::ops::{RangeBounds, RangeFull};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::{panic, thread};
+use std::{mem, panic, ptr, thread};
 
 const NEXT_SAVEPOINT_TABLE: SystemTableDefinition<(), SavepointId> =
     SystemTableDefinition::new("next_savepoint_id");
@@ -131,6 +139,14 @@ impl DatabaseStats {
     }
 }
 
+/// Durability level of a commit
+///
+/// The guarantees of [Durability::Eventual], [Durability::Immediate] and [Durability::Paranoid]
+/// rely on [`StorageBackend::sync_data`](crate::StorageBackend::sync_data). Files in the uqbar VFS
+/// are synced by the VFS before it responds. A backend which cannot sync is rejected, unless
+/// [`Builder::set_allow_unsynced`](crate::Builder::set_allow_unsynced) is enabled, in which case a
+/// warning is logged and all commits behave like [Durability::None], except that pages are still
+/// freed
 #[derive(Copy, Clone, Debug)]
 #[non_exhaustive]
 pub enum Durability {
@@ -306,7 +322,7 @@ impl<'db> TableNamespace<'db> {
         &mut self,
         name: &str,
         table_type: TableType,
-    ) -> Result<Option<(PageNumber, Checksum)>, TableError> {
+    ) -> Result<InternalTableDefinition, TableError> {
         if let Some(location) = self.open_tables.get(name) {
             return Err(TableError::TableAlreadyOpen(name.to_string(), location));
         }
@@ -317,7 +333,7 @@ impl<'db> TableNamespace<'db> {
         self.open_tables
             .insert(name.to_string(), panic::Location::caller());
 
-        Ok(internal_table.get_root())
+        Ok(internal_table)
     }
 
     #[track_caller]
@@ -328,12 +344,23 @@ impl<'db> TableNamespace<'db> {
     ) -> Result<MultimapTable<'db, 'txn, K, V>, TableError> {
         #[cfg(feature = "logging")]
         info!("Opening multimap table: {}", definition);
-        let root = self.inner_open::<K, V>(definition.name(), TableType::Multimap)?;
+        let internal_table = self.inner_open::<K, V>(definition.name(), TableType::Multimap)?;
+        let root = internal_table.get_root();
+        // Tables written by file format version 1 don't store their number of values, so count
+        // them once. It's stored when the table is closed
+        let num_values = match internal_table.get_num_values() {
+            Some(num_values) => num_values,
+            None => count_values::<K, V>(root, transaction.mem).map_err(|err| {
+                self.open_tables.remove(definition.name());
+                err
+            })?,
+        };
         transaction.dirty.store(true, Ordering::Release);
 
         Ok(MultimapTable::new(
             definition.name(),
             root,
+            num_values,
             transaction.freed_pages.clone(),
             transaction.mem,
             transaction,
@@ -348,7 +375,9 @@ impl<'db> TableNamespace<'db> {
     ) -> Result<Table<'db, 'txn, K, V>, TableError> {
         #[cfg(feature = "logging")]
         info!("Opening table: {}", definition);
-        let root = self.inner_open::<K, V>(definition.name(), TableType::Normal)?;
+        let root = self
+            .inner_open::<K, V>(definition.name(), TableType::Normal)?
+            .get_root();
         transaction.dirty.store(true, Ordering::Release);
 
         Ok(Table::new(
@@ -357,7 +386,8 @@ impl<'db> TableNamespace<'db> {
             transaction.freed_pages.clone(),
             transaction.mem,
             transaction,
-        ))
+        )
+        .with_merge_operator(definition.merge_operator()))
     }
 
     pub(crate) fn close_table<K: RedbKey + 'static, V: RedbValue + 'static>(
@@ -369,6 +399,16 @@ impl<'db> TableNamespace<'db> {
         self.table_tree
             .stage_update_table_root(name, table.get_root());
     }
+
+    pub(crate) fn close_multimap_table<K: RedbKey + 'static, V: RedbValue + 'static>(
+        &mut self,
+        name: &str,
+        table: &BtreeMut<K, V>,
+        num_values: u64,
+    ) {
+        self.close_table(name, table);
+        self.table_tree.stage_update_num_values(name, num_values);
+    }
 }
 
 /// A read/write transaction
@@ -394,6 +434,8 @@ pub struct WriteTransaction<'db> {
     // Persistent savepoints created during this transaction
     created_persistent_savepoints: Mutex<HashSet<SavepointId>>,
     deleted_persistent_savepoints: Mutex<Vec<(SavepointId, TransactionId)>>,
+    // Keys changed in tables which have subscribers
+    changes: Mutex<ChangeLog>,
 }
 
 impl<'db> WriteTransaction<'db> {
@@ -436,6 +478,7 @@ impl<'db> WriteTransaction<'db> {
             durability: Durability::Immediate,
             created_persistent_savepoints: Mutex::new(Default::default()),
             deleted_persistent_savepoints: Mutex::new(vec![]),
+            changes: Default::default(),
         })
     }
 
@@ -602,9 +645,12 @@ impl<'db> WriteTransaction<'db> {
             self.transaction_id
         );
         // Restoring a savepoint that reverted a file format or checksum type change could corrupt
-        // the database
-        assert_eq!(self.db.get_memory().get_version(), savepoint.get_version());
+        // the database. Savepoints from older versions are fine, because they're still readable and
+        // the restore doesn't change the version
+        assert!(savepoint.get_version() <= self.db.get_memory().get_version());
         self.dirty.store(true, Ordering::Release);
+        // Changes made so far are reverted by the restore
+        self.changes.lock().unwrap().clear();
 
         let allocated_since_savepoint = self
             .mem
@@ -712,7 +758,11 @@ impl<'db> WriteTransaction<'db> {
         &'txn self,
         definition: TableDefinition<K, V>,
     ) -> Result<Table<'db, 'txn, K, V>, TableError> {
-        self.tables.lock().unwrap().open_table(self, definition)
+        let mut table = self.tables.lock().unwrap().open_table(self, definition)?;
+        for index in self.db.indexes().open(definition.name(), self)? {
+            table.add_index(index);
+        }
+        Ok(table)
     }
 
     /// Open the given table
@@ -729,6 +779,20 @@ impl<'db> WriteTransaction<'db> {
             .open_multimap_table(self, definition)
     }
 
+    /// Open the given table whose entries expire
+    ///
+    /// The table will be created if it does not exist
+    #[track_caller]
+    pub fn open_ttl_table<'txn, K: RedbKey + 'static, V: RedbValue + 'static>(
+        &'txn self,
+        definition: TtlTableDefinition<K, V>,
+    ) -> Result<TtlTable<'db, 'txn, K, V>, TableError> {
+        let table = self.open_table(TableDefinition::new(definition.name()))?;
+        let expiry_name = expiry_table_name(definition.name());
+        let expiry = self.open_multimap_table(MultimapTableDefinition::new(&expiry_name))?;
+        Ok(TtlTable::new(table, expiry))
+    }
+
     pub(crate) fn close_table<K: RedbKey + 'static, V: RedbValue + 'static>(
         &self,
         name: &str,
@@ -737,6 +801,18 @@ impl<'db> WriteTransaction<'db> {
         self.tables.lock().unwrap().close_table(name, table);
     }
 
+    pub(crate) fn close_multimap_table<K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        name: &str,
+        table: &BtreeMut<K, V>,
+        num_values: u64,
+    ) {
+        self.tables
+            .lock()
+            .unwrap()
+            .close_multimap_table(name, table, num_values);
+    }
+
     /// Delete the given table
     ///
     /// Returns a bool indicating whether the table existed
@@ -790,6 +866,91 @@ impl<'db> WriteTransaction<'db> {
             .map(|x| x.into_iter().map(UntypedMultimapTableHandle::new))
     }
 
+    /// Loads `entries` into the table of `definition`, which is created if it does not exist
+    ///
+    /// The table must be empty, and `entries` must be sorted in ascending key order without
+    /// duplicates. Rather than being inserted one at a time, the entries are written into full
+    /// pages, which is much faster for large inputs. If an entry is out of order, or can't be
+    /// loaded, the entries before it are kept. Returns the number of entries loaded
+    pub fn bulk_load<'k, 'v, K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        definition: TableDefinition<K, V>,
+        entries: impl IntoIterator<Item = (impl Borrow<K::SelfType<'k>>, impl Borrow<V::SelfType<'v>>)>,
+    ) -> Result<u64, BulkLoadError> {
+        self.bulk_load_with_fill_factor(definition, 1.0, entries)
+    }
+
+    /// Like [`WriteTransaction::bulk_load`], but fills each page only up to `fill_factor` of its
+    /// capacity, which must be greater than 0 and at most 1. Leaving free space in the pages
+    /// makes later inserts into the table less likely to split them
+    ///
+    /// Returns [`BulkLoadError::InvalidFillFactor`] if `fill_factor` is out of range
+    pub fn bulk_load_with_fill_factor<'k, 'v, K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        definition: TableDefinition<K, V>,
+        fill_factor: f32,
+        entries: impl IntoIterator<Item = (impl Borrow<K::SelfType<'k>>, impl Borrow<V::SelfType<'v>>)>,
+    ) -> Result<u64, BulkLoadError> {
+        self.open_table(definition)?.bulk_load(fill_factor, entries)
+    }
+
+    /// Imports the table with the name of `definition` from a dump made by
+    /// [`ReadTransaction::export_table`] or [`ReadTransaction::export`]. Its entries are inserted
+    /// into the table, which is created if it does not exist
+    ///
+    /// The key and value types recorded in the dump must match those of `definition`. Returns the
+    /// number of entries imported
+    pub fn import_table<K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        definition: TableDefinition<K, V>,
+        mut reader: impl BufRead,
+    ) -> Result<u64, ImportError> {
+        let mut reader = DumpReader::new(&mut reader);
+        reader.find_table(definition.name())?.check::<K, V>(false)?;
+        import_table(&mut self.open_table(definition)?, &mut reader)
+    }
+
+    /// Imports the multimap table with the name of `definition` from a dump. See
+    /// [`WriteTransaction::import_table`]
+    pub fn import_multimap_table<K: RedbKey + 'static, V: RedbKey + 'static>(
+        &self,
+        definition: MultimapTableDefinition<K, V>,
+        mut reader: impl BufRead,
+    ) -> Result<u64, ImportError> {
+        let mut reader = DumpReader::new(&mut reader);
+        reader.find_table(definition.name())?.check::<K, V>(true)?;
+        import_multimap_table(&mut self.open_multimap_table(definition)?, &mut reader)
+    }
+
+    /// Imports every table in a dump made by [`ReadTransaction::export`]. Each table must be
+    /// part of `schema`, which gives its key and value types
+    ///
+    /// Returns the number of entries imported
+    pub fn import(
+        &self,
+        mut reader: impl BufRead,
+        schema: &ImportSchema,
+    ) -> Result<u64, ImportError> {
+        let mut reader = DumpReader::new(&mut reader);
+        let mut entries = 0;
+        while let Some(header) = reader.next_table()? {
+            entries += schema.import(self, &header, &mut reader)?;
+        }
+        Ok(entries)
+    }
+
+    // Returns true if changes to the given table should be recorded with record_change()
+    pub(crate) fn records_changes(&self, table: &str) -> bool {
+        self.db.subscriptions().is_subscribed(table)
+    }
+
+    pub(crate) fn record_change(&self, table: &str, key: &[u8], existed: bool, exists: bool) {
+        self.changes
+            .lock()
+            .unwrap()
+            .record(table, key, existed, exists);
+    }
+
     /// Commit the transaction
     ///
     /// All writes performed in this transaction will be visible to future transactions, and are
@@ -820,6 +981,11 @@ impl<'db> WriteTransaction<'db> {
                 .deallocate_savepoint(*savepoint, *transaction);
         }
 
+        let changes = mem::take(&mut *self.changes.lock().unwrap());
+        self.db
+            .subscriptions()
+            .notify(self.transaction_id.0, changes);
+
         #[cfg(feature = "logging")]
         info!(
             "Finished commit of transaction id={:?}",
@@ -965,23 +1131,28 @@ impl<'db> WriteTransaction<'db> {
         Ok(())
     }
 
-    // Relocate pages to lower number regions/pages
-    // Returns true if a page(s) was moved
-    pub(crate) fn compact_pages(&mut self) -> Result<bool> {
+    // Relocate pages to lower number regions/pages, reading at most `max_pages` pages, and
+    // resuming from where the previous call with `cursor` stopped
+    // Returns true if a page(s) was moved, and true if all pages have been visited
+    pub(crate) fn compact_pages(
+        &mut self,
+        max_pages: usize,
+        cursor: &mut CompactionCursor,
+    ) -> Result<(bool, bool)> {
         let mut progress = false;
-        // Relocate the region tracker page
-        if self.mem.relocate_region_tracker()? {
+        let mut budget = max_pages;
+        // Relocate the region tracker page, at the start of each pass
+        if budget > 0 && cursor.at_start() && self.mem.relocate_region_tracker()? {
             progress = true;
+            budget -= 1;
         }
 
         // Relocate the btree pages
         let mut tables = self.tables.lock().unwrap();
         let table_tree = &mut tables.table_tree;
-        if table_tree.compact_tables()? {
-            progress = true;
-        }
+        let (relocated, finished) = table_tree.compact_tables(&mut budget, cursor)?;
 
-        Ok(progress)
+        Ok((progress || relocated, finished))
     }
 
     // NOTE: must be called before store_freed_pages() during commit, since this can create
@@ -1129,8 +1300,8 @@ impl<'db> ReadTransaction<'db> {
         mem: &'db TransactionalMemory,
         transaction_tracker: Arc<Mutex<TransactionTracker>>,
         transaction_id: TransactionId,
+        root_page: Option<(PageNumber, Checksum)>,
     ) -> Self {
-        let root_page = mem.get_data_root();
         Self {
             transaction_tracker,
             mem,
@@ -1139,6 +1310,14 @@ impl<'db> ReadTransaction<'db> {
         }
     }
 
+    pub(crate) fn transaction_id(&self) -> TransactionId {
+        self.transaction_id
+    }
+
+    pub(crate) fn root(&self) -> Option<(PageNumber, Checksum)> {
+        self.tree.get_root()
+    }
+
     /// Open the given table
     pub fn open_table<K: RedbKey + 'static, V: RedbValue + 'static>(
         &self,
@@ -1149,11 +1328,10 @@ impl<'db> ReadTransaction<'db> {
             .get_table::<K, V>(definition.name(), TableType::Normal)?
             .ok_or_else(|| TableError::TableDoesNotExist(definition.name().to_string()))?;
 
-        Ok(ReadOnlyTable::new(
-            header.get_root(),
-            PageHint::Clean,
-            self.mem,
-        )?)
+        Ok(
+            ReadOnlyTable::new(header.get_root(), PageHint::Clean, self.mem)?
+                .with_transaction(self),
+        )
     }
 
     /// Open the given table
@@ -1168,11 +1346,21 @@ impl<'db> ReadTransaction<'db> {
 
         Ok(ReadOnlyMultimapTable::new(
             header.get_root(),
+            header.get_num_values(),
             PageHint::Clean,
             self.mem,
         )?)
     }
 
+    /// Open the given table whose entries expire
+    pub fn open_ttl_table<K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        definition: TtlTableDefinition<K, V>,
+    ) -> Result<ReadOnlyTtlTable<'_, K, V>, TableError> {
+        let table = self.open_table(TableDefinition::new(definition.name()))?;
+        Ok(ReadOnlyTtlTable::new(table))
+    }
+
     /// List all the tables
     pub fn list_tables(&self) -> Result<impl Iterator<Item = UntypedTableHandle>> {
         self.tree
@@ -1186,6 +1374,173 @@ impl<'db> ReadTransaction<'db> {
             .list_tables(TableType::Multimap)
             .map(|x| x.into_iter().map(UntypedMultimapTableHandle::new))
     }
+
+    /// Returns the keys of the given table which differ between this snapshot and `newer`, in
+    /// ascending order. A table which does not exist in a snapshot is treated as empty
+    ///
+    /// Subtrees shared by both snapshots are skipped without being read, so this is much cheaper
+    /// than scanning both tables when few keys have changed. Use [`Database::begin_read_at`] to
+    /// compare against a persistent savepoint
+    ///
+    /// Panics if the transactions belong to different databases
+    pub fn diff_table<K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        newer: &ReadTransaction,
+        definition: TableDefinition<K, V>,
+    ) -> Result<Vec<KeyChange>, TableError> {
+        assert!(ptr::eq(self.mem, newer.mem));
+        let old_root = self.table_root::<K, V>(definition.name(), TableType::Normal)?;
+        let new_root = newer.table_root::<K, V>(definition.name(), TableType::Normal)?;
+        Ok(btree_diff::<K>(
+            old_root,
+            new_root,
+            self.mem,
+            K::fixed_width(),
+            V::fixed_width(),
+            |old, new| Ok(old == new),
+        )?)
+    }
+
+    /// Returns the keys of the given multimap table whose values differ between this snapshot and
+    /// `newer`, in ascending order. See [`ReadTransaction::diff_table`]
+    pub fn diff_multimap_table<K: RedbKey + 'static, V: RedbKey + 'static>(
+        &self,
+        newer: &ReadTransaction,
+        definition: MultimapTableDefinition<K, V>,
+    ) -> Result<Vec<KeyChange>, TableError> {
+        assert!(ptr::eq(self.mem, newer.mem));
+        let old_root = self.table_root::<K, V>(definition.name(), TableType::Multimap)?;
+        let new_root = newer.table_root::<K, V>(definition.name(), TableType::Multimap)?;
+        Ok(btree_diff::<K>(
+            old_root,
+            new_root,
+            self.mem,
+            K::fixed_width(),
+            DynamicCollection::<V>::fixed_width_with(V::fixed_width()),
+            |old, new| collections_equal::<V>(old, new, self.mem),
+        )?)
+    }
+
+    /// Returns the tables which were modified between this snapshot and `newer`, including those
+    /// which exist in only one of them. A table which was modified and then restored to its
+    /// previous contents may also be returned
+    ///
+    /// Panics if the transactions belong to different databases
+    pub fn changed_tables(
+        &self,
+        newer: &ReadTransaction,
+    ) -> Result<impl Iterator<Item = UntypedTableHandle>, TableError> {
+        self.changed_table_names(newer, TableType::Normal)
+            .map(|x| x.into_iter().map(UntypedTableHandle::new))
+    }
+
+    /// Returns the multimap tables which were modified between this snapshot and `newer`. See
+    /// [`ReadTransaction::changed_tables`]
+    pub fn changed_multimap_tables(
+        &self,
+        newer: &ReadTransaction,
+    ) -> Result<impl Iterator<Item = UntypedMultimapTableHandle>, TableError> {
+        self.changed_table_names(newer, TableType::Multimap)
+            .map(|x| x.into_iter().map(UntypedMultimapTableHandle::new))
+    }
+
+    /// Writes the given table to `writer`, as JSON lines which can be read by
+    /// [`WriteTransaction::import_table`]. Keys and values of the integer, float, `&str` and `()`
+    /// types are written as JSON values, and those of other types as the hex of their `RedbValue`
+    /// encoding, so the dump can be imported into a database with a different file format
+    ///
+    /// Returns the number of entries written
+    pub fn export_table<K: RedbKey + 'static, V: RedbValue + 'static>(
+        &self,
+        definition: TableDefinition<K, V>,
+        writer: impl Write,
+    ) -> Result<u64, TableError> {
+        self.export_tables(&[(definition.name(), TableType::Normal)], writer)
+    }
+
+    /// Writes the given multimap table to `writer`. See [`ReadTransaction::export_table`]
+    pub fn export_multimap_table<K: RedbKey + 'static, V: RedbKey + 'static>(
+        &self,
+        definition: MultimapTableDefinition<K, V>,
+        writer: impl Write,
+    ) -> Result<u64, TableError> {
+        self.export_tables(&[(definition.name(), TableType::Multimap)], writer)
+    }
+
+    /// Writes every table and multimap table to `writer`. See [`ReadTransaction::export_table`]
+    pub fn export(&self, writer: impl Write) -> Result<u64> {
+        let mut tables = vec![];
+        for table_type in [TableType::Normal, TableType::Multimap] {
+            for name in self.tree.list_tables(table_type)? {
+                tables.push((name, table_type));
+            }
+        }
+        tables.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let tables: Vec<(&str, TableType)> = tables
+            .iter()
+            .map(|(name, table_type)| (name.as_str(), *table_type))
+            .collect();
+        self.export_tables(&tables, writer)
+            .map_err(|e| e.into_storage_error_or_corrupted("Table tree corrupted"))
+    }
+
+    fn export_tables(
+        &self,
+        tables: &[(&str, TableType)],
+        writer: impl Write,
+    ) -> Result<u64, TableError> {
+        let mut writer = BufWriter::new(writer);
+        let mut entries = 0;
+        for (name, table_type) in tables {
+            let definition = self
+                .tree
+                .get_table_untyped(name, *table_type)?
+                .ok_or_else(|| TableError::TableDoesNotExist(name.to_string()))?;
+            entries += export_table(self.mem, name, &definition, &mut writer)?;
+        }
+        writer.flush().map_err(StorageError::from)?;
+        Ok(entries)
+    }
+
+    fn table_root<K: RedbKey, V: RedbValue>(
+        &self,
+        name: &str,
+        table_type: TableType,
+    ) -> Result<Option<PageNumber>, TableError> {
+        Ok(self
+            .tree
+            .get_table::<K, V>(name, table_type)?
+            .and_then(|definition| definition.get_root())
+            .map(|(page, _)| page))
+    }
+
+    fn changed_table_names(
+        &self,
+        newer: &ReadTransaction,
+        table_type: TableType,
+    ) -> Result<Vec<String>, TableError> {
+        assert!(ptr::eq(self.mem, newer.mem));
+        let mut names = self.tree.list_tables(table_type)?;
+        names.extend(newer.tree.list_tables(table_type)?);
+        names.sort();
+        names.dedup();
+        let mut changed = vec![];
+        for name in names {
+            let old_root = self
+                .tree
+                .get_table_untyped(&name, table_type)?
+                .map(|definition| definition.get_root());
+            let new_root = newer
+                .tree
+                .get_table_untyped(&name, table_type)?
+                .map(|definition| definition.get_root());
+            // Pages are copied on write, so an unmodified table keeps its root page
+            if old_root != new_root {
+                changed.push(name);
+            }
+        }
+        Ok(changed)
+    }
 }
 
 impl<'a> Drop for ReadTransaction<'a> {
@@ -1205,8 +1560,8 @@ mod test {
 
     #[test]
     fn transaction_id_persistence() {
-        let tmpfile = crate::create_tempfile();
-        let db = Database::create(tmpfile.path()).unwrap();
+        let vfs = crate::MockVfs::new();
+        let db = Database::create(crate::TEST_DB_PATH, vfs.config(), vfs.clone()).unwrap();
         let write_txn = db.begin_write().unwrap();
         {
             let mut table = write_txn.open_table(X).unwrap();
@@ -1216,7 +1571,7 @@ mod test {
         write_txn.commit().unwrap();
         drop(db);
 
-        let db2 = Database::create(tmpfile.path()).unwrap();
+        let db2 = Database::create(crate::TEST_DB_PATH, vfs.config(), vfs.clone()).unwrap();
         let write_txn = db2.begin_write().unwrap();
         assert!(write_txn.transaction_id > first_txn_id);
     }
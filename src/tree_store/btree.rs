@@ -1,21 +1,23 @@
 use crate::tree_store::btree_base::{
-    branch_checksum, leaf_checksum, BranchAccessor, BranchMutator, Checksum, LeafAccessor, BRANCH,
-    DEFERRED, LEAF,
+    branch_checksum, leaf_checksum, subtree_len, BranchAccessor, BranchMutator, Checksum,
+    LeafAccessor, BRANCH, DEFERRED, LEAF, LEGACY_BRANCH,
 };
 use crate::tree_store::btree_iters::BtreeDrain;
+use crate::tree_store::btree_loader::BtreeLoader;
 use crate::tree_store::btree_mutator::MutateHelper;
 use crate::tree_store::page_store::{CachePriority, Page, PageImpl, PageMut, TransactionalMemory};
 use crate::tree_store::{
-    AccessGuardMut, AllPageNumbersBtreeIter, BtreeDrainFilter, BtreeRangeIter, PageHint, PageNumber,
+    AccessGuardMut, AllPageNumbersBtreeIter, BtreeCursor, BtreeDrainFilter, BtreeRangeIter,
+    PageHint, PageNumber,
 };
-use crate::types::{RedbKey, RedbValue, RedbValueMutInPlace};
-use crate::{AccessGuard, Result};
+use crate::types::{RedbKey, RedbKeyPrefix, RedbValue, RedbValueMutInPlace};
+use crate::{AccessGuard, ChangeKind, KeyChange, Result};
 #[cfg(feature = "logging")]
 use log::trace;
 use std::borrow::Borrow;
-use std::cmp::max;
+use std::cmp::{max, Ordering};
 use std::marker::PhantomData;
-use std::ops::{RangeBounds, RangeFull};
+use std::ops::{Bound, RangeBounds};
 use std::sync::{Arc, Mutex};
 
 pub(crate) struct BtreeStats {
@@ -78,7 +80,7 @@ impl<'a> UntypedBtreeMut<'a> {
 
         match page.memory()[0] {
             LEAF => leaf_checksum(&page, self.key_width, self.value_width),
-            BRANCH => {
+            BRANCH | LEGACY_BRANCH => {
                 let accessor = BranchAccessor::new(&page, self.key_width);
                 let mut new_children = vec![];
                 for i in 0..accessor.count_children() {
@@ -122,7 +124,7 @@ impl<'a> UntypedBtreeMut<'a> {
                 LEAF => {
                     visitor(page)?;
                 }
-                BRANCH => {
+                BRANCH | LEGACY_BRANCH => {
                     drop(page);
                     self.dirty_leaf_visitor_helper(*page_number, &visitor)?;
                 }
@@ -144,7 +146,7 @@ impl<'a> UntypedBtreeMut<'a> {
             LEAF => {
                 visitor(page)?;
             }
-            BRANCH => {
+            BRANCH | LEGACY_BRANCH => {
                 let accessor = BranchAccessor::new(&page, self.key_width);
                 for i in 0..accessor.count_children() {
                     let child_page = accessor.child_page(i).unwrap();
@@ -159,60 +161,112 @@ impl<'a> UntypedBtreeMut<'a> {
         Ok(())
     }
 
-    // Relocate the btree to lower pages
-    pub(crate) fn relocate(&mut self) -> Result<bool> {
+    // Relocate the btree to lower pages, reading at most `budget` pages. `path` holds the index of
+    // the child being visited at each level, and is updated so that the next call resumes where
+    // this one stopped. Returns whether any pages were relocated, and whether the whole tree has
+    // been visited
+    pub(crate) fn relocate(
+        &mut self,
+        budget: &mut usize,
+        path: &mut Vec<usize>,
+    ) -> Result<(bool, bool)> {
         if let Some(root) = self.get_root() {
-            if let Some(new_root) = self.relocate_helper(root.0)? {
+            let (new_root, finished) = self.relocate_helper(root.0, 0, budget, path)?;
+            if let Some(new_root) = new_root {
                 self.root = Some(new_root);
-                return Ok(true);
+                return Ok((true, finished));
             }
+            Ok((false, finished))
+        } else {
+            path.clear();
+            Ok((false, true))
         }
-        Ok(false)
     }
 
-    // Relocates the given page to a lower page if possible, and returns the new page number
+    // Relocates the given page to a lower page if possible, and returns the new page number.
+    // Children are relocated first, so a page which can't be moved lower is still copied if any
+    // of its children were moved. Pages on the path being resumed aren't counted against the
+    // budget, since they're only re-read to get back to where the previous call stopped
     fn relocate_helper(
         &mut self,
         page_number: PageNumber,
-    ) -> Result<Option<(PageNumber, Checksum)>> {
+        depth: usize,
+        budget: &mut usize,
+        path: &mut Vec<usize>,
+    ) -> Result<(Option<(PageNumber, Checksum)>, bool)> {
+        if *budget == 0 {
+            return Ok((None, false));
+        }
+        if path.len() <= depth {
+            *budget -= 1;
+        }
         let old_page = self.mem.get_page(page_number)?;
+        let mut relocated_children = vec![];
+        let mut finished = true;
+        if matches!(old_page.memory()[0], BRANCH | LEGACY_BRANCH) {
+            let accessor = BranchAccessor::new(&old_page, self.key_width);
+            let start = path.get(depth).copied().unwrap_or(0);
+            for i in start..accessor.count_children() {
+                if i != start || path.len() <= depth {
+                    // Start at the beginning of this child
+                    path.truncate(depth);
+                    path.push(i);
+                }
+                let child = accessor.child_page(i).unwrap();
+                let (new_child, child_finished) =
+                    self.relocate_helper(child, depth + 1, budget, path)?;
+                if let Some(new_child) = new_child {
+                    relocated_children.push((i, new_child));
+                }
+                if !child_finished {
+                    finished = false;
+                    break;
+                }
+            }
+        }
+        if finished {
+            path.truncate(depth);
+        }
+
         let mut new_page = self.mem.allocate_lowest(
             old_page.memory().len(),
             CachePriority::default_btree(old_page.memory()),
         )?;
         let new_page_number = new_page.get_page_number();
-        if !new_page_number.is_before(page_number) {
+        // If the page can't be moved lower, it still has to be copied to point to its relocated
+        // children, unless it was already copied by this transaction
+        if !new_page_number.is_before(page_number)
+            && (relocated_children.is_empty() || self.mem.uncommitted(page_number))
+        {
             drop(new_page);
             self.mem.free(new_page_number);
-            return Ok(None);
+            if relocated_children.is_empty() {
+                return Ok((None, finished));
+            }
+            drop(old_page);
+            let mut page = self.mem.get_page_mut(page_number)?;
+            let mut mutator = BranchMutator::new(&mut page);
+            for (i, (new_child, new_checksum)) in relocated_children {
+                mutator.write_child_page(i, new_child, new_checksum);
+            }
+            return Ok((Some((page_number, DEFERRED)), finished));
         }
 
         new_page.memory_mut().copy_from_slice(old_page.memory());
-
-        let node_mem = old_page.memory();
-        match node_mem[0] {
-            LEAF => {
-                // No-op
-            }
-            BRANCH => {
-                let accessor = BranchAccessor::new(&old_page, self.key_width);
-                let mut mutator = BranchMutator::new(&mut new_page);
-                for i in 0..accessor.count_children() {
-                    let child = accessor.child_page(i).unwrap();
-                    if let Some((new_child, new_checksum)) = self.relocate_helper(child)? {
-                        mutator.write_child_page(i, new_child, new_checksum);
-                    }
-                }
+        if !relocated_children.is_empty() {
+            let mut mutator = BranchMutator::new(&mut new_page);
+            for (i, (new_child, new_checksum)) in relocated_children {
+                mutator.write_child_page(i, new_child, new_checksum);
             }
-            _ => unreachable!(),
         }
+        drop(new_page);
 
         let mut freed_pages = self.freed_pages.lock().unwrap();
         if !self.mem.free_if_uncommitted(page_number) {
             freed_pages.push(page_number);
         }
 
-        Ok(Some((new_page_number, DEFERRED)))
+        Ok((Some((new_page_number, DEFERRED)), finished))
     }
 }
 
@@ -279,7 +333,11 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
         *(*self.root).lock().unwrap()
     }
 
-    pub(crate) fn relocate(&mut self) -> Result<bool> {
+    pub(crate) fn relocate(
+        &mut self,
+        budget: &mut usize,
+        path: &mut Vec<usize>,
+    ) -> Result<(bool, bool)> {
         let mut tree = UntypedBtreeMut::new(
             self.get_root(),
             self.mem,
@@ -287,12 +345,11 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
             K::fixed_width(),
             V::fixed_width(),
         );
-        if tree.relocate()? {
+        let (relocated, finished) = tree.relocate(budget, path)?;
+        if relocated {
             *self.root.lock().unwrap() = tree.get_root();
-            Ok(true)
-        } else {
-            Ok(false)
         }
+        Ok((relocated, finished))
     }
 
     pub(crate) fn insert(
@@ -315,6 +372,47 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
         Ok(old_value)
     }
 
+    // Replaces the value of `key` with the result of `f`, or removes it if `f` returns None.
+    // Returns the old value, and the new one
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn update(
+        &mut self,
+        key: &K::SelfType<'_>,
+        f: impl FnOnce(Option<&[u8]>) -> Option<Vec<u8>>,
+    ) -> Result<(Option<AccessGuard<'_, V>>, Option<Vec<u8>>)> {
+        let mut freed_pages = self.freed_pages.lock().unwrap();
+        let mut root = self.root.lock().unwrap();
+        let mut operation: MutateHelper<'_, '_, K, V> =
+            MutateHelper::new(&mut root, self.mem, freed_pages.as_mut());
+        operation.update(key, f)
+    }
+
+    // Inserts the entries, which must be sorted by key
+    pub(crate) fn insert_many<'k>(
+        &mut self,
+        entries: impl IntoIterator<Item = (&'k [u8], &'k [u8])>,
+    ) -> Result {
+        let mut freed_pages = self.freed_pages.lock().unwrap();
+        let mut root = self.root.lock().unwrap();
+        let mut operation: MutateHelper<'_, '_, K, V> =
+            MutateHelper::new(&mut root, self.mem, freed_pages.as_mut());
+        operation.insert_many(entries)
+    }
+
+    // Returns a loader for building the contents of this tree, which must be empty
+    pub(crate) fn bulk_loader(&self, fill_factor: f32) -> BtreeLoader<'a, K, V> {
+        assert!(self.get_root().is_none());
+        BtreeLoader::new(self.mem, fill_factor)
+    }
+
+    pub(crate) fn finish_bulk_load(&mut self, loader: BtreeLoader<'a, K, V>) -> Result {
+        let root = loader.finish()?;
+        let mut current = self.root.lock().unwrap();
+        assert!(current.is_none());
+        *current = root;
+        Ok(())
+    }
+
     pub(crate) fn remove(&mut self, key: &K::SelfType<'_>) -> Result<Option<AccessGuard<V>>> {
         #[cfg(feature = "logging")]
         trace!("Btree(root={:?}): Deleting {:?}", &self.root, key);
@@ -348,6 +446,13 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
         self.read_tree()?.get(key)
     }
 
+    pub(crate) fn get_many<Q: AsRef<[u8]>>(
+        &self,
+        keys: &[Q],
+    ) -> Result<Vec<Option<AccessGuard<'_, V>>>> {
+        self.read_tree()?.get_many(keys)
+    }
+
     pub(crate) fn range<'a0, T: RangeBounds<KR> + 'a0, KR: Borrow<K::SelfType<'a0>> + 'a0>(
         &self,
         range: &'_ T,
@@ -358,6 +463,29 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
         self.read_tree()?.range(range)
     }
 
+    pub(crate) fn prefix<P: RedbValue>(
+        &self,
+        prefix: &P::SelfType<'_>,
+    ) -> Result<BtreeRangeIter<'a, K, V>>
+    where
+        K: RedbKeyPrefix<P>,
+    {
+        self.read_tree()?.prefix(prefix)
+    }
+
+    pub(crate) fn cursor(&self) -> Result<BtreeCursor<'a, K, V>> {
+        self.read_tree()?.cursor()
+    }
+
+    // Removes all entries with keys in the range, and returns the number removed
+    pub(crate) fn remove_range(&mut self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<u64> {
+        let mut root = self.root.lock().unwrap();
+        let mut freed_pages = self.freed_pages.lock().unwrap();
+        let mut operation: MutateHelper<'_, '_, K, V> =
+            MutateHelper::new(&mut root, self.mem, freed_pages.as_mut());
+        operation.delete_range(start, end)
+    }
+
     pub(crate) fn drain<'a0, T: RangeBounds<KR> + 'a0, KR: Borrow<K::SelfType<'a0>> + 'a0>(
         &mut self,
         range: &'_ T,
@@ -427,6 +555,25 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeMut<'a, K, V> {
     pub(crate) fn len(&self) -> Result<u64> {
         self.read_tree()?.len()
     }
+
+    pub(crate) fn range_len<'a0, T: RangeBounds<KR> + 'a0, KR: Borrow<K::SelfType<'a0>> + 'a0>(
+        &self,
+        range: &'_ T,
+    ) -> Result<u64>
+    where
+        K: 'a0,
+    {
+        self.read_tree()?.range_len(range)
+    }
+
+    pub(crate) fn rank(&self, key: &K::SelfType<'_>) -> Result<u64> {
+        self.read_tree()?.rank(key)
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn nth(&self, n: u64) -> Result<Option<(AccessGuard<'_, K>, AccessGuard<'_, V>)>> {
+        self.read_tree()?.nth(n)
+    }
 }
 
 impl<'a, K: RedbKey + 'a, V: RedbValueMutInPlace + 'a> BtreeMut<'a, K, V> {
@@ -503,7 +650,7 @@ impl<'a> RawBtree<'a> {
                     false
                 }
             }
-            BRANCH => {
+            BRANCH | LEGACY_BRANCH => {
                 if let Ok(computed) = branch_checksum(&page, self.fixed_key_size) {
                     if expected_checksum != computed {
                         return Ok(false);
@@ -570,6 +717,78 @@ impl<'a, K: RedbKey, V: RedbValue> Btree<'a, K, V> {
         }
     }
 
+    // Returns the values for the queried keys, in the same order. The keys are looked up in sorted
+    // order, so that pages shared by consecutive keys are only fetched once
+    pub(crate) fn get_many<Q: AsRef<[u8]>>(
+        &self,
+        keys: &[Q],
+    ) -> Result<Vec<Option<AccessGuard<'a, V>>>> {
+        let mut results: Vec<Option<AccessGuard<'a, V>>> = keys.iter().map(|_| None).collect();
+        let root = if let Some(ref root) = self.cached_root {
+            root.clone()
+        } else {
+            return Ok(results);
+        };
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_by(|a, b| K::compare(keys[*a].as_ref(), keys[*b].as_ref()));
+
+        // Pages on the path to the current leaf, with the bounds of the keys which they contain,
+        // as in range_overlap()
+        let mut path = vec![(root, None::<Vec<u8>>, None::<Vec<u8>>)];
+        for i in order {
+            let query = keys[i].as_ref();
+            while path.len() > 1 {
+                let (_, lower, upper) = path.last().unwrap();
+                let after_lower = lower
+                    .as_ref()
+                    .map_or(true, |lower| K::compare(query, lower).is_gt());
+                let before_upper = upper
+                    .as_ref()
+                    .map_or(true, |upper| K::compare(query, upper).is_le());
+                if after_lower && before_upper {
+                    break;
+                }
+                path.pop();
+            }
+            loop {
+                let (page, lower, upper) = path.last().unwrap();
+                match page.memory()[0] {
+                    LEAF => {
+                        let accessor =
+                            LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width());
+                        if let Some(entry_index) = accessor.find_key::<K>(query) {
+                            let (start, end) = accessor.value_range(entry_index).unwrap();
+                            // Safety: free_on_drop is false
+                            let guard =
+                                AccessGuard::new(page.clone(), start, end - start, false, self.mem);
+                            results[i] = Some(guard);
+                        }
+                        break;
+                    }
+                    BRANCH | LEGACY_BRANCH => {
+                        let accessor = BranchAccessor::new(page, K::fixed_width());
+                        let (child_index, child_page) = accessor.child_for_key::<K>(query);
+                        let child_lower = if child_index > 0 {
+                            Some(accessor.key(child_index - 1).unwrap().to_vec())
+                        } else {
+                            lower.clone()
+                        };
+                        let child_upper = if child_index < accessor.count_children() - 1 {
+                            Some(accessor.key(child_index).unwrap().to_vec())
+                        } else {
+                            upper.clone()
+                        };
+                        let child = self.mem.get_page_extended(child_page, self.hint)?;
+                        path.push((child, child_lower, child_upper));
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
     // Returns the value for the queried key, if present
     fn get_helper(&self, page: PageImpl<'a>, query: &[u8]) -> Result<Option<AccessGuard<'a, V>>> {
         let node_mem = page.memory();
@@ -585,7 +804,7 @@ impl<'a, K: RedbKey, V: RedbValue> Btree<'a, K, V> {
                     Ok(None)
                 }
             }
-            BRANCH => {
+            BRANCH | LEGACY_BRANCH => {
                 let accessor = BranchAccessor::new(&page, K::fixed_width());
                 let (_, child_page) = accessor.child_for_key::<K>(query);
                 self.get_helper(self.mem.get_page_extended(child_page, self.hint)?, query)
@@ -604,18 +823,139 @@ impl<'a, K: RedbKey, V: RedbValue> Btree<'a, K, V> {
         BtreeRangeIter::new(range, self.root.map(|(p, _)| p), self.mem)
     }
 
-    pub(crate) fn len(&self) -> Result<u64> {
-        let iter: BtreeRangeIter<K, V> = BtreeRangeIter::new::<RangeFull, K::SelfType<'_>>(
-            &(..),
+    pub(crate) fn prefix<P: RedbValue>(
+        &self,
+        prefix: &P::SelfType<'_>,
+    ) -> Result<BtreeRangeIter<'a, K, V>>
+    where
+        K: RedbKeyPrefix<P>,
+    {
+        BtreeRangeIter::new_prefix(
+            P::as_bytes(prefix).as_ref(),
             self.root.map(|(p, _)| p),
             self.mem,
-        )?;
-        let mut count = 0;
-        for v in iter {
-            v?;
-            count += 1;
+        )
+    }
+
+    pub(crate) fn cursor(&self) -> Result<BtreeCursor<'a, K, V>> {
+        Ok(BtreeCursor::new(self.root.map(|(p, _)| p), self.mem))
+    }
+
+    pub(crate) fn len(&self) -> Result<u64> {
+        if let Some((p, _)) = self.root {
+            subtree_len(self.mem, p)
+        } else {
+            Ok(0)
+        }
+    }
+
+    pub(crate) fn range_len<'a0, T: RangeBounds<KR> + 'a0, KR: Borrow<K::SelfType<'a0>> + 'a0>(
+        &self,
+        range: &'_ T,
+    ) -> Result<u64>
+    where
+        K: 'a0,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(key) => self.rank_bytes(K::as_bytes(key.borrow()).as_ref(), false)?,
+            Bound::Excluded(key) => self.rank_bytes(K::as_bytes(key.borrow()).as_ref(), true)?,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => self.rank_bytes(K::as_bytes(key.borrow()).as_ref(), true)?,
+            Bound::Excluded(key) => self.rank_bytes(K::as_bytes(key.borrow()).as_ref(), false)?,
+            Bound::Unbounded => self.len()?,
+        };
+        Ok(end.saturating_sub(start))
+    }
+
+    // Returns the number of entries with keys less than `key`
+    pub(crate) fn rank(&self, key: &K::SelfType<'_>) -> Result<u64> {
+        self.rank_bytes(K::as_bytes(key).as_ref(), false)
+    }
+
+    // Returns the number of entries with keys less than `query`, or less than or equal to it if
+    // `inclusive` is true
+    fn rank_bytes(&self, query: &[u8], inclusive: bool) -> Result<u64> {
+        let mut rank = 0;
+        let mut page = if let Some(ref root_page) = self.cached_root {
+            root_page.clone()
+        } else {
+            return Ok(0);
+        };
+        loop {
+            let child_page = match page.memory()[0] {
+                LEAF => {
+                    let accessor =
+                        LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width());
+                    let (position, found) = accessor.position::<K>(query);
+                    let position = if found && inclusive {
+                        position + 1
+                    } else {
+                        position
+                    };
+                    return Ok(rank + u64::try_from(position).unwrap());
+                }
+                BRANCH | LEGACY_BRANCH => {
+                    let accessor = BranchAccessor::new(&page, K::fixed_width());
+                    let (child_index, child_page) = accessor.child_for_key::<K>(query);
+                    for i in 0..child_index {
+                        rank += accessor.child_len(i, self.mem)?;
+                    }
+                    child_page
+                }
+                _ => unreachable!(),
+            };
+            page = self.mem.get_page_extended(child_page, self.hint)?;
+        }
+    }
+
+    // Returns the nth entry in key order
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn nth(
+        &self,
+        mut n: u64,
+    ) -> Result<Option<(AccessGuard<'a, K>, AccessGuard<'a, V>)>> {
+        let mut page = if let Some(ref root_page) = self.cached_root {
+            root_page.clone()
+        } else {
+            return Ok(None);
+        };
+        loop {
+            let child_page = match page.memory()[0] {
+                LEAF => {
+                    let accessor =
+                        LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width());
+                    let position = match usize::try_from(n) {
+                        Ok(position) if position < accessor.num_pairs() => position,
+                        _ => return Ok(None),
+                    };
+                    let (key_range, value_range) = accessor.entry_ranges(position).unwrap();
+                    let key = AccessGuard::with_page(page.clone(), key_range);
+                    let value = AccessGuard::with_page(page, value_range);
+                    return Ok(Some((key, value)));
+                }
+                BRANCH | LEGACY_BRANCH => {
+                    let accessor = BranchAccessor::new(&page, K::fixed_width());
+                    let mut child_page = None;
+                    for i in 0..accessor.count_children() {
+                        let len = accessor.child_len(i, self.mem)?;
+                        if n < len {
+                            child_page = accessor.child_page(i);
+                            break;
+                        }
+                        n -= len;
+                    }
+                    if let Some(child_page) = child_page {
+                        child_page
+                    } else {
+                        return Ok(None);
+                    }
+                }
+                _ => unreachable!(),
+            };
+            page = self.mem.get_page_extended(child_page, self.hint)?;
         }
-        Ok(count)
     }
 
     pub(crate) fn stats(&self) -> Result<BtreeStats> {
@@ -642,7 +982,7 @@ impl<'a, K: RedbKey, V: RedbValue> Btree<'a, K, V> {
                                 .print_node::<K, V>(include_values);
                             eprint!("]");
                         }
-                        BRANCH => {
+                        BRANCH | LEGACY_BRANCH => {
                             let accessor = BranchAccessor::new(&page, K::fixed_width());
                             for i in 0..accessor.count_children() {
                                 let child = accessor.child_page(i).unwrap();
@@ -664,6 +1004,43 @@ impl<'a, K: RedbKey, V: RedbValue> Btree<'a, K, V> {
     }
 }
 
+// Calls `visitor` with the key and value of each entry in the tree, in ascending order
+pub(crate) fn btree_visit_entries<F: FnMut(&[u8], &[u8]) -> Result>(
+    root: Option<PageNumber>,
+    mem: &TransactionalMemory,
+    fixed_key_size: Option<usize>,
+    fixed_value_size: Option<usize>,
+    visitor: &mut F,
+) -> Result {
+    if let Some(page_number) = root {
+        let page = mem.get_page(page_number)?;
+        match page.memory()[0] {
+            LEAF => {
+                let accessor = LeafAccessor::new(page.memory(), fixed_key_size, fixed_value_size);
+                for i in 0..accessor.num_pairs() {
+                    let entry = accessor.entry(i).unwrap();
+                    visitor(entry.key(), entry.value())?;
+                }
+            }
+            BRANCH | LEGACY_BRANCH => {
+                let accessor = BranchAccessor::new(&page, fixed_key_size);
+                for i in 0..accessor.count_children() {
+                    btree_visit_entries(
+                        accessor.child_page(i),
+                        mem,
+                        fixed_key_size,
+                        fixed_value_size,
+                        visitor,
+                    )?;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    Ok(())
+}
+
 pub(crate) fn btree_stats(
     root: Option<PageNumber>,
     mem: &TransactionalMemory,
@@ -684,6 +1061,162 @@ pub(crate) fn btree_stats(
     }
 }
 
+// A node which btree_diff() has yet to compare
+enum DiffNode<'a> {
+    // A page, and its height. Leaves have height 1
+    Subtree(PageNumber, usize),
+    // The entry at the given index of a leaf
+    Entry(PageImpl<'a>, usize),
+}
+
+struct DiffSide<'a> {
+    // Nodes in descending key order, so that the next one to compare is last
+    pending: Vec<DiffNode<'a>>,
+    mem: &'a TransactionalMemory,
+    fixed_key_size: Option<usize>,
+    fixed_value_size: Option<usize>,
+}
+
+impl<'a> DiffSide<'a> {
+    fn new(
+        root: Option<PageNumber>,
+        mem: &'a TransactionalMemory,
+        fixed_key_size: Option<usize>,
+        fixed_value_size: Option<usize>,
+    ) -> Result<Self> {
+        let mut pending = vec![];
+        if let Some(root) = root {
+            // The tree is balanced, so the height of the leftmost path is the height of the tree
+            let mut height = 1;
+            let mut page = mem.get_page(root)?;
+            while matches!(page.memory()[0], BRANCH | LEGACY_BRANCH) {
+                let child = BranchAccessor::new(&page, fixed_key_size)
+                    .child_page(0)
+                    .unwrap();
+                page = mem.get_page(child)?;
+                height += 1;
+            }
+            pending.push(DiffNode::Subtree(root, height));
+        }
+        Ok(Self {
+            pending,
+            mem,
+            fixed_key_size,
+            fixed_value_size,
+        })
+    }
+
+    // Replaces the next node, which must be a subtree, with its children
+    fn expand(&mut self) -> Result {
+        let (page_number, height) = match self.pending.pop() {
+            Some(DiffNode::Subtree(page_number, height)) => (page_number, height),
+            _ => unreachable!(),
+        };
+        let page = self.mem.get_page(page_number)?;
+        match page.memory()[0] {
+            LEAF => {
+                let accessor =
+                    LeafAccessor::new(page.memory(), self.fixed_key_size, self.fixed_value_size);
+                for i in (0..accessor.num_pairs()).rev() {
+                    self.pending.push(DiffNode::Entry(page.clone(), i));
+                }
+            }
+            BRANCH | LEGACY_BRANCH => {
+                let accessor = BranchAccessor::new(&page, self.fixed_key_size);
+                for i in (0..accessor.count_children()).rev() {
+                    let child = accessor.child_page(i).unwrap();
+                    self.pending.push(DiffNode::Subtree(child, height - 1));
+                }
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    // Returns the key and value of the next node, which must be an entry
+    fn entry(&self) -> (&[u8], &[u8]) {
+        match self.pending.last() {
+            Some(DiffNode::Entry(page, index)) => {
+                let accessor =
+                    LeafAccessor::new(page.memory(), self.fixed_key_size, self.fixed_value_size);
+                let entry = accessor.entry(*index).unwrap();
+                (entry.key(), entry.value())
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn pop_key(&mut self) -> Vec<u8> {
+        let key = self.entry().0.to_vec();
+        self.pending.pop();
+        key
+    }
+}
+
+// Returns the keys which differ between two trees, in ascending order.
+// Both trees are walked in lockstep, and subtrees which share a page are skipped without being
+// read, so the cost is proportional to the number of pages which differ
+pub(crate) fn btree_diff<K: RedbKey>(
+    old_root: Option<PageNumber>,
+    new_root: Option<PageNumber>,
+    mem: &TransactionalMemory,
+    fixed_key_size: Option<usize>,
+    fixed_value_size: Option<usize>,
+    mut values_equal: impl FnMut(&[u8], &[u8]) -> Result<bool>,
+) -> Result<Vec<KeyChange>> {
+    let mut old = DiffSide::new(old_root, mem, fixed_key_size, fixed_value_size)?;
+    let mut new = DiffSide::new(new_root, mem, fixed_key_size, fixed_value_size)?;
+    let mut changes = vec![];
+    loop {
+        match (old.pending.last(), new.pending.last()) {
+            (None, None) => break,
+            (Some(DiffNode::Subtree(a, _)), Some(DiffNode::Subtree(b, _))) if a == b => {
+                old.pending.pop();
+                new.pending.pop();
+            }
+            (Some(DiffNode::Subtree(_, a)), Some(DiffNode::Subtree(_, b))) => {
+                // Expand the taller subtree first, so that shared pages line up at the same height
+                let (a, b) = (*a, *b);
+                if a >= b {
+                    old.expand()?;
+                }
+                if b >= a {
+                    new.expand()?;
+                }
+            }
+            (Some(DiffNode::Subtree(..)), _) => old.expand()?,
+            (_, Some(DiffNode::Subtree(..))) => new.expand()?,
+            (Some(DiffNode::Entry(..)), None) => {
+                changes.push(KeyChange::new(ChangeKind::Removed, old.pop_key()));
+            }
+            (None, Some(DiffNode::Entry(..))) => {
+                changes.push(KeyChange::new(ChangeKind::Inserted, new.pop_key()));
+            }
+            (Some(DiffNode::Entry(..)), Some(DiffNode::Entry(..))) => {
+                let (old_key, old_value) = old.entry();
+                let (new_key, new_value) = new.entry();
+                match K::compare(old_key, new_key) {
+                    Ordering::Less => {
+                        changes.push(KeyChange::new(ChangeKind::Removed, old.pop_key()));
+                    }
+                    Ordering::Greater => {
+                        changes.push(KeyChange::new(ChangeKind::Inserted, new.pop_key()));
+                    }
+                    Ordering::Equal => {
+                        if !values_equal(old_value, new_value)? {
+                            changes.push(KeyChange::new(ChangeKind::Updated, new_key.to_vec()));
+                        }
+                        old.pending.pop();
+                        new.pending.pop();
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(changes)
+}
+
 fn stats_helper(
     page_number: PageNumber,
     mem: &TransactionalMemory,
@@ -707,7 +1240,7 @@ fn stats_helper(
                 fragmented_bytes,
             })
         }
-        BRANCH => {
+        BRANCH | LEGACY_BRANCH => {
             let accessor = BranchAccessor::new(&page, fixed_key_size);
             let mut max_child_height = 0;
             let mut leaf_pages = 0;
@@ -11,7 +11,10 @@ use std::ops::Range;
 use std::{mem, thread};
 
 pub(crate) const LEAF: u8 = 1;
-pub(crate) const BRANCH: u8 = 2;
+// Branch page written by file format version 1, which does not store the number of entries in
+// each child. These are still readable, and are replaced by BRANCH pages when they're rewritten
+pub(crate) const LEGACY_BRANCH: u8 = 2;
+pub(crate) const BRANCH: u8 = 3;
 
 pub(crate) type Checksum = u128;
 // Dummy value. Final value will be computed during commit
@@ -36,6 +39,20 @@ pub(super) fn leaf_checksum<T: Page>(
     }
 }
 
+// Returns the number of entries in the subtree rooted at `page_number`
+pub(crate) fn subtree_len(mem: &TransactionalMemory, page_number: PageNumber) -> Result<u64> {
+    let page = mem.get_page(page_number)?;
+    match page.memory()[0] {
+        // The number of entries does not depend on the key or value width
+        LEAF => Ok(LeafAccessor::new(page.memory(), None, None)
+            .num_pairs()
+            .try_into()
+            .unwrap()),
+        BRANCH | LEGACY_BRANCH => BranchAccessor::new(&page, None).len(mem),
+        _ => unreachable!(),
+    }
+}
+
 pub(super) fn branch_checksum<T: Page>(
     page: &T,
     fixed_key_size: Option<usize>,
@@ -155,6 +172,23 @@ impl<'a, V: RedbValue> AccessGuard<'a, V> {
         }
     }
 
+    // Converts the guard into one for the end of the value, after its first `prefix_len` bytes,
+    // which holds a value of type `T`
+    pub(crate) fn into_suffix<T: RedbValue>(mut self, prefix_len: usize) -> AccessGuard<'a, T> {
+        assert!(prefix_len <= self.len);
+        assert!(!matches!(self.on_drop, OnDrop::RemoveEntry { .. }));
+        let page = mem::replace(&mut self.page, EitherPage::OwnedMemory(vec![]));
+        let on_drop = mem::replace(&mut self.on_drop, OnDrop::None);
+        AccessGuard {
+            page,
+            offset: self.offset + prefix_len,
+            len: self.len - prefix_len,
+            on_drop,
+            mem: self.mem,
+            _value_type: Default::default(),
+        }
+    }
+
     pub fn value(&self) -> V::SelfType<'_> {
         V::from_bytes(&self.page.memory()[self.offset..(self.offset + self.len)])
     }
@@ -268,6 +302,11 @@ impl<'a> LeafAccessor<'a> {
     }
 
     pub(crate) fn position<K: RedbKey>(&self, query: &[u8]) -> (usize, bool) {
+        self.position_by(|key| K::compare(query, key))
+    }
+
+    // Like position(), but `compare` orders the query relative to the given key
+    pub(crate) fn position_by(&self, compare: impl Fn(&[u8]) -> Ordering) -> (usize, bool) {
         // inclusive
         let mut min_entry = 0;
         // inclusive. Start past end, since it might be positioned beyond the end of the leaf
@@ -275,7 +314,7 @@ impl<'a> LeafAccessor<'a> {
         while min_entry < max_entry {
             let mid = (min_entry + max_entry) / 2;
             let key = self.key_unchecked(mid);
-            match K::compare(query, key) {
+            match compare(key) {
                 Ordering::Less => {
                     max_entry = mid;
                 }
@@ -1045,7 +1084,7 @@ pub(crate) struct BranchAccessor<'a: 'b, 'b, T: Page + 'a> {
 
 impl<'a: 'b, 'b, T: Page + 'a> BranchAccessor<'a, 'b, T> {
     pub(crate) fn new(page: &'b T, fixed_key_size: Option<usize>) -> Self {
-        debug_assert_eq!(page.memory()[0], BRANCH);
+        debug_assert!(matches!(page.memory()[0], BRANCH | LEGACY_BRANCH));
         let num_keys = u16::from_le_bytes(page.memory()[2..4].try_into().unwrap()) as usize;
         BranchAccessor {
             page,
@@ -1077,11 +1116,16 @@ impl<'a: 'b, 'b, T: Page + 'a> BranchAccessor<'a, 'b, T> {
     }
 
     pub(super) fn child_for_key<K: RedbKey>(&self, query: &[u8]) -> (usize, PageNumber) {
+        self.child_by(|key| K::compare(query, key))
+    }
+
+    // Like child_for_key(), but `compare` orders the query relative to the given key
+    pub(super) fn child_by(&self, compare: impl Fn(&[u8]) -> Ordering) -> (usize, PageNumber) {
         let mut min_child = 0; // inclusive
         let mut max_child = self.num_keys(); // inclusive
         while min_child < max_child {
             let mid = (min_child + max_child) / 2;
-            match K::compare(query, self.key(mid).unwrap()) {
+            match compare(self.key(mid).unwrap()) {
                 Ordering::Less => {
                     max_child = mid;
                 }
@@ -1098,12 +1142,24 @@ impl<'a: 'b, 'b, T: Page + 'a> BranchAccessor<'a, 'b, T> {
         (min_child, self.child_page(min_child).unwrap())
     }
 
+    fn counted(&self) -> bool {
+        self.page.memory()[0] == BRANCH
+    }
+
+    // End of the fixed size section of each child
+    fn children_end(&self) -> usize {
+        let mut child_size = PageNumber::serialized_size() + size_of::<Checksum>();
+        if self.counted() {
+            child_size += size_of::<u64>();
+        }
+        8 + child_size * self.count_children()
+    }
+
     fn key_section_start(&self) -> usize {
         if self.fixed_key_size.is_none() {
-            8 + (PageNumber::serialized_size() + size_of::<Checksum>()) * self.count_children()
-                + size_of::<u32>() * self.num_keys()
+            self.children_end() + size_of::<u32>() * self.num_keys()
         } else {
-            8 + (PageNumber::serialized_size() + size_of::<Checksum>()) * self.count_children()
+            self.children_end()
         }
     }
 
@@ -1119,9 +1175,7 @@ impl<'a: 'b, 'b, T: Page + 'a> BranchAccessor<'a, 'b, T> {
         if let Some(fixed) = self.fixed_key_size {
             return self.key_section_start() + fixed * (n + 1);
         }
-        let offset = 8
-            + (PageNumber::serialized_size() + size_of::<Checksum>()) * self.count_children()
-            + size_of::<u32>() * n;
+        let offset = self.children_end() + size_of::<u32>() * n;
         u32::from_le_bytes(
             self.page.memory()[offset..(offset + size_of::<u32>())]
                 .try_into()
@@ -1142,7 +1196,7 @@ impl<'a: 'b, 'b, T: Page + 'a> BranchAccessor<'a, 'b, T> {
         self.num_keys() + 1
     }
 
-    pub(super) fn child_checksum(&self, n: usize) -> Option<Checksum> {
+    pub(crate) fn child_checksum(&self, n: usize) -> Option<Checksum> {
         if n >= self.count_children() {
             return None;
         }
@@ -1169,13 +1223,57 @@ impl<'a: 'b, 'b, T: Page + 'a> BranchAccessor<'a, 'b, T> {
         ))
     }
 
+    // Returns the number of entries in the nth child, if it's stored in this page
+    pub(super) fn stored_child_len(&self, n: usize) -> Option<u64> {
+        if n >= self.count_children() || !self.counted() {
+            return None;
+        }
+
+        let offset = 8
+            + (PageNumber::serialized_size() + size_of::<Checksum>()) * self.count_children()
+            + size_of::<u64>() * n;
+        Some(u64::from_le_bytes(
+            self.page.memory()[offset..(offset + size_of::<u64>())]
+                .try_into()
+                .unwrap(),
+        ))
+    }
+
+    // Returns the number of entries in the nth child. The children of a legacy page are counted
+    // when one of them is first needed, and the counts are cached
+    pub(crate) fn child_len(&self, n: usize, mem: &TransactionalMemory) -> Result<u64> {
+        if let Some(len) = self.stored_child_len(n) {
+            return Ok(len);
+        }
+        let page_number = self.page.get_page_number();
+        if let Some(len) = mem.legacy_child_len(page_number, n) {
+            return Ok(len);
+        }
+        let lens = (0..self.count_children())
+            .map(|i| subtree_len(mem, self.child_page(i).unwrap()))
+            .collect::<Result<Vec<u64>>>()?;
+        let len = lens[n];
+        mem.cache_legacy_child_lens(page_number, lens);
+        Ok(len)
+    }
+
+    // Returns the number of entries in this subtree
+    pub(crate) fn len(&self, mem: &TransactionalMemory) -> Result<u64> {
+        let mut len = 0;
+        for i in 0..self.count_children() {
+            len += self.child_len(i, mem)?;
+        }
+        Ok(len)
+    }
+
     fn num_keys(&self) -> usize {
         self.num_keys
     }
 }
 
 pub(super) struct BranchBuilder<'a, 'b> {
-    children: Vec<(PageNumber, Checksum)>,
+    // Each child, along with the number of entries in it
+    children: Vec<(PageNumber, Checksum, u64)>,
     keys: Vec<&'a [u8]>,
     total_key_bytes: usize,
     fixed_key_size: Option<usize>,
@@ -1197,12 +1295,18 @@ impl<'a, 'b> BranchBuilder<'a, 'b> {
         }
     }
 
-    pub(super) fn replace_child(&mut self, index: usize, child: PageNumber, checksum: Checksum) {
-        self.children[index] = (child, checksum);
+    pub(super) fn replace_child(
+        &mut self,
+        index: usize,
+        child: PageNumber,
+        checksum: Checksum,
+        len: u64,
+    ) {
+        self.children[index] = (child, checksum, len);
     }
 
-    pub(super) fn push_child(&mut self, child: PageNumber, checksum: Checksum) {
-        self.children.push((child, checksum));
+    pub(super) fn push_child(&mut self, child: PageNumber, checksum: Checksum, len: u64) {
+        self.children.push((child, checksum, len));
     }
 
     pub(super) fn push_key(&mut self, key: &'a [u8]) {
@@ -1210,18 +1314,20 @@ impl<'a, 'b> BranchBuilder<'a, 'b> {
         self.total_key_bytes += key.len();
     }
 
-    pub(super) fn push_all<T: Page>(&mut self, accessor: &'a BranchAccessor<'_, '_, T>) {
+    pub(super) fn push_all<T: Page>(&mut self, accessor: &'a BranchAccessor<'_, '_, T>) -> Result {
         for i in 0..accessor.count_children() {
             let child = accessor.child_page(i).unwrap();
             let checksum = accessor.child_checksum(i).unwrap();
-            self.push_child(child, checksum);
+            let len = accessor.child_len(i, self.mem)?;
+            self.children.push((child, checksum, len));
         }
         for i in 0..(accessor.count_children() - 1) {
             self.push_key(accessor.key(i).unwrap());
         }
+        Ok(())
     }
 
-    pub(super) fn to_single_child(&self) -> Option<(PageNumber, Checksum)> {
+    pub(super) fn to_single_child(&self) -> Option<(PageNumber, Checksum, u64)> {
         if self.children.len() > 1 {
             None
         } else {
@@ -1238,10 +1344,12 @@ impl<'a, 'b> BranchBuilder<'a, 'b> {
         );
         let mut page = self.mem.allocate(size, CachePriority::High)?;
         let mut builder = RawBranchBuilder::new(&mut page, self.keys.len(), self.fixed_key_size);
-        builder.write_first_page(self.children[0].0, self.children[0].1);
+        let (child, checksum, len) = self.children[0];
+        builder.write_first_page(child, checksum, len);
         for i in 1..self.children.len() {
             let key = &self.keys[i - 1];
-            builder.write_nth_key(key.as_ref(), self.children[i].0, self.children[i].1, i - 1);
+            let (child, checksum, len) = self.children[i];
+            builder.write_nth_key(key.as_ref(), child, checksum, len, i - 1);
         }
         drop(builder);
 
@@ -1269,15 +1377,12 @@ impl<'a, 'b> BranchBuilder<'a, 'b> {
             RawBranchBuilder::required_bytes(division, first_split_key_len, self.fixed_key_size);
         let mut page1 = self.mem.allocate(size, CachePriority::High)?;
         let mut builder = RawBranchBuilder::new(&mut page1, division, self.fixed_key_size);
-        builder.write_first_page(self.children[0].0, self.children[0].1);
+        let (child, checksum, len) = self.children[0];
+        builder.write_first_page(child, checksum, len);
         for i in 0..division {
             let key = &self.keys[i];
-            builder.write_nth_key(
-                key.as_ref(),
-                self.children[i + 1].0,
-                self.children[i + 1].1,
-                i,
-            );
+            let (child, checksum, len) = self.children[i + 1];
+            builder.write_nth_key(key.as_ref(), child, checksum, len, i);
         }
         drop(builder);
 
@@ -1292,15 +1397,12 @@ impl<'a, 'b> BranchBuilder<'a, 'b> {
             self.keys.len() - division - 1,
             self.fixed_key_size,
         );
-        builder.write_first_page(self.children[division + 1].0, self.children[division + 1].1);
+        let (child, checksum, len) = self.children[division + 1];
+        builder.write_first_page(child, checksum, len);
         for i in (division + 1)..self.keys.len() {
             let key = &self.keys[i];
-            builder.write_nth_key(
-                key.as_ref(),
-                self.children[i + 1].0,
-                self.children[i + 1].1,
-                i - division - 1,
-            );
+            let (child, checksum, len) = self.children[i + 1];
+            builder.write_nth_key(key.as_ref(), child, checksum, len, i - division - 1);
         }
         drop(builder);
 
@@ -1308,6 +1410,10 @@ impl<'a, 'b> BranchBuilder<'a, 'b> {
     }
 }
 
+// Size of the checksum, page number, and number of entries stored for each child
+const BRANCH_CHILD_SIZE: usize =
+    size_of::<Checksum>() + PageNumber::serialized_size() + size_of::<u64>();
+
 // Note the caller is responsible for ensuring that the buffer is large enough
 // and rewriting all fields if any dynamically sized fields are written
 // Layout is:
@@ -1319,6 +1425,8 @@ impl<'a, 'b> BranchBuilder<'a, 'b> {
 // 16 bytes: child page checksum
 // repeating (num_keys + 1 times):
 // 8 bytes: page number
+// repeating (num_keys + 1 times):
+// 8 bytes: number of entries in the child subtree (not present in LEGACY_BRANCH pages)
 // (optional) repeating (num_keys times):
 // * 4 bytes: key end. Ending offset of the key, exclusive
 // repeating (num_keys times):
@@ -1337,13 +1445,10 @@ impl<'a: 'b, 'b> RawBranchBuilder<'a, 'b> {
         fixed_key_size: Option<usize>,
     ) -> usize {
         if fixed_key_size.is_none() {
-            let fixed_size = 8
-                + (PageNumber::serialized_size() + size_of::<Checksum>()) * (num_keys + 1)
-                + size_of::<u32>() * num_keys;
+            let fixed_size = 8 + BRANCH_CHILD_SIZE * (num_keys + 1) + size_of::<u32>() * num_keys;
             size_of_keys + fixed_size
         } else {
-            let fixed_size =
-                8 + (PageNumber::serialized_size() + size_of::<Checksum>()) * (num_keys + 1);
+            let fixed_size = 8 + BRANCH_CHILD_SIZE * (num_keys + 1);
             size_of_keys + fixed_size
         }
     }
@@ -1359,11 +1464,10 @@ impl<'a: 'b, 'b> RawBranchBuilder<'a, 'b> {
         page.memory_mut()[2..4].copy_from_slice(&u16::try_from(num_keys).unwrap().to_le_bytes());
         #[cfg(debug_assertions)]
         {
-            // Poison all the child pointers & key offsets, in case the caller forgets to write them
+            // Poison all the child pointers, lengths & key offsets, in case the caller forgets to
+            // write them
             let start = 8 + size_of::<Checksum>() * (num_keys + 1);
-            let last = 8
-                + (PageNumber::serialized_size() + size_of::<Checksum>()) * (num_keys + 1)
-                + size_of::<u32>() * num_keys;
+            let last = 8 + BRANCH_CHILD_SIZE * (num_keys + 1) + size_of::<u32>() * num_keys;
             for x in &mut page.memory_mut()[start..last] {
                 *x = 0xFF;
             }
@@ -1376,18 +1480,32 @@ impl<'a: 'b, 'b> RawBranchBuilder<'a, 'b> {
         }
     }
 
-    pub(super) fn write_first_page(&mut self, page_number: PageNumber, checksum: Checksum) {
-        let offset = 8;
+    fn write_child(&mut self, i: usize, page_number: PageNumber, checksum: Checksum, len: u64) {
+        let offset = 8 + size_of::<Checksum>() * i;
         self.page.memory_mut()[offset..(offset + size_of::<Checksum>())]
             .copy_from_slice(&checksum.to_le_bytes());
-        let offset = 8 + size_of::<Checksum>() * (self.num_keys + 1);
+        let offset =
+            8 + size_of::<Checksum>() * (self.num_keys + 1) + PageNumber::serialized_size() * i;
         self.page.memory_mut()[offset..(offset + PageNumber::serialized_size())]
             .copy_from_slice(&page_number.to_le_bytes());
+        let offset = 8
+            + (size_of::<Checksum>() + PageNumber::serialized_size()) * (self.num_keys + 1)
+            + size_of::<u64>() * i;
+        self.page.memory_mut()[offset..(offset + size_of::<u64>())]
+            .copy_from_slice(&len.to_le_bytes());
+    }
+
+    pub(super) fn write_first_page(
+        &mut self,
+        page_number: PageNumber,
+        checksum: Checksum,
+        len: u64,
+    ) {
+        self.write_child(0, page_number, checksum, len);
     }
 
     fn key_section_start(&self) -> usize {
-        let mut offset =
-            8 + (PageNumber::serialized_size() + size_of::<Checksum>()) * (self.num_keys + 1);
+        let mut offset = 8 + BRANCH_CHILD_SIZE * (self.num_keys + 1);
         if self.fixed_key_size.is_none() {
             offset += size_of::<u32>() * self.num_keys;
         }
@@ -1399,9 +1517,7 @@ impl<'a: 'b, 'b> RawBranchBuilder<'a, 'b> {
         if let Some(fixed) = self.fixed_key_size {
             return self.key_section_start() + fixed * (n + 1);
         }
-        let offset = 8
-            + (PageNumber::serialized_size() + size_of::<Checksum>()) * (self.num_keys + 1)
-            + size_of::<u32>() * n;
+        let offset = 8 + BRANCH_CHILD_SIZE * (self.num_keys + 1) + size_of::<u32>() * n;
         u32::from_le_bytes(
             self.page.memory()[offset..(offset + size_of::<u32>())]
                 .try_into()
@@ -1416,19 +1532,13 @@ impl<'a: 'b, 'b> RawBranchBuilder<'a, 'b> {
         key: &[u8],
         page_number: PageNumber,
         checksum: Checksum,
+        len: u64,
         n: usize,
     ) {
         assert!(n < self.num_keys);
         assert_eq!(n, self.keys_written);
         self.keys_written += 1;
-        let offset = 8 + size_of::<Checksum>() * (n + 1);
-        self.page.memory_mut()[offset..(offset + size_of::<Checksum>())]
-            .copy_from_slice(&checksum.to_le_bytes());
-        let offset = 8
-            + size_of::<Checksum>() * (self.num_keys + 1)
-            + PageNumber::serialized_size() * (n + 1);
-        self.page.memory_mut()[offset..(offset + PageNumber::serialized_size())]
-            .copy_from_slice(&page_number.to_le_bytes());
+        self.write_child(n + 1, page_number, checksum, len);
 
         let data_offset = if n > 0 {
             self.key_end(n - 1)
@@ -1436,9 +1546,7 @@ impl<'a: 'b, 'b> RawBranchBuilder<'a, 'b> {
             self.key_section_start()
         };
         if self.fixed_key_size.is_none() {
-            let offset = 8
-                + (PageNumber::serialized_size() + size_of::<Checksum>()) * (self.num_keys + 1)
-                + size_of::<u32>() * n;
+            let offset = 8 + BRANCH_CHILD_SIZE * (self.num_keys + 1) + size_of::<u32>() * n;
             self.page.memory_mut()[offset..(offset + size_of::<u32>())].copy_from_slice(
                 &u32::try_from(data_offset + key.len())
                     .unwrap()
@@ -1446,7 +1554,7 @@ impl<'a: 'b, 'b> RawBranchBuilder<'a, 'b> {
             );
         }
 
-        debug_assert!(data_offset > offset);
+        debug_assert!(data_offset >= self.key_section_start());
         self.page.memory_mut()[data_offset..(data_offset + key.len())].copy_from_slice(key);
     }
 }
@@ -1465,7 +1573,7 @@ pub(super) struct BranchMutator<'a: 'b, 'b> {
 
 impl<'a: 'b, 'b> BranchMutator<'a, 'b> {
     pub(super) fn new(page: &'b mut PageMut<'a>) -> Self {
-        assert_eq!(page.memory()[0], BRANCH);
+        assert!(matches!(page.memory()[0], BRANCH | LEGACY_BRANCH));
         Self { page }
     }
 
@@ -1488,4 +1596,17 @@ impl<'a: 'b, 'b> BranchMutator<'a, 'b> {
         self.page.memory_mut()[offset..(offset + PageNumber::serialized_size())]
             .copy_from_slice(&page_number.to_le_bytes());
     }
+
+    // Legacy pages don't store the number of entries in their children, so this is a no-op for them
+    pub(super) fn write_child_len(&mut self, i: usize, len: u64) {
+        debug_assert!(i <= self.num_keys());
+        if self.page.memory()[0] == LEGACY_BRANCH {
+            return;
+        }
+        let offset = 8
+            + (size_of::<Checksum>() + PageNumber::serialized_size()) * (self.num_keys() + 1)
+            + size_of::<u64>() * i;
+        self.page.memory_mut()[offset..(offset + size_of::<u64>())]
+            .copy_from_slice(&len.to_le_bytes());
+    }
 }
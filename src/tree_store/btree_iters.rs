@@ -1,11 +1,12 @@
 use crate::tree_store::btree_base::{BranchAccessor, LeafAccessor};
-use crate::tree_store::btree_base::{BRANCH, LEAF};
+use crate::tree_store::btree_base::{BRANCH, LEAF, LEGACY_BRANCH};
 use crate::tree_store::btree_iters::RangeIterState::{Internal, Leaf};
 use crate::tree_store::page_store::{Page, PageImpl, TransactionalMemory};
 use crate::tree_store::PageNumber;
-use crate::types::{RedbKey, RedbValue};
+use crate::types::{RedbKey, RedbKeyPrefix, RedbValue};
 use crate::Result;
 use std::borrow::Borrow;
+use std::cmp::Ordering;
 use std::collections::Bound;
 use std::marker::PhantomData;
 use std::ops::{Range, RangeBounds};
@@ -74,6 +75,24 @@ impl<'a> RangeIterState<'a> {
             } => {
                 let accessor = BranchAccessor::new(&page, fixed_key_size);
                 let child_page = accessor.child_page(child).unwrap();
+                // Moving to the next child means the previous one was consumed, so the scan is
+                // sequential
+                let window = manager.read_ahead_pages();
+                if window > 1 {
+                    let siblings: Vec<PageNumber> = if reverse {
+                        (0..=child)
+                            .rev()
+                            .take(window)
+                            .map(|i| accessor.child_page(i).unwrap())
+                            .collect()
+                    } else {
+                        (child..accessor.count_children())
+                            .take(window)
+                            .map(|i| accessor.child_page(i).unwrap())
+                            .collect()
+                    };
+                    manager.read_ahead(&siblings)?;
+                }
                 let child_page = manager.get_page(child_page)?;
                 let direction = if reverse { -1 } else { 1 };
                 let next_child = isize::try_from(child).unwrap() + direction;
@@ -106,7 +125,7 @@ impl<'a> RangeIterState<'a> {
                             parent,
                         }))
                     }
-                    BRANCH => {
+                    BRANCH | LEGACY_BRANCH => {
                         let child_accessor = BranchAccessor::new(&child_page, fixed_key_size);
                         let child = if reverse {
                             child_accessor.count_children() - 1
@@ -204,7 +223,7 @@ impl<'a> AllPageNumbersBtreeIter<'a> {
                 entry: 0,
                 parent: None,
             },
-            BRANCH => Internal {
+            BRANCH | LEGACY_BRANCH => Internal {
                 page: root_page,
                 fixed_key_size,
                 fixed_value_size,
@@ -422,20 +441,26 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeRangeIter<'a, K, V> {
     {
         if let Some(root) = table_root {
             let (include_left, left) = match query_range.start_bound() {
-                Bound::Included(k) => find_iter_left::<K, V>(
-                    manager.get_page(root)?,
-                    None,
-                    K::as_bytes(k.borrow()).as_ref(),
-                    true,
-                    manager,
-                )?,
-                Bound::Excluded(k) => find_iter_left::<K, V>(
-                    manager.get_page(root)?,
-                    None,
-                    K::as_bytes(k.borrow()).as_ref(),
-                    false,
-                    manager,
-                )?,
+                Bound::Included(k) => {
+                    let query = K::as_bytes(k.borrow());
+                    find_iter_left::<K, V>(
+                        manager.get_page(root)?,
+                        None,
+                        &|key| K::compare(query.as_ref(), key),
+                        true,
+                        manager,
+                    )?
+                }
+                Bound::Excluded(k) => {
+                    let query = K::as_bytes(k.borrow());
+                    find_iter_left::<K, V>(
+                        manager.get_page(root)?,
+                        None,
+                        &|key| K::compare(query.as_ref(), key),
+                        false,
+                        manager,
+                    )?
+                }
                 Bound::Unbounded => {
                     let state =
                         find_iter_unbounded::<K, V>(manager.get_page(root)?, None, false, manager)?;
@@ -443,20 +468,26 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeRangeIter<'a, K, V> {
                 }
             };
             let (include_right, right) = match query_range.end_bound() {
-                Bound::Included(k) => find_iter_right::<K, V>(
-                    manager.get_page(root)?,
-                    None,
-                    K::as_bytes(k.borrow()).as_ref(),
-                    true,
-                    manager,
-                )?,
-                Bound::Excluded(k) => find_iter_right::<K, V>(
-                    manager.get_page(root)?,
-                    None,
-                    K::as_bytes(k.borrow()).as_ref(),
-                    false,
-                    manager,
-                )?,
+                Bound::Included(k) => {
+                    let query = K::as_bytes(k.borrow());
+                    find_iter_right::<K, V>(
+                        manager.get_page(root)?,
+                        None,
+                        &|key| K::compare(query.as_ref(), key),
+                        true,
+                        manager,
+                    )?
+                }
+                Bound::Excluded(k) => {
+                    let query = K::as_bytes(k.borrow());
+                    find_iter_right::<K, V>(
+                        manager.get_page(root)?,
+                        None,
+                        &|key| K::compare(query.as_ref(), key),
+                        false,
+                        manager,
+                    )?
+                }
                 Bound::Unbounded => {
                     let state =
                         find_iter_unbounded::<K, V>(manager.get_page(root)?, None, true, manager)?;
@@ -473,15 +504,66 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeRangeIter<'a, K, V> {
                 _value_type: Default::default(),
             })
         } else {
+            Ok(Self::empty(manager))
+        }
+    }
+
+    // Iterates over the keys for which K::compare_prefix() returns Equal
+    pub(crate) fn new_prefix<P: RedbValue>(
+        prefix: &[u8],
+        table_root: Option<PageNumber>,
+        manager: &'a TransactionalMemory,
+    ) -> Result<Self>
+    where
+        K: RedbKeyPrefix<P>,
+    {
+        if let Some(root) = table_root {
+            // Search for the gaps just before the first, and just after the last, matching key.
+            // The queries never compare equal, so neither bound includes the key it stops at,
+            // unless it ran off the end of the tree
+            let (include_left, left) = find_iter_left::<K, V>(
+                manager.get_page(root)?,
+                None,
+                &|key| match K::compare_prefix(prefix, key) {
+                    Ordering::Equal => Ordering::Less,
+                    ordering => ordering,
+                },
+                true,
+                manager,
+            )?;
+            let (include_right, right) = find_iter_right::<K, V>(
+                manager.get_page(root)?,
+                None,
+                &|key| match K::compare_prefix(prefix, key) {
+                    Ordering::Equal => Ordering::Greater,
+                    ordering => ordering,
+                },
+                true,
+                manager,
+            )?;
             Ok(Self {
-                left: None,
-                right: None,
-                include_left: false,
-                include_right: false,
+                left,
+                right,
+                include_left,
+                include_right,
                 manager,
                 _key_type: Default::default(),
                 _value_type: Default::default(),
             })
+        } else {
+            Ok(Self::empty(manager))
+        }
+    }
+
+    fn empty(manager: &'a TransactionalMemory) -> Self {
+        Self {
+            left: None,
+            right: None,
+            include_left: false,
+            include_right: false,
+            manager,
+            _key_type: Default::default(),
+            _value_type: Default::default(),
         }
     }
 }
@@ -620,6 +702,201 @@ impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> DoubleEndedIterator for BtreeRangeI
     }
 }
 
+// A position in the tree, which can be moved in either direction. Unlike RangeIterState, which only
+// holds the siblings still to be visited, this keeps the full path from the root to the leaf
+pub(crate) struct BtreeCursor<'a, K: RedbKey + 'a, V: RedbValue + 'a> {
+    root: Option<PageNumber>,
+    // Branch pages from the root downwards, and the index of the child on the path
+    path: Vec<(PageImpl<'a>, usize)>,
+    // Leaf page and entry index of the current position. None if the cursor is not positioned
+    leaf: Option<(PageImpl<'a>, usize)>,
+    manager: &'a TransactionalMemory,
+    _key_type: PhantomData<K>,
+    _value_type: PhantomData<V>,
+}
+
+impl<'a, K: RedbKey + 'a, V: RedbValue + 'a> BtreeCursor<'a, K, V> {
+    pub(crate) fn new(table_root: Option<PageNumber>, manager: &'a TransactionalMemory) -> Self {
+        Self {
+            root: table_root,
+            path: vec![],
+            leaf: None,
+            manager,
+            _key_type: Default::default(),
+            _value_type: Default::default(),
+        }
+    }
+
+    pub(crate) fn current(&self) -> Option<EntryGuard<'a, K, V>> {
+        let (page, entry) = self.leaf.as_ref()?;
+        let (key, value) = LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width())
+            .entry_ranges(*entry)?;
+        Some(EntryGuard::new(page.clone(), key, value))
+    }
+
+    pub(crate) fn seek_first(&mut self) -> Result {
+        self.reset();
+        if let Some(root) = self.root {
+            self.descend_to_edge(self.manager.get_page(root)?, false)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn seek_last(&mut self) -> Result {
+        self.reset();
+        if let Some(root) = self.root {
+            self.descend_to_edge(self.manager.get_page(root)?, true)?;
+        }
+        Ok(())
+    }
+
+    // Moves to the first entry greater than or equal to query
+    pub(crate) fn seek(&mut self, query: &[u8]) -> Result {
+        self.descend_to(query)?;
+        if let Some((page, entry)) = self.leaf.as_mut() {
+            let accessor = LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width());
+            if *entry == accessor.num_pairs() {
+                // The query is after the last entry in the leaf
+                *entry -= 1;
+                self.next()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Moves to the last entry less than or equal to query
+    pub(crate) fn seek_for_prev(&mut self, query: &[u8]) -> Result {
+        let found = self.descend_to(query)?;
+        if let Some((_, entry)) = self.leaf.as_mut() {
+            if !found {
+                if *entry == 0 {
+                    // The query is before the first entry in the leaf
+                    self.prev()?;
+                } else {
+                    *entry -= 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Moves to the next entry. Moving past the last entry leaves the cursor unpositioned
+    pub(crate) fn next(&mut self) -> Result {
+        if let Some((page, entry)) = self.leaf.as_mut() {
+            let accessor = LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width());
+            if *entry + 1 < accessor.num_pairs() {
+                *entry += 1;
+                Ok(())
+            } else {
+                self.step_leaf(false)
+            }
+        } else {
+            Ok(())
+        }
+    }
+
+    // Moves to the previous entry. Moving past the first entry leaves the cursor unpositioned
+    pub(crate) fn prev(&mut self) -> Result {
+        if let Some((_, entry)) = self.leaf.as_mut() {
+            if *entry > 0 {
+                *entry -= 1;
+                Ok(())
+            } else {
+                self.step_leaf(true)
+            }
+        } else {
+            Ok(())
+        }
+    }
+
+    fn reset(&mut self) {
+        self.path.clear();
+        self.leaf = None;
+    }
+
+    // Descends to the leaf which contains query, and positions the cursor where query is, or
+    // would be inserted. That may be one past the last entry of the leaf.
+    // Returns true if query was found
+    fn descend_to(&mut self, query: &[u8]) -> Result<bool> {
+        self.reset();
+        let mut page = if let Some(root) = self.root {
+            self.manager.get_page(root)?
+        } else {
+            return Ok(false);
+        };
+        loop {
+            match page.memory()[0] {
+                LEAF => {
+                    let accessor =
+                        LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width());
+                    let (entry, found) = accessor.position::<K>(query);
+                    self.leaf = Some((page, entry));
+                    return Ok(found);
+                }
+                BRANCH | LEGACY_BRANCH => {
+                    let accessor = BranchAccessor::new(&page, K::fixed_width());
+                    let (child, child_page) = accessor.child_for_key::<K>(query);
+                    let child_page = self.manager.get_page(child_page)?;
+                    self.path.push((page, child));
+                    page = child_page;
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    // Descends to the first, or last if reverse is true, entry of the subtree rooted at page
+    fn descend_to_edge(&mut self, mut page: PageImpl<'a>, reverse: bool) -> Result {
+        loop {
+            match page.memory()[0] {
+                LEAF => {
+                    let accessor =
+                        LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width());
+                    let entry = if reverse { accessor.num_pairs() - 1 } else { 0 };
+                    self.leaf = Some((page, entry));
+                    return Ok(());
+                }
+                BRANCH | LEGACY_BRANCH => {
+                    let accessor = BranchAccessor::new(&page, K::fixed_width());
+                    let child = if reverse {
+                        accessor.count_children() - 1
+                    } else {
+                        0
+                    };
+                    let child_page = self.manager.get_page(accessor.child_page(child).unwrap())?;
+                    self.path.push((page, child));
+                    page = child_page;
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    // Moves to the first entry of the next leaf, or the last entry of the previous leaf if
+    // reverse is true
+    fn step_leaf(&mut self, reverse: bool) -> Result {
+        self.leaf = None;
+        while let Some((page, child)) = self.path.pop() {
+            let accessor = BranchAccessor::new(&page, K::fixed_width());
+            let sibling = if reverse {
+                child.checked_sub(1)
+            } else if child + 1 < accessor.count_children() {
+                Some(child + 1)
+            } else {
+                None
+            };
+            if let Some(sibling) = sibling {
+                let child_page = self
+                    .manager
+                    .get_page(accessor.child_page(sibling).unwrap())?;
+                self.path.push((page, sibling));
+                return self.descend_to_edge(child_page, reverse);
+            }
+        }
+        Ok(())
+    }
+}
+
 fn find_iter_unbounded<'a, K: RedbKey, V: RedbValue>(
     page: PageImpl<'a>,
     mut parent: Option<Box<RangeIterState<'a>>>,
@@ -639,7 +916,7 @@ fn find_iter_unbounded<'a, K: RedbKey, V: RedbValue>(
                 parent,
             }))
         }
-        BRANCH => {
+        BRANCH | LEGACY_BRANCH => {
             let accessor = BranchAccessor::new(&page, K::fixed_width());
             let child_index = if reverse {
                 accessor.count_children() - 1
@@ -665,11 +942,11 @@ fn find_iter_unbounded<'a, K: RedbKey, V: RedbValue>(
 }
 
 // Returns a bool indicating whether the first entry pointed to by the state is included in the
-// queried range
+// queried range. `query` compares the queried key to the given key
 fn find_iter_left<'a, K: RedbKey, V: RedbValue>(
     page: PageImpl<'a>,
     mut parent: Option<Box<RangeIterState<'a>>>,
-    query: &[u8],
+    query: &impl Fn(&[u8]) -> Ordering,
     include_query: bool,
     manager: &'a TransactionalMemory,
 ) -> Result<(bool, Option<RangeIterState<'a>>)> {
@@ -677,7 +954,7 @@ fn find_iter_left<'a, K: RedbKey, V: RedbValue>(
     match node_mem[0] {
         LEAF => {
             let accessor = LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width());
-            let (mut position, found) = accessor.position::<K>(query);
+            let (mut position, found) = accessor.position_by(query);
             let include = if position < accessor.num_pairs() {
                 include_query || !found
             } else {
@@ -695,9 +972,9 @@ fn find_iter_left<'a, K: RedbKey, V: RedbValue>(
             };
             Ok((include, Some(result)))
         }
-        BRANCH => {
+        BRANCH | LEGACY_BRANCH => {
             let accessor = BranchAccessor::new(&page, K::fixed_width());
-            let (child_index, child_page_number) = accessor.child_for_key::<K>(query);
+            let (child_index, child_page_number) = accessor.child_by(query);
             let child_page = manager.get_page(child_page_number)?;
             if child_index < accessor.count_children() - 1 {
                 parent = Some(Box::new(Internal {
@@ -717,7 +994,7 @@ fn find_iter_left<'a, K: RedbKey, V: RedbValue>(
 fn find_iter_right<'a, K: RedbKey, V: RedbValue>(
     page: PageImpl<'a>,
     mut parent: Option<Box<RangeIterState<'a>>>,
-    query: &[u8],
+    query: &impl Fn(&[u8]) -> Ordering,
     include_query: bool,
     manager: &'a TransactionalMemory,
 ) -> Result<(bool, Option<RangeIterState<'a>>)> {
@@ -725,7 +1002,7 @@ fn find_iter_right<'a, K: RedbKey, V: RedbValue>(
     match node_mem[0] {
         LEAF => {
             let accessor = LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width());
-            let (mut position, found) = accessor.position::<K>(query);
+            let (mut position, found) = accessor.position_by(query);
             let include = if position < accessor.num_pairs() {
                 include_query && found
             } else {
@@ -743,9 +1020,9 @@ fn find_iter_right<'a, K: RedbKey, V: RedbValue>(
             };
             Ok((include, Some(result)))
         }
-        BRANCH => {
+        BRANCH | LEGACY_BRANCH => {
             let accessor = BranchAccessor::new(&page, K::fixed_width());
-            let (child_index, child_page_number) = accessor.child_for_key::<K>(query);
+            let (child_index, child_page_number) = accessor.child_by(query);
             let child_page = manager.get_page(child_page_number)?;
             if child_index > 0 && accessor.child_page(child_index - 1).is_some() {
                 parent = Some(Box::new(Internal {
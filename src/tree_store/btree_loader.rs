@@ -0,0 +1,164 @@
+use crate::tree_store::btree_base::{BranchBuilder, LeafBuilder, RawBranchBuilder, DEFERRED};
+use crate::tree_store::page_store::{Page, TransactionalMemory};
+use crate::tree_store::{Checksum, PageNumber};
+use crate::types::{RedbKey, RedbValue};
+use crate::Result;
+use std::marker::PhantomData;
+use std::ops::Range;
+
+// A completed page, waiting to be added to the level above it
+struct LoadedPage {
+    page_number: PageNumber,
+    // Number of entries in the subtree
+    len: u64,
+    // The largest key in the subtree, which separates it from the next page
+    last_key: Vec<u8>,
+}
+
+// Builds a btree bottom-up from entries pushed in ascending key order. Leaves are filled left to
+// right, and then each level of branches is built over the one below it, so every page is written
+// exactly once
+pub(crate) struct BtreeLoader<'a, K: RedbKey, V: RedbValue> {
+    mem: &'a TransactionalMemory,
+    // Pages are filled with up to this many bytes
+    target_bytes: usize,
+    // Entries of the leaf being filled
+    pairs: Vec<(Vec<u8>, Vec<u8>)>,
+    pair_bytes: usize,
+    leaves: Vec<LoadedPage>,
+    _key_type: PhantomData<K>,
+    _value_type: PhantomData<V>,
+}
+
+impl<'a, K: RedbKey, V: RedbValue> BtreeLoader<'a, K, V> {
+    // `fill_factor` is the fraction of each page to fill. Callers must check that it's in (0, 1]
+    pub(crate) fn new(mem: &'a TransactionalMemory, fill_factor: f32) -> Self {
+        debug_assert!(fill_factor > 0.0 && fill_factor <= 1.0);
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_precision_loss,
+            clippy::cast_sign_loss
+        )]
+        let target_bytes = (mem.get_page_size() as f32 * fill_factor) as usize;
+        Self {
+            mem,
+            target_bytes,
+            pairs: vec![],
+            pair_bytes: 0,
+            leaves: vec![],
+            _key_type: Default::default(),
+            _value_type: Default::default(),
+        }
+    }
+
+    // The key most recently pushed
+    pub(crate) fn last_key(&self) -> Option<&[u8]> {
+        if let Some((key, _)) = self.pairs.last() {
+            Some(key)
+        } else {
+            self.leaves.last().map(|leaf| leaf.last_key.as_slice())
+        }
+    }
+
+    // Caller must ensure that key is greater than all previously pushed keys
+    pub(crate) fn push(&mut self, key: &[u8], value: &[u8]) -> Result {
+        let pair_bytes = self.pair_bytes + key.len() + value.len();
+        if !self.pairs.is_empty()
+            && LeafBuilder::required_bytes(self.pairs.len() + 1, pair_bytes) > self.target_bytes
+        {
+            self.flush_leaf()?;
+        }
+        self.pairs.push((key.to_vec(), value.to_vec()));
+        self.pair_bytes += key.len() + value.len();
+        Ok(())
+    }
+
+    // Builds the branch levels, and returns the root of the tree
+    pub(crate) fn finish(mut self) -> Result<Option<(PageNumber, Checksum)>> {
+        if !self.pairs.is_empty() {
+            self.flush_leaf()?;
+        }
+        let mut level = std::mem::take(&mut self.leaves);
+        while level.len() > 1 {
+            level = self.build_branches(level)?;
+        }
+        Ok(level.pop().map(|root| (root.page_number, DEFERRED)))
+    }
+
+    fn flush_leaf(&mut self) -> Result {
+        let mut builder = LeafBuilder::new(
+            self.mem,
+            self.pairs.len(),
+            K::fixed_width(),
+            V::fixed_width(),
+        );
+        for (key, value) in self.pairs.iter() {
+            builder.push(key, value);
+        }
+        let page = builder.build()?;
+        let (last_key, _) = self.pairs.pop().unwrap();
+        self.leaves.push(LoadedPage {
+            page_number: page.get_page_number(),
+            len: (self.pairs.len() + 1).try_into().unwrap(),
+            last_key,
+        });
+        self.pairs.clear();
+        self.pair_bytes = 0;
+        Ok(())
+    }
+
+    // Builds the level of branches above `children`
+    fn build_branches(&self, mut children: Vec<LoadedPage>) -> Result<Vec<LoadedPage>> {
+        let mut groups: Vec<Range<usize>> = vec![];
+        let mut start = 0;
+        let mut key_bytes = 0;
+        for i in 1..children.len() {
+            // Adding child i makes the key of child i - 1 a separator
+            let num_keys = i - start;
+            let new_key_bytes = key_bytes + children[i - 1].last_key.len();
+            let required =
+                RawBranchBuilder::required_bytes(num_keys, new_key_bytes, K::fixed_width());
+            if num_keys >= 2 && required > self.target_bytes {
+                groups.push(start..i);
+                start = i;
+                key_bytes = 0;
+            } else {
+                key_bytes = new_key_bytes;
+            }
+        }
+        groups.push(start..children.len());
+
+        // Every branch needs at least two children
+        if groups.len() > 1 && groups.last().unwrap().len() == 1 {
+            let last = groups.pop().unwrap();
+            let previous = groups.last_mut().unwrap();
+            if previous.len() > 2 {
+                previous.end -= 1;
+                let start = previous.end;
+                groups.push(start..last.end);
+            } else {
+                previous.end = last.end;
+            }
+        }
+
+        let mut branches = Vec::with_capacity(groups.len());
+        for group in groups {
+            let mut builder = BranchBuilder::new(self.mem, group.len(), K::fixed_width());
+            let mut len = 0;
+            for i in group.clone() {
+                builder.push_child(children[i].page_number, DEFERRED, children[i].len);
+                if i + 1 < group.end {
+                    builder.push_key(&children[i].last_key);
+                }
+                len += children[i].len;
+            }
+            let page = builder.build()?;
+            branches.push(LoadedPage {
+                page_number: page.get_page_number(),
+                len,
+                last_key: std::mem::take(&mut children[group.end - 1].last_key),
+            });
+        }
+        Ok(branches)
+    }
+}
@@ -1,22 +1,32 @@
 use crate::tree_store::btree_base::{
-    BranchAccessor, BranchBuilder, BranchMutator, Checksum, LeafAccessor, LeafBuilder, LeafMutator,
-    BRANCH, DEFERRED, LEAF,
+    subtree_len, BranchAccessor, BranchBuilder, BranchMutator, Checksum, LeafAccessor, LeafBuilder,
+    LeafMutator, BRANCH, DEFERRED, LEAF, LEGACY_BRANCH,
 };
 use crate::tree_store::btree_mutator::DeletionResult::{
     DeletedBranch, DeletedLeaf, PartialBranch, PartialLeaf, Subtree,
 };
-use crate::tree_store::page_store::{Page, PageImpl};
-use crate::tree_store::{AccessGuardMut, PageNumber, TransactionalMemory};
+use crate::tree_store::btree_mutator::Siblings::{One, Two};
+use crate::tree_store::page_store::{Page, PageImpl, PageMut};
+use crate::tree_store::{AccessGuardMut, PageNumber, TransactionalMemory, MAX_VALUE_LENGTH};
 use crate::types::{RedbKey, RedbValue};
-use crate::{AccessGuard, Result};
-use std::cmp::{max, min};
+use crate::{AccessGuard, Result, StorageError};
+use std::cmp::{max, min, Ordering};
 use std::marker::PhantomData;
+use std::ops::Bound;
+
+fn leaf_len<K: RedbKey, V: RedbValue>(page: &PageMut) -> u64 {
+    LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width())
+        .num_pairs()
+        .try_into()
+        .unwrap()
+}
 
 // TODO: it seems like Checksum can be removed from most/all of these, now that we're using deferred checksums
 #[derive(Debug)]
+// The u64 in each variant is the number of entries in the resulting subtree
 enum DeletionResult {
     // A proper subtree
-    Subtree(PageNumber, Checksum),
+    Subtree(PageNumber, Checksum, u64),
     // A leaf with zero children
     DeletedLeaf,
     // A leaf with fewer entries than desired
@@ -24,7 +34,110 @@ enum DeletionResult {
     // A branch page subtree with fewer children than desired
     PartialBranch(PageNumber, Checksum),
     // Indicates that the branch node was deleted, and includes the only remaining child
-    DeletedBranch(PageNumber, Checksum),
+    DeletedBranch(PageNumber, Checksum, u64),
+}
+
+// A subtree built while deleting a range of keys
+#[derive(Copy, Clone, Debug)]
+struct RangeSubtree {
+    page_number: PageNumber,
+    checksum: Checksum,
+    // Number of entries in the subtree
+    len: u64,
+    // Distance from the root of the subtree to its leaves
+    height: usize,
+}
+
+// The subtrees which replace a subtree, after deleting a range from it or joining it with another
+enum Siblings {
+    One(RangeSubtree),
+    // Two subtrees of the same height, and the key which separates them
+    Two(RangeSubtree, Vec<u8>, RangeSubtree),
+}
+
+// Where the keys of a subtree lie, relative to a range
+enum RangeOverlap {
+    Disjoint,
+    Contained,
+    Partial,
+}
+
+// Keys in the subtree are greater than `lower` and less than or equal to `upper`, where None is
+// unbounded
+fn range_overlap<K: RedbKey>(
+    lower: Option<&[u8]>,
+    upper: Option<&[u8]>,
+    start: Bound<&[u8]>,
+    end: Bound<&[u8]>,
+) -> RangeOverlap {
+    let before_start = match (upper, start) {
+        (Some(upper), Bound::Included(start)) => K::compare(upper, start).is_lt(),
+        (Some(upper), Bound::Excluded(start)) => K::compare(upper, start).is_le(),
+        _ => false,
+    };
+    let after_end = match (lower, end) {
+        (Some(lower), Bound::Included(end) | Bound::Excluded(end)) => {
+            K::compare(lower, end).is_ge()
+        }
+        _ => false,
+    };
+    if before_start || after_end {
+        return RangeOverlap::Disjoint;
+    }
+    let after_start = match (lower, start) {
+        (_, Bound::Unbounded) => true,
+        (Some(lower), Bound::Included(start) | Bound::Excluded(start)) => {
+            K::compare(lower, start).is_ge()
+        }
+        (None, _) => false,
+    };
+    let before_end = match (upper, end) {
+        (_, Bound::Unbounded) => true,
+        (Some(upper), Bound::Included(end)) => K::compare(upper, end).is_le(),
+        (Some(upper), Bound::Excluded(end)) => K::compare(upper, end).is_lt(),
+        (None, _) => false,
+    };
+    if after_start && before_end {
+        RangeOverlap::Contained
+    } else {
+        RangeOverlap::Partial
+    }
+}
+
+fn in_range<K: RedbKey>(key: &[u8], start: Bound<&[u8]>, end: Bound<&[u8]>) -> bool {
+    let after_start = match start {
+        Bound::Included(start) => K::compare(key, start).is_ge(),
+        Bound::Excluded(start) => K::compare(key, start).is_gt(),
+        Bound::Unbounded => true,
+    };
+    let before_end = match end {
+        Bound::Included(end) => K::compare(key, end).is_le(),
+        Bound::Excluded(end) => K::compare(key, end).is_lt(),
+        Bound::Unbounded => true,
+    };
+    after_start && before_end
+}
+
+// Branches on the path to a leaf, and the index of the child taken in each
+type LeafBranches = Vec<(PageNumber, usize)>;
+// Exclusive lower and inclusive upper bounds of the keys in a page, as in range_overlap()
+type KeyBounds = (Option<Vec<u8>>, Option<Vec<u8>>);
+
+struct LeafPath<'a> {
+    // Branches from the root down, and the index of the child taken in each
+    branches: Vec<(PageImpl<'a>, usize)>,
+    leaf: PageImpl<'a>,
+    // True if every page on the path can be modified in place
+    dirty: bool,
+}
+
+impl<'a> LeafPath<'a> {
+    fn branch_numbers(&self) -> LeafBranches {
+        self.branches
+            .iter()
+            .map(|(page, child_index)| (page.get_page_number(), *child_index))
+            .collect()
+    }
 }
 
 struct InsertionResult<'a, V: RedbValue> {
@@ -32,8 +145,10 @@ struct InsertionResult<'a, V: RedbValue> {
     new_root: PageNumber,
     // checksum of the root page
     root_checksum: Checksum,
+    // number of entries in the new root's subtree
+    root_len: u64,
     // Following sibling, if the root had to be split
-    additional_sibling: Option<(Vec<u8>, PageNumber, Checksum)>,
+    additional_sibling: Option<(Vec<u8>, PageNumber, Checksum, u64)>,
     // The inserted value for .insert_reserve() to use
     inserted_value: AccessGuardMut<'a, V>,
     // The previous value, if any
@@ -45,6 +160,9 @@ pub(crate) struct MutateHelper<'a, 'b, K: RedbKey, V: RedbValue> {
     modify_uncommitted: bool,
     mem: &'a TransactionalMemory,
     freed: &'b mut Vec<PageNumber>,
+    // Pages on the path to a leaf which have already been read, with the root last. They're used
+    // by insert() and delete() instead of reading the pages again
+    prefetched: Vec<PageImpl<'a>>,
     _key_type: PhantomData<K>,
     _value_type: PhantomData<V>,
 }
@@ -60,6 +178,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
             modify_uncommitted: true,
             mem,
             freed,
+            prefetched: vec![],
             _key_type: Default::default(),
             _value_type: Default::default(),
         }
@@ -77,11 +196,43 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
             modify_uncommitted: false,
             mem,
             freed,
+            prefetched: vec![],
             _key_type: Default::default(),
             _value_type: Default::default(),
         }
     }
 
+    // Returns the number of entries in a page built by a BranchBuilder
+    fn branch_len(&self, page: &PageMut) -> Result<u64> {
+        BranchAccessor::new(page, K::fixed_width()).len(self.mem)
+    }
+
+    // Returns the number of entries in all the children of the branch, except the given one
+    fn len_replacing_child<T: Page>(
+        &self,
+        accessor: &BranchAccessor<'_, '_, T>,
+        child_index: usize,
+    ) -> Result<u64> {
+        let mut len = 0;
+        for i in 0..accessor.count_children() {
+            if i != child_index {
+                len += accessor.child_len(i, self.mem)?;
+            }
+        }
+        Ok(len)
+    }
+
+    // Returns the page, taking it from the prefetched path if it's the next page on it
+    fn path_page(&mut self, page_number: PageNumber) -> Result<PageImpl<'a>> {
+        match self.prefetched.pop() {
+            Some(page) if page.get_page_number() == page_number => Ok(page),
+            _ => {
+                self.prefetched.clear();
+                self.mem.get_page(page_number)
+            }
+        }
+    }
+
     fn conditional_free(&mut self, page_number: PageNumber) {
         if self.modify_uncommitted {
             if !self.mem.free_if_uncommitted(page_number) {
@@ -93,11 +244,16 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
     }
 
     pub(crate) fn delete(&mut self, key: &K::SelfType<'_>) -> Result<Option<AccessGuard<'a, V>>> {
-        if let Some((p, checksum)) = *self.root {
+        if let Some((p, _)) = *self.root {
+            let root = self.path_page(p)?;
             let (deletion_result, found) =
-                self.delete_helper(self.mem.get_page(p)?, checksum, K::as_bytes(key).as_ref())?;
+                if let Some(x) = self.delete_helper(root, K::as_bytes(key).as_ref())? {
+                    x
+                } else {
+                    return Ok(None);
+                };
             let new_root = match deletion_result {
-                Subtree(page, checksum) => Some((page, checksum)),
+                Subtree(page, checksum, _) => Some((page, checksum)),
                 DeletedLeaf => None,
                 PartialLeaf { deleted_pair } => {
                     let page = self.mem.get_page(p)?;
@@ -114,15 +270,274 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     Some((page.get_page_number(), DEFERRED))
                 }
                 PartialBranch(page_number, checksum) => Some((page_number, checksum)),
-                DeletedBranch(remaining_child, checksum) => Some((remaining_child, checksum)),
+                DeletedBranch(remaining_child, checksum, _) => Some((remaining_child, checksum)),
             };
             *self.root = new_root;
-            Ok(found)
+            Ok(Some(found))
         } else {
             Ok(None)
         }
     }
 
+    // Replaces the value of `key` with the result of `f`, which is passed the current value, or
+    // deletes the key if `f` returns None. Returns the old value and the new one
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn update(
+        &mut self,
+        key: &K::SelfType<'_>,
+        f: impl FnOnce(Option<&[u8]>) -> Option<Vec<u8>>,
+    ) -> Result<(Option<AccessGuard<'a, V>>, Option<Vec<u8>>)> {
+        let key_bytes = K::as_bytes(key);
+        let key_bytes = key_bytes.as_ref();
+        let f = |old_value| {
+            let new_value = f(old_value);
+            match new_value {
+                Some(ref value) if value.len() > MAX_VALUE_LENGTH => {
+                    Err(StorageError::ValueTooLarge(value.len()))
+                }
+                _ => Ok(new_value),
+            }
+        };
+        let (old_value, new_value) = if let Some(path) = self.find_leaf(key_bytes)? {
+            let accessor =
+                LeafAccessor::new(path.leaf.memory(), K::fixed_width(), V::fixed_width());
+            let (position, found) = accessor.position::<K>(key_bytes);
+            let old_value = if found {
+                Some(accessor.entry(position).unwrap().value())
+            } else {
+                None
+            };
+            let new_value = f(old_value)?;
+
+            // Fast-path for values which fit in the leaf, when the whole path is dirty
+            if let Some(ref value) = new_value {
+                if path.dirty
+                    && LeafMutator::sufficient_insert_inplace_space(
+                        &path.leaf,
+                        position,
+                        found,
+                        K::fixed_width(),
+                        V::fixed_width(),
+                        key_bytes,
+                        value,
+                    )
+                {
+                    let old_value =
+                        old_value.map(|old| AccessGuard::with_owned_value(old.to_vec()));
+                    let leaf = path.leaf.get_page_number();
+                    drop(accessor);
+                    let branches = path.branch_numbers();
+                    drop(path);
+                    self.insert_in_place(&branches, leaf, position, found, key_bytes, value)?;
+                    return Ok((old_value, new_value));
+                }
+            }
+            drop(accessor);
+            // Otherwise the pages are copied by insert() or delete(), which reuse the path
+            self.prefetch(path);
+            (found, new_value)
+        } else {
+            (false, f(None)?)
+        };
+
+        let old_value = match new_value {
+            Some(ref value) => self.insert(key, &V::from_bytes(value)).map(|(old, _)| old),
+            None if old_value => self.delete(key),
+            None => Ok(None),
+        };
+        self.prefetched.clear();
+        Ok((old_value?, new_value))
+    }
+
+    // Inserts the entries, which must be in ascending key order. While consecutive keys fall in
+    // the same dirty leaf, they are inserted into it directly instead of descending from the root.
+    // Otherwise the tree is descended once, and the path is reused to copy its pages if needed
+    pub(crate) fn insert_many<'k>(
+        &mut self,
+        entries: impl IntoIterator<Item = (&'k [u8], &'k [u8])>,
+    ) -> Result {
+        // The branches and the leaf which the previous entry was inserted into, if they are all
+        // dirty, and the bounds of the keys in the leaf
+        let mut cached: Option<(LeafBranches, PageNumber, KeyBounds)> = None;
+        for (key, value) in entries {
+            if let Some((ref branches, leaf, (ref lower, ref upper))) = cached {
+                let in_leaf = lower
+                    .as_ref()
+                    .map_or(true, |lower| K::compare(key, lower).is_gt())
+                    && upper
+                        .as_ref()
+                        .map_or(true, |upper| K::compare(key, upper).is_le());
+                if in_leaf {
+                    let page = self.mem.get_page(leaf)?;
+                    if let Some((position, found)) = Self::in_place_position(&page, key, value) {
+                        drop(page);
+                        self.insert_in_place(branches, leaf, position, found, key, value)?;
+                        continue;
+                    }
+                }
+            }
+
+            cached = None;
+            if let Some(path) = self.find_leaf(key)? {
+                if path.dirty {
+                    if let Some((position, found)) = Self::in_place_position(&path.leaf, key, value)
+                    {
+                        let branches = path.branch_numbers();
+                        let leaf = path.leaf.get_page_number();
+                        let bounds = Self::leaf_bounds(&path);
+                        drop(path);
+                        self.insert_in_place(&branches, leaf, position, found, key, value)?;
+                        cached = Some((branches, leaf, bounds));
+                        continue;
+                    }
+                }
+                self.prefetch(path);
+            }
+            let result = self.insert(&K::from_bytes(key), &V::from_bytes(value));
+            self.prefetched.clear();
+            result?;
+        }
+        Ok(())
+    }
+
+    // Returns the path to the leaf which contains, or would contain, `key`
+    fn find_leaf(&self, key: &[u8]) -> Result<Option<LeafPath<'a>>> {
+        let root = if let Some((root, _)) = *self.root {
+            root
+        } else {
+            return Ok(None);
+        };
+        let mut branches = vec![];
+        let mut dirty = self.modify_uncommitted;
+        let mut page = self.mem.get_page(root)?;
+        while page.memory()[0] != LEAF {
+            let accessor = BranchAccessor::new(&page, K::fixed_width());
+            let (child_index, child_page) = accessor.child_for_key::<K>(key);
+            drop(accessor);
+            dirty &= self.mem.uncommitted(page.get_page_number());
+            let child = self.mem.get_page(child_page)?;
+            branches.push((page, child_index));
+            page = child;
+        }
+        dirty &= self.mem.uncommitted(page.get_page_number());
+        Ok(Some(LeafPath {
+            branches,
+            leaf: page,
+            dirty,
+        }))
+    }
+
+    // Queues the pages on the path for the next insert() or delete() of a key in the leaf
+    fn prefetch(&mut self, path: LeafPath<'a>) {
+        self.prefetched.push(path.leaf);
+        self.prefetched
+            .extend(path.branches.into_iter().rev().map(|(page, _)| page));
+    }
+
+    // Returns the bounds of the keys in the leaf at the end of `path`
+    fn leaf_bounds(path: &LeafPath<'a>) -> KeyBounds {
+        let mut lower = None;
+        let mut upper = None;
+        for (page, child_index) in path.branches.iter().rev() {
+            if lower.is_some() && upper.is_some() {
+                break;
+            }
+            let accessor = BranchAccessor::new(page, K::fixed_width());
+            if lower.is_none() && *child_index > 0 {
+                lower = Some(accessor.key(child_index - 1).unwrap().to_vec());
+            }
+            if upper.is_none() && *child_index < accessor.count_children() - 1 {
+                upper = Some(accessor.key(*child_index).unwrap().to_vec());
+            }
+        }
+        (lower, upper)
+    }
+
+    // Returns the position of `key` in `leaf`, and whether it's already there, if the entry can be
+    // inserted without rebuilding the leaf
+    fn in_place_position(leaf: &PageImpl<'a>, key: &[u8], value: &[u8]) -> Option<(usize, bool)> {
+        let accessor = LeafAccessor::new(leaf.memory(), K::fixed_width(), V::fixed_width());
+        let (position, found) = accessor.position::<K>(key);
+        drop(accessor);
+        if LeafMutator::sufficient_insert_inplace_space(
+            leaf,
+            position,
+            found,
+            K::fixed_width(),
+            V::fixed_width(),
+            key,
+            value,
+        ) {
+            Some((position, found))
+        } else {
+            None
+        }
+    }
+
+    // Inserts into a leaf in place. The caller must ensure that the leaf and all the branches on
+    // the path to it are dirty, and that the leaf has enough space
+    fn insert_in_place(
+        &mut self,
+        branches: &LeafBranches,
+        leaf: PageNumber,
+        position: usize,
+        overwrite: bool,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result {
+        let mut page = self.mem.get_page_mut(leaf)?;
+        let mut mutator = LeafMutator::new(&mut page, K::fixed_width(), V::fixed_width());
+        mutator.insert(position, overwrite, key, value);
+        if !overwrite {
+            for &(branch, child_index) in branches {
+                let mut page = self.mem.get_page_mut(branch)?;
+                let len = BranchAccessor::new(&page, K::fixed_width())
+                    .child_len(child_index, self.mem)?;
+                BranchMutator::new(&mut page).write_child_len(child_index, len + 1);
+            }
+        }
+        Ok(())
+    }
+
+    // Deletes all entries with keys in the range, and returns the number deleted. Subtrees which
+    // lie entirely within the range are freed without being read
+    pub(crate) fn delete_range(&mut self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<u64> {
+        let (root, checksum) = if let Some(root) = *self.root {
+            root
+        } else {
+            return Ok(0);
+        };
+        let mut height = 0;
+        let mut page = self.mem.get_page(root)?;
+        while page.memory()[0] != LEAF {
+            let child = BranchAccessor::new(&page, K::fixed_width())
+                .child_page(0)
+                .unwrap();
+            page = self.mem.get_page(child)?;
+            height += 1;
+        }
+        drop(page);
+        let subtree = RangeSubtree {
+            page_number: root,
+            checksum,
+            len: subtree_len(self.mem, root)?,
+            height,
+        };
+
+        let (result, deleted) = self.delete_range_helper(subtree, None, None, start, end)?;
+        *self.root = match result {
+            None => None,
+            Some(One(subtree)) => Some((subtree.page_number, subtree.checksum)),
+            Some(Two(first, key, second)) => {
+                match self.build_branch(vec![first, second], vec![key])? {
+                    One(subtree) => Some((subtree.page_number, subtree.checksum)),
+                    Two(..) => unreachable!(),
+                }
+            }
+        };
+        Ok(deleted)
+    }
+
     #[allow(clippy::type_complexity)]
     pub(crate) fn insert(
         &mut self,
@@ -130,23 +545,25 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
         value: &V::SelfType<'_>,
     ) -> Result<(Option<AccessGuard<'a, V>>, AccessGuardMut<'a, V>)> {
         let (new_root, old_value, guard) = if let Some((p, checksum)) = *self.root {
+            let root = self.path_page(p)?;
             let result = self.insert_helper(
-                self.mem.get_page(p)?,
+                root,
                 checksum,
                 K::as_bytes(key).as_ref(),
                 V::as_bytes(value).as_ref(),
             )?;
 
-            let new_root = if let Some((key, page2, page2_checksum)) = result.additional_sibling {
-                let mut builder = BranchBuilder::new(self.mem, 2, K::fixed_width());
-                builder.push_child(result.new_root, result.root_checksum);
-                builder.push_key(&key);
-                builder.push_child(page2, page2_checksum);
-                let new_page = builder.build()?;
-                (new_page.get_page_number(), DEFERRED)
-            } else {
-                (result.new_root, result.root_checksum)
-            };
+            let new_root =
+                if let Some((key, page2, page2_checksum, page2_len)) = result.additional_sibling {
+                    let mut builder = BranchBuilder::new(self.mem, 2, K::fixed_width());
+                    builder.push_child(result.new_root, result.root_checksum, result.root_len);
+                    builder.push_key(&key);
+                    builder.push_child(page2, page2_checksum, page2_len);
+                    let new_page = builder.build()?;
+                    (new_page.get_page_number(), DEFERRED)
+                } else {
+                    (result.new_root, result.root_checksum)
+                };
             (new_root, result.old_value, result.inserted_value)
         } else {
             let key_bytes = K::as_bytes(key);
@@ -199,10 +616,12 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                         Ok(InsertionResult {
                             new_root: new_page_number,
                             root_checksum: DEFERRED,
+                            root_len: 1,
                             additional_sibling: Some((
                                 key.to_vec(),
                                 page.get_page_number(),
                                 page_checksum,
+                                1,
                             )),
                             inserted_value: guard,
                             old_value: None,
@@ -212,7 +631,8 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                         Ok(InsertionResult {
                             new_root: page.get_page_number(),
                             root_checksum: page_checksum,
-                            additional_sibling: Some((split_key, new_page_number, DEFERRED)),
+                            root_len: 1,
+                            additional_sibling: Some((split_key, new_page_number, DEFERRED, 1)),
                             inserted_value: guard,
                             old_value: None,
                         })
@@ -233,6 +653,11 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     )
                 {
                     let page_number = page.get_page_number();
+                    let new_len = if found {
+                        accessor.num_pairs()
+                    } else {
+                        accessor.num_pairs() + 1
+                    };
                     let existing_value = if found {
                         let copied_value = accessor.entry(position).unwrap().value().to_vec();
                         Some(AccessGuard::with_owned_value(copied_value))
@@ -252,6 +677,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     return Ok(InsertionResult {
                         new_root: page_number,
                         root_checksum: DEFERRED,
+                        root_len: new_len.try_into().unwrap(),
                         additional_sibling: None,
                         inserted_value: guard,
                         old_value: existing_value,
@@ -304,11 +730,13 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     let accessor =
                         LeafAccessor::new(new_page.memory(), K::fixed_width(), V::fixed_width());
                     let offset = accessor.offset_of_value(position).unwrap();
+                    let new_len = accessor.num_pairs().try_into().unwrap();
                     let guard = AccessGuardMut::new(new_page, offset, value.len());
 
                     InsertionResult {
                         new_root: new_page_number,
                         root_checksum: DEFERRED,
+                        root_len: new_len,
                         additional_sibling: None,
                         inserted_value: guard,
                         old_value: existing_value,
@@ -342,6 +770,9 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     let accessor =
                         LeafAccessor::new(new_page1.memory(), K::fixed_width(), V::fixed_width());
                     let division = accessor.num_pairs();
+                    let len2 =
+                        LeafAccessor::new(new_page2.memory(), K::fixed_width(), V::fixed_width())
+                            .num_pairs();
                     let guard = if position < division {
                         let accessor = LeafAccessor::new(
                             new_page1.memory(),
@@ -363,23 +794,31 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     InsertionResult {
                         new_root: new_page_number,
                         root_checksum: DEFERRED,
-                        additional_sibling: Some((split_key, new_page_number2, DEFERRED)),
+                        root_len: division.try_into().unwrap(),
+                        additional_sibling: Some((
+                            split_key,
+                            new_page_number2,
+                            DEFERRED,
+                            len2.try_into().unwrap(),
+                        )),
                         inserted_value: guard,
                         old_value: existing_value,
                     }
                 }
             }
-            BRANCH => {
+            BRANCH | LEGACY_BRANCH => {
                 let accessor = BranchAccessor::new(&page, K::fixed_width());
                 let (child_index, child_page) = accessor.child_for_key::<K>(key);
                 let child_checksum = accessor.child_checksum(child_index).unwrap();
-                let sub_result =
-                    self.insert_helper(self.mem.get_page(child_page)?, child_checksum, key, value)?;
+                let child = self.path_page(child_page)?;
+                let sub_result = self.insert_helper(child, child_checksum, key, value)?;
 
                 if sub_result.additional_sibling.is_none()
                     && self.modify_uncommitted
                     && self.mem.uncommitted(page.get_page_number())
                 {
+                    let new_len =
+                        self.len_replacing_child(&accessor, child_index)? + sub_result.root_len;
                     let page_number = page.get_page_number();
                     drop(page);
                     let mut mutpage = self.mem.get_page_mut(page_number)?;
@@ -389,9 +828,11 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                         sub_result.new_root,
                         sub_result.root_checksum,
                     );
+                    mutator.write_child_len(child_index, sub_result.root_len);
                     return Ok(InsertionResult {
                         new_root: mutpage.get_page_number(),
                         root_checksum: DEFERRED,
+                        root_len: new_len,
                         additional_sibling: None,
                         inserted_value: sub_result.inserted_value,
                         old_value: sub_result.old_value,
@@ -402,34 +843,44 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                 let mut builder =
                     BranchBuilder::new(self.mem, accessor.count_children() + 1, K::fixed_width());
                 if child_index == 0 {
-                    builder.push_child(sub_result.new_root, sub_result.root_checksum);
-                    if let Some((ref index_key2, page2, page2_checksum)) =
+                    builder.push_child(
+                        sub_result.new_root,
+                        sub_result.root_checksum,
+                        sub_result.root_len,
+                    );
+                    if let Some((ref index_key2, page2, page2_checksum, page2_len)) =
                         sub_result.additional_sibling
                     {
                         builder.push_key(index_key2);
-                        builder.push_child(page2, page2_checksum);
+                        builder.push_child(page2, page2_checksum, page2_len);
                     }
                 } else {
                     builder.push_child(
                         accessor.child_page(0).unwrap(),
                         accessor.child_checksum(0).unwrap(),
+                        accessor.child_len(0, self.mem)?,
                     );
                 }
                 for i in 1..accessor.count_children() {
                     if let Some(key) = accessor.key(i - 1) {
                         builder.push_key(key);
                         if i == child_index {
-                            builder.push_child(sub_result.new_root, sub_result.root_checksum);
-                            if let Some((ref index_key2, page2, page2_checksum)) =
+                            builder.push_child(
+                                sub_result.new_root,
+                                sub_result.root_checksum,
+                                sub_result.root_len,
+                            );
+                            if let Some((ref index_key2, page2, page2_checksum, page2_len)) =
                                 sub_result.additional_sibling
                             {
                                 builder.push_key(index_key2);
-                                builder.push_child(page2, page2_checksum);
+                                builder.push_child(page2, page2_checksum, page2_len);
                             }
                         } else {
                             builder.push_child(
                                 accessor.child_page(i).unwrap(),
                                 accessor.child_checksum(i).unwrap(),
+                                accessor.child_len(i, self.mem)?,
                             );
                         }
                     } else {
@@ -442,10 +893,12 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     InsertionResult {
                         new_root: new_page1.get_page_number(),
                         root_checksum: DEFERRED,
+                        root_len: self.branch_len(&new_page1)?,
                         additional_sibling: Some((
                             split_key.to_vec(),
                             new_page2.get_page_number(),
                             DEFERRED,
+                            self.branch_len(&new_page2)?,
                         )),
                         inserted_value: sub_result.inserted_value,
                         old_value: sub_result.old_value,
@@ -455,6 +908,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     InsertionResult {
                         new_root: new_page.get_page_number(),
                         root_checksum: DEFERRED,
+                        root_len: self.branch_len(&new_page)?,
                         additional_sibling: None,
                         inserted_value: sub_result.inserted_value,
                         old_value: sub_result.old_value,
@@ -474,14 +928,14 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
     fn delete_leaf_helper(
         &mut self,
         page: PageImpl<'a>,
-        checksum: Checksum,
         key: &[u8],
-    ) -> Result<(DeletionResult, Option<AccessGuard<'a, V>>)> {
+    ) -> Result<Option<(DeletionResult, AccessGuard<'a, V>)>> {
         let accessor = LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width());
         let (position, found) = accessor.position::<K>(key);
         if !found {
-            return Ok((Subtree(page.get_page_number(), checksum), None));
+            return Ok(None);
         }
+        let new_len = (accessor.num_pairs() - 1).try_into().unwrap();
         let new_kv_bytes = accessor.length_of_pairs(0, accessor.num_pairs())
             - accessor.length_of_pairs(position, position + 1);
         let new_required_bytes =
@@ -507,7 +961,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                 K::fixed_width(),
                 self.mem,
             );
-            return Ok((Subtree(page_number, DEFERRED), Some(guard)));
+            return Ok(Some((Subtree(page_number, DEFERRED, new_len), guard)));
         }
 
         let result = if accessor.num_pairs() == 1 {
@@ -533,7 +987,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                 builder.push(entry.key(), entry.value());
             }
             let new_page = builder.build()?;
-            Subtree(new_page.get_page_number(), DEFERRED)
+            Subtree(new_page.get_page_number(), DEFERRED, new_len)
         };
         let free_on_drop = if !uncommitted || !self.modify_uncommitted {
             // Won't be freed until the end of the transaction, so returning the page
@@ -544,19 +998,13 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
             true
         };
         let (start, end) = accessor.value_range(position).unwrap();
-        let guard = Some(AccessGuard::new(
-            page,
-            start,
-            end - start,
-            free_on_drop,
-            self.mem,
-        ));
-        Ok((result, guard))
+        let guard = AccessGuard::new(page, start, end - start, free_on_drop, self.mem);
+        Ok(Some((result, guard)))
     }
 
     fn finalize_branch_builder(&self, builder: BranchBuilder<'_, '_>) -> Result<DeletionResult> {
-        let result = if let Some((only_child, checksum)) = builder.to_single_child() {
-            DeletedBranch(only_child, checksum)
+        let result = if let Some((only_child, checksum, len)) = builder.to_single_child() {
+            DeletedBranch(only_child, checksum, len)
         } else {
             // TODO: can we optimize away this page allocation?
             // The PartialInternal gets returned, and then the caller has to merge it immediately
@@ -567,7 +1015,11 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
             if accessor.total_length() < self.mem.get_page_size() / 3 {
                 PartialBranch(new_page.get_page_number(), DEFERRED)
             } else {
-                Subtree(new_page.get_page_number(), DEFERRED)
+                Subtree(
+                    new_page.get_page_number(),
+                    DEFERRED,
+                    accessor.len(self.mem)?,
+                )
             }
         };
         Ok(result)
@@ -576,43 +1028,45 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
     fn delete_branch_helper(
         &mut self,
         page: PageImpl<'a>,
-        checksum: Checksum,
         key: &[u8],
-    ) -> Result<(DeletionResult, Option<AccessGuard<'a, V>>)> {
+    ) -> Result<Option<(DeletionResult, AccessGuard<'a, V>)>> {
         let accessor = BranchAccessor::new(&page, K::fixed_width());
         let original_page_number = page.get_page_number();
         let (child_index, child_page_number) = accessor.child_for_key::<K>(key);
-        let child_checksum = accessor.child_checksum(child_index).unwrap();
-        let (result, found) =
-            self.delete_helper(self.mem.get_page(child_page_number)?, child_checksum, key)?;
-        if found.is_none() {
-            return Ok((Subtree(original_page_number, checksum), None));
-        }
-        if let Subtree(new_child, new_child_checksum) = result {
-            let result_page =
-                if self.mem.uncommitted(original_page_number) && self.modify_uncommitted {
-                    drop(page);
-                    let mut mutpage = self.mem.get_page_mut(original_page_number)?;
-                    let mut mutator = BranchMutator::new(&mut mutpage);
-                    mutator.write_child_page(child_index, new_child, new_child_checksum);
-                    original_page_number
-                } else {
-                    let mut builder =
-                        BranchBuilder::new(self.mem, accessor.count_children(), K::fixed_width());
-                    builder.push_all(&accessor);
-                    builder.replace_child(child_index, new_child, new_child_checksum);
-                    let new_page = builder.build()?;
-                    self.conditional_free(original_page_number);
-                    new_page.get_page_number()
-                };
-            return Ok((Subtree(result_page, DEFERRED), found));
+        let child = self.path_page(child_page_number)?;
+        let (result, found) = if let Some(x) = self.delete_helper(child, key)? {
+            x
+        } else {
+            return Ok(None);
+        };
+        if let Subtree(new_child, new_child_checksum, new_child_len) = result {
+            let new_len = self.len_replacing_child(&accessor, child_index)? + new_child_len;
+            let result_page = if self.mem.uncommitted(original_page_number)
+                && self.modify_uncommitted
+            {
+                drop(page);
+                let mut mutpage = self.mem.get_page_mut(original_page_number)?;
+                let mut mutator = BranchMutator::new(&mut mutpage);
+                mutator.write_child_page(child_index, new_child, new_child_checksum);
+                mutator.write_child_len(child_index, new_child_len);
+                original_page_number
+            } else {
+                let mut builder =
+                    BranchBuilder::new(self.mem, accessor.count_children(), K::fixed_width());
+                builder.push_all(&accessor)?;
+                builder.replace_child(child_index, new_child, new_child_checksum, new_child_len);
+                let new_page = builder.build()?;
+                self.conditional_free(original_page_number);
+                new_page.get_page_number()
+            };
+            return Ok(Some((Subtree(result_page, DEFERRED, new_len), found)));
         }
 
         // Child is requesting to be merged with a sibling
         let mut builder = BranchBuilder::new(self.mem, accessor.count_children(), K::fixed_width());
 
         let final_result = match result {
-            Subtree(_, _) => {
+            Subtree(..) => {
                 // Handled in the if above
                 unreachable!();
             }
@@ -624,6 +1078,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     builder.push_child(
                         accessor.child_page(i).unwrap(),
                         accessor.child_checksum(i).unwrap(),
+                        accessor.child_len(i, self.mem)?,
                     );
                 }
                 let end = if child_index == accessor.count_children() - 1 {
@@ -669,8 +1124,13 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     );
                     child_builder.push_all_except(&partial_child_accessor, Some(deleted_pair));
                     let new_page = child_builder.build()?;
-                    builder.push_all(&accessor);
-                    builder.replace_child(child_index, new_page.get_page_number(), DEFERRED);
+                    builder.push_all(&accessor)?;
+                    builder.replace_child(
+                        child_index,
+                        new_page.get_page_number(),
+                        DEFERRED,
+                        (partial_child_accessor.num_pairs() - 1).try_into().unwrap(),
+                    );
 
                     let result = self.finalize_branch_builder(builder)?;
 
@@ -679,7 +1139,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                     // child_page_number does not need to be freed, because it's a leaf and the
                     // MutAccessGuard will free it
 
-                    return Ok((result, found));
+                    return Ok(Some((result, found)));
                 }
 
                 for i in 0..accessor.count_children() {
@@ -708,11 +1168,23 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                         if child_builder.should_split() {
                             let (new_page1, split_key, new_page2) = child_builder.build_split()?;
                             builder.push_key(split_key);
-                            builder.push_child(new_page1.get_page_number(), DEFERRED);
-                            builder.push_child(new_page2.get_page_number(), DEFERRED);
+                            builder.push_child(
+                                new_page1.get_page_number(),
+                                DEFERRED,
+                                leaf_len::<K, V>(&new_page1),
+                            );
+                            builder.push_child(
+                                new_page2.get_page_number(),
+                                DEFERRED,
+                                leaf_len::<K, V>(&new_page2),
+                            );
                         } else {
                             let new_page = child_builder.build()?;
-                            builder.push_child(new_page.get_page_number(), DEFERRED);
+                            builder.push_child(
+                                new_page.get_page_number(),
+                                DEFERRED,
+                                leaf_len::<K, V>(&new_page),
+                            );
                         }
 
                         let merged_key_index = max(child_index, merge_with);
@@ -720,7 +1192,11 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                             builder.push_key(accessor.key(merged_key_index).unwrap());
                         }
                     } else {
-                        builder.push_child(page_number, page_checksum);
+                        builder.push_child(
+                            page_number,
+                            page_checksum,
+                            accessor.child_len(i, self.mem)?,
+                        );
                         if i < accessor.count_children() - 1 {
                             builder.push_key(accessor.key(i).unwrap());
                         }
@@ -737,7 +1213,7 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
 
                 result
             }
-            DeletedBranch(only_grandchild, grandchild_checksum) => {
+            DeletedBranch(only_grandchild, grandchild_checksum, grandchild_len) => {
                 let merge_with = if child_index == 0 { 1 } else { child_index - 1 };
                 let merge_with_page = self
                     .mem
@@ -758,30 +1234,34 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                         );
                         let separator_key = accessor.key(min(child_index, merge_with)).unwrap();
                         if child_index < merge_with {
-                            child_builder.push_child(only_grandchild, grandchild_checksum);
+                            child_builder.push_child(
+                                only_grandchild,
+                                grandchild_checksum,
+                                grandchild_len,
+                            );
                             child_builder.push_key(separator_key);
                         }
-                        child_builder.push_all(&merge_with_accessor);
+                        child_builder.push_all(&merge_with_accessor)?;
                         if child_index > merge_with {
                             child_builder.push_key(separator_key);
-                            child_builder.push_child(only_grandchild, grandchild_checksum);
-                        }
-                        if child_builder.should_split() {
-                            let (new_page1, separator, new_page2) = child_builder.build_split()?;
-                            builder.push_child(new_page1.get_page_number(), DEFERRED);
-                            builder.push_key(separator);
-                            builder.push_child(new_page2.get_page_number(), DEFERRED);
-                        } else {
-                            let new_page = child_builder.build()?;
-                            builder.push_child(new_page.get_page_number(), DEFERRED);
+                            child_builder.push_child(
+                                only_grandchild,
+                                grandchild_checksum,
+                                grandchild_len,
+                            );
                         }
+                        self.push_merged_branch(&mut builder, child_builder)?;
 
                         let merged_key_index = max(child_index, merge_with);
                         if merged_key_index < accessor.count_children() - 1 {
                             builder.push_key(accessor.key(merged_key_index).unwrap());
                         }
                     } else {
-                        builder.push_child(page_number, page_checksum);
+                        builder.push_child(
+                            page_number,
+                            page_checksum,
+                            accessor.child_len(i, self.mem)?,
+                        );
                         if i < accessor.count_children() - 1 {
                             builder.push_key(accessor.key(i).unwrap());
                         }
@@ -820,30 +1300,26 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
                         );
                         let separator_key = accessor.key(min(child_index, merge_with)).unwrap();
                         if child_index < merge_with {
-                            child_builder.push_all(&partial_child_accessor);
+                            child_builder.push_all(&partial_child_accessor)?;
                             child_builder.push_key(separator_key);
                         }
-                        child_builder.push_all(&merge_with_accessor);
+                        child_builder.push_all(&merge_with_accessor)?;
                         if child_index > merge_with {
                             child_builder.push_key(separator_key);
-                            child_builder.push_all(&partial_child_accessor);
-                        }
-                        if child_builder.should_split() {
-                            let (new_page1, separator, new_page2) = child_builder.build_split()?;
-                            builder.push_child(new_page1.get_page_number(), DEFERRED);
-                            builder.push_key(separator);
-                            builder.push_child(new_page2.get_page_number(), DEFERRED);
-                        } else {
-                            let new_page = child_builder.build()?;
-                            builder.push_child(new_page.get_page_number(), DEFERRED);
+                            child_builder.push_all(&partial_child_accessor)?;
                         }
+                        self.push_merged_branch(&mut builder, child_builder)?;
 
                         let merged_key_index = max(child_index, merge_with);
                         if merged_key_index < accessor.count_children() - 1 {
                             builder.push_key(accessor.key(merged_key_index).unwrap());
                         }
                     } else {
-                        builder.push_child(page_number, page_checksum);
+                        builder.push_child(
+                            page_number,
+                            page_checksum,
+                            accessor.child_len(i, self.mem)?,
+                        );
                         if i < accessor.count_children() - 1 {
                             builder.push_key(accessor.key(i).unwrap());
                         }
@@ -864,7 +1340,37 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
         drop(page);
         self.conditional_free(original_page_number);
 
-        Ok((final_result, found))
+        Ok(Some((final_result, found)))
+    }
+
+    // Builds the result of merging two sibling branches, and pushes it into their parent's builder
+    fn push_merged_branch<'k>(
+        &self,
+        builder: &mut BranchBuilder<'k, 'a>,
+        child_builder: BranchBuilder<'k, 'a>,
+    ) -> Result {
+        if child_builder.should_split() {
+            let (new_page1, separator, new_page2) = child_builder.build_split()?;
+            builder.push_child(
+                new_page1.get_page_number(),
+                DEFERRED,
+                self.branch_len(&new_page1)?,
+            );
+            builder.push_key(separator);
+            builder.push_child(
+                new_page2.get_page_number(),
+                DEFERRED,
+                self.branch_len(&new_page2)?,
+            );
+        } else {
+            let new_page = child_builder.build()?;
+            builder.push_child(
+                new_page.get_page_number(),
+                DEFERRED,
+                self.branch_len(&new_page)?,
+            );
+        }
+        Ok(())
     }
 
     // Returns the page number of the sub-tree with this key deleted, or None if the sub-tree is empty.
@@ -872,14 +1378,345 @@ impl<'a, 'b, K: RedbKey, V: RedbValue> MutateHelper<'a, 'b, K, V> {
     fn delete_helper(
         &mut self,
         page: PageImpl<'a>,
-        checksum: Checksum,
         key: &[u8],
-    ) -> Result<(DeletionResult, Option<AccessGuard<'a, V>>)> {
+    ) -> Result<Option<(DeletionResult, AccessGuard<'a, V>)>> {
         let node_mem = page.memory();
         match node_mem[0] {
-            LEAF => self.delete_leaf_helper(page, checksum, key),
-            BRANCH => self.delete_branch_helper(page, checksum, key),
+            LEAF => self.delete_leaf_helper(page, key),
+            BRANCH | LEGACY_BRANCH => self.delete_branch_helper(page, key),
             _ => unreachable!(),
         }
     }
+
+    // Returns the subtrees which replace `subtree` after deleting the range from it, and the
+    // number of entries deleted. The keys of `subtree` are bounded by `lower` and `upper`, as in
+    // range_overlap(). The returned subtrees are no taller than `subtree`, but may be shorter
+    fn delete_range_helper(
+        &mut self,
+        subtree: RangeSubtree,
+        lower: Option<&[u8]>,
+        upper: Option<&[u8]>,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Result<(Option<Siblings>, u64)> {
+        if subtree.height == 0 {
+            return self.delete_range_leaf(subtree, start, end);
+        }
+
+        let page = self.mem.get_page(subtree.page_number)?;
+        let accessor = BranchAccessor::new(&page, K::fixed_width());
+        let count = accessor.count_children();
+        let mut children = vec![];
+        // keys[i] separates children[i] and children[i + 1]
+        let mut keys = vec![];
+        let mut deleted_children = vec![];
+        let mut deleted = 0;
+        for i in 0..count {
+            let child = RangeSubtree {
+                page_number: accessor.child_page(i).unwrap(),
+                checksum: accessor.child_checksum(i).unwrap(),
+                len: accessor.child_len(i, self.mem)?,
+                height: subtree.height - 1,
+            };
+            let child_lower = if i == 0 { lower } else { accessor.key(i - 1) };
+            let child_upper = if i == count - 1 {
+                upper
+            } else {
+                accessor.key(i)
+            };
+            match range_overlap::<K>(child_lower, child_upper, start, end) {
+                RangeOverlap::Disjoint => children.push(child),
+                RangeOverlap::Contained => {
+                    deleted += child.len;
+                    deleted_children.push(child.page_number);
+                    continue;
+                }
+                RangeOverlap::Partial => {
+                    let (result, child_deleted) =
+                        self.delete_range_helper(child, child_lower, child_upper, start, end)?;
+                    deleted += child_deleted;
+                    match result {
+                        None => continue,
+                        Some(One(child)) => children.push(child),
+                        Some(Two(first, key, second)) => {
+                            children.push(first);
+                            keys.push(key);
+                            children.push(second);
+                        }
+                    }
+                }
+            }
+            // The key following a child still separates it from the next remaining child
+            if i < count - 1 {
+                keys.push(accessor.key(i).unwrap().to_vec());
+            }
+        }
+        if deleted == 0 {
+            return Ok((Some(One(subtree)), 0));
+        }
+        if keys.len() == children.len() && !keys.is_empty() {
+            keys.pop();
+        }
+
+        drop(accessor);
+        drop(page);
+        for child in deleted_children {
+            self.free_subtree(child, subtree.height - 1)?;
+        }
+        self.conditional_free(subtree.page_number);
+
+        // Children that lost levels have to be joined with a sibling, so that all the leaves
+        // remain at the same depth
+        let height = subtree.height - 1;
+        while children.len() > 1 {
+            let short = if let Some(i) = children.iter().position(|child| child.height < height) {
+                i
+            } else {
+                break;
+            };
+            let i = if short > 0 { short - 1 } else { 0 };
+            let second = children.remove(i + 1);
+            let first = children.remove(i);
+            let key = keys.remove(i);
+            match self.join(first, &key, second)? {
+                One(joined) => children.insert(i, joined),
+                Two(first, key, second) if first.height < height => {
+                    match self.build_branch(vec![first, second], vec![key])? {
+                        One(joined) => children.insert(i, joined),
+                        Two(..) => unreachable!(),
+                    }
+                }
+                Two(first, key, second) => {
+                    children.insert(i, second);
+                    children.insert(i, first);
+                    keys.insert(i, key);
+                }
+            }
+        }
+
+        let result = match children.len() {
+            0 => None,
+            1 => Some(One(children.pop().unwrap())),
+            _ => Some(self.build_branch(children, keys)?),
+        };
+        Ok((result, deleted))
+    }
+
+    fn delete_range_leaf(
+        &mut self,
+        subtree: RangeSubtree,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Result<(Option<Siblings>, u64)> {
+        let page = self.mem.get_page(subtree.page_number)?;
+        let accessor = LeafAccessor::new(page.memory(), K::fixed_width(), V::fixed_width());
+        let mut builder = LeafBuilder::new(
+            self.mem,
+            accessor.num_pairs(),
+            K::fixed_width(),
+            V::fixed_width(),
+        );
+        let mut remaining = 0;
+        for i in 0..accessor.num_pairs() {
+            let entry = accessor.entry(i).unwrap();
+            if !in_range::<K>(entry.key(), start, end) {
+                builder.push(entry.key(), entry.value());
+                remaining += 1;
+            }
+        }
+        let deleted: u64 = (accessor.num_pairs() - remaining).try_into().unwrap();
+        if deleted == 0 {
+            return Ok((Some(One(subtree)), 0));
+        }
+
+        let result = if remaining > 0 {
+            let new_page = builder.build()?;
+            Some(One(RangeSubtree {
+                page_number: new_page.get_page_number(),
+                checksum: DEFERRED,
+                len: remaining.try_into().unwrap(),
+                height: 0,
+            }))
+        } else {
+            None
+        };
+        drop(accessor);
+        drop(page);
+        self.conditional_free(subtree.page_number);
+        Ok((result, deleted))
+    }
+
+    // Frees all the pages of the subtree. Leaves are not read
+    fn free_subtree(&mut self, page_number: PageNumber, height: usize) -> Result {
+        if height > 0 {
+            let page = self.mem.get_page(page_number)?;
+            let accessor = BranchAccessor::new(&page, K::fixed_width());
+            let children: Vec<PageNumber> = (0..accessor.count_children())
+                .map(|i| accessor.child_page(i).unwrap())
+                .collect();
+            drop(accessor);
+            drop(page);
+            for child in children {
+                self.free_subtree(child, height - 1)?;
+            }
+        }
+        self.conditional_free(page_number);
+        Ok(())
+    }
+
+    // Returns the children of a branch, and the keys separating them
+    fn branch_children(&self, subtree: RangeSubtree) -> Result<(Vec<RangeSubtree>, Vec<Vec<u8>>)> {
+        let page = self.mem.get_page(subtree.page_number)?;
+        let accessor = BranchAccessor::new(&page, K::fixed_width());
+        let mut children = vec![];
+        let mut keys = vec![];
+        for i in 0..accessor.count_children() {
+            children.push(RangeSubtree {
+                page_number: accessor.child_page(i).unwrap(),
+                checksum: accessor.child_checksum(i).unwrap(),
+                len: accessor.child_len(i, self.mem)?,
+                height: subtree.height - 1,
+            });
+            if i < accessor.count_children() - 1 {
+                keys.push(accessor.key(i).unwrap().to_vec());
+            }
+        }
+        Ok((children, keys))
+    }
+
+    // Builds a branch over children of the same height, splitting it if it doesn't fit in a page
+    fn build_branch(&self, children: Vec<RangeSubtree>, keys: Vec<Vec<u8>>) -> Result<Siblings> {
+        let height = children[0].height + 1;
+        let mut builder = BranchBuilder::new(self.mem, children.len(), K::fixed_width());
+        for (i, child) in children.iter().enumerate() {
+            builder.push_child(child.page_number, child.checksum, child.len);
+            if let Some(key) = keys.get(i) {
+                builder.push_key(key);
+            }
+        }
+        if builder.should_split() {
+            let (first, key, second) = builder.build_split()?;
+            let key = key.to_vec();
+            let first = RangeSubtree {
+                page_number: first.get_page_number(),
+                checksum: DEFERRED,
+                len: self.branch_len(&first)?,
+                height,
+            };
+            let second = RangeSubtree {
+                page_number: second.get_page_number(),
+                checksum: DEFERRED,
+                len: self.branch_len(&second)?,
+                height,
+            };
+            Ok(Two(first, key, second))
+        } else {
+            let page = builder.build()?;
+            Ok(One(RangeSubtree {
+                page_number: page.get_page_number(),
+                checksum: DEFERRED,
+                len: children.iter().map(|child| child.len).sum(),
+                height,
+            }))
+        }
+    }
+
+    // Joins two subtrees, where `key` separates the keys of `first` from those of `second`. The
+    // shorter subtree is attached along the near edge of the taller one
+    fn join(&mut self, first: RangeSubtree, key: &[u8], second: RangeSubtree) -> Result<Siblings> {
+        match first.height.cmp(&second.height) {
+            Ordering::Equal => self.merge_siblings(first, key, second),
+            Ordering::Greater => {
+                let (mut children, mut keys) = self.branch_children(first)?;
+                self.conditional_free(first.page_number);
+                let last = children.pop().unwrap();
+                match self.join(last, key, second)? {
+                    One(joined) => children.push(joined),
+                    Two(joined_first, joined_key, joined_second) => {
+                        children.push(joined_first);
+                        keys.push(joined_key);
+                        children.push(joined_second);
+                    }
+                }
+                self.build_branch(children, keys)
+            }
+            Ordering::Less => {
+                let (mut children, mut keys) = self.branch_children(second)?;
+                self.conditional_free(second.page_number);
+                let head = children.remove(0);
+                match self.join(first, key, head)? {
+                    One(joined) => children.insert(0, joined),
+                    Two(joined_first, joined_key, joined_second) => {
+                        children.insert(0, joined_second);
+                        children.insert(0, joined_first);
+                        keys.insert(0, joined_key);
+                    }
+                }
+                self.build_branch(children, keys)
+            }
+        }
+    }
+
+    // Merges two subtrees of the same height into one, if the result fits in a page
+    fn merge_siblings(
+        &mut self,
+        first: RangeSubtree,
+        key: &[u8],
+        second: RangeSubtree,
+    ) -> Result<Siblings> {
+        let first_page = self.mem.get_page(first.page_number)?;
+        let second_page = self.mem.get_page(second.page_number)?;
+        let new_page = if first.height == 0 {
+            let first_accessor =
+                LeafAccessor::new(first_page.memory(), K::fixed_width(), V::fixed_width());
+            let second_accessor =
+                LeafAccessor::new(second_page.memory(), K::fixed_width(), V::fixed_width());
+            let mut builder = LeafBuilder::new(
+                self.mem,
+                first_accessor.num_pairs() + second_accessor.num_pairs(),
+                K::fixed_width(),
+                V::fixed_width(),
+            );
+            builder.push_all_except(&first_accessor, None);
+            builder.push_all_except(&second_accessor, None);
+            if builder.should_split() {
+                None
+            } else {
+                Some(builder.build()?)
+            }
+        } else {
+            let first_accessor = BranchAccessor::new(&first_page, K::fixed_width());
+            let second_accessor = BranchAccessor::new(&second_page, K::fixed_width());
+            let mut builder = BranchBuilder::new(
+                self.mem,
+                first_accessor.count_children() + second_accessor.count_children(),
+                K::fixed_width(),
+            );
+            builder.push_all(&first_accessor)?;
+            builder.push_key(key);
+            builder.push_all(&second_accessor)?;
+            if builder.should_split() {
+                None
+            } else {
+                Some(builder.build()?)
+            }
+        };
+        drop(first_page);
+        drop(second_page);
+
+        if let Some(new_page) = new_page {
+            let merged = RangeSubtree {
+                page_number: new_page.get_page_number(),
+                checksum: DEFERRED,
+                len: first.len + second.len,
+                height: first.height,
+            };
+            drop(new_page);
+            self.conditional_free(first.page_number);
+            self.conditional_free(second.page_number);
+            Ok(One(merged))
+        } else {
+            Ok(Two(first, key.to_vec(), second))
+        }
+    }
 }
@@ -1,22 +1,29 @@
 mod btree;
 mod btree_base;
 mod btree_iters;
+mod btree_loader;
 mod btree_mutator;
 mod page_store;
 mod table_tree;
 
-pub(crate) use btree::{btree_stats, Btree, BtreeMut, BtreeStats, RawBtree, UntypedBtreeMut};
+pub(crate) use btree::{
+    btree_diff, btree_stats, btree_visit_entries, Btree, BtreeMut, BtreeStats, RawBtree,
+    UntypedBtreeMut,
+};
+pub(crate) use btree_base::{
+    subtree_len, LeafAccessor, LeafMutator, RawLeafBuilder, BRANCH, LEAF, LEGACY_BRANCH,
+};
 pub use btree_base::{AccessGuard, AccessGuardMut};
 pub(crate) use btree_base::{BranchAccessor, Checksum};
-pub(crate) use btree_base::{LeafAccessor, LeafMutator, RawLeafBuilder, BRANCH, LEAF};
 pub(crate) use btree_iters::{
-    AllPageNumbersBtreeIter, BtreeDrain, BtreeDrainFilter, BtreeRangeIter,
+    AllPageNumbersBtreeIter, BtreeCursor, BtreeDrain, BtreeDrainFilter, BtreeRangeIter,
 };
-pub use page_store::Savepoint;
+pub(crate) use btree_loader::BtreeLoader;
 pub(crate) use page_store::{
     CachePriority, Page, PageHint, PageNumber, SerializedSavepoint, TransactionalMemory,
     FILE_FORMAT_VERSION, MAX_VALUE_LENGTH, PAGE_SIZE,
 };
+pub use page_store::{InMemoryBackend, Savepoint, StorageBackend};
 pub(crate) use table_tree::{
-    FreedPageList, FreedTableKey, InternalTableDefinition, TableTree, TableType,
+    CompactionCursor, FreedPageList, FreedTableKey, InternalTableDefinition, TableTree, TableType,
 };
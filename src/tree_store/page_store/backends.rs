@@ -0,0 +1,176 @@
+use crate::DatabaseError;
+use std::fmt::Debug;
+use std::io;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Implements persistent storage for a database.
+///
+/// Offsets and lengths are in bytes. Implementations must behave like a regular file: reads beyond
+/// the end of the storage are an error, and writes beyond the end extend it.
+#[allow(clippy::len_without_is_empty)]
+pub trait StorageBackend: 'static + Debug + Send + Sync {
+    /// Gets the current length of the storage.
+    fn len(&self) -> Result<u64, io::Error>;
+
+    /// Reads exactly `buf.len()` bytes, starting at `offset`.
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<(), io::Error>;
+
+    /// Writes all of `buf`, starting at `offset`.
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> Result<(), io::Error>;
+
+    /// Writes each buffer in `writes` at its offset. The offsets are in ascending order, and the
+    /// writes do not overlap.
+    ///
+    /// The default implementation calls [`Self::write_all_at`] for each buffer. Backends with a
+    /// high per-request cost should override it to submit the whole batch at once
+    fn write_batch(&self, writes: &[(u64, &[u8])]) -> Result<(), io::Error> {
+        for (offset, buf) in writes {
+            self.write_all_at(buf, *offset)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the length of the storage, truncating or zero extending it as needed.
+    fn set_len(&self, len: u64) -> Result<(), io::Error>;
+
+    /// Syncs all buffered data with the persistent storage.
+    ///
+    /// If `eventual` is true, the implementation may return before the data is durable, as long as
+    /// writes made before this call are persisted before any writes made after it.
+    ///
+    /// Backends which cannot sync must return an error of kind [`io::ErrorKind::Unsupported`]. Such
+    /// backends can only be used if
+    /// [`Builder::set_allow_unsynced`](crate::Builder::set_allow_unsynced) is enabled, in which case
+    /// commits succeed without any durability guarantee
+    fn sync_data(&self, eventual: bool) -> Result<(), io::Error>;
+
+    /// Acquires an exclusive lock on the storage. Called once, when a database is opened.
+    ///
+    /// Must return an error of kind [`io::ErrorKind::WouldBlock`] if the storage is already locked
+    fn lock(&self) -> Result<(), io::Error>;
+
+    /// Renews the lock acquired by [`Self::lock`], for backends whose locks expire. Called by
+    /// [`Database::renew_lease`](crate::Database::renew_lease).
+    fn renew_lock(&self) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    /// Releases the lock acquired by [`Self::lock`]. Called when the database is closed.
+    fn unlock(&self) -> Result<(), io::Error> {
+        Ok(())
+    }
+}
+
+/// Temporary in-memory storage. Its contents are lost when the database is dropped
+#[derive(Debug, Default)]
+pub struct InMemoryBackend(RwLock<Vec<u8>>);
+
+impl InMemoryBackend {
+    /// Creates a new, empty memory backend.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn out_of_range() -> io::Error {
+        io::Error::from(io::ErrorKind::InvalidInput)
+    }
+
+    /// Gets a read guard for this backend.
+    fn read(&self) -> RwLockReadGuard<'_, Vec<u8>> {
+        self.0.read().expect("Could not acquire read lock.")
+    }
+
+    /// Gets a write guard for this backend.
+    fn write(&self) -> RwLockWriteGuard<'_, Vec<u8>> {
+        self.0.write().expect("Could not acquire write lock.")
+    }
+}
+
+impl StorageBackend for InMemoryBackend {
+    fn len(&self) -> Result<u64, io::Error> {
+        Ok(self.read().len() as u64)
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<(), io::Error> {
+        let guard = self.read();
+        let offset = usize::try_from(offset).map_err(|_| Self::out_of_range())?;
+        let end = offset
+            .checked_add(buf.len())
+            .ok_or_else(Self::out_of_range)?;
+        if end <= guard.len() {
+            buf.copy_from_slice(&guard[offset..end]);
+            Ok(())
+        } else {
+            Err(io::Error::from(io::ErrorKind::UnexpectedEof))
+        }
+    }
+
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> Result<(), io::Error> {
+        let mut guard = self.write();
+        let offset = usize::try_from(offset).map_err(|_| Self::out_of_range())?;
+        let end = offset
+            .checked_add(buf.len())
+            .ok_or_else(Self::out_of_range)?;
+        if end > guard.len() {
+            guard.resize(end, 0);
+        }
+        guard[offset..end].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn set_len(&self, len: u64) -> Result<(), io::Error> {
+        let len = usize::try_from(len).map_err(|_| Self::out_of_range())?;
+        self.write().resize(len, 0);
+        Ok(())
+    }
+
+    fn sync_data(&self, _: bool) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<(), io::Error> {
+        // Each backend is owned by exactly one Database, so there is nothing to lock
+        Ok(())
+    }
+}
+
+/// Holds the lock of a [`StorageBackend`] for as long as the database is open
+pub(super) struct LockedBackend {
+    backend: Box<dyn StorageBackend>,
+}
+
+impl LockedBackend {
+    pub(super) fn new(backend: Box<dyn StorageBackend>) -> Result<Self, DatabaseError> {
+        match backend.lock() {
+            Ok(()) => Ok(Self { backend }),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                Err(DatabaseError::DatabaseAlreadyOpen)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub(super) fn backend(&self) -> &dyn StorageBackend {
+        self.backend.as_ref()
+    }
+
+    pub(super) fn read(&self, offset: u64, len: usize) -> Result<Vec<u8>, io::Error> {
+        let mut buffer = vec![0; len];
+        self.backend.read_exact_at(&mut buffer, offset)?;
+        Ok(buffer)
+    }
+
+    pub(super) fn write(&self, offset: u64, data: &[u8]) -> Result<(), io::Error> {
+        self.backend.write_all_at(data, offset)
+    }
+
+    pub(super) fn write_batch(&self, writes: &[(u64, &[u8])]) -> Result<(), io::Error> {
+        self.backend.write_batch(writes)
+    }
+}
+
+impl Drop for LockedBackend {
+    fn drop(&mut self) {
+        let _ = self.backend.unlock();
+    }
+}
@@ -1,21 +1,24 @@
+use crate::tree_store::page_store::backends::LockedBackend;
 use crate::tree_store::page_store::base::PageHint;
-use crate::tree_store::page_store::file_lock::LockedFile;
 use crate::tree_store::LEAF;
-use crate::{DatabaseError, Result, StorageError};
+use crate::{DatabaseError, Result, StorageBackend, StorageError};
+use std::borrow::Cow;
 use std::collections::BTreeMap;
-// use std::fs::File;
 use std::io;
 use std::mem;
 use std::ops::{Index, IndexMut};
-#[cfg(any(target_os = "linux", all(unix, not(fuzzing))))]
-use std::os::unix::io::AsRawFd;
 use std::slice::SliceIndex;
 #[cfg(any(fuzzing, test, feature = "cache_metrics"))]
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 
-use crate::File;
+#[cfg(feature = "logging")]
+use log::warn;
+
+// Pages being read ahead, which are separated by at most this many pages, are fetched with a single
+// read. The pages in between are discarded
+const READ_AHEAD_MAX_GAP_PAGES: u64 = 4;
 
 // Leaf pages are cached with low priority. Everything is cached with high priority
 #[derive(Clone, Copy)]
@@ -129,6 +132,21 @@ impl PrioritizedCache {
     }
 }
 
+// Merges pages, sorted by offset, which are adjacent in the file into a single contiguous write
+fn coalesce_pages<'a>(pages: &[(u64, &'a [u8])]) -> Vec<(u64, Cow<'a, [u8]>)> {
+    let mut runs: Vec<(u64, Cow<'a, [u8]>)> = vec![];
+    for &(offset, data) in pages {
+        if let Some((run_offset, run)) = runs.last_mut() {
+            if *run_offset + run.len() as u64 == offset {
+                run.to_mut().extend_from_slice(data);
+                continue;
+            }
+        }
+        runs.push((offset, Cow::Borrowed(data)));
+    }
+    runs
+}
+
 #[derive(Default)]
 struct PrioritizedWriteCache {
     cache: BTreeMap<u64, Option<Arc<Vec<u8>>>>,
@@ -153,6 +171,10 @@ impl PrioritizedWriteCache {
         }
     }
 
+    fn contains(&self, key: &u64) -> bool {
+        self.cache.contains_key(key) || self.low_pri_cache.contains_key(key)
+    }
+
     fn get(&self, key: &u64) -> Option<&Arc<Vec<u8>>> {
         let result = self.cache.get(key);
         if result.is_some() {
@@ -227,7 +249,7 @@ impl PrioritizedWriteCache {
 }
 
 pub(super) struct PagedCachedFile {
-    file: LockedFile,
+    file: LockedBackend,
     page_size: u64,
     max_read_cache_bytes: usize,
     read_cache_bytes: AtomicUsize,
@@ -238,6 +260,10 @@ pub(super) struct PagedCachedFile {
     #[cfg(feature = "cache_metrics")]
     reads_hits: AtomicU64,
     fsync_failed: AtomicBool,
+    // Whether commits may proceed without durability when the backend cannot sync
+    allow_unsynced: bool,
+    // Set once the backend has reported that it cannot sync
+    sync_unsupported: AtomicBool,
     read_cache: Vec<RwLock<PrioritizedCache>>,
     // TODO: maybe move this cache to WriteTransaction?
     write_buffer: Mutex<PrioritizedWriteCache>,
@@ -247,24 +273,18 @@ pub(super) struct PagedCachedFile {
 
 impl PagedCachedFile {
     pub(super) fn new(
-        file: File,
+        file: Box<dyn StorageBackend>,
         page_size: u64,
         max_read_cache_bytes: usize,
         max_write_buffer_bytes: usize,
+        allow_unsynced: bool,
     ) -> Result<Self, DatabaseError> {
         let mut read_cache = Vec::with_capacity(Self::lock_stripes());
         for _ in 0..Self::lock_stripes() {
             read_cache.push(RwLock::new(PrioritizedCache::new()));
         }
 
-        let lock = LockedFile::new(file)?;
-
-        // Try to flush any pages in the page cache that are out of sync with disk.
-        // See here for why: <https://github.com/cberner/redb/issues/450>
-        #[cfg(target_os = "linux")]
-        unsafe {
-            libc::posix_fadvise64(lock.file().as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
-        }
+        let lock = LockedBackend::new(file)?;
 
         Ok(Self {
             file: lock,
@@ -278,6 +298,8 @@ impl PagedCachedFile {
             #[cfg(feature = "cache_metrics")]
             reads_hits: Default::default(),
             fsync_failed: Default::default(),
+            allow_unsynced,
+            sync_unsupported: Default::default(),
             read_cache,
             write_buffer: Mutex::new(PrioritizedWriteCache::new()),
             #[cfg(any(fuzzing, test))]
@@ -286,7 +308,11 @@ impl PagedCachedFile {
     }
 
     pub(crate) fn raw_file_len(&self) -> Result<u64> {
-        Ok(self.file.file().metadata()?.len())
+        Ok(self.file.backend().len()?)
+    }
+
+    pub(super) fn renew_lock(&self) -> Result {
+        Ok(self.file.backend().renew_lock()?)
     }
 
     #[cfg(any(fuzzing, test))]
@@ -323,12 +349,20 @@ impl PagedCachedFile {
         self.check_fsync_failure()?;
         let mut write_buffer = self.write_buffer.lock().unwrap();
 
-        for (offset, buffer) in write_buffer.cache.iter() {
-            self.file.write(*offset, buffer.as_ref().unwrap())?;
-        }
-        for (offset, buffer) in write_buffer.low_pri_cache.iter() {
-            self.file.write(*offset, buffer.as_ref().unwrap())?;
-        }
+        let mut pages: Vec<(u64, &[u8])> = write_buffer
+            .cache
+            .iter()
+            .chain(write_buffer.low_pri_cache.iter())
+            .map(|(offset, buffer)| (*offset, buffer.as_ref().unwrap().as_slice()))
+            .collect();
+        pages.sort_unstable_by_key(|(offset, _)| *offset);
+        let runs = coalesce_pages(&pages);
+        let writes: Vec<(u64, &[u8])> = runs
+            .iter()
+            .map(|(offset, data)| (*offset, data.as_ref()))
+            .collect();
+        self.file.write_batch(&writes)?;
+
         self.write_buffer_bytes.store(0, Ordering::Release);
         write_buffer.clear();
 
@@ -340,52 +374,50 @@ impl PagedCachedFile {
         // TODO: be more fine-grained about this invalidation
         self.invalidate_cache_all();
 
-        self.file.file().set_len(len).map_err(StorageError::from)
+        self.file.backend().set_len(len).map_err(StorageError::from)
     }
 
     pub(super) fn flush(&self) -> Result {
         self.check_fsync_failure()?;
         self.flush_write_buffer()?;
-        // Disable fsync when fuzzing, since it doesn't test crash consistency
-        #[cfg(not(fuzzing))]
-        {
-            let res = self.file.file().sync_data().map_err(StorageError::from);
-            if res.is_err() {
-                self.set_fsync_failed(true);
-                // Try to flush any pages in the page cache that are out of sync with disk.
-                // See here for why: <https://github.com/cberner/redb/issues/450>
-                #[cfg(target_os = "linux")]
-                unsafe {
-                    libc::posix_fadvise64(
-                        self.file.file().as_raw_fd(),
-                        0,
-                        0,
-                        libc::POSIX_FADV_DONTNEED,
-                    );
-                }
-                return res;
-            }
-        }
-
-        Ok(())
+        self.sync_data(false)
     }
 
     pub(super) fn eventual_flush(&self) -> Result {
         self.check_fsync_failure()?;
+        self.flush_write_buffer()?;
+        self.sync_data(true)
+    }
 
-        #[cfg(not(target_os = "macos"))]
-        {
-            self.flush()?;
-        }
-        #[cfg(all(target_os = "macos", not(fuzzing)))]
+    fn sync_data(&self, eventual: bool) -> Result {
+        // Disable fsync when fuzzing, since it doesn't test crash consistency
+        #[cfg(not(fuzzing))]
         {
-            self.flush_write_buffer()?;
-            let code = unsafe { libc::fcntl(self.file.file().as_raw_fd(), libc::F_BARRIERFSYNC) };
-            if code == -1 {
-                self.set_fsync_failed(true);
-                return Err(io::Error::last_os_error().into());
+            match self.file.backend().sync_data(eventual) {
+                Ok(()) => {}
+                // The backend cannot sync, so durable commits are downgraded to non-durable ones
+                Err(err) if err.kind() == io::ErrorKind::Unsupported && self.allow_unsynced => {
+                    if !self.sync_unsupported.swap(true, Ordering::AcqRel) {
+                        #[cfg(feature = "logging")]
+                        warn!("Storage backend does not support sync. Commits will not be durable");
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::Unsupported => {
+                    self.set_fsync_failed(true);
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "Storage backend does not support sync. Use Builder::set_allow_unsynced() to open it without durability",
+                    )
+                    .into());
+                }
+                Err(err) => {
+                    self.set_fsync_failed(true);
+                    return Err(err.into());
+                }
             }
         }
+        #[cfg(fuzzing)]
+        let _ = eventual;
 
         Ok(())
     }
@@ -443,9 +475,78 @@ impl PagedCachedFile {
         }
 
         let buffer = Arc::new(self.read_direct(offset, len)?);
-        let cache_size = self.read_cache_bytes.fetch_add(len, Ordering::AcqRel);
+        self.insert_read_cache(offset, buffer.clone(), cache_policy(&buffer));
+
+        Ok(buffer)
+    }
+
+    // Read the given pages, which are expected to be accessed in order, into the read cache. Nothing
+    // is read unless the first page is missing from the cache. Pages which are close together in the
+    // file are fetched with a single read
+    pub(super) fn read_ahead(
+        &self,
+        pages: &[(u64, usize)],
+        cache_policy: impl Fn(&[u8]) -> CachePriority,
+    ) -> Result {
+        let mut missing: Vec<(u64, usize)> = {
+            let write_buffer = self.write_buffer.lock().unwrap();
+            pages
+                .iter()
+                .copied()
+                .filter(|(offset, _)| {
+                    !write_buffer.contains(offset) && !self.read_cache_contains(*offset)
+                })
+                .collect()
+        };
+        // A single page is left to be read on demand
+        if missing.len() < 2 || missing[0].0 != pages[0].0 {
+            return Ok(());
+        }
+
+        missing.sort_unstable_by_key(|(offset, _)| *offset);
+        let max_gap = READ_AHEAD_MAX_GAP_PAGES * self.page_size;
+        let mut start = 0;
+        while start < missing.len() {
+            let run_offset = missing[start].0;
+            let mut run_end = run_offset + missing[start].1 as u64;
+            let mut end = start + 1;
+            while end < missing.len() && missing[end].0 <= run_end + max_gap {
+                run_end = run_end.max(missing[end].0 + missing[end].1 as u64);
+                end += 1;
+            }
+
+            let data = self.read_direct(run_offset, (run_end - run_offset).try_into().unwrap())?;
+            for &(offset, len) in &missing[start..end] {
+                let page_start: usize = (offset - run_offset).try_into().unwrap();
+                let buffer = data[page_start..(page_start + len)].to_vec();
+                let priority = cache_policy(&buffer);
+                self.insert_read_cache(offset, Arc::new(buffer), priority);
+            }
+            start = end;
+        }
+
+        Ok(())
+    }
+
+    fn read_cache_contains(&self, offset: u64) -> bool {
+        let cache_slot: usize = (offset % Self::lock_stripes() as u64).try_into().unwrap();
+        self.read_cache[cache_slot]
+            .read()
+            .unwrap()
+            .get(&offset)
+            .is_some()
+    }
+
+    fn insert_read_cache(&self, offset: u64, buffer: Arc<Vec<u8>>, priority: CachePriority) {
+        let len = buffer.len();
+        let cache_slot: usize = (offset % Self::lock_stripes() as u64).try_into().unwrap();
         let mut write_lock = self.read_cache[cache_slot].write().unwrap();
-        write_lock.insert(offset, buffer.clone(), cache_policy(&buffer));
+        // Another reader may have cached the page in the meantime
+        if write_lock.get(&offset).is_some() {
+            return;
+        }
+        let cache_size = self.read_cache_bytes.fetch_add(len, Ordering::AcqRel);
+        write_lock.insert(offset, buffer, priority);
         let mut removed = 0;
         if cache_size + len > self.max_read_cache_bytes {
             while removed < len {
@@ -459,8 +560,6 @@ impl PagedCachedFile {
         if removed > 0 {
             self.read_cache_bytes.fetch_sub(removed, Ordering::AcqRel);
         }
-
-        Ok(buffer)
     }
 
     // Discard pending writes to the given range
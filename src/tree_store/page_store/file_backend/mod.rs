@@ -0,0 +1,5 @@
+#[cfg(any(unix, target_os = "wasi"))]
+mod unix;
+
+#[cfg(windows)]
+mod windows;
@@ -0,0 +1,92 @@
+// TODO once Rust's libc has flock implemented for WASI, this file needs to be revisited.
+// What needs to be changed is commented below.
+// See also: https://github.com/WebAssembly/wasi-filesystem/issues/2
+
+// Remove this line once wasi-libc has flock
+#![cfg_attr(target_os = "wasi", allow(unused_imports))]
+
+use crate::StorageBackend;
+use std::fs::File;
+use std::io;
+
+#[cfg(unix)]
+use std::os::unix::{fs::FileExt, io::AsRawFd};
+
+#[cfg(target_os = "wasi")]
+use std::os::wasi::{fs::FileExt, io::AsRawFd};
+
+impl StorageBackend for File {
+    fn len(&self) -> Result<u64, io::Error> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<(), io::Error> {
+        FileExt::read_exact_at(self, buf, offset)
+    }
+
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> Result<(), io::Error> {
+        FileExt::write_all_at(self, buf, offset)
+    }
+
+    fn set_len(&self, len: u64) -> Result<(), io::Error> {
+        File::set_len(self, len)
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn sync_data(&self, _: bool) -> Result<(), io::Error> {
+        let result = File::sync_data(self);
+        if result.is_err() {
+            // Try to flush any pages in the page cache that are out of sync with disk.
+            // See here for why: <https://github.com/cberner/redb/issues/450>
+            #[cfg(target_os = "linux")]
+            unsafe {
+                libc::posix_fadvise64(self.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+            }
+        }
+        result
+    }
+
+    #[cfg(target_os = "macos")]
+    fn sync_data(&self, eventual: bool) -> Result<(), io::Error> {
+        if eventual {
+            let code = unsafe { libc::fcntl(self.as_raw_fd(), libc::F_BARRIERFSYNC) };
+            if code == -1 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        } else {
+            File::sync_data(self)
+        }
+    }
+
+    // This is a no-op until we get flock in wasi-libc.
+    // Delete this function when we get flock.
+    #[cfg(target_os = "wasi")]
+    fn lock(&self) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    #[cfg(unix)] // remove this line when wasi-libc gets flock
+    fn lock(&self) -> Result<(), io::Error> {
+        let result = unsafe { libc::flock(self.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Try to flush any pages in the page cache that are out of sync with disk.
+        // See here for why: <https://github.com/cberner/redb/issues/450>
+        #[cfg(target_os = "linux")]
+        unsafe {
+            libc::posix_fadvise64(self.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)] // remove this line when wasi-libc gets flock
+    fn unlock(&self) -> Result<(), io::Error> {
+        unsafe { libc::flock(self.as_raw_fd(), libc::LOCK_UN) };
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+#![allow(clippy::upper_case_acronyms)]
+
+use crate::StorageBackend;
+use std::fs::File;
+use std::io;
+use std::os::windows::fs::FileExt;
+use std::os::windows::io::AsRawHandle;
+use std::os::windows::io::RawHandle;
+
+const ERROR_LOCK_VIOLATION: i32 = 0x21;
+const ERROR_IO_PENDING: i32 = 997;
+
+extern "system" {
+    /// <https://learn.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-lockfile>
+    fn LockFile(
+        file: RawHandle,
+        offset_low: u32,
+        offset_high: u32,
+        length_low: u32,
+        length_high: u32,
+    ) -> i32;
+
+    /// <https://learn.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-unlockfile>
+    fn UnlockFile(
+        file: RawHandle,
+        offset_low: u32,
+        offset_high: u32,
+        length_low: u32,
+        length_high: u32,
+    ) -> i32;
+}
+
+impl StorageBackend for File {
+    fn len(&self) -> Result<u64, io::Error> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], mut offset: u64) -> Result<(), io::Error> {
+        let mut data_offset = 0;
+        while data_offset < buf.len() {
+            let read = self.seek_read(&mut buf[data_offset..], offset)?;
+            if read == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+            }
+            offset += read as u64;
+            data_offset += read;
+        }
+        Ok(())
+    }
+
+    fn write_all_at(&self, buf: &[u8], mut offset: u64) -> Result<(), io::Error> {
+        let mut data_offset = 0;
+        while data_offset < buf.len() {
+            let written = self.seek_write(&buf[data_offset..], offset)?;
+            offset += written as u64;
+            data_offset += written;
+        }
+        Ok(())
+    }
+
+    fn set_len(&self, len: u64) -> Result<(), io::Error> {
+        File::set_len(self, len)
+    }
+
+    fn sync_data(&self, _: bool) -> Result<(), io::Error> {
+        File::sync_data(self)
+    }
+
+    fn lock(&self) -> Result<(), io::Error> {
+        let handle = self.as_raw_handle();
+        let result = unsafe { LockFile(handle, 0, 0, u32::MAX, u32::MAX) };
+        if result == 0 {
+            let err = io::Error::last_os_error();
+            return if err.raw_os_error() == Some(ERROR_IO_PENDING)
+                || err.raw_os_error() == Some(ERROR_LOCK_VIOLATION)
+            {
+                Err(io::Error::from(io::ErrorKind::WouldBlock))
+            } else {
+                Err(err)
+            };
+        }
+
+        Ok(())
+    }
+
+    fn unlock(&self) -> Result<(), io::Error> {
+        unsafe { UnlockFile(self.as_raw_handle(), 0, 0, u32::MAX, u32::MAX) };
+        Ok(())
+    }
+}
@@ -411,21 +411,18 @@ mod test {
         TRANSACTION_0_OFFSET, TRANSACTION_1_OFFSET, USER_ROOT_CHECKSUM_OFFSET,
     };
     use crate::tree_store::page_store::TransactionalMemory;
-    #[cfg(not(target_os = "windows"))]
     use crate::StorageError;
-    use crate::{Database, ReadableTable};
-    // use std::fs::OpenOptions;
-    use std::io::{Read, Seek, SeekFrom, Write};
+    use crate::{Database, OpenOptions, ReadableTable};
     use std::mem::size_of;
 
-    use crate::OpenOptions;
-
     const X: TableDefinition<&str, &str> = TableDefinition::new("x");
 
     #[test]
     fn repair_allocator_checksums() {
-        let tmpfile = crate::create_tempfile();
-        let db = Database::builder().create(tmpfile.path()).unwrap();
+        let vfs = crate::MockVfs::new();
+        let db = Database::builder()
+            .create(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
+            .unwrap();
         let write_txn = db.begin_write().unwrap();
         {
             let mut table = write_txn.open_table(X).unwrap();
@@ -445,38 +442,31 @@ mod test {
         drop(read_txn);
         drop(db);
 
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(tmpfile.path())
-            .unwrap();
-
-        file.seek(SeekFrom::Start(GOD_BYTE_OFFSET as u64)).unwrap();
-        let mut buffer = [0u8; 1];
-        file.read_exact(&mut buffer).unwrap();
-        file.seek(SeekFrom::Start(GOD_BYTE_OFFSET as u64)).unwrap();
-        buffer[0] |= RECOVERY_REQUIRED;
-        file.write_all(&buffer).unwrap();
+        let mut contents = vfs.file(crate::TEST_DB_PATH).unwrap();
+        contents[GOD_BYTE_OFFSET] |= RECOVERY_REQUIRED;
 
         // Overwrite the primary checksum to simulate a failure during commit
-        let primary_slot_offset = if buffer[0] & PRIMARY_BIT == 0 {
+        let primary_slot_offset = if contents[GOD_BYTE_OFFSET] & PRIMARY_BIT == 0 {
             TRANSACTION_0_OFFSET
         } else {
             TRANSACTION_1_OFFSET
         };
-        file.seek(SeekFrom::Start(
-            (primary_slot_offset + USER_ROOT_CHECKSUM_OFFSET) as u64,
-        ))
-        .unwrap();
-        file.write_all(&[0; size_of::<u128>()]).unwrap();
+        let checksum_offset = primary_slot_offset + USER_ROOT_CHECKSUM_OFFSET;
+        contents[checksum_offset..(checksum_offset + size_of::<u128>())].fill(0);
+        vfs.insert_file(crate::TEST_DB_PATH, contents);
 
-        assert!(TransactionalMemory::new(file, PAGE_SIZE, None, 0, 0)
-            .unwrap()
-            .needs_repair()
-            .unwrap());
+        let file = OpenOptions::new()
+            .open(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
+            .unwrap();
+        assert!(
+            TransactionalMemory::new(Box::new(file), PAGE_SIZE, None, 0, 0, 0, false)
+                .unwrap()
+                .needs_repair()
+                .unwrap()
+        );
 
         #[allow(unused_mut)]
-        let mut db2 = Database::create(tmpfile.path()).unwrap();
+        let mut db2 = Database::create(crate::TEST_DB_PATH, vfs.config(), vfs.clone()).unwrap();
         let write_txn = db2.begin_write().unwrap();
         {
             let mut table = write_txn.open_table(X).unwrap();
@@ -485,86 +475,63 @@ mod test {
         }
         write_txn.commit().unwrap();
 
-        // Locks are exclusive on Windows, so we can't concurrently overwrite the file
-        #[cfg(not(target_os = "windows"))]
-        {
-            let mut file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(tmpfile.path())
-                .unwrap();
-            file.seek(SeekFrom::Start(GOD_BYTE_OFFSET as u64)).unwrap();
-            let mut buffer = [0u8; 1];
-            file.read_exact(&mut buffer).unwrap();
-
-            // Overwrite the primary checksum to simulate a failure during commit
-            let primary_slot_offset = if buffer[0] & PRIMARY_BIT == 0 {
-                TRANSACTION_0_OFFSET
-            } else {
-                TRANSACTION_1_OFFSET
-            };
-            file.seek(SeekFrom::Start(
-                (primary_slot_offset + USER_ROOT_CHECKSUM_OFFSET) as u64,
-            ))
-            .unwrap();
-            file.write_all(&[0; size_of::<u128>()]).unwrap();
-
-            assert!(!db2.check_integrity().unwrap());
+        // Overwrite the primary checksum to simulate a failure during commit
+        let mut contents = vfs.file(crate::TEST_DB_PATH).unwrap();
+        let primary_slot_offset = if contents[GOD_BYTE_OFFSET] & PRIMARY_BIT == 0 {
+            TRANSACTION_0_OFFSET
+        } else {
+            TRANSACTION_1_OFFSET
+        };
+        let checksum_offset = primary_slot_offset + USER_ROOT_CHECKSUM_OFFSET;
+        contents[checksum_offset..(checksum_offset + size_of::<u128>())].fill(0);
+        vfs.insert_file(crate::TEST_DB_PATH, contents.clone());
 
-            // Overwrite both checksums to simulate corruption
-            file.seek(SeekFrom::Start(GOD_BYTE_OFFSET as u64)).unwrap();
-            let mut buffer = [0u8; 1];
-            file.read_exact(&mut buffer).unwrap();
+        assert!(!db2.check_integrity().unwrap());
 
-            file.seek(SeekFrom::Start(
-                (TRANSACTION_0_OFFSET + USER_ROOT_CHECKSUM_OFFSET) as u64,
-            ))
-            .unwrap();
-            file.write_all(&[0; size_of::<u128>()]).unwrap();
-            file.seek(SeekFrom::Start(
-                (TRANSACTION_1_OFFSET + USER_ROOT_CHECKSUM_OFFSET) as u64,
-            ))
-            .unwrap();
-            file.write_all(&[0; size_of::<u128>()]).unwrap();
-
-            assert!(matches!(
-                db2.check_integrity().unwrap_err(),
-                StorageError::Corrupted(_)
-            ));
+        // Overwrite both checksums to simulate corruption
+        for slot_offset in [TRANSACTION_0_OFFSET, TRANSACTION_1_OFFSET] {
+            let checksum_offset = slot_offset + USER_ROOT_CHECKSUM_OFFSET;
+            contents[checksum_offset..(checksum_offset + size_of::<u128>())].fill(0);
         }
+        vfs.insert_file(crate::TEST_DB_PATH, contents);
+
+        assert!(matches!(
+            db2.check_integrity().unwrap_err(),
+            StorageError::Corrupted(_)
+        ));
     }
 
     #[test]
     fn repair_empty() {
-        let tmpfile = crate::create_tempfile();
-        let db = Database::builder().create(tmpfile.path()).unwrap();
-        drop(db);
-
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(tmpfile.path())
+        let vfs = crate::MockVfs::new();
+        let db = Database::builder()
+            .create(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
             .unwrap();
+        drop(db);
 
-        file.seek(SeekFrom::Start(GOD_BYTE_OFFSET as u64)).unwrap();
-        let mut buffer = [0u8; 1];
-        file.read_exact(&mut buffer).unwrap();
-        file.seek(SeekFrom::Start(GOD_BYTE_OFFSET as u64)).unwrap();
-        buffer[0] |= RECOVERY_REQUIRED;
-        file.write_all(&buffer).unwrap();
+        let mut contents = vfs.file(crate::TEST_DB_PATH).unwrap();
+        contents[GOD_BYTE_OFFSET] |= RECOVERY_REQUIRED;
+        vfs.insert_file(crate::TEST_DB_PATH, contents);
 
-        assert!(TransactionalMemory::new(file, PAGE_SIZE, None, 0, 0)
-            .unwrap()
-            .needs_repair()
-            .unwrap());
+        let file = OpenOptions::new()
+            .open(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
+            .unwrap();
+        assert!(
+            TransactionalMemory::new(Box::new(file), PAGE_SIZE, None, 0, 0, 0, false)
+                .unwrap()
+                .needs_repair()
+                .unwrap()
+        );
 
-        Database::open(tmpfile.path()).unwrap();
+        Database::open(crate::TEST_DB_PATH, vfs.config(), vfs.clone()).unwrap();
     }
 
     #[test]
     fn repair_insert_reserve_regression() {
-        let tmpfile = crate::create_tempfile();
-        let db = Database::builder().create(tmpfile.path()).unwrap();
+        let vfs = crate::MockVfs::new();
+        let db = Database::builder()
+            .create(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
+            .unwrap();
 
         let def: TableDefinition<&str, &[u8]> = TableDefinition::new("x");
 
@@ -586,25 +553,21 @@ mod test {
 
         drop(db);
 
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(tmpfile.path())
-            .unwrap();
+        let mut contents = vfs.file(crate::TEST_DB_PATH).unwrap();
+        contents[GOD_BYTE_OFFSET] |= RECOVERY_REQUIRED;
+        vfs.insert_file(crate::TEST_DB_PATH, contents);
 
-        file.seek(SeekFrom::Start(GOD_BYTE_OFFSET as u64)).unwrap();
-        let mut buffer = [0u8; 1];
-        file.read_exact(&mut buffer).unwrap();
-        file.seek(SeekFrom::Start(GOD_BYTE_OFFSET as u64)).unwrap();
-        buffer[0] |= RECOVERY_REQUIRED;
-        file.write_all(&buffer).unwrap();
-
-        assert!(TransactionalMemory::new(file, PAGE_SIZE, None, 0, 0)
-            .unwrap()
-            .needs_repair()
-            .unwrap());
+        let file = OpenOptions::new()
+            .open(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
+            .unwrap();
+        assert!(
+            TransactionalMemory::new(Box::new(file), PAGE_SIZE, None, 0, 0, 0, false)
+                .unwrap()
+                .needs_repair()
+                .unwrap()
+        );
 
-        Database::open(tmpfile.path()).unwrap();
+        Database::open(crate::TEST_DB_PATH, vfs.config(), vfs.clone()).unwrap();
     }
 
     #[test]
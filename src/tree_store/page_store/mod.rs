@@ -1,8 +1,9 @@
+mod backends;
 mod base;
 mod bitmap;
 mod buddy_allocator;
 mod cached_file;
-mod file_lock;
+mod file_backend;
 mod header;
 mod layout;
 mod page_manager;
@@ -11,6 +12,7 @@ mod savepoint;
 #[allow(dead_code)]
 mod xxh3;
 
+pub use backends::{InMemoryBackend, StorageBackend};
 pub(crate) use base::{Page, PageHint, PageNumber, MAX_VALUE_LENGTH};
 pub(crate) use header::PAGE_SIZE;
 pub(crate) use page_manager::{xxh3_checksum, TransactionalMemory, FILE_FORMAT_VERSION};
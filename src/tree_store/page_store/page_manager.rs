@@ -8,7 +8,7 @@ use crate::tree_store::page_store::layout::DatabaseLayout;
 use crate::tree_store::page_store::region::{Allocators, RegionTracker};
 use crate::tree_store::page_store::{hash128_with_seed, PageImpl, PageMut};
 use crate::tree_store::{Page, PageNumber};
-use crate::{DatabaseError, Result, StorageError};
+use crate::{DatabaseError, Result, StorageBackend, StorageError};
 #[cfg(feature = "logging")]
 use log::warn;
 use std::cmp::{max, min};
@@ -16,12 +16,9 @@ use std::cmp::{max, min};
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::convert::TryInto;
-// use std::fs::File;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
 
-use crate::File;
-
 // Regions have a maximum size of 4GiB. A `4GiB - overhead` value is the largest that can be represented,
 // because the leaf node format uses 32bit offsets
 const MAX_USABLE_REGION_SPACE: u64 = 4 * 1024 * 1024 * 1024;
@@ -32,7 +29,15 @@ const MIN_DESIRED_USABLE_BYTES: u64 = 1024 * 1024;
 
 pub(super) const INITIAL_REGIONS: u32 = 1000; // Enough for a 4TiB database
 
-pub(crate) const FILE_FORMAT_VERSION: u8 = 1;
+// Maximum number of legacy branch pages whose child counts are cached
+const MAX_LEGACY_CHILD_LENS: usize = 1024;
+
+// Version 2 stores the number of entries in each child of a branch page, and the number of values
+// in each multimap table. Version 1 files are upgraded in place: the header is updated on the next
+// commit, and their branch pages are rewritten in the new format when they're next modified. Until
+// then, the entries in the children of each of their branch pages are counted once, and cached
+pub(crate) const FILE_FORMAT_VERSION: u8 = 2;
+const MIN_FILE_FORMAT_VERSION: u8 = 1;
 
 fn ceil_log2(x: usize) -> u8 {
     if x.is_power_of_two() {
@@ -95,16 +100,23 @@ pub(crate) struct TransactionalMemory {
     // code path where there is no locking
     region_size: u64,
     region_header_with_padding_size: u64,
+    // The number of pages read at once by sequential scans
+    read_ahead_pages: usize,
+    // Number of entries in each child of the legacy branch pages that have been counted. Legacy
+    // pages are never written, so the counts are valid until their page is freed
+    legacy_child_lens: Mutex<HashMap<PageNumber, Vec<u64>>>,
 }
 
 impl TransactionalMemory {
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
-        file: File,
+        file: Box<dyn StorageBackend>,
         page_size: usize,
         requested_region_size: Option<u64>,
         read_cache_size_bytes: usize,
         write_cache_size_bytes: usize,
+        read_ahead_pages: usize,
+        allow_unsynced: bool,
     ) -> Result<Self, DatabaseError> {
         assert!(page_size.is_power_of_two() && page_size >= DB_HEADER_SIZE);
 
@@ -117,6 +129,7 @@ impl TransactionalMemory {
             page_size as u64,
             read_cache_size_bytes,
             write_cache_size_bytes,
+            allow_unsynced,
         )?;
 
         let magic_number: [u8; MAGICNUMBER.len()] =
@@ -202,7 +215,7 @@ impl TransactionalMemory {
             ))
             .into());
         }
-        if version < FILE_FORMAT_VERSION {
+        if version < MIN_FILE_FORMAT_VERSION {
             return Err(DatabaseError::UpgradeRequired(version));
         }
         let version = header.secondary_slot().version;
@@ -212,7 +225,7 @@ impl TransactionalMemory {
             ))
             .into());
         }
-        if version < FILE_FORMAT_VERSION {
+        if version < MIN_FILE_FORMAT_VERSION {
             return Err(DatabaseError::UpgradeRequired(version));
         }
 
@@ -271,6 +284,8 @@ impl TransactionalMemory {
             page_size: page_size.try_into().unwrap(),
             region_size,
             region_header_with_padding_size: region_header_size,
+            read_ahead_pages,
+            legacy_child_lens: Mutex::new(HashMap::new()),
         })
     }
 
@@ -283,11 +298,16 @@ impl TransactionalMemory {
         self.storage.invalidate_cache_all()
     }
 
+    pub(crate) fn renew_lock(&self) -> Result {
+        self.storage.renew_lock()
+    }
+
     pub(crate) fn clear_cache_and_reload(&mut self) -> Result {
         assert!(self.allocated_since_commit.lock().unwrap().is_empty());
 
         self.storage.flush()?;
         self.storage.invalidate_cache_all();
+        self.legacy_child_lens.get_mut().unwrap().clear();
 
         let header_bytes = self.storage.read_direct(0, DB_HEADER_SIZE)?;
         let (mut header, repair_info) = DatabaseHeader::from_bytes(&header_bytes);
@@ -418,6 +438,39 @@ impl TransactionalMemory {
         result
     }
 
+    // Returns the header of a copy of the database which holds only the given user tree, and the
+    // length of the copy. The copy requires recovery, which rebuilds its allocator state
+    pub(crate) fn backup_header(
+        &self,
+        user_root: Option<(PageNumber, Checksum)>,
+        transaction_id: TransactionId,
+    ) -> (Vec<u8>, u64) {
+        let mut header = self.state.lock().unwrap().header.clone();
+        header.recovery_required = true;
+        for _ in 0..2 {
+            let slot = header.secondary_slot_mut();
+            slot.user_root = user_root;
+            slot.system_root = None;
+            slot.freed_root = None;
+            slot.transaction_id = transaction_id;
+            header.swap_primary_slot();
+        }
+        let len = header.layout().len();
+
+        (header.to_bytes(true, false).to_vec(), len)
+    }
+
+    // Returns the offset of the page in the file
+    pub(crate) fn page_offset(&self, page: PageNumber) -> u64 {
+        page.address_range(
+            self.page_size as u64,
+            self.region_size,
+            self.region_header_with_padding_size,
+            self.page_size,
+        )
+        .start
+    }
+
     // Relocates the region tracker to a lower page, if possible
     // Returns true if the page was moved
     pub(crate) fn relocate_region_tracker(&self) -> Result<bool> {
@@ -528,6 +581,7 @@ impl TransactionalMemory {
         let shrunk = self.try_shrink(&mut state)?;
 
         let secondary = state.header.secondary_slot_mut();
+        secondary.version = FILE_FORMAT_VERSION;
         secondary.transaction_id = transaction_id;
         secondary.user_root = data_root;
         secondary.system_root = system_root;
@@ -586,6 +640,7 @@ impl TransactionalMemory {
 
         let mut state = self.state.lock().unwrap();
         let secondary = state.header.secondary_slot_mut();
+        secondary.version = FILE_FORMAT_VERSION;
         secondary.transaction_id = transaction_id;
         secondary.user_root = data_root;
         secondary.system_root = system_root;
@@ -643,6 +698,48 @@ impl TransactionalMemory {
         Ok(())
     }
 
+    // Returns the number of entries in the nth child of a legacy branch page, if it's been counted
+    pub(crate) fn legacy_child_len(&self, page_number: PageNumber, n: usize) -> Option<u64> {
+        self.legacy_child_lens
+            .lock()
+            .unwrap()
+            .get(&page_number)
+            .map(|lens| lens[n])
+    }
+
+    pub(crate) fn cache_legacy_child_lens(&self, page_number: PageNumber, lens: Vec<u64>) {
+        let mut legacy_child_lens = self.legacy_child_lens.lock().unwrap();
+        if legacy_child_lens.len() >= MAX_LEGACY_CHILD_LENS {
+            // Evict an arbitrary page. Its children are counted again if it's visited again
+            let evicted = *legacy_child_lens.keys().next().unwrap();
+            legacy_child_lens.remove(&evicted);
+        }
+        legacy_child_lens.insert(page_number, lens);
+    }
+
+    pub(crate) fn read_ahead_pages(&self) -> usize {
+        self.read_ahead_pages
+    }
+
+    // Read ahead pages which are about to be accessed in order, starting with the first one. See
+    // read_ahead_pages()
+    pub(crate) fn read_ahead(&self, pages: &[PageNumber]) -> Result {
+        let ranges: Vec<(u64, usize)> = pages
+            .iter()
+            .map(|page_number| {
+                let range = page_number.address_range(
+                    self.page_size as u64,
+                    self.region_size,
+                    self.region_header_with_padding_size,
+                    self.page_size,
+                );
+                (range.start, (range.end - range.start).try_into().unwrap())
+            })
+            .collect();
+        self.storage
+            .read_ahead(&ranges, CachePriority::default_btree)
+    }
+
     // TODO: make all callers explicitly provide a hint
     pub(crate) fn get_page(&self, page_number: PageNumber) -> Result<PageImpl> {
         self.get_page_extended(page_number, PageHint::None)
@@ -783,6 +880,8 @@ impl TransactionalMemory {
     }
 
     fn free_helper(&self, page: PageNumber) {
+        // The page may be reused for a different branch
+        self.legacy_child_lens.lock().unwrap().remove(&page);
         let mut state = self.state.lock().unwrap();
         let region_index = page.region;
         // Free in the regional allocator
@@ -1024,6 +1123,10 @@ impl TransactionalMemory {
         Ok(count)
     }
 
+    pub(crate) fn get_file_len(&self) -> u64 {
+        self.state.lock().unwrap().header.layout().len()
+    }
+
     pub(crate) fn get_page_size(&self) -> usize {
         self.page_size.try_into().unwrap()
     }
@@ -1104,13 +1207,40 @@ impl Drop for TransactionalMemory {
 
 #[cfg(test)]
 mod test {
-    use crate::tree_store::page_store::page_manager::INITIAL_REGIONS;
+    use crate::tree_store::page_store::cached_file::CachePriority;
+    use crate::tree_store::page_store::page_manager::{INITIAL_REGIONS, MAX_LEGACY_CHILD_LENS};
+    use crate::tree_store::{Page, PageNumber};
     use crate::{Database, TableDefinition};
 
+    #[test]
+    fn legacy_child_lens_dropped() {
+        let vfs = crate::MockVfs::new();
+        let db = Database::create(crate::TEST_DB_PATH, vfs.config(), vfs.clone()).unwrap();
+        let mem = db.get_memory();
+
+        let page = mem
+            .allocate(1, CachePriority::High)
+            .unwrap()
+            .get_page_number();
+        mem.cache_legacy_child_lens(page, vec![3, 5]);
+        assert_eq!(mem.legacy_child_len(page, 1), Some(5));
+        mem.free(page);
+        assert_eq!(mem.legacy_child_len(page, 1), None);
+
+        for i in 0..(2 * MAX_LEGACY_CHILD_LENS) {
+            let page = PageNumber::new(0, i.try_into().unwrap(), 0);
+            mem.cache_legacy_child_lens(page, vec![1]);
+        }
+        assert_eq!(
+            mem.legacy_child_lens.lock().unwrap().len(),
+            MAX_LEGACY_CHILD_LENS
+        );
+    }
+
     // Test that the region tracker expansion code works, by adding more data than fits into the initial max regions
     #[test]
     fn out_of_regions() {
-        let tmpfile = crate::create_tempfile();
+        let vfs = crate::MockVfs::new();
         let table_definition: TableDefinition<u32, &[u8]> = TableDefinition::new("x");
         let page_size = 1024;
         let big_value = vec![0u8; 5 * page_size];
@@ -1118,7 +1248,7 @@ mod test {
         let db = Database::builder()
             .set_region_size((8 * page_size).try_into().unwrap())
             .set_page_size(page_size)
-            .create(tmpfile.path())
+            .create(crate::TEST_DB_PATH, vfs.config(), vfs.clone())
             .unwrap();
 
         let txn = db.begin_write().unwrap();
@@ -12,7 +12,7 @@ use std::cmp::max;
 use std::collections::{HashMap, HashSet};
 use std::mem;
 use std::mem::size_of;
-use std::ops::RangeFull;
+use std::ops::{Bound, RangeFull};
 use std::sync::{Arc, Mutex};
 
 // Forward compatibility feature in case alignment can be supported in the future
@@ -133,7 +133,6 @@ impl RedbValue for FreedPageList<'_> {
     type AsBytes<'a> = &'a [u8]
         where
             Self: 'a;
-
     fn fixed_width() -> Option<usize> {
         None
     }
@@ -188,11 +187,15 @@ impl Into<u8> for TableType {
     }
 }
 
+// Type of a multimap table which is followed by the number of values in the table. Tables written
+// by file format version 1 don't store it
+const COUNTED_MULTIMAP: u8 = 3;
+
 impl From<u8> for TableType {
     fn from(value: u8) -> Self {
         match value {
             1 => TableType::Normal,
-            2 => TableType::Multimap,
+            2 | COUNTED_MULTIMAP => TableType::Multimap,
             _ => unreachable!(),
         }
     }
@@ -208,6 +211,8 @@ pub(crate) struct InternalTableDefinition {
     value_alignment: usize,
     key_type: TypeName,
     value_type: TypeName,
+    // Number of values in a multimap table, if it's known
+    num_values: Option<u64>,
 }
 
 impl InternalTableDefinition {
@@ -234,6 +239,18 @@ impl InternalTableDefinition {
     pub(crate) fn get_type(&self) -> TableType {
         self.table_type
     }
+
+    pub(crate) fn get_key_type(&self) -> &TypeName {
+        &self.key_type
+    }
+
+    pub(crate) fn get_value_type(&self) -> &TypeName {
+        &self.value_type
+    }
+
+    pub(crate) fn get_num_values(&self) -> Option<u64> {
+        self.num_values
+    }
 }
 
 impl RedbValue for InternalTableDefinition {
@@ -251,7 +268,19 @@ impl RedbValue for InternalTableDefinition {
         debug_assert!(data.len() > 22);
         let mut offset = 0;
         let table_type = TableType::from(data[offset]);
-        offset += 1;
+        let num_values = if data[offset] == COUNTED_MULTIMAP {
+            offset += 1;
+            let num_values = u64::from_le_bytes(
+                data[offset..(offset + size_of::<u64>())]
+                    .try_into()
+                    .unwrap(),
+            );
+            offset += size_of::<u64>();
+            Some(num_values)
+        } else {
+            offset += 1;
+            None
+        };
 
         let non_null = data[offset] != 0;
         offset += 1;
@@ -334,6 +363,7 @@ impl RedbValue for InternalTableDefinition {
             value_alignment,
             key_type,
             value_type,
+            num_values,
         }
     }
 
@@ -342,7 +372,13 @@ impl RedbValue for InternalTableDefinition {
         Self: 'a,
         Self: 'b,
     {
-        let mut result = vec![value.table_type.into()];
+        let mut result = vec![];
+        if let Some(num_values) = value.num_values {
+            result.push(COUNTED_MULTIMAP);
+            result.extend_from_slice(&num_values.to_le_bytes());
+        } else {
+            result.push(value.table_type.into());
+        }
         if let Some((root, checksum)) = value.table_root {
             result.push(1);
             result.extend_from_slice(&root.to_le_bytes());
@@ -406,11 +442,32 @@ impl<'a> Iterator for TableNameIter<'a> {
     }
 }
 
+// Where incremental compaction stopped, so that the next step can resume there
+#[derive(Default)]
+pub(crate) struct CompactionCursor {
+    // The table being relocated, or None if no table has been started
+    table: Option<String>,
+    // True once all the tables have been relocated, and the table tree itself is being relocated
+    table_tree: bool,
+    // The index of the child being relocated at each level of the current tree
+    path: Vec<usize>,
+}
+
+impl CompactionCursor {
+    // Returns true if no tree has been started
+    pub(crate) fn at_start(&self) -> bool {
+        self.table.is_none() && !self.table_tree
+    }
+}
+
 pub(crate) struct TableTree<'txn> {
     tree: BtreeMut<'txn, &'static str, InternalTableDefinition>,
     mem: &'txn TransactionalMemory,
     // Cached updates from tables that have been closed. These must be flushed to the btree
     pending_table_updates: HashMap<String, Option<(PageNumber, Checksum)>>,
+    // Cached number of values in multimap tables that have been closed. These are flushed along
+    // with the table roots
+    pending_num_values: HashMap<String, u64>,
     freed_pages: Arc<Mutex<Vec<PageNumber>>>,
 }
 
@@ -424,10 +481,15 @@ impl<'txn> TableTree<'txn> {
             tree: BtreeMut::new(master_root, mem, freed_pages.clone()),
             mem,
             pending_table_updates: Default::default(),
+            pending_num_values: Default::default(),
             freed_pages,
         }
     }
 
+    pub(crate) fn get_root(&self) -> Option<(PageNumber, Checksum)> {
+        self.tree.get_root()
+    }
+
     pub(crate) fn all_referenced_pages(&self) -> Result<HashSet<PageNumber>> {
         // All the pages in the table tree itself
         let mut result = HashSet::new();
@@ -474,12 +536,19 @@ impl<'txn> TableTree<'txn> {
             .insert(name.to_string(), table_root);
     }
 
+    // Queues an update to the number of values in a multimap table, along with its root
+    pub(crate) fn stage_update_num_values(&mut self, name: &str, num_values: u64) {
+        self.pending_num_values.insert(name.to_string(), num_values);
+    }
+
     pub(crate) fn clear_table_root_updates(&mut self) {
         self.pending_table_updates.clear();
+        self.pending_num_values.clear();
     }
 
     pub(crate) fn verify_checksums(&self) -> Result<bool> {
         assert!(self.pending_table_updates.is_empty());
+        assert!(self.pending_num_values.is_empty());
         if !self.tree.verify_checksum()? {
             return Ok(false);
         }
@@ -524,12 +593,19 @@ impl<'txn> TableTree<'txn> {
         for (name, table_root) in self.pending_table_updates.drain() {
             // Bypass .get_table() since the table types are dynamic
             let mut definition = self.tree.get(&name.as_str())?.unwrap().value();
-            // No-op if the root has not changed
-            if definition.table_root == table_root {
+            let num_values = self.pending_num_values.remove(&name);
+            let recounted = num_values.is_some() && definition.num_values != num_values;
+            // No-op if the root and number of values have not changed
+            if definition.table_root == table_root && !recounted {
                 continue;
             }
+            if recounted {
+                definition.num_values = num_values;
+            }
             // Finalize any dirty checksums
-            if definition.table_type == TableType::Normal {
+            if definition.table_root == table_root {
+                // Only the number of values changed
+            } else if definition.table_type == TableType::Normal {
                 let mut tree = UntypedBtreeMut::new(
                     table_root,
                     self.mem,
@@ -549,6 +625,7 @@ impl<'txn> TableTree<'txn> {
             }
             self.tree.insert(&name.as_str(), &definition)?;
         }
+        debug_assert!(self.pending_num_values.is_empty());
         self.tree.finalize_dirty_checksums()?;
         Ok(self.tree.get_root())
     }
@@ -599,6 +676,9 @@ impl<'txn> TableTree<'txn> {
             if let Some(updated_root) = self.pending_table_updates.get(name) {
                 definition.table_root = *updated_root;
             }
+            if let Some(num_values) = self.pending_num_values.get(name) {
+                definition.num_values = Some(*num_values);
+            }
 
             Ok(Some(definition))
         } else {
@@ -665,6 +745,7 @@ impl<'txn> TableTree<'txn> {
             }
 
             self.pending_table_updates.remove(name);
+            self.pending_num_values.remove(name);
 
             let found = self.tree.remove(&name)?.is_some();
             return Ok(found);
@@ -693,39 +774,71 @@ impl<'txn> TableTree<'txn> {
             value_alignment: ALIGNMENT,
             key_type: K::type_name(),
             value_type: V::type_name(),
+            num_values: if table_type == TableType::Multimap {
+                Some(0)
+            } else {
+                None
+            },
         };
         self.tree.insert(&name, &table)?;
         Ok(table)
     }
 
-    pub(crate) fn compact_tables(&mut self) -> Result<bool> {
+    // Relocates the pages of all tables, and then of the table tree, to lower pages, reading at
+    // most `budget` pages. Resumes from `cursor`, and updates it. Returns whether any pages were
+    // relocated, and whether all the trees have been visited
+    pub(crate) fn compact_tables(
+        &mut self,
+        budget: &mut usize,
+        cursor: &mut CompactionCursor,
+    ) -> Result<(bool, bool)> {
         let mut progress = false;
-        for entry in self.tree.range::<RangeFull, &str>(&(..))? {
-            let entry = entry?;
-            let mut definition = entry.value();
-            if let Some(updated_root) = self.pending_table_updates.get(entry.key()) {
-                definition.table_root = *updated_root;
-            }
+        if !cursor.table_tree {
+            let start = match cursor.table {
+                Some(ref table) => Bound::Included(table.as_str()),
+                None => Bound::Unbounded,
+            };
+            for entry in self
+                .tree
+                .range::<(Bound<&str>, Bound<&str>), &str>(&(start, Bound::Unbounded))?
+            {
+                let entry = entry?;
+                if cursor.table.as_deref() != Some(entry.key()) {
+                    cursor.table = Some(entry.key().to_string());
+                    cursor.path.clear();
+                }
+                let mut definition = entry.value();
+                if let Some(updated_root) = self.pending_table_updates.get(entry.key()) {
+                    definition.table_root = *updated_root;
+                }
 
-            let mut tree = UntypedBtreeMut::new(
-                definition.table_root,
-                self.mem,
-                self.freed_pages.clone(),
-                definition.fixed_key_size,
-                definition.fixed_value_size,
-            );
-            if tree.relocate()? {
-                progress = true;
-                self.pending_table_updates
-                    .insert(entry.key().to_string(), tree.get_root());
+                let mut tree = UntypedBtreeMut::new(
+                    definition.table_root,
+                    self.mem,
+                    self.freed_pages.clone(),
+                    definition.fixed_key_size,
+                    definition.fixed_value_size,
+                );
+                let (relocated, finished) = tree.relocate(budget, &mut cursor.path)?;
+                if relocated {
+                    progress = true;
+                    self.pending_table_updates
+                        .insert(entry.key().to_string(), tree.get_root());
+                }
+                if !finished {
+                    return Ok((progress, false));
+                }
             }
+            cursor.table = None;
+            cursor.table_tree = true;
+            cursor.path.clear();
         }
 
-        if self.tree.relocate()? {
-            progress = true;
+        let (relocated, finished) = self.tree.relocate(budget, &mut cursor.path)?;
+        if finished {
+            *cursor = CompactionCursor::default();
         }
-
-        Ok(progress)
+        Ok((progress || relocated, finished))
     }
 
     pub fn stats(&self) -> Result<DatabaseStats> {
@@ -807,6 +920,15 @@ mod test {
             value_alignment: 7,
             key_type: TypeName::new("test::Key"),
             value_type: TypeName::new("test::Value"),
+            num_values: Some(42),
+        };
+        let y = InternalTableDefinition::from_bytes(InternalTableDefinition::as_bytes(&x).as_ref());
+        assert_eq!(x, y);
+
+        // Written by file format version 1
+        let x = InternalTableDefinition {
+            num_values: None,
+            ..x
         };
         let y = InternalTableDefinition::from_bytes(InternalTableDefinition::as_bytes(&x).as_ref());
         assert_eq!(x, y);
@@ -0,0 +1,405 @@
+use crate::sealed::Sealed;
+use crate::types::{RedbKey, RedbValue, TypeName};
+use crate::{
+    AccessGuard, MultimapTable, Range, ReadOnlyTable, ReadableMultimapTable, ReadableTable, Result,
+    Table,
+};
+use std::borrow::Borrow;
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::ops::RangeBounds;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// The expiry time of each entry, in milliseconds since the Unix epoch, is stored before its value.
+// Entries are expired once the current time reaches their expiry time
+const EXPIRY_SIZE: usize = size_of::<u64>();
+
+// The maximum number of expired entries purged by each write to a TTL table
+const LAZY_PURGE_LIMIT: u64 = 16;
+
+fn to_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |duration| {
+        duration.as_millis().try_into().unwrap_or(u64::MAX)
+    })
+}
+
+fn now_millis() -> u64 {
+    to_millis(SystemTime::now())
+}
+
+// The name of the multimap table, from expiry time to keys, which indexes the entries of a TTL
+// table by expiry time
+pub(crate) fn expiry_table_name(name: &str) -> String {
+    format!("{name}$expiry")
+}
+
+// A value, prefixed by its expiry time
+#[derive(Debug)]
+pub(crate) struct Expiring<V: RedbValue>(PhantomData<V>);
+
+impl<V: RedbValue> Expiring<V> {
+    fn encode(expiry: u64, value: &V::SelfType<'_>) -> Vec<u8> {
+        let value = V::as_bytes(value);
+        let mut result = Vec::with_capacity(EXPIRY_SIZE + value.as_ref().len());
+        result.extend_from_slice(&expiry.to_le_bytes());
+        result.extend_from_slice(value.as_ref());
+        result
+    }
+}
+
+impl<V: RedbValue> RedbValue for Expiring<V> {
+    type SelfType<'a> = (u64, V::SelfType<'a>)
+    where
+        Self: 'a;
+    type AsBytes<'a> = Vec<u8>
+    where
+        Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        V::fixed_width().map(|width| EXPIRY_SIZE + width)
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> (u64, V::SelfType<'a>)
+    where
+        Self: 'a,
+    {
+        let expiry = u64::from_le_bytes(data[..EXPIRY_SIZE].try_into().unwrap());
+        (expiry, V::from_bytes(&data[EXPIRY_SIZE..]))
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Vec<u8>
+    where
+        Self: 'a,
+        Self: 'b,
+    {
+        Self::encode(value.0, &value.1)
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::internal(&format!("redb::Expiring<{}>", V::type_name().name()))
+    }
+}
+
+// Returns the value of the entry, or None if it has expired
+fn unexpired<V: RedbValue + 'static>(
+    guard: AccessGuard<Expiring<V>>,
+    now: u64,
+) -> Option<AccessGuard<V>> {
+    if guard.value().0 > now {
+        Some(guard.into_suffix(EXPIRY_SIZE))
+    } else {
+        None
+    }
+}
+
+/// Defines the name and types of a table whose entries expire
+///
+/// The entries are stored in a table named `name`, and indexed by expiry time in a multimap
+/// table named `name$expiry`
+pub struct TtlTableDefinition<'a, K: RedbKey + 'static, V: RedbValue + 'static> {
+    name: &'a str,
+    _key_type: PhantomData<K>,
+    _value_type: PhantomData<V>,
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> TtlTableDefinition<'a, K, V> {
+    /// Construct a new table with given `name`
+    ///
+    /// ## Invariant
+    ///
+    /// `name` must not be empty.
+    pub const fn new(name: &'a str) -> Self {
+        assert!(!name.is_empty());
+        Self {
+            name,
+            _key_type: PhantomData,
+            _value_type: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name
+    }
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Clone for TtlTableDefinition<'a, K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Copy for TtlTableDefinition<'a, K, V> {}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Display for TtlTableDefinition<'a, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}<{}, {}>",
+            self.name,
+            K::type_name().name(),
+            V::type_name().name()
+        )
+    }
+}
+
+/// A table whose entries expire
+///
+/// Expired entries are hidden from reads. They are purged by later writes to the table, a few at
+/// a time, or by [`TtlTable::purge_expired`]
+pub struct TtlTable<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> {
+    table: Table<'db, 'txn, K, Expiring<V>>,
+    expiry: MultimapTable<'db, 'txn, u64, K>,
+}
+
+impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> TtlTable<'db, 'txn, K, V> {
+    pub(crate) fn new(
+        table: Table<'db, 'txn, K, Expiring<V>>,
+        expiry: MultimapTable<'db, 'txn, u64, K>,
+    ) -> Self {
+        Self { table, expiry }
+    }
+
+    // Purges up to `limit` entries which expired at or before `now`
+    fn purge(&mut self, now: u64, limit: u64) -> Result<u64> {
+        let mut expired = vec![];
+        'outer: for entry in self.expiry.range(..=now)? {
+            let (expiry, keys) = entry?;
+            for key in keys {
+                if u64::try_from(expired.len()).unwrap() >= limit {
+                    break 'outer;
+                }
+                expired.push((expiry.value(), K::as_bytes(&key?.value()).as_ref().to_vec()));
+            }
+        }
+        for (expiry, key) in expired.iter() {
+            self.expiry.remove(expiry, K::from_bytes(key))?;
+            self.table.remove(K::from_bytes(key))?;
+        }
+        Ok(expired.len().try_into().unwrap())
+    }
+
+    /// Removes up to `limit` expired entries, and returns the number removed
+    ///
+    /// The cost is proportional to the number of entries removed, not to the size of the table
+    pub fn purge_expired(&mut self, limit: u64) -> Result<u64> {
+        self.purge(now_millis(), limit)
+    }
+
+    /// Insert mapping of the given key to the given value, which expires after `ttl`
+    ///
+    /// Returns the old value, if the key was present in the table and had not expired
+    pub fn insert<'k, 'v>(
+        &mut self,
+        key: impl Borrow<K::SelfType<'k>>,
+        value: impl Borrow<V::SelfType<'v>>,
+        ttl: Duration,
+    ) -> Result<Option<AccessGuard<'_, V>>> {
+        let ttl = ttl.as_millis().try_into().unwrap_or(u64::MAX);
+        self.insert_expiring(key, value, now_millis().saturating_add(ttl))
+    }
+
+    /// Insert mapping of the given key to the given value, which expires at `expires_at`
+    ///
+    /// Returns the old value, if the key was present in the table and had not expired
+    pub fn insert_until<'k, 'v>(
+        &mut self,
+        key: impl Borrow<K::SelfType<'k>>,
+        value: impl Borrow<V::SelfType<'v>>,
+        expires_at: SystemTime,
+    ) -> Result<Option<AccessGuard<'_, V>>> {
+        self.insert_expiring(key, value, to_millis(expires_at))
+    }
+
+    fn insert_expiring<'k, 'v>(
+        &mut self,
+        key: impl Borrow<K::SelfType<'k>>,
+        value: impl Borrow<V::SelfType<'v>>,
+        expiry: u64,
+    ) -> Result<Option<AccessGuard<'_, V>>> {
+        let now = now_millis();
+        self.purge(now, LAZY_PURGE_LIMIT)?;
+        let encoded = Expiring::<V>::encode(expiry, value.borrow());
+        let old = self
+            .table
+            .insert(key.borrow(), Expiring::<V>::from_bytes(&encoded))?;
+        if let Some(ref old) = old {
+            self.expiry.remove(old.value().0, key.borrow())?;
+        }
+        self.expiry.insert(expiry, key.borrow())?;
+        Ok(old.and_then(|old| unexpired(old, now)))
+    }
+
+    /// Removes the given key
+    ///
+    /// Returns the old value, if the key was present in the table and had not expired
+    pub fn remove<'a>(
+        &mut self,
+        key: impl Borrow<K::SelfType<'a>>,
+    ) -> Result<Option<AccessGuard<'_, V>>>
+    where
+        K: 'a,
+    {
+        let now = now_millis();
+        self.purge(now, LAZY_PURGE_LIMIT)?;
+        let old = self.table.remove(key.borrow())?;
+        if let Some(ref old) = old {
+            self.expiry.remove(old.value().0, key.borrow())?;
+        }
+        Ok(old.and_then(|old| unexpired(old, now)))
+    }
+}
+
+impl<'db, 'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadableTtlTable<K, V>
+    for TtlTable<'db, 'txn, K, V>
+{
+    fn get<'a>(&self, key: impl Borrow<K::SelfType<'a>>) -> Result<Option<AccessGuard<'_, V>>>
+    where
+        K: 'a,
+    {
+        let now = now_millis();
+        Ok(self.table.get(key)?.and_then(|guard| unexpired(guard, now)))
+    }
+
+    fn expires_at<'a>(&self, key: impl Borrow<K::SelfType<'a>>) -> Result<Option<SystemTime>>
+    where
+        K: 'a,
+    {
+        expires_at(self.table.get(key)?)
+    }
+
+    fn range<'a, KR>(&self, range: impl RangeBounds<KR> + 'a) -> Result<TtlRange<'_, K, V>>
+    where
+        K: 'a,
+        KR: Borrow<K::SelfType<'a>> + 'a,
+    {
+        self.table.range(range).map(TtlRange::new)
+    }
+}
+
+impl<K: RedbKey, V: RedbValue> Sealed for TtlTable<'_, '_, K, V> {}
+
+fn expires_at<V: RedbValue + 'static>(
+    guard: Option<AccessGuard<Expiring<V>>>,
+) -> Result<Option<SystemTime>> {
+    let now = now_millis();
+    Ok(guard.and_then(|guard| {
+        let expiry = guard.value().0;
+        if expiry > now {
+            UNIX_EPOCH.checked_add(Duration::from_millis(expiry))
+        } else {
+            None
+        }
+    }))
+}
+
+pub trait ReadableTtlTable<K: RedbKey + 'static, V: RedbValue + 'static>: Sealed {
+    /// Returns the value corresponding to the given key, if it has not expired
+    fn get<'a>(&self, key: impl Borrow<K::SelfType<'a>>) -> Result<Option<AccessGuard<'_, V>>>
+    where
+        K: 'a;
+
+    /// Returns the time at which the entry with the given key expires, if it has not expired
+    fn expires_at<'a>(&self, key: impl Borrow<K::SelfType<'a>>) -> Result<Option<SystemTime>>
+    where
+        K: 'a;
+
+    /// Returns a double-ended iterator over the entries in a range of the table which have not
+    /// expired
+    fn range<'a, KR>(&self, range: impl RangeBounds<KR> + 'a) -> Result<TtlRange<'_, K, V>>
+    where
+        K: 'a,
+        KR: Borrow<K::SelfType<'a>> + 'a;
+
+    /// Returns a double-ended iterator over all entries in the table which have not expired
+    fn iter(&self) -> Result<TtlRange<'_, K, V>> {
+        self.range::<K::SelfType<'_>>(..)
+    }
+}
+
+/// A read-only table whose entries expire
+pub struct ReadOnlyTtlTable<'txn, K: RedbKey + 'static, V: RedbValue + 'static> {
+    table: ReadOnlyTable<'txn, K, Expiring<V>>,
+}
+
+impl<'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadOnlyTtlTable<'txn, K, V> {
+    pub(crate) fn new(table: ReadOnlyTable<'txn, K, Expiring<V>>) -> Self {
+        Self { table }
+    }
+}
+
+impl<'txn, K: RedbKey + 'static, V: RedbValue + 'static> ReadableTtlTable<K, V>
+    for ReadOnlyTtlTable<'txn, K, V>
+{
+    fn get<'a>(&self, key: impl Borrow<K::SelfType<'a>>) -> Result<Option<AccessGuard<'_, V>>>
+    where
+        K: 'a,
+    {
+        let now = now_millis();
+        Ok(self.table.get(key)?.and_then(|guard| unexpired(guard, now)))
+    }
+
+    fn expires_at<'a>(&self, key: impl Borrow<K::SelfType<'a>>) -> Result<Option<SystemTime>>
+    where
+        K: 'a,
+    {
+        expires_at(self.table.get(key)?)
+    }
+
+    fn range<'a, KR>(&self, range: impl RangeBounds<KR> + 'a) -> Result<TtlRange<'_, K, V>>
+    where
+        K: 'a,
+        KR: Borrow<K::SelfType<'a>> + 'a,
+    {
+        self.table.range(range).map(TtlRange::new)
+    }
+}
+
+impl<K: RedbKey, V: RedbValue> Sealed for ReadOnlyTtlTable<'_, K, V> {}
+
+/// An iterator over the entries of a [`TtlTable`] which have not expired
+pub struct TtlRange<'a, K: RedbKey + 'static, V: RedbValue + 'static> {
+    inner: Range<'a, K, Expiring<V>>,
+    now: u64,
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> TtlRange<'a, K, V> {
+    fn new(inner: Range<'a, K, Expiring<V>>) -> Self {
+        Self {
+            inner,
+            now: now_millis(),
+        }
+    }
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> Iterator for TtlRange<'a, K, V> {
+    type Item = Result<(AccessGuard<'a, K>, AccessGuard<'a, V>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next()? {
+                Ok((key, value)) => {
+                    if let Some(value) = unexpired(value, self.now) {
+                        return Some(Ok((key, value)));
+                    }
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+impl<'a, K: RedbKey + 'static, V: RedbValue + 'static> DoubleEndedIterator for TtlRange<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next_back()? {
+                Ok((key, value)) => {
+                    if let Some(value) = unexpired(value, self.now) {
+                        return Some(Ok((key, value)));
+                    }
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
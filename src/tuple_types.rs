@@ -1,4 +1,4 @@
-use crate::types::{RedbKey, RedbValue, TypeName};
+use crate::types::{RedbKey, RedbKeyPrefix, RedbValue, TypeName};
 use std::borrow::Borrow;
 use std::cmp::Ordering;
 use std::mem::size_of;
@@ -34,6 +34,43 @@ fn parse_lens<const N: usize>(data: &[u8]) -> [usize; N] {
     result
 }
 
+// Encodes the fields of a type deriving RedbValue, in the same format as a tuple.
+// `widths` are the fixed widths of the fields
+pub fn encode_fields<const N: usize>(widths: [Option<usize>; N], fields: [&[u8]; N]) -> Vec<u8> {
+    if N == 0 {
+        vec![]
+    } else if widths.iter().all(Option::is_some) {
+        serialize_tuple_elements_fixed(&fields)
+    } else {
+        serialize_tuple_elements_variable(&fields)
+    }
+}
+
+// Splits data encoded by encode_fields() into the encoded fields
+pub fn split_fields<const N: usize>(widths: [Option<usize>; N], data: &[u8]) -> [&[u8]; N] {
+    let mut result = [&data[..0]; N];
+    if N == 0 {
+        return result;
+    }
+    let mut offset = 0;
+    if widths.iter().all(Option::is_some) {
+        for (field, width) in result.iter_mut().zip(widths) {
+            let len = width.unwrap();
+            *field = &data[offset..(offset + len)];
+            offset += len;
+        }
+    } else {
+        offset = (N - 1) * size_of::<u32>();
+        for i in 0..(N - 1) {
+            let len = u32::from_le_bytes(data[4 * i..4 * (i + 1)].try_into().unwrap()) as usize;
+            result[i] = &data[offset..(offset + len)];
+            offset += len;
+        }
+        result[N - 1] = &data[offset..];
+    }
+    result
+}
+
 fn not_equal<T: RedbKey>(data1: &[u8], data2: &[u8]) -> Option<Ordering> {
     match T::compare(data1, data2) {
         Ordering::Less => Some(Ordering::Less),
@@ -232,6 +269,19 @@ macro_rules! tuple_impl {
                 }
             }
         }
+
+        impl<$($t: RedbKey,)+ $t_last: RedbKey> RedbKeyPrefix<T0> for ($($t,)+ $t_last) {
+            fn compare_prefix(prefix: &[u8], key: &[u8]) -> Ordering {
+                let first = if Self::fixed_width().is_some() {
+                    &key[..T0::fixed_width().unwrap()]
+                } else {
+                    let len: [usize; 1] = parse_lens(key);
+                    let offset = $i_last * size_of::<u32>();
+                    &key[offset..(offset + len[0])]
+                };
+                T0::compare(prefix, first)
+            }
+        }
     };
 }
 
@@ -65,7 +65,7 @@ impl TypeName {
         }
     }
 
-    pub(crate) fn name(&self) -> &str {
+    pub fn name(&self) -> &str {
         &self.name
     }
 }
@@ -129,6 +129,14 @@ pub trait RedbKey: RedbValue {
     fn compare(data1: &[u8], data2: &[u8]) -> Ordering;
 }
 
+/// Implementing this trait indicates that keys can be queried by a prefix of type `P`.
+/// This enables the .prefix() method on tables
+pub trait RedbKeyPrefix<P: RedbValue>: RedbKey {
+    /// Compare prefix with key. Returns `Equal` if key starts with prefix, and otherwise orders
+    /// prefix relative to key in the same way as [`RedbKey::compare`]
+    fn compare_prefix(prefix: &[u8], key: &[u8]) -> Ordering;
+}
+
 impl RedbValue for () {
     type SelfType<'a> = ()
     where
@@ -247,6 +255,16 @@ impl RedbKey for &[u8] {
     }
 }
 
+impl RedbKeyPrefix<&[u8]> for &[u8] {
+    fn compare_prefix(prefix: &[u8], key: &[u8]) -> Ordering {
+        if key.starts_with(prefix) {
+            Ordering::Equal
+        } else {
+            prefix.cmp(key)
+        }
+    }
+}
+
 impl<const N: usize> RedbValue for &[u8; N] {
     type SelfType<'a> = &'a [u8; N]
     where
@@ -325,6 +343,13 @@ impl RedbKey for &str {
     }
 }
 
+impl RedbKeyPrefix<&str> for &str {
+    fn compare_prefix(prefix: &[u8], key: &[u8]) -> Ordering {
+        // UTF-8 encoded strings sort in the same order as their bytes
+        <&[u8]>::compare_prefix(prefix, key)
+    }
+}
+
 macro_rules! be_value {
     ($t:ty) => {
         impl RedbValue for $t {
@@ -1,231 +1,677 @@
-use crate::kernel_types::{AddEntryType, VfsAction, VfsRequest, VfsResponse};
+use crate::kernel_types::{AddEntryType, VfsAction, VfsError, VfsRequest, VfsResponse};
+use crate::StorageBackend;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-pub struct Metadata {
-    our_node: String,
-    path: String,
+// Carried inside an io::Error, so that it can pass through StorageBackend. Converted to
+// StorageError::Vfs by From<io::Error>
+#[derive(Debug)]
+pub(crate) struct VfsFailure {
+    pub(crate) kind: String,
+    pub(crate) action: &'static str,
+}
+
+impl VfsFailure {
+    fn io_error(kind: &str, action: &VfsAction) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Other,
+            VfsFailure {
+                kind: kind.to_string(),
+                action: action_name(action),
+            },
+        )
+    }
+}
+
+impl Display for VfsFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "VFS returned {} for {}", self.kind, self.action)
+    }
+}
+
+impl Error for VfsFailure {}
+
+pub(crate) fn action_name(action: &VfsAction) -> &'static str {
+    match action {
+        VfsAction::New => "New",
+        VfsAction::Add { .. } => "Add",
+        VfsAction::Rename { .. } => "Rename",
+        VfsAction::Delete(_) => "Delete",
+        VfsAction::WriteOffset { .. } => "WriteOffset",
+        VfsAction::WriteOffsets { .. } => "WriteOffsets",
+        VfsAction::SetSize { .. } => "SetSize",
+        VfsAction::SyncData(_) => "SyncData",
+        VfsAction::GetPath(_) => "GetPath",
+        VfsAction::GetHash(_) => "GetHash",
+        VfsAction::GetEntry(_) => "GetEntry",
+        VfsAction::GetFileChunk { .. } => "GetFileChunk",
+        VfsAction::GetEntryLength(_) => "GetEntryLength",
+    }
+}
+
+/// Delivers requests to the uqbar VFS
+///
+/// Implement this on top of the runtime of the process that owns the database, for example by
+/// wrapping `send_and_await_response()` from the process library. Test doubles can implement it
+/// directly.
+pub trait VfsTransport: 'static + Send + Sync {
+    /// Sends `ipc`, and optionally `payload`, to `process` on `node`, then waits up to `timeout`
+    /// for the response.
+    ///
+    /// `process` is a process id of the form `process:package:publisher`. Returns the ipc bytes of
+    /// the response, along with its payload bytes if it had one
+    fn send_and_await_response(
+        &self,
+        node: &str,
+        process: &str,
+        ipc: Vec<u8>,
+        payload: Option<Vec<u8>>,
+        timeout: Duration,
+    ) -> io::Result<(Vec<u8>, Option<Vec<u8>>)>;
+}
+
+/// Where a database stored in the uqbar VFS lives, and how to reach it
+#[derive(Clone, Debug)]
+pub struct VfsConfig {
+    node: String,
     drive: String,
-    send_and_await_response: fn(String, String, String, String, Vec<u8>, Option<String>, Option<(Option<String>, Vec<u8>)>, u64) -> (Vec<u8>, Option<String>),
+    process: String,
+    timeout: Duration,
+    lease_duration: Duration,
+}
+
+impl VfsConfig {
+    /// Construct a new [`VfsConfig`] for `drive` on `node`
+    ///
+    /// ## Defaults
+    ///
+    /// - `process`: `vfs:sys:uqbar`
+    /// - `timeout`: 15 seconds
+    /// - `lease_duration`: 30 seconds
+    pub fn new(node: impl Into<String>, drive: impl Into<String>) -> Self {
+        Self {
+            node: node.into(),
+            drive: drive.into(),
+            process: "vfs:sys:uqbar".to_string(),
+            timeout: Duration::from_secs(15),
+            lease_duration: Duration::from_secs(30),
+        }
+    }
+
+    /// Set the process id of the VFS, of the form `process:package:publisher`
+    pub fn set_process(&mut self, process: impl Into<String>) -> &mut Self {
+        self.process = process.into();
+        self
+    }
+
+    /// Set how long to wait for the VFS to respond to each request
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how long the lease on an open database lasts
+    ///
+    /// The lease is renewed by any access to the database once half of it has elapsed. A database
+    /// which may be idle for longer than that must call
+    /// [`Database::renew_lease`](crate::Database::renew_lease) periodically to keep it. If the
+    /// lease expires, another process may open the database, and any further access from this
+    /// process fails
+    pub fn set_lease_duration(&mut self, lease_duration: Duration) -> &mut Self {
+        self.lease_duration = lease_duration;
+        self
+    }
+
+    pub fn node(&self) -> &str {
+        &self.node
+    }
+
+    pub fn drive(&self) -> &str {
+        &self.drive
+    }
+
+    pub fn process(&self) -> &str {
+        &self.process
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn lease_duration(&self) -> Duration {
+        self.lease_duration
+    }
+}
+
+// Sends a request to the VFS and returns its response, which may be VfsResponse::Err, along with
+// the response payload
+fn send_raw(
+    config: &VfsConfig,
+    transport: &dyn VfsTransport,
+    action: &VfsAction,
+    payload: Option<Vec<u8>>,
+) -> io::Result<(VfsResponse, Option<Vec<u8>>)> {
+    let request = serde_json::to_vec(&VfsRequest {
+        drive: config.drive.clone(),
+        action: action.clone(),
+    })?;
+    let (ipc, payload) = transport.send_and_await_response(
+        &config.node,
+        &config.process,
+        request,
+        payload,
+        config.timeout,
+    )?;
+    let response = serde_json::from_slice(&ipc)
+        .map_err(|_| VfsFailure::io_error("UnparseableResponse", action))?;
+    Ok((response, payload))
+}
+
+// Sends a request to the VFS, and converts VfsResponse::Err into an error
+fn send(
+    config: &VfsConfig,
+    transport: &dyn VfsTransport,
+    action: &VfsAction,
+    payload: Option<Vec<u8>>,
+) -> io::Result<(VfsResponse, Option<Vec<u8>>)> {
+    match send_raw(config, transport, action, payload)? {
+        (VfsResponse::Err(err), _) => Err(VfsFailure::io_error(err.kind(), action)),
+        response => Ok(response),
+    }
+}
+
+// Contents of the lock file which guards a database against concurrent use
+#[derive(Serialize, Deserialize)]
+struct Lease {
+    holder: u64,
+    // Milliseconds since the unix epoch
+    expires: u64,
+}
+
+// Length of a lock file. Leases are padded to it, so that a lease is always replaced by a single
+// write, and readers never see a partially written one
+const LEASE_LEN: usize = 64;
+
+impl Lease {
+    fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = serde_json::to_vec(self)?;
+        assert!(bytes.len() <= LEASE_LEN);
+        bytes.resize(LEASE_LEN, b' ');
+        Ok(bytes)
+    }
+}
+
+enum LockFile {
+    Missing,
+    // Contents which aren't a lease, for example because the file was written by hand
+    Unparseable(Vec<u8>),
+    Lease(Lease),
+}
+
+fn now_millis() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    now.as_millis().try_into().unwrap()
+}
+
+fn lock_path(path: &str) -> String {
+    format!("{path}.lock")
+}
+
+// Returns the contents of the lock file of the database at `path`, if it exists
+fn read_lock_file(
+    config: &VfsConfig,
+    transport: &dyn VfsTransport,
+    path: &str,
+) -> io::Result<Option<Vec<u8>>> {
+    let action = VfsAction::GetEntry(lock_path(path));
+    match send_raw(config, transport, &action, None)? {
+        (VfsResponse::GetEntry { .. }, Some(contents)) => Ok(Some(contents)),
+        (VfsResponse::GetEntry { .. }, None) => {
+            Err(VfsFailure::io_error("MissingPayload", &action))
+        }
+        (VfsResponse::Err(VfsError::BadDescriptor), _) => Ok(None),
+        (VfsResponse::Err(err), _) => Err(VfsFailure::io_error(err.kind(), &action)),
+        _ => Err(VfsFailure::io_error("UnexpectedResponse", &action)),
+    }
+}
+
+// Deletes the lock file of the database at `path`, if there is one
+pub(crate) fn break_lease(
+    path: &str,
+    config: &VfsConfig,
+    transport: &dyn VfsTransport,
+) -> io::Result<()> {
+    if read_lock_file(config, transport, path)?.is_none() {
+        return Ok(());
+    }
+    let action = VfsAction::Delete(lock_path(path));
+    match send(config, transport, &action, None)? {
+        (VfsResponse::Ok, _) => Ok(()),
+        _ => Err(VfsFailure::io_error("UnexpectedResponse", &action)),
+    }
+}
+
+pub struct Metadata {
+    len: u64,
 }
 
 impl Metadata {
-    pub fn len(self) -> u64 {
-        let response = (self.send_and_await_response)(
-            self.our_node.clone(),
-            "vfs".into(),
-            "sys".into(),
-            "uqbar".into(),
-            serde_json::to_vec(&VfsRequest {
-                drive: self.drive.clone(),
-                action: VfsAction::GetEntryLength(self.path.clone()),
-            }).unwrap(),
-            None,
-            None,
-            15,
-        );
-        let (ipc, _) =
-            response
-        else {
-            panic!("");
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+// The lease on a file
+struct HeldLease {
+    path: String,
+    config: VfsConfig,
+    // Identifies the lease of this file
+    holder: u64,
+    // Expiry of the lease, in milliseconds since the unix epoch, or 0 if unlocked
+    expires: AtomicU64,
+    // Set once another process has taken over the lease
+    lost: AtomicBool,
+}
+
+impl HeldLease {
+    fn read_lease(&self, transport: &dyn VfsTransport) -> io::Result<LockFile> {
+        let contents = match read_lock_file(&self.config, transport, &self.path)? {
+            Some(contents) => contents,
+            None => return Ok(LockFile::Missing),
         };
-        let VfsResponse::GetEntryLength(length) = serde_json::from_slice(&ipc).unwrap()
-        else {
-            panic!("");
+        let lease = contents.get(..LEASE_LEN).unwrap_or(&contents);
+        match serde_json::from_slice(lease) {
+            Ok(lease) => Ok(LockFile::Lease(lease)),
+            Err(_) => Ok(LockFile::Unparseable(contents)),
+        }
+    }
+
+    // Replaces the lease in a single request, so that no reader sees a partially written lease
+    fn write_lease(
+        &self,
+        transport: &dyn VfsTransport,
+        exists: bool,
+        expires: u64,
+    ) -> io::Result<()> {
+        let contents = Lease {
+            holder: self.holder,
+            expires,
+        }
+        .to_bytes()?;
+        let full_path = lock_path(&self.path);
+        let action = if exists {
+            VfsAction::WriteOffset {
+                full_path,
+                offset: 0,
+            }
+        } else {
+            VfsAction::Add {
+                full_path,
+                entry_type: AddEntryType::NewFile,
+            }
         };
-        length
+        match send(&self.config, transport, &action, Some(contents))? {
+            (VfsResponse::Ok, _) => Ok(()),
+            _ => Err(VfsFailure::io_error("UnexpectedResponse", &action)),
+        }
+    }
+
+    fn lost_error(&self) -> io::Error {
+        let action = VfsAction::GetEntry(lock_path(&self.path));
+        VfsFailure::io_error("LeaseLost", &action)
+    }
+
+    // Renews the lease once half of it has elapsed, and fails if it was taken by another process
+    fn check(&self, transport: &dyn VfsTransport) -> io::Result<()> {
+        if self.lost.load(Ordering::Acquire) {
+            return Err(self.lost_error());
+        }
+        let expires = self.expires.load(Ordering::Acquire);
+        if expires == 0 {
+            return Ok(());
+        }
+        let now = now_millis();
+        if now + self.half_millis() < expires {
+            return Ok(());
+        }
+        match self.read_lease(transport)? {
+            LockFile::Lease(lease) if lease.holder == self.holder => {}
+            _ => {
+                self.lost.store(true, Ordering::Release);
+                self.expires.store(0, Ordering::Release);
+                return Err(self.lost_error());
+            }
+        }
+        let expires = now + self.lease_millis();
+        self.write_lease(transport, true, expires)?;
+        self.expires.store(expires, Ordering::Release);
+        Ok(())
+    }
+
+    fn lease_millis(&self) -> u64 {
+        self.config.lease_duration.as_millis().try_into().unwrap()
+    }
+
+    fn half_millis(&self) -> u64 {
+        self.lease_millis() / 2
+    }
+
+    // Waits until the lock file is free, and returns whether it exists
+    fn wait_until_free(&self, transport: &dyn VfsTransport) -> io::Result<bool> {
+        // Contents which could not be parsed, and when they were first read
+        let mut unparseable: Option<(Vec<u8>, Instant)> = None;
+        loop {
+            match self.read_lease(transport)? {
+                LockFile::Missing => return Ok(false),
+                LockFile::Lease(lease)
+                    if lease.holder != self.holder && lease.expires > now_millis() =>
+                {
+                    return Err(io::ErrorKind::WouldBlock.into());
+                }
+                LockFile::Lease(_) => return Ok(true),
+                // The lease could belong to anyone, so it is considered held until it has been
+                // left unchanged for a whole lease
+                LockFile::Unparseable(contents) => {
+                    let since = match unparseable {
+                        Some((seen, since)) if seen == contents => since,
+                        _ => Instant::now(),
+                    };
+                    if since.elapsed() >= self.config.lease_duration {
+                        return Ok(true);
+                    }
+                    unparseable = Some((contents, since));
+                    thread::sleep(self.config.lease_duration / 8);
+                }
+            }
+        }
+    }
+
+    fn lock(&self, transport: &dyn VfsTransport) -> io::Result<()> {
+        let exists = self.wait_until_free(transport)?;
+        let start = Instant::now();
+        let expires = now_millis() + self.lease_millis();
+        self.write_lease(transport, exists, expires)?;
+        // The VFS can't atomically create a file, so a concurrent lock() which also found the
+        // lease free may overwrite it. Its write reaches the VFS within about a round trip of
+        // ours, so wait for that before checking that the lease is still ours
+        thread::sleep(start.elapsed() * 2);
+        match self.read_lease(transport)? {
+            LockFile::Lease(lease) if lease.holder == self.holder => {}
+            _ => return Err(io::ErrorKind::WouldBlock.into()),
+        }
+        self.lost.store(false, Ordering::Release);
+        self.expires.store(expires, Ordering::Release);
+        Ok(())
+    }
+
+    fn unlock(&self, transport: &dyn VfsTransport) -> io::Result<()> {
+        if self.expires.swap(0, Ordering::AcqRel) == 0 {
+            return Ok(());
+        }
+        match self.read_lease(transport)? {
+            LockFile::Lease(lease) if lease.holder == self.holder => {
+                break_lease(&self.path, &self.config, transport)
+            }
+            // The lease was broken, or taken over after it expired
+            _ => Ok(()),
+        }
     }
 }
 
+/// A file stored in the uqbar VFS
 pub struct File {
-    our_node: String,
     path: String,
-    drive: String,
-    get_payload: fn() -> Option<(Option<String>, Vec<u8>)>,
-    send_and_await_response: fn(String, String, String, String, Vec<u8>, Option<String>, Option<(Option<String>, Vec<u8>)>, u64) -> (Vec<u8>, Option<String>),
+    config: VfsConfig,
+    transport: Box<dyn VfsTransport>,
+    lease: HeldLease,
 }
 
 impl File {
-    pub fn metadata(&self) -> std::io::Result<Metadata> {
-        Ok(Metadata {
-            our_node: self.our_node.clone(),
-            path: self.path.clone(),
-            drive: self.drive.clone(),
-            send_and_await_response: self.send_and_await_response,
-        })
+    fn send(
+        &self,
+        action: &VfsAction,
+        payload: Option<Vec<u8>>,
+    ) -> io::Result<(VfsResponse, Option<Vec<u8>>)> {
+        send(&self.config, self.transport.as_ref(), action, payload)
+    }
+
+    fn check_lease(&self) -> io::Result<()> {
+        self.lease.check(self.transport.as_ref())
+    }
+
+    /// Acquires a lease on the file, which other processes respect until it expires
+    ///
+    /// Every access to the file renews the lease once half of it has elapsed. Call
+    /// [`Self::renew_lease`] to keep the lease while the file is idle.
+    ///
+    /// Returns an error of kind [`io::ErrorKind::WouldBlock`] if another process holds an
+    /// unexpired lease
+    pub fn lock(&self) -> io::Result<()> {
+        self.lease.lock(self.transport.as_ref())
     }
-    pub fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
-        let length = buf.len();
-
-        let response = (self.send_and_await_response)(
-            self.our_node.clone(),
-            "vfs".into(),
-            "sys".into(),
-            "uqbar".into(),
-            serde_json::to_vec(&VfsRequest {
-                drive: self.drive.clone(),
-                action: VfsAction::GetFileChunk {
-                    full_path: self.path.clone(),
-                    offset,
-                    length: length as u64,
-                },
-            }).unwrap(),
-            None,
-            None,
-            15,
-        );
-        //  TODO: check Response is not error
-        let payload = (self.get_payload)();
-        let Some((_, bytes)) = payload else {
-            panic!("");
+
+    /// Renews the lease acquired by [`Self::lock`], if half of it has elapsed
+    pub fn renew_lease(&self) -> io::Result<()> {
+        self.check_lease()
+    }
+
+    /// Releases the lease acquired by [`Self::lock`]
+    pub fn unlock(&self) -> io::Result<()> {
+        self.lease.unlock(self.transport.as_ref())
+    }
+
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        self.check_lease()?;
+        let action = VfsAction::GetEntryLength(self.path.clone());
+        match self.send(&action, None)? {
+            (VfsResponse::GetEntryLength(len), _) => Ok(Metadata { len }),
+            _ => Err(VfsFailure::io_error("UnexpectedResponse", &action)),
+        }
+    }
+
+    pub fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self.check_lease()?;
+        let action = VfsAction::GetFileChunk {
+            full_path: self.path.clone(),
+            offset,
+            length: buf.len() as u64,
+        };
+        let bytes = match self.send(&action, None)? {
+            (VfsResponse::GetFileChunk, Some(bytes)) => bytes,
+            (VfsResponse::GetFileChunk, None) => {
+                return Err(VfsFailure::io_error("MissingPayload", &action));
+            }
+            _ => return Err(VfsFailure::io_error("UnexpectedResponse", &action)),
         };
+        if bytes.len() < buf.len() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
         buf.copy_from_slice(&bytes[..buf.len()]);
         Ok(())
     }
-    pub fn set_len(&self, size: u64) -> std::io::Result<()> {
-        let response = (self.send_and_await_response)(
-            self.our_node.clone(),
-            "vfs".into(),
-            "sys".into(),
-            "uqbar".into(),
-            serde_json::to_vec(&VfsRequest {
-                drive: self.drive.clone(),
-                action: VfsAction::SetSize {
-                    full_path: self.path.clone(),
-                    size,
-                },
-            }).unwrap(),
-            None,
-            None,
-            15,
-        );
-        //  TODO: check Response is not error
-        Ok(())
+
+    pub fn set_len(&self, size: u64) -> io::Result<()> {
+        self.check_lease()?;
+        let action = VfsAction::SetSize {
+            full_path: self.path.clone(),
+            size,
+        };
+        match self.send(&action, None)? {
+            (VfsResponse::Ok, _) => Ok(()),
+            _ => Err(VfsFailure::io_error("UnexpectedResponse", &action)),
+        }
     }
-    pub fn sync_data(&self) -> std::io::Result<()> { Ok(()) }
-    pub fn write_all_at(&self, buf: &[u8], offset: u64) -> std::io::Result<()> {
-        let response = (self.send_and_await_response)(
-            self.our_node.clone(),
-            "vfs".into(),
-            "sys".into(),
-            "uqbar".into(),
-            serde_json::to_vec(&VfsRequest {
-                drive: self.drive.clone(),
-                action: VfsAction::WriteOffset {
-                    full_path: self.path.clone(),
-                    offset,
-                },
-            }).unwrap(),
-            None,
-            Some((None, buf.to_vec())),
-            15,
-        );
-        //  TODO: check Response is not error
-        Ok(())
+
+    pub fn sync_data(&self) -> io::Result<()> {
+        self.check_lease()?;
+        let action = VfsAction::SyncData(self.path.clone());
+        match self.send(&action, None)? {
+            (VfsResponse::Ok, _) => Ok(()),
+            _ => Err(VfsFailure::io_error("UnexpectedResponse", &action)),
+        }
+    }
+
+    pub fn write_all_at(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+        self.check_lease()?;
+        let action = VfsAction::WriteOffset {
+            full_path: self.path.clone(),
+            offset,
+        };
+        match self.send(&action, Some(buf.to_vec()))? {
+            (VfsResponse::Ok, _) => Ok(()),
+            _ => Err(VfsFailure::io_error("UnexpectedResponse", &action)),
+        }
+    }
+
+    /// Writes each buffer at its offset, in a single request to the VFS
+    pub fn write_batch(&self, writes: &[(u64, &[u8])]) -> io::Result<()> {
+        self.check_lease()?;
+        match writes {
+            [] => return Ok(()),
+            [(offset, buf)] => return self.write_all_at(buf, *offset),
+            _ => {}
+        }
+        let action = VfsAction::WriteOffsets {
+            full_path: self.path.clone(),
+            writes: writes
+                .iter()
+                .map(|(offset, buf)| (*offset, buf.len() as u64))
+                .collect(),
+        };
+        let payload = writes
+            .iter()
+            .flat_map(|(_, buf)| buf.iter().copied())
+            .collect();
+        match self.send(&action, Some(payload))? {
+            (VfsResponse::Ok, _) => Ok(()),
+            _ => Err(VfsFailure::io_error("UnexpectedResponse", &action)),
+        }
     }
 }
 
-pub struct OpenOptions {
-    our_node: Option<String>,
-    create: bool,
-    drive: Option<String>,
-    get_payload: Option<fn() -> Option<(Option<String>, Vec<u8>)>>,
-    send_and_await_response: Option<fn(String, String, String, String, Vec<u8>, Option<String>, Option<(Option<String>, Vec<u8>)>, u64) -> (Vec<u8>, Option<String>)>,
+impl Debug for File {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("File")
+            .field("node", &self.config.node)
+            .field("drive", &self.config.drive)
+            .field("path", &self.path)
+            .finish()
+    }
 }
 
-impl OpenOptions {
-    pub fn new() -> Self {
-        // OpenOptions { our_node: None, create: false, receiver: None, sender: None }
-        OpenOptions {
-            our_node: None,
-            create: false,
-            drive: None,
-            get_payload: None,
-            send_and_await_response: None,
-        }
+impl StorageBackend for File {
+    fn len(&self) -> Result<u64, io::Error> {
+        Ok(self.metadata()?.len())
     }
-    pub fn create(mut self, b: bool) -> Self {
-        self.create = b;
-        self
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<(), io::Error> {
+        File::read_exact_at(self, buf, offset)
     }
-    pub fn read(self, b: bool) -> Self { self }
-    pub fn write(self, b: bool) -> Self { self }
-    pub fn our_node(mut self, our_node: String) -> Self {
-        self.our_node = Some(our_node);
-        self
+
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> Result<(), io::Error> {
+        File::write_all_at(self, buf, offset)
     }
-    pub fn drive(mut self, drive: String) -> Self {
-        self.drive = Some(drive);
-        self
+
+    fn write_batch(&self, writes: &[(u64, &[u8])]) -> Result<(), io::Error> {
+        File::write_batch(self, writes)
     }
-    pub fn get_payload(mut self, get_payload: fn() -> Option<(Option<String>, Vec<u8>)>) -> Self {
-        self.get_payload = Some(get_payload);
-        self
+
+    fn set_len(&self, len: u64) -> Result<(), io::Error> {
+        File::set_len(self, len)
+    }
+
+    fn sync_data(&self, _: bool) -> Result<(), io::Error> {
+        File::sync_data(self)
+    }
+
+    fn lock(&self) -> Result<(), io::Error> {
+        File::lock(self)
+    }
+
+    fn renew_lock(&self) -> Result<(), io::Error> {
+        File::renew_lease(self)
+    }
+
+    fn unlock(&self) -> Result<(), io::Error> {
+        File::unlock(self)
+    }
+}
+
+/// Options used to open a [`File`] in the uqbar VFS
+pub struct OpenOptions {
+    create: bool,
+}
+
+impl OpenOptions {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        OpenOptions { create: false }
     }
-    pub fn send_and_await_response(
-        mut self,
-        send_and_await_response: fn(String, String, String, String, Vec<u8>, Option<String>, Option<(Option<String>, Vec<u8>)>, u64) -> (Vec<u8>, Option<String>),
-    ) -> Self {
-        self.send_and_await_response = Some(send_and_await_response);
+
+    /// Create the file if it does not exist
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
         self
     }
-    pub fn open(self, path: String) -> std::io::Result<File> {
-        let Some(drive) = self.drive else {
-            return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
-        };
-        let Some(get_payload) = self.get_payload else {
-            return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
-        };
-        let Some(send_and_await_response) = self.send_and_await_response else {
-            return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
-        };
-        let Some(our_node) = self.our_node else {
-            return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
-        };
+
+    /// Opens the file at `path` on the drive given by `config`
+    pub fn open(
+        &self,
+        path: impl Into<String>,
+        config: VfsConfig,
+        transport: impl VfsTransport,
+    ) -> io::Result<File> {
+        let path = path.into();
         //  does file already exist?
-        let response = send_and_await_response(
-            our_node.clone(),
-            "vfs".into(),
-            "sys".into(),
-            "uqbar".into(),
-            serde_json::to_vec(&VfsRequest {
-                drive: drive.clone(),
-                action: VfsAction::GetEntry(path.clone()),
-            }).unwrap(),
-            None,
-            None,
-            15,
-        );
-        //  TODO: check Response is not error
-        let payload = get_payload();
-        let is_file_exists = match payload {
-            None => false,
-            Some(_) => true,
+        let action = VfsAction::GetEntry(path.clone());
+        let exists = match send_raw(&config, &transport, &action, None)? {
+            // file contents are returned in the payload, if the entry is a file
+            (VfsResponse::GetEntry { .. }, payload) => payload.is_some(),
+            // the VFS reports entries which do not exist as bad descriptors
+            (VfsResponse::Err(VfsError::BadDescriptor), _) => false,
+            (VfsResponse::Err(err), _) => return Err(VfsFailure::io_error(err.kind(), &action)),
+            _ => return Err(VfsFailure::io_error("UnexpectedResponse", &action)),
         };
 
-        if is_file_exists {
-            Ok(File { our_node, path, drive, get_payload, send_and_await_response })
-        } else {
+        if !exists {
             if !self.create {
-                return Err(std::io::Error::from(std::io::ErrorKind::NotFound));
-            } else {
-                let response = send_and_await_response(
-                    our_node.clone(),
-                    "vfs".into(),
-                    "sys".into(),
-                    "uqbar".into(),
-                    serde_json::to_vec(&VfsRequest {
-                        drive: drive.clone(),
-                        action: VfsAction::Add {
-                            full_path: path.clone(),
-                            entry_type: AddEntryType::NewFile,
-                        },
-                    }).unwrap(),
-                    None,
-                    Some((None, vec![])),
-                    15,
-                );
-                //  TODO: check Response is not error
-                Ok(File { our_node, path, drive, get_payload, send_and_await_response })
+                return Err(io::Error::from(io::ErrorKind::NotFound));
+            }
+            let action = VfsAction::Add {
+                full_path: path.clone(),
+                entry_type: AddEntryType::NewFile,
+            };
+            if !matches!(
+                send(&config, &transport, &action, Some(vec![]))?,
+                (VfsResponse::Ok, _)
+            ) {
+                return Err(VfsFailure::io_error("UnexpectedResponse", &action));
             }
         }
+
+        let lease = HeldLease {
+            path: path.clone(),
+            config: config.clone(),
+            holder: rand::random(),
+            expires: AtomicU64::new(0),
+            lost: AtomicBool::new(false),
+        };
+        Ok(File {
+            path,
+            config,
+            transport: Box::new(transport),
+            lease,
+        })
     }
 }
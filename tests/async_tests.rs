@@ -0,0 +1,118 @@
+use futures_core::Stream;
+use redb::{AsyncDatabase, Database, MockVfs, RangeStream, ReadableTable, TableDefinition};
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+const DB_PATH: &str = "/test.redb";
+
+const U64_TABLE: TableDefinition<u64, u64> = TableDefinition::new("u64");
+
+fn create() -> AsyncDatabase {
+    let vfs = MockVfs::new();
+    Database::create(DB_PATH, vfs.config(), vfs).unwrap().into()
+}
+
+fn create_with_latency(latency: Duration) -> AsyncDatabase {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    vfs.set_latency(latency);
+    db.into()
+}
+
+async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+    poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+}
+
+#[tokio::test]
+async fn write_and_stream() {
+    let db = create();
+    let txn = db.begin_write().await.unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        for i in 0..1000 {
+            table.insert(i, i * 2).unwrap();
+        }
+    }
+    txn.commit().await.unwrap();
+
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(U64_TABLE).unwrap();
+    let mut stream = RangeStream::new(table.range(100..200).unwrap());
+    let mut expected = 100;
+    while let Some(entry) = next(&mut stream).await {
+        let (key, value) = entry.unwrap();
+        assert_eq!(key.value(), expected);
+        assert_eq!(value.value(), expected * 2);
+        expected += 1;
+    }
+    assert_eq!(expected, 200);
+}
+
+#[tokio::test]
+async fn abort() {
+    let db = create();
+    let txn = db.begin_write().await.unwrap();
+    txn.open_table(U64_TABLE).unwrap().insert(0, 0).unwrap();
+    txn.abort().await.unwrap();
+
+    let txn = db.begin_write().await.unwrap();
+    assert!(txn.open_table(U64_TABLE).unwrap().is_empty().unwrap());
+}
+
+// Runs on a single thread, so would deadlock if waiting for the write slot parked the thread
+#[tokio::test(flavor = "current_thread")]
+async fn writer_waits() {
+    let db = create();
+    let first = async {
+        let txn = db.begin_write().await.unwrap();
+        txn.open_table(U64_TABLE).unwrap().insert(0, 1).unwrap();
+        tokio::task::yield_now().await;
+        txn.commit().await.unwrap();
+    };
+    let second = async {
+        tokio::task::yield_now().await;
+        let txn = db.begin_write().await.unwrap();
+        {
+            let mut table = txn.open_table(U64_TABLE).unwrap();
+            let value = table.get(0).unwrap().unwrap().value();
+            table.insert(0, value + 1).unwrap();
+        }
+        txn.commit().await.unwrap();
+    };
+    tokio::join!(first, second);
+
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(U64_TABLE).unwrap();
+    assert_eq!(table.get(0).unwrap().unwrap().value(), 2);
+}
+
+// Runs on a single thread, so other tasks could only make progress during the commit if it runs
+// off the executor
+#[tokio::test(flavor = "current_thread")]
+async fn commit_yields() {
+    let db = create_with_latency(Duration::from_millis(5));
+    let txn = db.begin_write().await.unwrap();
+    txn.open_table(U64_TABLE).unwrap().insert(0, 1).unwrap();
+
+    let committed = AtomicBool::new(false);
+    let commit = async {
+        txn.commit().await.unwrap();
+        committed.store(true, Ordering::Release);
+    };
+    let other = async {
+        let mut polls = 0;
+        while !committed.load(Ordering::Acquire) {
+            polls += 1;
+            tokio::task::yield_now().await;
+        }
+        polls
+    };
+    let ((), polls) = tokio::join!(commit, other);
+    assert!(polls > 1);
+
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(U64_TABLE).unwrap();
+    assert_eq!(table.get(0).unwrap().unwrap().value(), 1);
+}
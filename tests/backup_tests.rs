@@ -0,0 +1,260 @@
+use redb::{
+    BackupError, Database, InMemoryBackend, MockVfs, MultimapTableDefinition, OpenOptions,
+    ReadableMultimapTable, ReadableTable, StorageBackend, StorageError, TableDefinition,
+};
+
+const DB_PATH: &str = "/test.redb";
+const RESTORED_PATH: &str = "/restored.redb";
+
+const U64_TABLE: TableDefinition<u64, u64> = TableDefinition::new("u64");
+const STR_TABLE: TableDefinition<&str, &str> = TableDefinition::new("str");
+const MULTIMAP_TABLE: MultimapTableDefinition<u64, u64> = MultimapTableDefinition::new("multimap");
+
+fn insert_range(db: &Database, start: u64, end: u64, value: u64) {
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        for i in start..end {
+            table.insert(i, value).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+}
+
+fn restore(vfs: &MockVfs, backups: &[&dyn StorageBackend]) -> Database {
+    let target = OpenOptions::new()
+        .create(true)
+        .open(RESTORED_PATH, vfs.config(), vfs.clone())
+        .unwrap();
+    Database::restore(backups, &target).unwrap();
+    drop(target);
+    Database::open(RESTORED_PATH, vfs.config(), vfs.clone()).unwrap()
+}
+
+#[test]
+fn full_backup() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    insert_range(&db, 0, 1000, 1);
+    let txn = db.begin_write().unwrap();
+    txn.open_table(STR_TABLE)
+        .unwrap()
+        .insert("hello", "world")
+        .unwrap();
+    txn.commit().unwrap();
+
+    let backup = InMemoryBackend::new();
+    let transaction_id = db.backup_to(&backup).unwrap();
+    // Not included in the backup
+    insert_range(&db, 1000, 2000, 1);
+
+    let restored = restore(&vfs, &[&backup]);
+    {
+        let txn = restored.begin_read().unwrap();
+        let table = txn.open_table(U64_TABLE).unwrap();
+        assert_eq!(table.len().unwrap(), 1000);
+        assert_eq!(table.get(999).unwrap().unwrap().value(), 1);
+        let table = txn.open_table(STR_TABLE).unwrap();
+        assert_eq!(table.get("hello").unwrap().unwrap().value(), "world");
+    }
+
+    // The restored database can be written to
+    insert_range(&restored, 1000, 1500, 2);
+    let txn = restored.begin_read().unwrap();
+    assert_eq!(txn.open_table(U64_TABLE).unwrap().len().unwrap(), 1500);
+
+    assert_eq!(
+        Database::restore(&[&backup], &InMemoryBackend::new()).unwrap(),
+        transaction_id
+    );
+}
+
+#[test]
+fn incremental_backup() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    insert_range(&db, 0, 10_000, 1);
+    let full = InMemoryBackend::new();
+    let first_id = db.backup_to(&full).unwrap();
+
+    insert_range(&db, 5000, 5001, 2);
+    let first = InMemoryBackend::new();
+    let second_id = db.backup_incremental_to(&full, &first).unwrap();
+    assert!(second_id > first_id);
+    // Only the pages on the path to the changed key are written again
+    assert!(
+        first.len().unwrap() * 10 < full.len().unwrap(),
+        "{} bytes, {} for the full backup",
+        first.len().unwrap(),
+        full.len().unwrap()
+    );
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        table.remove(0).unwrap();
+        table.insert(20_000, 3).unwrap();
+    }
+    txn.commit().unwrap();
+    let second = InMemoryBackend::new();
+    db.backup_incremental_to(&first, &second).unwrap();
+
+    {
+        let restored = restore(&vfs, &[&full, &first, &second]);
+        let txn = restored.begin_read().unwrap();
+        let table = txn.open_table(U64_TABLE).unwrap();
+        assert_eq!(table.len().unwrap(), 10_000);
+        assert!(table.get(0).unwrap().is_none());
+        assert_eq!(table.get(1).unwrap().unwrap().value(), 1);
+        assert_eq!(table.get(5000).unwrap().unwrap().value(), 2);
+        assert_eq!(table.get(20_000).unwrap().unwrap().value(), 3);
+    }
+
+    // Restoring part of the chain gives the database as of the last backup restored
+    let restored = restore(&vfs, &[&full, &first]);
+    let txn = restored.begin_read().unwrap();
+    let table = txn.open_table(U64_TABLE).unwrap();
+    assert_eq!(table.len().unwrap(), 10_000);
+    assert_eq!(table.get(0).unwrap().unwrap().value(), 1);
+    assert!(table.get(20_000).unwrap().is_none());
+}
+
+#[test]
+fn multimap_table() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_multimap_table(MULTIMAP_TABLE).unwrap();
+        table.insert(0, 0).unwrap();
+        // Enough values to be stored in a subtree
+        for i in 0..1000 {
+            table.insert(1, i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    let full = InMemoryBackend::new();
+    db.backup_to(&full).unwrap();
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_multimap_table(MULTIMAP_TABLE).unwrap();
+        table.insert(1, 1000).unwrap();
+        table.remove(0, 0).unwrap();
+    }
+    txn.commit().unwrap();
+    let incremental = InMemoryBackend::new();
+    db.backup_incremental_to(&full, &incremental).unwrap();
+
+    let restored = restore(&vfs, &[&full, &incremental]);
+    let txn = restored.begin_read().unwrap();
+    let table = txn.open_multimap_table(MULTIMAP_TABLE).unwrap();
+    assert!(table.get(0).unwrap().next().is_none());
+    let values: Vec<u64> = table
+        .get(1)
+        .unwrap()
+        .map(|value| value.unwrap().value())
+        .collect();
+    assert_eq!(values, (0..=1000).collect::<Vec<u64>>());
+}
+
+#[test]
+fn writes_during_backup() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    insert_range(&db, 0, 1000, 1);
+    let backup = InMemoryBackend::new();
+    std::thread::scope(|scope| {
+        scope.spawn(|| db.backup_to(&backup).unwrap());
+        for i in 0..10 {
+            insert_range(&db, 0, 1000, i + 2);
+        }
+    });
+
+    let restored = restore(&vfs, &[&backup]);
+    let txn = restored.begin_read().unwrap();
+    let table = txn.open_table(U64_TABLE).unwrap();
+    assert_eq!(table.len().unwrap(), 1000);
+    // The backup holds a single transaction
+    let first = table.get(0).unwrap().unwrap().value();
+    for entry in table.iter().unwrap() {
+        assert_eq!(entry.unwrap().1.value(), first);
+    }
+}
+
+#[test]
+fn invalid_backups() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    insert_range(&db, 0, 100, 1);
+    let full = InMemoryBackend::new();
+    db.backup_to(&full).unwrap();
+    insert_range(&db, 0, 100, 2);
+    let first = InMemoryBackend::new();
+    let first_base = db.backup_incremental_to(&full, &first).unwrap();
+    insert_range(&db, 0, 100, 3);
+    let second = InMemoryBackend::new();
+    db.backup_incremental_to(&first, &second).unwrap();
+
+    let target = InMemoryBackend::new();
+    assert!(matches!(
+        Database::restore(&[], &target),
+        Err(BackupError::InvalidBackup)
+    ));
+    assert!(matches!(
+        Database::restore(&[&target], &target),
+        Err(BackupError::InvalidBackup)
+    ));
+    assert!(matches!(
+        db.backup_incremental_to(&target, &InMemoryBackend::new()),
+        Err(BackupError::InvalidBackup)
+    ));
+    // The first incremental backup is missing
+    match Database::restore(&[&full, &second], &target) {
+        Err(BackupError::MissingBase(id)) => assert_eq!(id, first_base),
+        other => panic!("Unexpected result: {other:?}"),
+    }
+    assert!(matches!(
+        Database::restore(&[&second], &target),
+        Err(BackupError::MissingBase(_))
+    ));
+
+    // An incomplete backup has no magic number
+    let truncated = InMemoryBackend::new();
+    let mut data = vec![0; full.len().unwrap().try_into().unwrap()];
+    full.read_exact_at(&mut data, 0).unwrap();
+    data[..16].fill(0);
+    truncated.write_all_at(&data, 0).unwrap();
+    assert!(matches!(
+        Database::restore(&[&truncated], &target),
+        Err(BackupError::InvalidBackup)
+    ));
+}
+
+#[test]
+fn corrupted_page_type() {
+    let vfs = MockVfs::new();
+    let db = Database::builder()
+        .set_cache_size(0)
+        .create(DB_PATH, vfs.config(), vfs.clone())
+        .unwrap();
+    let txn = db.begin_write().unwrap();
+    txn.open_table(STR_TABLE)
+        .unwrap()
+        .insert("key", "x".repeat(64).as_str())
+        .unwrap();
+    txn.commit().unwrap();
+
+    // Overwrite the type of the leaf which holds the entry
+    let mut file = vfs.file(DB_PATH).unwrap();
+    let position = file.windows(64).position(|x| x == [b'x'; 64]).unwrap();
+    let page_start = position / 4096 * 4096;
+    assert_eq!(file[page_start], 1);
+    file[page_start] = 0xFF;
+    vfs.insert_file(DB_PATH, file);
+
+    match db.backup_to(&InMemoryBackend::new()) {
+        Err(BackupError::Storage(StorageError::Corrupted(_))) => {}
+        other => panic!("Unexpected result: {other:?}"),
+    }
+}
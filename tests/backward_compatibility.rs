@@ -1,7 +1,9 @@
-use redb::ReadableTable;
+use redb::{ReadableMultimapTable, ReadableTable};
 
 const ELEMENTS: usize = 3;
 
+const DB_PATH: &str = "/test.redb";
+
 trait TestData: redb::RedbValue + redb1::RedbValue {
     fn gen1() -> [<Self as redb1::RedbValue>::SelfType<'static>; ELEMENTS];
 
@@ -210,7 +212,9 @@ fn test_helper<K: TestData + redb::RedbKey + redb1::RedbKey + 'static, V: TestDa
     write_txn.commit().unwrap();
     drop(db);
 
-    let db = redb::Database::open(tmpfile.path()).unwrap();
+    let vfs = redb::MockVfs::new();
+    vfs.insert_file(DB_PATH, std::fs::read(tmpfile.path()).unwrap());
+    let db = redb::Database::open(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let read_txn = db.begin_read().unwrap();
     let table_def: redb::TableDefinition<K, V> = redb::TableDefinition::new("table");
     let table = read_txn.open_table(table_def).unwrap();
@@ -256,3 +260,151 @@ fn mixed_width() {
     test_helper::<u8, &[u8]>();
     test_helper::<&[u8; 5], &str>();
 }
+
+#[test]
+fn upgrade_branch_pages() {
+    let tmpfile = create_tempfile();
+    let db = redb1::Database::create(tmpfile.path()).unwrap();
+    let table_def: redb1::TableDefinition<u64, &[u8]> = redb1::TableDefinition::new("table");
+    let value = [0u8; 100];
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(table_def).unwrap();
+        for i in 0..10_000u64 {
+            table.insert(&i, value.as_slice()).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+    drop(db);
+
+    let vfs = redb::MockVfs::new();
+    vfs.insert_file(DB_PATH, std::fs::read(tmpfile.path()).unwrap());
+    let db = redb::Database::open(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let table_def: redb::TableDefinition<u64, &[u8]> = redb::TableDefinition::new("table");
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(table_def).unwrap();
+    assert_eq!(table.len().unwrap(), 10_000);
+    assert_eq!(table.nth(1234).unwrap().unwrap().0.value(), 1234);
+    assert_eq!(table.rank(5000).unwrap(), 5000);
+    drop(table);
+    drop(read_txn);
+
+    // Pages on the modified paths are rewritten with entry counts, and the rest are left as is
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(table_def).unwrap();
+        for i in 0..100u64 {
+            table.remove(i * 100).unwrap();
+        }
+        table.insert(20_000, value.as_slice()).unwrap();
+        assert_eq!(table.len().unwrap(), 9901);
+    }
+    write_txn.commit().unwrap();
+    drop(db);
+
+    let db = redb::Database::open(DB_PATH, vfs.config(), vfs).unwrap();
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(table_def).unwrap();
+    assert_eq!(table.len().unwrap(), 9901);
+    assert_eq!(table.range_len(100..200).unwrap(), 99);
+    assert_eq!(table.nth(9900).unwrap().unwrap().0.value(), 20_000);
+    for (i, entry) in table.iter().unwrap().enumerate().step_by(101) {
+        let key = entry.unwrap().0.value();
+        assert_eq!(table.rank(key).unwrap(), i as u64);
+        assert_eq!(table.nth(i as u64).unwrap().unwrap().0.value(), key);
+    }
+}
+
+#[test]
+fn legacy_branch_lengths_counted_once() {
+    let tmpfile = create_tempfile();
+    let db = redb1::Database::create(tmpfile.path()).unwrap();
+    let table_def: redb1::TableDefinition<u64, &[u8]> = redb1::TableDefinition::new("table");
+    let value = [0u8; 100];
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(table_def).unwrap();
+        for i in 0..10_000u64 {
+            table.insert(&i, value.as_slice()).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+    drop(db);
+
+    let vfs = redb::MockVfs::new();
+    vfs.insert_file(DB_PATH, std::fs::read(tmpfile.path()).unwrap());
+    let db = redb::Database::builder()
+        .set_cache_size(0)
+        .open(DB_PATH, vfs.config(), vfs.clone())
+        .unwrap();
+    let table_def: redb::TableDefinition<u64, &[u8]> = redb::TableDefinition::new("table");
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(table_def).unwrap();
+    let leaf_pages = table.stats().unwrap().leaf_pages();
+
+    let before = vfs.request_count("GetFileChunk");
+    assert_eq!(table.len().unwrap(), 10_000);
+    let first = vfs.request_count("GetFileChunk") - before;
+    assert!(first >= leaf_pages);
+
+    // The counts of the legacy branch pages are reused by later mutations and queries
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(table_def).unwrap();
+        let before = vfs.request_count("GetFileChunk");
+        table.insert(20_000, value.as_slice()).unwrap();
+        table.remove(5000).unwrap();
+        assert!(vfs.request_count("GetFileChunk") - before < leaf_pages / 4);
+        assert_eq!(table.len().unwrap(), 10_000);
+    }
+    write_txn.commit().unwrap();
+
+    let before = vfs.request_count("GetFileChunk");
+    assert_eq!(table.len().unwrap(), 10_000);
+    assert!(vfs.request_count("GetFileChunk") - before < leaf_pages / 4);
+}
+
+#[test]
+fn upgrade_multimap_len() {
+    let tmpfile = create_tempfile();
+    let db = redb1::Database::create(tmpfile.path()).unwrap();
+    let table_def: redb1::MultimapTableDefinition<u64, u64> =
+        redb1::MultimapTableDefinition::new("table");
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_multimap_table(table_def).unwrap();
+        for i in 0..1000u64 {
+            table.insert(&(i % 10), &i).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+    drop(db);
+
+    let vfs = redb::MockVfs::new();
+    vfs.insert_file(DB_PATH, std::fs::read(tmpfile.path()).unwrap());
+    let db = redb::Database::open(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let table_def: redb::MultimapTableDefinition<u64, u64> =
+        redb::MultimapTableDefinition::new("table");
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_multimap_table(table_def).unwrap();
+    assert_eq!(table.len().unwrap(), 1000);
+    drop(table);
+    drop(read_txn);
+
+    // The number of values is counted when the table is first opened for writing, and then stored
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_multimap_table(table_def).unwrap();
+        assert_eq!(table.len().unwrap(), 1000);
+        table.insert(10, 0).unwrap();
+        table.remove_all(0).unwrap();
+        assert_eq!(table.len().unwrap(), 901);
+    }
+    write_txn.commit().unwrap();
+    drop(db);
+
+    let db = redb::Database::open(DB_PATH, vfs.config(), vfs).unwrap();
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_multimap_table(table_def).unwrap();
+    assert_eq!(table.len().unwrap(), 901);
+}
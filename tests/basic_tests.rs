@@ -1,27 +1,26 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use redb::{
-    Database, MultimapTableDefinition, MultimapTableHandle, Range, ReadableTable, RedbKey,
-    RedbValue, TableDefinition, TableHandle, TypeName,
+    BulkLoadError, Database, DatabaseError, Durability, InMemoryBackend, MergeOperator, MockVfs,
+    MultimapTableDefinition, MultimapTableHandle, Range, ReadableTable, RedbKey, RedbValue,
+    StorageBackend, StorageError, TableDefinition, TableHandle, TypeName,
 };
 use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::{Bound, RangeBounds};
 #[cfg(not(target_os = "wasi"))]
 use std::sync;
 
+const DB_PATH: &str = "/test.redb";
+
 const SLICE_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("slice");
 const STR_TABLE: TableDefinition<&str, &str> = TableDefinition::new("x");
 const U64_TABLE: TableDefinition<u64, u64> = TableDefinition::new("u64");
 
-fn create_tempfile() -> tempfile::NamedTempFile {
-    if cfg!(target_os = "wasi") {
-        tempfile::NamedTempFile::new_in("/").unwrap()
-    } else {
-        tempfile::NamedTempFile::new().unwrap()
-    }
-}
-
 #[test]
 fn len() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(STR_TABLE).unwrap();
@@ -36,10 +35,210 @@ fn len() {
     assert_eq!(table.len().unwrap(), 3);
 }
 
+#[test]
+fn rank_and_select() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let definition: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+    let value = [0u8; 100];
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        // Even keys in 0..20000, so that the tree has several levels of branch pages
+        for i in 0..10_000u64 {
+            table.insert(i * 2, value.as_slice()).unwrap();
+        }
+        assert_eq!(table.len().unwrap(), 10_000);
+        assert_eq!(table.rank(5000).unwrap(), 2500);
+        assert_eq!(table.rank(5001).unwrap(), 2501);
+        assert_eq!(table.nth(2500).unwrap().unwrap().0.value(), 5000);
+    }
+    write_txn.commit().unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        table.drain(4000..6000).unwrap();
+        for i in 0..1000u64 {
+            table.remove(i * 20).unwrap();
+        }
+        for i in 0..500u64 {
+            table.insert(i * 20 + 1, value.as_slice()).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(definition).unwrap();
+    let keys: Vec<u64> = table
+        .iter()
+        .unwrap()
+        .map(|entry| entry.unwrap().0.value())
+        .collect();
+    assert_eq!(table.len().unwrap(), keys.len() as u64);
+    for (i, key) in keys.iter().enumerate().step_by(97) {
+        assert_eq!(table.nth(i as u64).unwrap().unwrap().0.value(), *key);
+        assert_eq!(table.rank(key).unwrap(), i as u64);
+        assert_eq!(table.rank(key + 1).unwrap(), i as u64 + 1);
+    }
+    assert!(table.nth(keys.len() as u64).unwrap().is_none());
+    let (key, value) = table.nth(0).unwrap().unwrap();
+    assert_eq!(key.value(), keys[0]);
+    assert_eq!(value.value().len(), 100);
+
+    let count = |start: u64, end: u64| keys.iter().filter(|k| (start..end).contains(k)).count();
+    assert_eq!(table.range_len::<u64>(..).unwrap(), keys.len() as u64);
+    assert_eq!(
+        table.range_len(3000..7000).unwrap(),
+        count(3000, 7000) as u64
+    );
+    assert_eq!(
+        table.range_len(3001..=7001).unwrap(),
+        count(3001, 7002) as u64
+    );
+    assert_eq!(table.range_len(..101).unwrap(), count(0, 101) as u64);
+    assert_eq!(
+        table.range_len(19_000..).unwrap(),
+        count(19_000, u64::MAX) as u64
+    );
+    assert_eq!(table.range_len(4002..4020).unwrap(), 0);
+    #[allow(clippy::reversed_empty_ranges)]
+    let reversed = 7000..3000;
+    assert_eq!(table.range_len(reversed).unwrap(), 0);
+}
+
+#[test]
+fn bulk_load() {
+    let vfs = MockVfs::new();
+    let mut db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let definition: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+    let value = [7u8; 50];
+
+    let write_txn = db.begin_write().unwrap();
+    let loaded = write_txn
+        .bulk_load(
+            definition,
+            (0..100_000u64).map(|i| (i * 2, value.as_slice())),
+        )
+        .unwrap();
+    assert_eq!(loaded, 100_000);
+    {
+        let table = write_txn.open_table(definition).unwrap();
+        assert_eq!(table.len().unwrap(), 100_000);
+        assert_eq!(table.get(1234).unwrap().unwrap().value(), value);
+        assert!(table.get(1235).unwrap().is_none());
+        assert_eq!(table.rank(5000).unwrap(), 2500);
+        assert_eq!(table.nth(99_999).unwrap().unwrap().0.value(), 199_998);
+    }
+    write_txn.commit().unwrap();
+    db.check_integrity().unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        for i in 0..1000u64 {
+            table.insert(i * 2 + 1, value.as_slice()).unwrap();
+            table.remove(i * 100).unwrap();
+        }
+        assert_eq!(table.len().unwrap(), 100_000);
+        let keys: Vec<u64> = table
+            .range(0..10)
+            .unwrap()
+            .map(|entry| entry.unwrap().0.value())
+            .collect();
+        assert_eq!(keys, [1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    }
+    write_txn.commit().unwrap();
+    db.check_integrity().unwrap();
+
+    // The table must be empty
+    let write_txn = db.begin_write().unwrap();
+    let result = write_txn.bulk_load(definition, [(u64::MAX, value.as_slice())]);
+    assert!(matches!(result, Err(BulkLoadError::TableNotEmpty(_))));
+    write_txn.abort().unwrap();
+
+    // The fill factor must be in (0, 1]
+    let write_txn = db.begin_write().unwrap();
+    for fill_factor in [0.0, -0.5, 1.5, f32::NAN] {
+        let result = write_txn.bulk_load_with_fill_factor(
+            TableDefinition::<u64, u64>::new("z"),
+            fill_factor,
+            [(1, 1)],
+        );
+        assert!(matches!(result, Err(BulkLoadError::InvalidFillFactor(_))));
+    }
+    write_txn.abort().unwrap();
+
+    // Out of order input is rejected, but the entries before it are loaded
+    let definition: TableDefinition<&str, u64> = TableDefinition::new("y");
+    let write_txn = db.begin_write().unwrap();
+    let result = write_txn.bulk_load_with_fill_factor(
+        definition,
+        0.5,
+        [("a", 1), ("b", 2), ("d", 3), ("c", 4)],
+    );
+    assert!(matches!(
+        result,
+        Err(BulkLoadError::UnsortedInput { position: 3, .. })
+    ));
+    let result = write_txn.bulk_load(definition, [("e", 5)]);
+    assert!(matches!(result, Err(BulkLoadError::TableNotEmpty(_))));
+    write_txn.delete_table(definition).unwrap();
+    let result = write_txn.bulk_load(definition, [("a", 1), ("a", 2)]);
+    assert!(matches!(
+        result,
+        Err(BulkLoadError::UnsortedInput { position: 1, .. })
+    ));
+    {
+        let table = write_txn.open_table(definition).unwrap();
+        assert_eq!(table.len().unwrap(), 1);
+        assert_eq!(table.get("a").unwrap().unwrap().value(), 1);
+    }
+    write_txn.commit().unwrap();
+    db.check_integrity().unwrap();
+
+    // Pages filled to half their capacity leave room for later inserts
+    let write_txn = db.begin_write().unwrap();
+    write_txn.delete_table(definition).unwrap();
+    let keys: Vec<String> = (0..50_000).map(|i| format!("{i:08}")).collect();
+    write_txn
+        .bulk_load_with_fill_factor(
+            definition,
+            0.5,
+            keys.iter()
+                .enumerate()
+                .map(|(i, key)| (key.as_str(), i as u64)),
+        )
+        .unwrap();
+    {
+        let table = write_txn.open_table(definition).unwrap();
+        let half_full = table.stats().unwrap().leaf_pages();
+        drop(table);
+        write_txn.delete_table(definition).unwrap();
+        write_txn
+            .bulk_load(
+                definition,
+                keys.iter()
+                    .enumerate()
+                    .map(|(i, key)| (key.as_str(), i as u64)),
+            )
+            .unwrap();
+        let table = write_txn.open_table(definition).unwrap();
+        let full = table.stats().unwrap().leaf_pages();
+        assert!(half_full > full * 3 / 2);
+        assert_eq!(table.len().unwrap(), 50_000);
+        let (key, value) = table.nth(31_415).unwrap().unwrap();
+        assert_eq!(key.value(), "00031415");
+        assert_eq!(value.value(), 31_415);
+    }
+    write_txn.commit().unwrap();
+    db.check_integrity().unwrap();
+}
+
 #[test]
 fn pop() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(STR_TABLE).unwrap();
@@ -80,8 +279,8 @@ fn pop() {
 
 #[test]
 fn drain() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(U64_TABLE).unwrap();
@@ -129,8 +328,8 @@ fn drain() {
 
 #[test]
 fn drain_filter() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(U64_TABLE).unwrap();
@@ -184,10 +383,351 @@ fn drain_filter() {
     write_txn.abort().unwrap();
 }
 
+#[test]
+fn remove_range() {
+    let vfs = MockVfs::new();
+    let mut db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    // Long keys make for a deep tree
+    let definition: TableDefinition<&str, u64> = TableDefinition::new("x");
+    let key = |i: u64| format!("{i:0200}");
+
+    let mut expected = BTreeSet::new();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        for i in 0..20_000 {
+            table.insert(key(i).as_str(), i).unwrap();
+            expected.insert(i);
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        let (start, end) = (key(100), key(200));
+        assert_eq!(
+            table.remove_range(start.as_str()..end.as_str()).unwrap(),
+            100
+        );
+        assert_eq!(
+            table.remove_range(start.as_str()..=end.as_str()).unwrap(),
+            1
+        );
+        assert_eq!(
+            table.remove_range(start.as_str()..=end.as_str()).unwrap(),
+            0
+        );
+        assert_eq!(table.len().unwrap(), 19_899);
+        assert_eq!(table.get(key(99).as_str()).unwrap().unwrap().value(), 99);
+        assert_eq!(table.get(key(201).as_str()).unwrap().unwrap().value(), 201);
+        assert_eq!(table.rank(key(201).as_str()).unwrap(), 100);
+    }
+    write_txn.abort().unwrap();
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let bound = |rng: &mut StdRng| {
+        let i = rng.gen_range(0..20_000);
+        match rng.gen_range(0..10) {
+            0 => (Bound::Unbounded, i),
+            1..=4 => (Bound::Included(i), i),
+            _ => (Bound::Excluded(i), i),
+        }
+    };
+    let key_bound = |bound: Bound<&u64>| match bound {
+        Bound::Included(i) => Bound::Included(key(*i)),
+        Bound::Excluded(i) => Bound::Excluded(key(*i)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    fn as_str(bound: &Bound<String>) -> Bound<&str> {
+        match bound {
+            Bound::Included(key) => Bound::Included(key.as_str()),
+            Bound::Excluded(key) => Bound::Excluded(key.as_str()),
+            Bound::Unbounded => Bound::Unbounded,
+        }
+    }
+    for _ in 0..40 {
+        let write_txn = db.begin_write().unwrap();
+        {
+            let mut table = write_txn.open_table(definition).unwrap();
+            for _ in 0..rng.gen_range(1..4) {
+                let (start, a) = bound(&mut rng);
+                let (end, b) = bound(&mut rng);
+                let range = if a <= b { (start, end) } else { (end, start) };
+                let removed: Vec<u64> = expected
+                    .iter()
+                    .copied()
+                    .filter(|i| range.contains(i))
+                    .collect();
+                for i in removed.iter() {
+                    expected.remove(i);
+                }
+                let start = key_bound(range.start_bound());
+                let end = key_bound(range.end_bound());
+                let range = (as_str(&start), as_str(&end));
+                assert_eq!(
+                    table.remove_range::<&str>(range).unwrap(),
+                    removed.len() as u64
+                );
+
+                for _ in 0..rng.gen_range(0..500) {
+                    let i = rng.gen_range(0..20_000);
+                    if rng.gen() {
+                        table.insert(key(i).as_str(), i).unwrap();
+                        expected.insert(i);
+                    } else {
+                        table.remove(key(i).as_str()).unwrap();
+                        expected.remove(&i);
+                    }
+                }
+            }
+            assert_eq!(table.len().unwrap(), expected.len() as u64);
+            let values: Vec<u64> = table
+                .iter()
+                .unwrap()
+                .map(|entry| entry.unwrap().1.value())
+                .collect();
+            assert!(values.iter().eq(expected.iter()));
+        }
+        write_txn.commit().unwrap();
+        db.check_integrity().unwrap();
+    }
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(definition).unwrap();
+        assert_eq!(
+            table.remove_range::<&str>(..).unwrap(),
+            expected.len() as u64
+        );
+        assert!(table.is_empty().unwrap());
+        table.insert(key(1).as_str(), 1).unwrap();
+        assert_eq!(table.len().unwrap(), 1);
+    }
+    write_txn.commit().unwrap();
+    db.check_integrity().unwrap();
+}
+
+#[test]
+fn update() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        for round in 0..3 {
+            for i in 0..10_000u64 {
+                let old = table
+                    .update(i % 5000, |old| Some(old.unwrap_or(0) + 1))
+                    .unwrap();
+                assert_eq!(
+                    old.map(|old| old.value()),
+                    (round > 0 || i >= 5000).then_some(round * 2 + i / 5000)
+                );
+            }
+        }
+        assert_eq!(table.len().unwrap(), 5000);
+        assert_eq!(table.get(1234).unwrap().unwrap().value(), 6);
+        assert_eq!(table.nth(4000).unwrap().unwrap().0.value(), 4000);
+        assert_eq!(table.rank(4000).unwrap(), 4000);
+
+        assert!(table.update(7, |_| None::<u64>).unwrap().is_some());
+        assert!(table.update(7, |_| None::<u64>).unwrap().is_none());
+        assert!(table.get(7).unwrap().is_none());
+        assert_eq!(table.len().unwrap(), 4999);
+    }
+    write_txn.commit().unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        assert_eq!(
+            table
+                .upsert(7, 100, |old| old + 1)
+                .unwrap()
+                .map(|x| x.value()),
+            None
+        );
+        assert_eq!(
+            table
+                .upsert(7, 100, |old| old + 1)
+                .unwrap()
+                .unwrap()
+                .value(),
+            100
+        );
+        assert_eq!(table.get(7).unwrap().unwrap().value(), 101);
+        assert_eq!(table.len().unwrap(), 5000);
+    }
+    write_txn.commit().unwrap();
+
+    // Values on committed pages are copied, and removed when the function returns None
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        for i in (0..5000u64).rev() {
+            table
+                .update(i, |old| (i % 2 == 1).then(|| old.unwrap() * 10))
+                .unwrap();
+        }
+        assert_eq!(table.len().unwrap(), 2500);
+        assert_eq!(table.get(1235).unwrap().unwrap().value(), 60);
+        assert!(table.get(1234).unwrap().is_none());
+        assert_eq!(table.nth(1000).unwrap().unwrap().0.value(), 2001);
+    }
+    write_txn.commit().unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(STR_TABLE).unwrap();
+        table.upsert("hello", "world", |_| "everyone").unwrap();
+        assert_eq!(table.get("hello").unwrap().unwrap().value(), "world");
+        table.upsert("hello", "world", |_| "everyone").unwrap();
+        assert_eq!(table.get("hello").unwrap().unwrap().value(), "everyone");
+        let long = "x".repeat(10_000);
+        table.update("hello", |_| Some(long.as_str())).unwrap();
+        assert_eq!(table.get("hello").unwrap().unwrap().value(), long);
+    }
+    write_txn.commit().unwrap();
+}
+
+#[test]
+fn merge() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let counters: TableDefinition<&str, u64> =
+        TableDefinition::new("counters").with_merge_operator(MergeOperator::<u64>::ADD);
+    let logs: TableDefinition<u64, &[u8]> =
+        TableDefinition::new("logs").with_merge_operator(MergeOperator::<&[u8]>::APPEND);
+    let maximums: TableDefinition<u64, i32> =
+        TableDefinition::new("max").with_merge_operator(MergeOperator::new(|existing, operand| {
+            existing
+                .map_or(*operand, |x| x.max(*operand))
+                .to_le_bytes()
+                .to_vec()
+        }));
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(counters).unwrap();
+        for i in 0..1000u64 {
+            table.merge("a", i).unwrap();
+            table.merge(format!("{}", i % 10).as_str(), 1).unwrap();
+        }
+        assert_eq!(table.get("a").unwrap().unwrap().value(), 499_500);
+        assert_eq!(table.get("3").unwrap().unwrap().value(), 100);
+        assert_eq!(table.len().unwrap(), 11);
+
+        let mut table = write_txn.open_table(logs).unwrap();
+        table.merge(1, b"hello".as_slice()).unwrap();
+        table.merge(1, b" world".as_slice()).unwrap();
+        assert_eq!(table.get(1).unwrap().unwrap().value(), b"hello world");
+
+        let mut table = write_txn.open_table(maximums).unwrap();
+        for x in [3, -5, 8, 1] {
+            table.merge(0, x).unwrap();
+        }
+        assert_eq!(table.get(0).unwrap().unwrap().value(), 8);
+
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        assert!(matches!(
+            table.merge(0, 1),
+            Err(StorageError::NoMergeOperator(name)) if name == "u64"
+        ));
+        assert!(table.is_empty().unwrap());
+    }
+    write_txn.commit().unwrap();
+}
+
+#[test]
+fn insert_many() {
+    let vfs = MockVfs::new();
+    let mut db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let mut rng = StdRng::seed_from_u64(0);
+    let definition: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
+    let mut expected = BTreeMap::new();
+
+    for round in 0..3u8 {
+        let mut entries = vec![];
+        for _ in 0..2000 {
+            let key = rng.gen_range(0..5000u64);
+            let value = vec![round; rng.gen_range(0..300)];
+            expected.insert(key, value.clone());
+            entries.push((key, value));
+        }
+        let write_txn = db.begin_write().unwrap();
+        {
+            let mut table = write_txn.open_table(definition).unwrap();
+            table
+                .insert_many(entries.iter().map(|(k, v)| (*k, v.as_slice())))
+                .unwrap();
+            let len = u64::try_from(expected.len()).unwrap();
+            assert_eq!(table.len().unwrap(), len);
+            assert_eq!(
+                table.nth(len / 2).unwrap().unwrap().0.value(),
+                *expected.keys().nth(expected.len() / 2).unwrap()
+            );
+        }
+        write_txn.commit().unwrap();
+    }
+    db.check_integrity().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(definition).unwrap();
+    let mut iter = table.iter().unwrap();
+    for (key, value) in expected.iter() {
+        let (k, v) = iter.next().unwrap().unwrap();
+        assert_eq!(k.value(), *key);
+        assert_eq!(v.value(), value.as_slice());
+    }
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn get_many() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let keys = [7000u64, 3, 10_000, 5, 3, 999, 0];
+    let expected: Vec<Option<u64>> = keys
+        .iter()
+        .map(|&key| (key < 5000 && key % 2 == 1).then_some(key * 2))
+        .collect();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        assert!(table
+            .get_many(keys)
+            .unwrap()
+            .iter()
+            .all(|value| value.is_none()));
+        for i in (1..5000u64).step_by(2) {
+            table.insert(i, i * 2).unwrap();
+        }
+        let values = table.get_many(keys).unwrap();
+        let values: Vec<_> = values
+            .iter()
+            .map(|v| v.as_ref().map(|v| v.value()))
+            .collect();
+        assert_eq!(values, expected);
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    let values = table.get_many(keys).unwrap();
+    let values: Vec<_> = values
+        .iter()
+        .map(|v| v.as_ref().map(|v| v.value()))
+        .collect();
+    assert_eq!(values, expected);
+    assert!(table.get_many(Vec::<u64>::new()).unwrap().is_empty());
+}
+
 #[test]
 fn stored_size() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(STR_TABLE).unwrap();
@@ -204,8 +744,8 @@ fn stored_size() {
 
 #[test]
 fn create_open() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(U64_TABLE).unwrap();
@@ -214,7 +754,7 @@ fn create_open() {
     write_txn.commit().unwrap();
     drop(db);
 
-    let db2 = Database::open(tmpfile.path()).unwrap();
+    let db2 = Database::open(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let read_txn = db2.begin_read().unwrap();
     let table = read_txn.open_table(U64_TABLE).unwrap();
@@ -226,8 +766,8 @@ fn multiple_tables() {
     let definition1: TableDefinition<&str, &str> = TableDefinition::new("1");
     let definition2: TableDefinition<&str, &str> = TableDefinition::new("2");
 
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(definition1).unwrap();
@@ -249,8 +789,8 @@ fn multiple_tables() {
 
 #[test]
 fn list_tables() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let definition_x: TableDefinition<&[u8], &[u8]> = TableDefinition::new("x");
     let definition_y: TableDefinition<&[u8], &[u8]> = TableDefinition::new("y");
@@ -315,8 +855,8 @@ fn tuple_type_function_lifetime() {
 
 #[test]
 fn tuple_type_lifetime() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<(&str, u8), (u16, u32)> = TableDefinition::new("table");
 
@@ -336,8 +876,8 @@ fn tuple_type_lifetime() {
 
 #[test]
 fn tuple2_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<(&str, u8), (u16, u32)> = TableDefinition::new("table");
 
@@ -355,8 +895,8 @@ fn tuple2_type() {
 
 #[test]
 fn tuple3_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<(&str, u8, u16), (u16, u32)> = TableDefinition::new("table");
 
@@ -377,8 +917,8 @@ fn tuple3_type() {
 
 #[test]
 fn tuple4_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<(&str, u8, u16, u32), (u16, u32)> =
         TableDefinition::new("table");
@@ -401,8 +941,8 @@ fn tuple4_type() {
 #[test]
 #[allow(clippy::type_complexity)]
 fn tuple5_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<(&str, u8, u16, u32, u64), (u16, u32)> =
         TableDefinition::new("table");
@@ -425,8 +965,8 @@ fn tuple5_type() {
 #[test]
 #[allow(clippy::type_complexity)]
 fn tuple6_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<(&str, u8, u16, u32, u64, u128), (u16, u32)> =
         TableDefinition::new("table");
@@ -453,8 +993,8 @@ fn tuple6_type() {
 #[test]
 #[allow(clippy::type_complexity)]
 fn tuple7_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<(&str, u8, u16, u32, u64, u128, i8), (u16, u32)> =
         TableDefinition::new("table");
@@ -483,8 +1023,8 @@ fn tuple7_type() {
 #[test]
 #[allow(clippy::type_complexity)]
 fn tuple8_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<(&str, u8, u16, u32, u64, u128, i8, i16), (u16, u32)> =
         TableDefinition::new("table");
@@ -513,8 +1053,8 @@ fn tuple8_type() {
 #[test]
 #[allow(clippy::type_complexity)]
 fn tuple9_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<(&str, u8, u16, u32, u64, u128, i8, i16, i32), (u16, u32)> =
         TableDefinition::new("table");
@@ -543,8 +1083,8 @@ fn tuple9_type() {
 #[test]
 #[allow(clippy::type_complexity)]
 fn tuple10_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<(&str, u8, u16, u32, u64, u128, i8, i16, i32, i64), (u16, u32)> =
         TableDefinition::new("table");
@@ -573,8 +1113,8 @@ fn tuple10_type() {
 #[test]
 #[allow(clippy::type_complexity)]
 fn tuple11_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<
         (&str, u8, u16, u32, u64, u128, i8, i16, i32, i64, i128),
@@ -605,8 +1145,8 @@ fn tuple11_type() {
 #[test]
 #[allow(clippy::type_complexity)]
 fn tuple12_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<
         (&str, u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, &str),
@@ -639,8 +1179,8 @@ fn tuple12_type() {
 
 #[test]
 fn is_empty() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let write_txn = db.begin_write().unwrap();
     {
@@ -658,8 +1198,8 @@ fn is_empty() {
 
 #[test]
 fn abort() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let write_txn = db.begin_write().unwrap();
     {
@@ -688,8 +1228,8 @@ fn abort() {
 
 #[test]
 fn insert_overwrite() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(STR_TABLE).unwrap();
@@ -716,8 +1256,8 @@ fn insert_overwrite() {
 
 #[test]
 fn insert_reserve() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let def: TableDefinition<&str, &[u8]> = TableDefinition::new("x");
     let value = "world";
     let write_txn = db.begin_write().unwrap();
@@ -740,8 +1280,8 @@ fn insert_reserve() {
 
 #[test]
 fn delete() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(STR_TABLE).unwrap();
@@ -771,8 +1311,8 @@ fn delete() {
 
 #[test]
 fn no_dirty_reads() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(STR_TABLE).unwrap();
@@ -791,8 +1331,8 @@ fn no_dirty_reads() {
 
 #[test]
 fn read_isolation() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(STR_TABLE).unwrap();
@@ -828,8 +1368,8 @@ fn read_isolation() {
 
 #[test]
 fn read_isolation2() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(STR_TABLE).unwrap();
@@ -864,8 +1404,8 @@ fn read_isolation2() {
 
 #[test]
 fn reopen_table() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(U64_TABLE).unwrap();
@@ -880,8 +1420,8 @@ fn reopen_table() {
 
 #[test]
 fn u64_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(U64_TABLE).unwrap();
@@ -898,15 +1438,15 @@ fn u64_type() {
             .range(0..2)
             .unwrap()
             .map(|item| item.unwrap().1.value())
-            .sum()
+            .sum::<u64>()
     );
     assert_eq!(1, table.get(&0).unwrap().unwrap().value());
 }
 
 #[test]
 fn i128_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
 
     let definition: TableDefinition<i128, i128> = TableDefinition::new("x");
@@ -931,8 +1471,8 @@ fn i128_type() {
 
 #[test]
 fn f32_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let definition: TableDefinition<u8, f32> = TableDefinition::new("x");
 
@@ -950,8 +1490,8 @@ fn f32_type() {
 
 #[test]
 fn str_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let definition: TableDefinition<&str, &str> = TableDefinition::new("x");
 
@@ -977,8 +1517,8 @@ fn str_type() {
 
 #[test]
 fn empty_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let definition: TableDefinition<u8, ()> = TableDefinition::new("x");
 
@@ -996,8 +1536,8 @@ fn empty_type() {
 
 #[test]
 fn option_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let definition: TableDefinition<u8, Option<u32>> = TableDefinition::new("x");
 
@@ -1017,8 +1557,8 @@ fn option_type() {
 
 #[test]
 fn array_type() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let definition: TableDefinition<&[u8; 5], &[u8; 9]> = TableDefinition::new("x");
 
@@ -1041,8 +1581,8 @@ fn array_type() {
 
 #[test]
 fn range_lifetime() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let definition: TableDefinition<&str, &str> = TableDefinition::new("x");
 
@@ -1064,10 +1604,160 @@ fn range_lifetime() {
     assert!(iter.next().is_none());
 }
 
+#[test]
+fn prefix() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let tuple_definition: TableDefinition<(u64, &str), u64> = TableDefinition::new("tuple");
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(STR_TABLE).unwrap();
+        for i in 0..1000u64 {
+            table.insert(format!("{i}").as_str(), "value").unwrap();
+        }
+        let mut table = write_txn.open_table(SLICE_TABLE).unwrap();
+        table.insert(b"\xff".as_slice(), b"".as_slice()).unwrap();
+        table
+            .insert(b"\xff\xff".as_slice(), b"".as_slice())
+            .unwrap();
+        table
+            .insert(b"\xff\x00".as_slice(), b"".as_slice())
+            .unwrap();
+        table
+            .insert(b"\xfe\xff".as_slice(), b"".as_slice())
+            .unwrap();
+        let mut table = write_txn.open_table(tuple_definition).unwrap();
+        for i in 0..100u64 {
+            for name in ["a", "bb", "c"] {
+                table.insert((i, name), i).unwrap();
+            }
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(STR_TABLE).unwrap();
+    let keys: Vec<String> = table
+        .prefix("12")
+        .unwrap()
+        .map(|entry| entry.unwrap().0.value().to_string())
+        .collect();
+    let mut expected = vec!["12".to_string()];
+    expected.extend((120..130).map(|i| format!("{i}")));
+    assert_eq!(keys, expected);
+    let mut iter = table.prefix("99").unwrap();
+    assert_eq!(iter.next_back().unwrap().unwrap().0.value(), "999");
+    assert_eq!(iter.next().unwrap().unwrap().0.value(), "99");
+    assert_eq!(iter.count(), 9);
+    assert_eq!(table.prefix("").unwrap().count(), 1000);
+    assert!(table.prefix("1a").unwrap().next().is_none());
+    assert!(table.prefix("a").unwrap().next().is_none());
+
+    let table = read_txn.open_table(SLICE_TABLE).unwrap();
+    let keys: Vec<Vec<u8>> = table
+        .prefix(b"\xff".as_slice())
+        .unwrap()
+        .map(|entry| entry.unwrap().0.value().to_vec())
+        .collect();
+    assert_eq!(
+        keys,
+        [b"\xff".to_vec(), b"\xff\x00".to_vec(), b"\xff\xff".to_vec()]
+    );
+
+    let table = read_txn.open_table(tuple_definition).unwrap();
+    let keys: Vec<(u64, String)> = table
+        .prefix(42)
+        .unwrap()
+        .map(|entry| {
+            let (key, _) = entry.unwrap();
+            let (i, name) = key.value();
+            (i, name.to_string())
+        })
+        .collect();
+    assert_eq!(
+        keys,
+        [
+            (42, "a".to_string()),
+            (42, "bb".to_string()),
+            (42, "c".to_string())
+        ]
+    );
+    assert!(table.prefix(100).unwrap().next().is_none());
+}
+
+#[test]
+fn cursor() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        let mut cursor = table.cursor().unwrap();
+        assert!(cursor.seek_first().unwrap().is_none());
+        assert!(cursor.seek(0).unwrap().is_none());
+        drop(cursor);
+        // Even keys only, enough to span many leaves
+        for i in 0..10_000u64 {
+            table.insert(i * 2, i).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    let mut cursor = table.cursor().unwrap();
+    assert!(cursor.current().is_none());
+    assert!(cursor.next().unwrap().is_none());
+
+    assert_eq!(cursor.seek(500).unwrap().unwrap().0.value(), 500);
+    assert_eq!(cursor.seek(501).unwrap().unwrap().0.value(), 502);
+    assert_eq!(cursor.current().unwrap().1.value(), 251);
+    assert_eq!(cursor.seek_for_prev(501).unwrap().unwrap().0.value(), 500);
+    assert_eq!(cursor.seek_for_prev(500).unwrap().unwrap().0.value(), 500);
+    assert!(cursor.seek(19_999).unwrap().is_none());
+    assert!(cursor.current().is_none());
+    assert_eq!(
+        cursor.seek_for_prev(u64::MAX).unwrap().unwrap().0.value(),
+        19_998
+    );
+    assert!(cursor.next().unwrap().is_none());
+    assert!(cursor.prev().unwrap().is_none());
+
+    // Walk the whole table in both directions
+    let mut expected = 0;
+    let mut entry = cursor.seek_first().unwrap();
+    while let Some((key, value)) = entry {
+        assert_eq!(key.value(), expected * 2);
+        assert_eq!(value.value(), expected);
+        expected += 1;
+        entry = cursor.next().unwrap();
+    }
+    assert_eq!(expected, 10_000);
+    let mut entry = cursor.seek_last().unwrap();
+    while let Some((key, _)) = entry {
+        expected -= 1;
+        assert_eq!(key.value(), expected * 2);
+        entry = cursor.prev().unwrap();
+    }
+    assert_eq!(expected, 0);
+    assert!(cursor.seek_for_prev(1).unwrap().is_some());
+    assert!(cursor.prev().unwrap().is_none());
+
+    // Change direction at every step, across leaf boundaries
+    cursor.seek(1000).unwrap();
+    for i in 500..5000u64 {
+        assert_eq!(cursor.next().unwrap().unwrap().0.value(), (i + 1) * 2);
+        assert_eq!(cursor.prev().unwrap().unwrap().0.value(), i * 2);
+        assert_eq!(cursor.next().unwrap().unwrap().0.value(), (i + 1) * 2);
+    }
+}
+
 #[test]
 fn drain_lifetime() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let definition: TableDefinition<&str, &str> = TableDefinition::new("x");
 
@@ -1091,8 +1781,8 @@ fn drain_lifetime() {
 
 #[test]
 fn drain_filter_lifetime() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let definition: TableDefinition<&str, &str> = TableDefinition::new("x");
 
@@ -1159,8 +1849,8 @@ fn custom_ordering() {
 
     let definition: TableDefinition<ReverseKey, &str> = TableDefinition::new("x");
 
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(definition).unwrap();
@@ -1186,8 +1876,8 @@ fn custom_ordering() {
 
 #[test]
 fn owned_get_signatures() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let definition: TableDefinition<u32, u32> = TableDefinition::new("x");
 
@@ -1224,8 +1914,8 @@ fn owned_get_signatures() {
 
 #[test]
 fn ref_get_signatures() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(SLICE_TABLE).unwrap();
@@ -1274,8 +1964,8 @@ fn ref_get_signatures() {
 #[cfg(not(target_os = "wasi"))]
 #[test]
 fn concurrent_write_transactions_block() {
-    let tmpfile = create_tempfile();
-    let db = sync::Arc::new(Database::create(tmpfile.path()).unwrap());
+    let vfs = MockVfs::new();
+    let db = sync::Arc::new(Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap());
     let wtx = db.begin_write().unwrap();
     let (sender, receiver) = sync::mpsc::channel();
 
@@ -1295,8 +1985,8 @@ fn concurrent_write_transactions_block() {
 
 #[test]
 fn iter() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(U64_TABLE).unwrap();
@@ -1318,8 +2008,8 @@ fn iter() {
 
 #[test]
 fn drain_next_back() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(U64_TABLE).unwrap();
@@ -1341,8 +2031,8 @@ fn drain_next_back() {
 
 #[test]
 fn drain_filter_all_elements_next_back() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(U64_TABLE).unwrap();
@@ -1364,8 +2054,8 @@ fn drain_filter_all_elements_next_back() {
 
 #[test]
 fn signature_lifetimes() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(STR_TABLE).unwrap();
@@ -1431,8 +2121,8 @@ fn generic_signature_lifetimes() {
         assert_eq!(table.get(key).unwrap().unwrap().value(), buf);
     }
 
-    let tmpfile = create_tempfile();
-    let db = &Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = &Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     {
         let (table, key) = (TableDefinition::<&str, _>::new("&str"), "key");
         write_key_generic(table, key, db);
@@ -1444,3 +2134,179 @@ fn generic_signature_lifetimes() {
         read_key_generic(table, key, db);
     }
 }
+
+#[test]
+fn in_memory_backend() {
+    let db = Database::builder()
+        .create_with_backend(InMemoryBackend::new())
+        .unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        for i in 0..1000 {
+            table.insert(i, i * 2).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    assert_eq!(table.len().unwrap(), 1000);
+    assert_eq!(table.get(42).unwrap().unwrap().value(), 84);
+}
+
+#[test]
+fn in_memory_backend_offset_overflow() {
+    let backend = InMemoryBackend::new();
+    let err = backend.write_all_at(&[1], u64::MAX).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    let err = backend.read_exact_at(&mut [0], u64::MAX).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(backend.len().unwrap(), 0);
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[test]
+fn file_backend_locked() {
+    let tmpfile = tempfile::NamedTempFile::new().unwrap();
+    let _db = Database::builder()
+        .create_with_backend(tmpfile.reopen().unwrap())
+        .unwrap();
+
+    let result = Database::builder().create_with_backend(tmpfile.reopen().unwrap());
+    assert!(matches!(result, Err(DatabaseError::DatabaseAlreadyOpen)));
+}
+
+// Offset and length of each write in a batch
+type WriteBatch = Vec<(u64, u64)>;
+
+#[derive(Debug, Default)]
+struct BatchRecordingBackend {
+    inner: InMemoryBackend,
+    batches: std::sync::Arc<std::sync::Mutex<Vec<WriteBatch>>>,
+}
+
+impl StorageBackend for BatchRecordingBackend {
+    fn len(&self) -> Result<u64, std::io::Error> {
+        self.inner.len()
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<(), std::io::Error> {
+        self.inner.read_exact_at(buf, offset)
+    }
+
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> Result<(), std::io::Error> {
+        self.write_batch(&[(offset, buf)])
+    }
+
+    fn write_batch(&self, writes: &[(u64, &[u8])]) -> Result<(), std::io::Error> {
+        self.batches.lock().unwrap().push(
+            writes
+                .iter()
+                .map(|(offset, buf)| (*offset, buf.len() as u64))
+                .collect(),
+        );
+        for (offset, buf) in writes {
+            self.inner.write_all_at(buf, *offset)?;
+        }
+        Ok(())
+    }
+
+    fn set_len(&self, len: u64) -> Result<(), std::io::Error> {
+        self.inner.set_len(len)
+    }
+
+    fn sync_data(&self, eventual: bool) -> Result<(), std::io::Error> {
+        self.inner.sync_data(eventual)
+    }
+
+    fn lock(&self) -> Result<(), std::io::Error> {
+        self.inner.lock()
+    }
+}
+
+#[test]
+fn coalesced_writes() {
+    let backend = BatchRecordingBackend::default();
+    let batches = backend.batches.clone();
+    let db = Database::builder().create_with_backend(backend).unwrap();
+    batches.lock().unwrap().clear();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_table(U64_TABLE).unwrap();
+        for i in 0..10_000 {
+            table.insert(i, i * 2).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    let batches = batches.lock().unwrap();
+    // The data and the header are each written by a single batch
+    assert!(batches.len() <= 2, "{} batches", batches.len());
+    for batch in batches.iter() {
+        for pair in batch.windows(2) {
+            let (offset, len) = pair[0];
+            // Adjacent pages must be merged into a single write
+            assert!(offset + len < pair[1].0);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct NoSyncBackend(InMemoryBackend);
+
+impl StorageBackend for NoSyncBackend {
+    fn len(&self) -> Result<u64, std::io::Error> {
+        self.0.len()
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<(), std::io::Error> {
+        self.0.read_exact_at(buf, offset)
+    }
+
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> Result<(), std::io::Error> {
+        self.0.write_all_at(buf, offset)
+    }
+
+    fn set_len(&self, len: u64) -> Result<(), std::io::Error> {
+        self.0.set_len(len)
+    }
+
+    fn sync_data(&self, _: bool) -> Result<(), std::io::Error> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    fn lock(&self) -> Result<(), std::io::Error> {
+        self.0.lock()
+    }
+}
+
+#[test]
+fn sync_unsupported() {
+    match Database::builder().create_with_backend(NoSyncBackend::default()) {
+        Err(DatabaseError::Storage(StorageError::Io(err))) => {
+            assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+        }
+        Err(err) => panic!("unexpected error: {err}"),
+        Ok(_) => panic!("database created without durability"),
+    }
+
+    let db = Database::builder()
+        .set_allow_unsynced(true)
+        .create_with_backend(NoSyncBackend::default())
+        .unwrap();
+    for durability in [Durability::Immediate, Durability::Paranoid] {
+        let mut write_txn = db.begin_write().unwrap();
+        write_txn.set_durability(durability);
+        {
+            let mut table = write_txn.open_table(U64_TABLE).unwrap();
+            table.insert(0, 1).unwrap();
+        }
+        write_txn.commit().unwrap();
+    }
+
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(U64_TABLE).unwrap();
+    assert_eq!(table.get(0).unwrap().unwrap().value(), 1);
+}
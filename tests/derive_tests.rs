@@ -0,0 +1,191 @@
+use redb::{Database, MockVfs, ReadableTable, TableDefinition, TableError, TypeName};
+use redb_derive::{RedbKey, RedbValue};
+
+const DB_PATH: &str = "/test.redb";
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, RedbValue, RedbKey)]
+struct Version<'a> {
+    name: &'a str,
+    major: u32,
+    minor: u32,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, RedbValue, RedbKey)]
+struct Point(i32, i32);
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, RedbValue, RedbKey)]
+enum Shape {
+    Empty,
+    Circle(u16),
+    Rectangle { width: u32, height: u8 },
+}
+
+#[derive(Debug, PartialEq, RedbValue)]
+#[redb(type_name = "my_crate::Named")]
+struct Named<'a> {
+    value: Option<&'a [u8]>,
+}
+
+fn create() -> Database {
+    let vfs = MockVfs::new();
+    Database::create(DB_PATH, vfs.config(), vfs).unwrap()
+}
+
+#[test]
+fn fixed_width() {
+    assert_eq!(<Version as redb::RedbValue>::fixed_width(), None);
+    assert_eq!(<Point as redb::RedbValue>::fixed_width(), Some(8));
+    assert_eq!(<Shape as redb::RedbValue>::fixed_width(), Some(6));
+    assert_eq!(<Named as redb::RedbValue>::fixed_width(), None);
+
+    let bytes = <Shape as redb::RedbValue>::as_bytes(&Shape::Empty);
+    assert_eq!(bytes.len(), 6);
+    assert_eq!(<Shape as redb::RedbValue>::from_bytes(&bytes), Shape::Empty);
+}
+
+#[test]
+fn struct_keys() {
+    let mut versions = vec![];
+    for name in ["", "a", "ab", "b"] {
+        for major in [0, 1, 10] {
+            for minor in [0, 5, u32::MAX] {
+                versions.push(Version { name, major, minor });
+            }
+        }
+    }
+
+    let definition: TableDefinition<Version, Point> = TableDefinition::new("versions");
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(definition).unwrap();
+        for (i, version) in versions.iter().rev().enumerate() {
+            let i: i32 = i.try_into().unwrap();
+            table.insert(version, Point(i, -i)).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    versions.sort();
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(definition).unwrap();
+    let mut stored = table.iter().unwrap();
+    for version in versions {
+        assert_eq!(stored.next().unwrap().unwrap().0.value(), version);
+    }
+    assert!(stored.next().is_none());
+    let version = Version {
+        name: "ab",
+        major: 10,
+        minor: 5,
+    };
+    let point = table.get(version).unwrap().unwrap().value();
+    assert_eq!(point.0, -point.1);
+}
+
+#[test]
+fn enum_keys() {
+    let mut shapes = vec![
+        Shape::Rectangle {
+            width: 1,
+            height: 2,
+        },
+        Shape::Circle(3),
+        Shape::Empty,
+        Shape::Rectangle {
+            width: 1,
+            height: 1,
+        },
+        Shape::Circle(1),
+        Shape::Rectangle {
+            width: 0,
+            height: 5,
+        },
+    ];
+
+    let definition: TableDefinition<Shape, Named> = TableDefinition::new("shapes");
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(definition).unwrap();
+        for shape in shapes.iter() {
+            let value = format!("{shape:?}");
+            table
+                .insert(
+                    shape,
+                    Named {
+                        value: Some(value.as_bytes()),
+                    },
+                )
+                .unwrap();
+        }
+        table.insert(Shape::Empty, Named { value: None }).unwrap();
+    }
+    txn.commit().unwrap();
+
+    shapes.sort();
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(definition).unwrap();
+    let stored: Vec<Shape> = table
+        .iter()
+        .unwrap()
+        .map(|entry| entry.unwrap().0.value())
+        .collect();
+    assert_eq!(stored, shapes);
+    assert_eq!(
+        table.get(Shape::Empty).unwrap().unwrap().value(),
+        Named { value: None }
+    );
+    assert_eq!(
+        table.get(Shape::Circle(3)).unwrap().unwrap().value(),
+        Named {
+            value: Some(b"Circle(3)")
+        }
+    );
+}
+
+#[test]
+fn type_names() {
+    mod v1 {
+        #[derive(Debug, redb_derive::RedbValue)]
+        pub struct Record {
+            pub id: u32,
+        }
+    }
+    mod v2 {
+        #[derive(Debug, redb_derive::RedbValue)]
+        pub struct Record {
+            pub id: u64,
+        }
+    }
+
+    assert_eq!(
+        <Version as redb::RedbValue>::type_name(),
+        TypeName::new("Version{name: &str, major: u32, minor: u32}")
+    );
+    assert_eq!(
+        <Point as redb::RedbValue>::type_name(),
+        TypeName::new("Point(i32, i32)")
+    );
+    assert_eq!(
+        <Shape as redb::RedbValue>::type_name(),
+        TypeName::new("Shape{Empty, Circle(u16), Rectangle{width: u32, height: u8}}")
+    );
+    assert_eq!(
+        <Named as redb::RedbValue>::type_name(),
+        TypeName::new("my_crate::Named")
+    );
+
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    let definition: TableDefinition<u64, v1::Record> = TableDefinition::new("records");
+    txn.open_table(definition)
+        .unwrap()
+        .insert(0, v1::Record { id: 1 })
+        .unwrap();
+    let definition: TableDefinition<u64, v2::Record> = TableDefinition::new("records");
+    assert!(matches!(
+        txn.open_table(definition),
+        Err(TableError::TableTypeMismatch { .. })
+    ));
+}
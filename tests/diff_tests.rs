@@ -0,0 +1,214 @@
+use redb::{
+    ChangeKind, Database, KeyChange, MockVfs, MultimapTableDefinition, MultimapTableHandle,
+    ReadableTable, TableDefinition, TableHandle,
+};
+
+const DB_PATH: &str = "/test.redb";
+
+const U64_TABLE: TableDefinition<u64, u64> = TableDefinition::new("u64");
+const STR_TABLE: TableDefinition<&str, &str> = TableDefinition::new("str");
+const MULTIMAP_TABLE: MultimapTableDefinition<u64, u64> = MultimapTableDefinition::new("multimap");
+
+fn create() -> Database {
+    let vfs = MockVfs::new();
+    Database::create(DB_PATH, vfs.config(), vfs).unwrap()
+}
+
+fn changes(changes: &[KeyChange]) -> Vec<(ChangeKind, u64)> {
+    changes
+        .iter()
+        .map(|change| (change.kind(), change.key::<u64>()))
+        .collect()
+}
+
+#[test]
+fn diff_table() {
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        for i in 0..1000 {
+            table.insert(i, i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    let old = db.begin_read().unwrap();
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        table.insert(5, 50).unwrap();
+        // Overwritten with the same value
+        table.insert(6, 6).unwrap();
+        table.remove(500).unwrap();
+        table.insert(2000, 2000).unwrap();
+        table.insert(3000, 3000).unwrap();
+        table.remove(3000).unwrap();
+    }
+    txn.commit().unwrap();
+    let new = db.begin_read().unwrap();
+
+    assert_eq!(
+        changes(&old.diff_table(&new, U64_TABLE).unwrap()),
+        vec![
+            (ChangeKind::Updated, 5),
+            (ChangeKind::Removed, 500),
+            (ChangeKind::Inserted, 2000)
+        ]
+    );
+    assert_eq!(
+        changes(&new.diff_table(&old, U64_TABLE).unwrap()),
+        vec![
+            (ChangeKind::Updated, 5),
+            (ChangeKind::Inserted, 500),
+            (ChangeKind::Removed, 2000)
+        ]
+    );
+    assert!(new.diff_table(&new, U64_TABLE).unwrap().is_empty());
+}
+
+#[test]
+fn missing_table() {
+    let db = create();
+    let old = db.begin_read().unwrap();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(STR_TABLE).unwrap();
+        table.insert("b", "").unwrap();
+        table.insert("a", "").unwrap();
+    }
+    txn.commit().unwrap();
+    let new = db.begin_read().unwrap();
+
+    let diff = old.diff_table(&new, STR_TABLE).unwrap();
+    let keys: Vec<(ChangeKind, &str)> = diff
+        .iter()
+        .map(|change| (change.kind(), change.key::<&str>()))
+        .collect();
+    assert_eq!(
+        keys,
+        vec![(ChangeKind::Inserted, "a"), (ChangeKind::Inserted, "b")]
+    );
+    assert!(old.diff_table(&old, STR_TABLE).unwrap().is_empty());
+}
+
+#[test]
+fn persistent_savepoint() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        for i in 0..10_000 {
+            table.insert(i, i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    let txn = db.begin_write().unwrap();
+    let savepoint_id = txn.persistent_savepoint().unwrap();
+    txn.open_table(U64_TABLE).unwrap().insert(1234, 0).unwrap();
+    txn.commit().unwrap();
+    drop(db);
+
+    let db = Database::builder()
+        .set_read_ahead(0)
+        .open(DB_PATH, vfs.config(), vfs.clone())
+        .unwrap();
+    let old = db.begin_read_at(savepoint_id).unwrap();
+    let new = db.begin_read().unwrap();
+
+    let before = vfs.request_count("GetFileChunk");
+    let diff = old.diff_table(&new, U64_TABLE).unwrap();
+    let diff_requests = vfs.request_count("GetFileChunk") - before;
+    assert_eq!(changes(&diff), vec![(ChangeKind::Updated, 1234)]);
+
+    let before = vfs.request_count("GetFileChunk");
+    for txn in [&old, &new] {
+        let table = txn.open_table(U64_TABLE).unwrap();
+        assert_eq!(table.iter().unwrap().count(), 10_000);
+    }
+    let scan_requests = vfs.request_count("GetFileChunk") - before;
+    // Only the pages on the path to the changed key differ, so the rest are never read
+    assert!(
+        diff_requests * 10 < scan_requests,
+        "{diff_requests} requests, {scan_requests} to scan"
+    );
+}
+
+#[test]
+fn diff_multimap_table() {
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_multimap_table(MULTIMAP_TABLE).unwrap();
+        for i in 0..3 {
+            table.insert(i, 0).unwrap();
+        }
+        // Enough values to be stored in a subtree
+        for i in 0..1000 {
+            table.insert(3, i).unwrap();
+            table.insert(4, i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    let old = db.begin_read().unwrap();
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_multimap_table(MULTIMAP_TABLE).unwrap();
+        table.insert(0, 1).unwrap();
+        // Already present
+        table.insert(1, 0).unwrap();
+        table.remove_all(2).unwrap();
+        table.remove(3, 500).unwrap();
+        // Removed and reinserted, so the subtree is rewritten but holds the same values
+        table.remove(4, 500).unwrap();
+        table.insert(4, 500).unwrap();
+        table.insert(5, 0).unwrap();
+    }
+    txn.commit().unwrap();
+    let new = db.begin_read().unwrap();
+
+    assert_eq!(
+        changes(&old.diff_multimap_table(&new, MULTIMAP_TABLE).unwrap()),
+        vec![
+            (ChangeKind::Updated, 0),
+            (ChangeKind::Removed, 2),
+            (ChangeKind::Updated, 3),
+            (ChangeKind::Inserted, 5)
+        ]
+    );
+}
+
+#[test]
+fn changed_tables() {
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    txn.open_table(U64_TABLE).unwrap().insert(0, 0).unwrap();
+    txn.open_table(STR_TABLE).unwrap().insert("a", "a").unwrap();
+    txn.commit().unwrap();
+    let old = db.begin_read().unwrap();
+
+    let txn = db.begin_write().unwrap();
+    txn.open_table(U64_TABLE).unwrap().insert(1, 1).unwrap();
+    txn.open_multimap_table(MULTIMAP_TABLE)
+        .unwrap()
+        .insert(0, 0)
+        .unwrap();
+    txn.commit().unwrap();
+    let new = db.begin_read().unwrap();
+
+    let changed: Vec<String> = old
+        .changed_tables(&new)
+        .unwrap()
+        .map(|handle| handle.name().to_string())
+        .collect();
+    assert_eq!(changed, vec!["u64".to_string()]);
+    let changed: Vec<String> = old
+        .changed_multimap_tables(&new)
+        .unwrap()
+        .map(|handle| handle.name().to_string())
+        .collect();
+    assert_eq!(changed, vec!["multimap".to_string()]);
+}
@@ -0,0 +1,249 @@
+use redb::{
+    Database, ImportError, ImportSchema, MockVfs, MultimapTableDefinition, ReadableMultimapTable,
+    ReadableTable, TableDefinition, TableError,
+};
+
+const DB_PATH: &str = "/test.redb";
+
+const U64_TABLE: TableDefinition<u64, &str> = TableDefinition::new("u64");
+const STR_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("str");
+const MULTIMAP_TABLE: MultimapTableDefinition<u64, u64> = MultimapTableDefinition::new("multimap");
+
+fn create() -> Database {
+    let vfs = MockVfs::new();
+    Database::create(DB_PATH, vfs.config(), vfs).unwrap()
+}
+
+fn populated() -> Database {
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        for i in 0..1000 {
+            table.insert(i, format!("value {i}").as_str()).unwrap();
+        }
+        let mut table = txn.open_table(STR_TABLE).unwrap();
+        table.insert("hello", b"world".as_slice()).unwrap();
+        let mut table = txn.open_multimap_table(MULTIMAP_TABLE).unwrap();
+        table.insert(0, 0).unwrap();
+        // Enough values to be stored in a subtree
+        for i in 0..1000 {
+            table.insert(1, i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    db
+}
+
+fn check_u64_table(db: &Database) {
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(U64_TABLE).unwrap();
+    assert_eq!(table.len().unwrap(), 1000);
+    for (i, entry) in table.iter().unwrap().enumerate() {
+        let (key, value) = entry.unwrap();
+        assert_eq!(key.value(), i as u64);
+        assert_eq!(value.value(), format!("value {i}"));
+    }
+}
+
+fn check_multimap_table(db: &Database) {
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_multimap_table(MULTIMAP_TABLE).unwrap();
+    let values: Vec<u64> = table
+        .get(0)
+        .unwrap()
+        .map(|value| value.unwrap().value())
+        .collect();
+    assert_eq!(values, vec![0]);
+    let values: Vec<u64> = table
+        .get(1)
+        .unwrap()
+        .map(|value| value.unwrap().value())
+        .collect();
+    assert_eq!(values, (0..1000).collect::<Vec<u64>>());
+}
+
+#[test]
+fn export_import_table() {
+    let db = populated();
+    let mut dump = vec![];
+    let txn = db.begin_read().unwrap();
+    assert_eq!(txn.export_table(U64_TABLE, &mut dump).unwrap(), 1000);
+
+    let text = String::from_utf8(dump.clone()).unwrap();
+    let mut lines = text.lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        r#"{"table":"u64","multimap":false,"key_type":"u64","value_type":"&str"}"#
+    );
+    assert_eq!(lines.next().unwrap(), r#"{"key":0,"value":"value 0"}"#);
+    assert_eq!(lines.count(), 999);
+
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    assert_eq!(txn.import_table(U64_TABLE, dump.as_slice()).unwrap(), 1000);
+    txn.commit().unwrap();
+    check_u64_table(&db);
+}
+
+#[test]
+fn export_import_multimap_table() {
+    let db = populated();
+    let mut dump = vec![];
+    let txn = db.begin_read().unwrap();
+    assert_eq!(
+        txn.export_multimap_table(MULTIMAP_TABLE, &mut dump)
+            .unwrap(),
+        1001
+    );
+
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    assert_eq!(
+        txn.import_multimap_table(MULTIMAP_TABLE, dump.as_slice())
+            .unwrap(),
+        1001
+    );
+    txn.commit().unwrap();
+    check_multimap_table(&db);
+}
+
+#[test]
+fn export_import_database() {
+    let db = populated();
+    let mut dump = vec![];
+    let txn = db.begin_read().unwrap();
+    assert_eq!(txn.export(&mut dump).unwrap(), 2002);
+
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    let mut schema = ImportSchema::new();
+    schema
+        .table(U64_TABLE)
+        .table(STR_TABLE)
+        .multimap_table(MULTIMAP_TABLE);
+    assert_eq!(txn.import(dump.as_slice(), &schema).unwrap(), 2002);
+    txn.commit().unwrap();
+    check_u64_table(&db);
+    check_multimap_table(&db);
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(STR_TABLE).unwrap();
+    assert_eq!(table.get("hello").unwrap().unwrap().value(), b"world");
+
+    // A single table can be imported from a dump of the database
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    assert_eq!(txn.import_table(STR_TABLE, dump.as_slice()).unwrap(), 1);
+    txn.commit().unwrap();
+    let txn = db.begin_read().unwrap();
+    assert_eq!(txn.list_tables().unwrap().count(), 1);
+
+    // Every table must be part of the schema
+    let txn = db.begin_write().unwrap();
+    let mut schema = ImportSchema::new();
+    schema.table(U64_TABLE);
+    assert!(matches!(
+        txn.import(dump.as_slice(), &schema),
+        Err(ImportError::Table(TableError::TableDoesNotExist(_)))
+    ));
+}
+
+#[test]
+fn invalid_imports() {
+    let db = populated();
+    let mut dump = vec![];
+    let txn = db.begin_read().unwrap();
+    txn.export(&mut dump).unwrap();
+
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    let definition: TableDefinition<u64, u64> = TableDefinition::new("u64");
+    match txn.import_table(definition, dump.as_slice()) {
+        Err(ImportError::DumpTypeMismatch { table, key, value }) => {
+            assert_eq!(table, "u64");
+            assert_eq!(key, "u64");
+            assert_eq!(value, "&str");
+        }
+        other => panic!("Unexpected result: {other:?}"),
+    }
+    let definition: TableDefinition<u64, u64> = TableDefinition::new("multimap");
+    assert!(matches!(
+        txn.import_table(definition, dump.as_slice()),
+        Err(ImportError::Table(TableError::TableIsMultimap(_)))
+    ));
+    let definition: TableDefinition<u64, u64> = TableDefinition::new("missing");
+    assert!(matches!(
+        txn.import_table(definition, dump.as_slice()),
+        Err(ImportError::Table(TableError::TableDoesNotExist(_)))
+    ));
+
+    let dump = concat!(
+        r#"{"table":"u64","multimap":false,"key_type":"u64","value_type":"&str"}"#,
+        "\n",
+        r#"{"key":"00","value":""}"#,
+        "\n"
+    );
+    match txn.import_table(U64_TABLE, dump.as_bytes()) {
+        Err(ImportError::InvalidDump(reason)) => assert!(reason.starts_with("line 2")),
+        other => panic!("Unexpected result: {other:?}"),
+    }
+    assert!(matches!(
+        txn.import_table(U64_TABLE, "not json".as_bytes()),
+        Err(ImportError::InvalidDump(_))
+    ));
+}
+
+#[test]
+fn rendered_values() {
+    const FLOATS: TableDefinition<i32, f64> = TableDefinition::new("floats");
+    const WIDE: TableDefinition<i128, ()> = TableDefinition::new("wide");
+    const TUPLES: TableDefinition<u8, (u8, u8)> = TableDefinition::new("tuples");
+
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(FLOATS).unwrap();
+        table.insert(-1, 0.5).unwrap();
+        table.insert(2, f64::NAN).unwrap();
+        let mut table = txn.open_table(WIDE).unwrap();
+        table.insert(i128::MIN, ()).unwrap();
+        let mut table = txn.open_table(TUPLES).unwrap();
+        table.insert(1, (2, 3)).unwrap();
+    }
+    txn.commit().unwrap();
+
+    let mut dump = vec![];
+    let txn = db.begin_read().unwrap();
+    assert_eq!(txn.export(&mut dump).unwrap(), 4);
+    let text = String::from_utf8(dump.clone()).unwrap();
+    let lines: Vec<&str> = text
+        .lines()
+        .filter(|line| line.contains("\"key\""))
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            r#"{"key":-1,"value":0.5}"#,
+            // NaN can't be represented in JSON, so is hex encoded
+            r#"{"key":2,"value":"000000000000f87f"}"#,
+            // Types without a JSON rendering are hex encoded
+            r#"{"key":1,"value":"0203"}"#,
+            r#"{"key":"-170141183460469231731687303715884105728","value":null}"#,
+        ]
+    );
+
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    let mut schema = ImportSchema::new();
+    schema.table(FLOATS).table(WIDE).table(TUPLES);
+    assert_eq!(txn.import(dump.as_slice(), &schema).unwrap(), 4);
+    txn.commit().unwrap();
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(FLOATS).unwrap();
+    assert_eq!(table.get(-1).unwrap().unwrap().value(), 0.5);
+    assert!(table.get(2).unwrap().unwrap().value().is_nan());
+    let table = txn.open_table(WIDE).unwrap();
+    assert!(table.get(i128::MIN).unwrap().is_some());
+    let table = txn.open_table(TUPLES).unwrap();
+    assert_eq!(table.get(1).unwrap().unwrap().value(), (2, 3));
+}
@@ -0,0 +1,210 @@
+use redb::{
+    AccessGuard, Database, IndexDefinition, MockVfs, ReadableMultimapTable, ReadableTable,
+    StorageError, TableDefinition,
+};
+
+const DB_PATH: &str = "/test.redb";
+
+const USERS: TableDefinition<u64, (&str, u32)> = TableDefinition::new("users");
+const BY_NAME: IndexDefinition<u64, (&str, u32), &str> =
+    IndexDefinition::new("users_by_name", USERS, |user| user.0);
+const BY_AGE: IndexDefinition<u64, (&str, u32), u32> =
+    IndexDefinition::new("users_by_age", USERS, |user| user.1);
+
+fn create() -> Database {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs).unwrap();
+    db.register_index(BY_NAME);
+    db.register_index(BY_AGE);
+    db
+}
+
+type Entry<'a> = Result<(AccessGuard<'a, u64>, AccessGuard<'a, (&'static str, u32)>), StorageError>;
+
+fn ids<'a>(entries: impl Iterator<Item = Entry<'a>>) -> Vec<u64> {
+    entries.map(|entry| entry.unwrap().0.value()).collect()
+}
+
+#[test]
+fn maintained_by_writes() {
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(USERS).unwrap();
+        table.insert(1, ("alice", 30)).unwrap();
+        table.insert(2, ("bob", 25)).unwrap();
+        table.insert(3, ("carol", 30)).unwrap();
+        table.insert(4, ("alice", 41)).unwrap();
+        assert_eq!(ids(table.get_by_index(BY_NAME, "alice").unwrap()), [1, 4]);
+        assert_eq!(ids(table.get_by_index(BY_AGE, 30).unwrap()), [1, 3]);
+
+        // Overwriting a value moves its index entry
+        table.insert(2, ("bob", 30)).unwrap();
+        assert!(ids(table.get_by_index(BY_AGE, 25).unwrap()).is_empty());
+        assert_eq!(ids(table.get_by_index(BY_AGE, 30).unwrap()), [1, 2, 3]);
+
+        table.remove(1).unwrap();
+        assert_eq!(ids(table.get_by_index(BY_NAME, "alice").unwrap()), [4]);
+    }
+    txn.commit().unwrap();
+
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(USERS).unwrap();
+    assert_eq!(ids(table.get_by_index(BY_NAME, "alice").unwrap()), [4]);
+    assert_eq!(ids(table.get_by_index(BY_AGE, 30).unwrap()), [2, 3]);
+    let (id, user) = table
+        .get_by_index(BY_NAME, "carol")
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(id.value(), 3);
+    assert_eq!(user.value(), ("carol", 30));
+
+    let index = txn.open_multimap_table(BY_AGE.multimap_table()).unwrap();
+    assert_eq!(index.len().unwrap(), 3);
+}
+
+#[test]
+fn drain_and_pop() {
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(USERS).unwrap();
+        for id in 0..10u64 {
+            let age: u32 = (id % 3).try_into().unwrap();
+            table.insert(id, ("user", age)).unwrap();
+        }
+        table.pop_first().unwrap();
+        table.pop_last().unwrap();
+        table.drain(7..).unwrap();
+        table
+            .drain_filter::<u64, _>(.., |_, user| user.1 == 1)
+            .unwrap();
+        assert_eq!(ids(table.iter().unwrap()), [2, 3, 5, 6]);
+        assert_eq!(
+            ids(table.get_by_index(BY_NAME, "user").unwrap()),
+            [2, 3, 5, 6]
+        );
+        assert_eq!(ids(table.get_by_index(BY_AGE, 0).unwrap()), [3, 6]);
+        assert_eq!(ids(table.get_by_index(BY_AGE, 2).unwrap()), [2, 5]);
+        assert!(!table.check_index(BY_AGE).unwrap());
+        assert!(!table.check_index(BY_NAME).unwrap());
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn range_scans() {
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(USERS).unwrap();
+        table.insert(1, ("a", 50)).unwrap();
+        table.insert(2, ("b", 10)).unwrap();
+        table.insert(3, ("c", 30)).unwrap();
+        table.insert(4, ("d", 20)).unwrap();
+        table.insert(5, ("e", 30)).unwrap();
+    }
+    txn.commit().unwrap();
+
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(USERS).unwrap();
+    assert_eq!(ids(table.index_range(BY_AGE, 20..=30).unwrap()), [4, 3, 5]);
+    assert_eq!(ids(table.index_range(BY_AGE, 25..).unwrap()), [3, 5, 1]);
+    assert_eq!(
+        ids(table.index_range::<u32, u32>(BY_AGE, ..).unwrap().rev()),
+        [1, 5, 3, 4, 2]
+    );
+    assert_eq!(ids(table.index_range(BY_NAME, "b".."d").unwrap()), [2, 3]);
+}
+
+#[test]
+fn rebuild_stale_index() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs).unwrap();
+    let txn = db.begin_write().unwrap();
+    {
+        // Written before the index was registered
+        let mut table = txn.open_table(USERS).unwrap();
+        table.insert(1, ("alice", 30)).unwrap();
+        table.insert(2, ("bob", 25)).unwrap();
+    }
+    txn.commit().unwrap();
+
+    db.register_index(BY_NAME);
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(USERS).unwrap();
+        table.insert(3, ("alice", 20)).unwrap();
+        assert_eq!(ids(table.get_by_index(BY_NAME, "alice").unwrap()), [3]);
+        assert!(table.check_index(BY_NAME).unwrap());
+        assert_eq!(ids(table.get_by_index(BY_NAME, "alice").unwrap()), [1, 3]);
+        assert_eq!(ids(table.get_by_index(BY_NAME, "bob").unwrap()), [2]);
+        assert!(!table.check_index(BY_NAME).unwrap());
+    }
+    txn.commit().unwrap();
+
+    // Stale entries, which point to removed keys or old values, are removed
+    let txn = db.begin_write().unwrap();
+    {
+        let mut index = txn.open_multimap_table(BY_NAME.multimap_table()).unwrap();
+        index.insert("carol", 2).unwrap();
+        index.insert("dave", 7).unwrap();
+    }
+    {
+        let mut table = txn.open_table(USERS).unwrap();
+        assert_eq!(ids(table.get_by_index(BY_NAME, "carol").unwrap()), [2]);
+        assert!(ids(table.get_by_index(BY_NAME, "dave").unwrap()).is_empty());
+        assert!(table.check_index(BY_NAME).unwrap());
+        assert!(ids(table.get_by_index(BY_NAME, "carol").unwrap()).is_empty());
+    }
+    txn.commit().unwrap();
+
+    // Indexes which have not been registered can still be checked, and rebuilt
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(USERS).unwrap();
+        assert!(table.check_index(BY_AGE).unwrap());
+        assert_eq!(ids(table.index_range(BY_AGE, ..=25).unwrap()), [3, 2]);
+    }
+    txn.commit().unwrap();
+}
+
+#[test]
+fn insert_reserve_rejected() {
+    const BLOBS: TableDefinition<u64, &[u8]> = TableDefinition::new("blobs");
+    const BY_CONTENTS: IndexDefinition<u64, &[u8], &[u8]> =
+        IndexDefinition::new("blobs_by_contents", BLOBS, |blob| &blob[..]);
+
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs).unwrap();
+    let txn = db.begin_write().unwrap();
+    {
+        // Allowed until the index is registered
+        let mut table = txn.open_table(BLOBS).unwrap();
+        table
+            .insert_reserve(1, 5)
+            .unwrap()
+            .as_mut()
+            .copy_from_slice(b"hello");
+    }
+    txn.commit().unwrap();
+
+    db.register_index(BY_CONTENTS);
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(BLOBS).unwrap();
+        assert!(matches!(
+            table.insert_reserve(2, 5),
+            Err(StorageError::IndexedTable(name)) if name == "blobs"
+        ));
+        table.insert(2, b"world".as_slice()).unwrap();
+        let mut entries = table
+            .get_by_index(BY_CONTENTS, b"world".as_slice())
+            .unwrap();
+        assert_eq!(entries.next().unwrap().unwrap().0.value(), 2);
+        assert!(entries.next().is_none());
+    }
+    txn.commit().unwrap();
+}
@@ -1,28 +1,21 @@
-use std::fs;
 use std::io::ErrorKind;
 
 use rand::prelude::SliceRandom;
 use rand::Rng;
 use redb::{
-    Builder, Database, Durability, MultimapTableDefinition, ReadableTable, TableDefinition,
+    Builder, Database, Durability, MockVfs, MultimapTableDefinition, ReadableTable, TableDefinition,
 };
 use redb::{DatabaseError, ReadableMultimapTable, SavepointError, StorageError, TableError};
 
 const ELEMENTS: usize = 100;
 
+const DB_PATH: &str = "/test.redb";
+
 const SLICE_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("slice");
 const SLICE_TABLE2: TableDefinition<&[u8], &[u8]> = TableDefinition::new("slice2");
 const STR_TABLE: TableDefinition<&str, &str> = TableDefinition::new("x");
 const U64_TABLE: TableDefinition<u64, u64> = TableDefinition::new("u64");
 
-fn create_tempfile() -> tempfile::NamedTempFile {
-    if cfg!(target_os = "wasi") {
-        tempfile::NamedTempFile::new_in("/").unwrap()
-    } else {
-        tempfile::NamedTempFile::new().unwrap()
-    }
-}
-
 /// Returns pairs of key, value
 fn gen_data(count: usize, key_size: usize, value_size: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
     let mut pairs = vec![];
@@ -38,9 +31,9 @@ fn gen_data(count: usize, key_size: usize, value_size: usize) -> Vec<(Vec<u8>, V
 
 #[test]
 fn mixed_durable_commit() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let mut txn = db.begin_write().unwrap();
     txn.set_durability(Durability::None);
     {
@@ -55,9 +48,9 @@ fn mixed_durable_commit() {
 
 #[test]
 fn non_durable_commit_persistence() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let mut txn = db.begin_write().unwrap();
     txn.set_durability(Durability::None);
     let pairs = gen_data(100, 16, 20);
@@ -72,7 +65,7 @@ fn non_durable_commit_persistence() {
 
     // Check that cleanly closing the database persists the non-durable commit
     drop(db);
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let txn = db.begin_read().unwrap();
     let table = txn.open_table(SLICE_TABLE).unwrap();
 
@@ -88,9 +81,9 @@ fn non_durable_commit_persistence() {
 }
 
 fn test_persistence(durability: Durability) {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let mut txn = db.begin_write().unwrap();
     txn.set_durability(durability);
     let pairs = gen_data(100, 16, 20);
@@ -104,7 +97,7 @@ fn test_persistence(durability: Durability) {
     txn.commit().unwrap();
 
     drop(db);
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let txn = db.begin_read().unwrap();
     let table = txn.open_table(SLICE_TABLE).unwrap();
 
@@ -131,9 +124,9 @@ fn immediate_persistence() {
 
 #[test]
 fn free() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let txn = db.begin_write().unwrap();
     {
         let _table = txn.open_table(SLICE_TABLE).unwrap();
@@ -198,9 +191,9 @@ fn free() {
 
 #[test]
 fn large_values() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let txn = db.begin_write().unwrap();
 
     let mut key = vec![0u8; 1024];
@@ -227,9 +220,9 @@ fn large_values() {
 
 #[test]
 fn large_keys() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let txn = db.begin_write().unwrap();
 
     let mut key = vec![0u8; 1024];
@@ -256,12 +249,12 @@ fn large_keys() {
 
 #[test]
 fn dynamic_growth() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
     let table_definition: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
     let big_value = vec![0u8; 1024];
 
     let expected_size = 10 * 1024 * 1024;
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let txn = db.begin_write().unwrap();
     {
         let mut table = txn.open_table(table_definition).unwrap();
@@ -269,7 +262,7 @@ fn dynamic_growth() {
     }
     txn.commit().unwrap();
 
-    let initial_file_size = tmpfile.as_file().metadata().unwrap().len();
+    let initial_file_size = vfs.file(DB_PATH).unwrap().len() as u64;
     assert!(initial_file_size < (expected_size / 2) as u64);
 
     let txn = db.begin_write().unwrap();
@@ -281,18 +274,20 @@ fn dynamic_growth() {
     }
     txn.commit().unwrap();
 
-    let file_size = tmpfile.as_file().metadata().unwrap().len();
+    let file_size = vfs.file(DB_PATH).unwrap().len() as u64;
 
     assert!(file_size > initial_file_size);
 }
 
 #[test]
 fn multi_page_kv() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
     let elements = 4;
     let page_size = 4096;
 
-    let db = Builder::new().create(tmpfile.path()).unwrap();
+    let db = Builder::new()
+        .create(DB_PATH, vfs.config(), vfs.clone())
+        .unwrap();
     let txn = db.begin_write().unwrap();
 
     let mut key = vec![0u8; page_size + 1];
@@ -329,9 +324,9 @@ fn multi_page_kv() {
 #[test]
 // Test for a bug in the deletion code, where deleting a key accidentally deleted other keys
 fn regression() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let txn = db.begin_write().unwrap();
     {
         let mut table = txn.open_table(U64_TABLE).unwrap();
@@ -390,9 +385,9 @@ fn regression() {
 #[test]
 // Test for a bug in table creation code, where multiple tables could end up with the same id
 fn regression2() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let tx = db.begin_write().unwrap();
 
     let a_def: TableDefinition<&str, &str> = TableDefinition::new("a");
@@ -410,9 +405,9 @@ fn regression2() {
 // Test for a bug in deletion code, where deletions could delete neighboring keys in a leaf,
 // due to the partial leaf entries being dropped
 fn regression3() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let tx = db.begin_write().unwrap();
     {
         let mut t = tx.open_table(SLICE_TABLE).unwrap();
@@ -432,9 +427,9 @@ fn regression3() {
 
 #[test]
 fn regression7() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
 
@@ -475,9 +470,9 @@ fn regression7() {
 
 #[test]
 fn regression8() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
 
@@ -524,9 +519,9 @@ fn regression8() {
 
 #[test]
 fn regression9() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
 
@@ -542,9 +537,9 @@ fn regression9() {
 
 #[test]
 fn regression10() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
 
@@ -574,9 +569,9 @@ fn regression10() {
 
 #[test]
 fn regression11() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
 
@@ -611,9 +606,9 @@ fn regression11() {
 #[test]
 // Test that for stale read bug when re-opening a table during a write
 fn regression12() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: TableDefinition<u64, u64> = TableDefinition::new("x");
 
@@ -632,9 +627,9 @@ fn regression12() {
 
 #[test]
 fn regression13() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: MultimapTableDefinition<u64, &[u8]> = MultimapTableDefinition::new("x");
 
@@ -652,9 +647,9 @@ fn regression13() {
 
 #[test]
 fn regression14() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let table_def: MultimapTableDefinition<u64, &[u8]> = MultimapTableDefinition::new("x");
 
@@ -691,9 +686,11 @@ fn regression14() {
 
 #[test]
 fn regression17() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::builder().create(tmpfile.path()).unwrap();
+    let db = Database::builder()
+        .create(DB_PATH, vfs.config(), vfs.clone())
+        .unwrap();
 
     let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
 
@@ -712,9 +709,11 @@ fn regression17() {
 
 #[test]
 fn regression18() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::builder().create(tmpfile.path()).unwrap();
+    let db = Database::builder()
+        .create(DB_PATH, vfs.config(), vfs.clone())
+        .unwrap();
 
     let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
 
@@ -778,9 +777,11 @@ fn regression18() {
 
 #[test]
 fn regression19() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::builder().create(tmpfile.path()).unwrap();
+    let db = Database::builder()
+        .create(DB_PATH, vfs.config(), vfs.clone())
+        .unwrap();
 
     let table_def: TableDefinition<u64, &[u8]> = TableDefinition::new("x");
 
@@ -819,13 +820,15 @@ fn regression19() {
 
 #[test]
 fn regression20() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
     let table_def: MultimapTableDefinition<'static, u128, u128> =
         MultimapTableDefinition::new("some-table");
 
     for _ in 0..3 {
-        let mut db = Database::builder().create(tmpfile.path()).unwrap();
+        let mut db = Database::builder()
+            .create(DB_PATH, vfs.config(), vfs.clone())
+            .unwrap();
         db.check_integrity().unwrap();
 
         let txn = db.begin_write().unwrap();
@@ -842,8 +845,10 @@ fn regression20() {
 
 #[test]
 fn regression21() {
-    let tmpfile = create_tempfile();
-    let db = Database::builder().create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::builder()
+        .create(DB_PATH, vfs.config(), vfs.clone())
+        .unwrap();
 
     let txn = db.begin_write().unwrap();
     let mut table = txn.open_table(U64_TABLE).unwrap();
@@ -860,8 +865,10 @@ fn regression21() {
 
 #[test]
 fn multimap_stats() {
-    let tmpfile = create_tempfile();
-    let db = Database::builder().create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::builder()
+        .create(DB_PATH, vfs.config(), vfs.clone())
+        .unwrap();
 
     let table_def: MultimapTableDefinition<u128, u128> = MultimapTableDefinition::new("x");
 
@@ -883,11 +890,11 @@ fn multimap_stats() {
 
 #[test]
 fn no_savepoint_resurrection() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
     let db = Database::builder()
         .set_cache_size(41178283)
-        .create(tmpfile.path())
+        .create(DB_PATH, vfs.config(), vfs.clone())
         .unwrap();
 
     let tx = db.begin_write().unwrap();
@@ -909,8 +916,8 @@ fn no_savepoint_resurrection() {
 
 #[test]
 fn non_durable_read_isolation() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let mut write_txn = db.begin_write().unwrap();
     write_txn.set_durability(Durability::None);
     {
@@ -954,8 +961,8 @@ fn non_durable_read_isolation() {
 
 #[test]
 fn range_query() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(U64_TABLE).unwrap();
@@ -993,8 +1000,8 @@ fn range_query() {
 
 #[test]
 fn range_query_reversed() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_table(U64_TABLE).unwrap();
@@ -1034,8 +1041,8 @@ fn range_query_reversed() {
 
 #[test]
 fn alias_table() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let write_txn = db.begin_write().unwrap();
     let table = write_txn.open_table(STR_TABLE).unwrap();
@@ -1049,8 +1056,8 @@ fn alias_table() {
 
 #[test]
 fn delete_table() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let y_def: MultimapTableDefinition<&str, &str> = MultimapTableDefinition::new("y");
 
@@ -1079,8 +1086,8 @@ fn delete_table() {
 
 #[test]
 fn delete_all_tables() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let x_def: TableDefinition<&str, &str> = TableDefinition::new("x");
     let y_def: TableDefinition<&str, &str> = TableDefinition::new("y");
@@ -1109,8 +1116,8 @@ fn delete_all_tables() {
 
 #[test]
 fn dropped_write() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let write_txn = db.begin_write().unwrap();
     {
@@ -1125,9 +1132,9 @@ fn dropped_write() {
 
 #[test]
 fn non_page_size_multiple() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let txn = db.begin_write().unwrap();
     let key = vec![0u8; 1024];
     let value = vec![0u8; 1];
@@ -1144,18 +1151,18 @@ fn non_page_size_multiple() {
 
 #[test]
 fn does_not_exist() {
-    let tmpfile = create_tempfile();
-    fs::remove_file(tmpfile.path()).unwrap();
-    let result = Database::open(tmpfile.path());
+    let vfs = MockVfs::new();
+    let result = Database::open(DB_PATH, vfs.config(), vfs.clone());
     if let Err(DatabaseError::Storage(StorageError::Io(e))) = result {
         assert!(matches!(e.kind(), ErrorKind::NotFound));
     } else {
         panic!();
     }
 
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
+    vfs.insert_file(DB_PATH, vec![]);
 
-    let result = Database::open(tmpfile.path());
+    let result = Database::open(DB_PATH, vfs.config(), vfs.clone());
     if let Err(DatabaseError::Storage(StorageError::Io(e))) = result {
         assert!(matches!(e.kind(), ErrorKind::InvalidData));
     } else {
@@ -1165,8 +1172,8 @@ fn does_not_exist() {
 
 #[test]
 fn wrong_types() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let definition: TableDefinition<u32, u32> = TableDefinition::new("x");
     let wrong_definition: TableDefinition<u64, u64> = TableDefinition::new("x");
@@ -1207,14 +1214,16 @@ fn tree_balance() {
         height.try_into().unwrap()
     }
 
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
 
     // One for the last table id counter, and one for the "x" -> TableDefinition entry
     let num_internal_entries = 2;
 
     // Pages are 4kb, so use a key size such that 9 keys will fit
     let key_size = 410;
-    let db = Database::builder().create(tmpfile.path()).unwrap();
+    let db = Database::builder()
+        .create(DB_PATH, vfs.config(), vfs.clone())
+        .unwrap();
     let txn = db.begin_write().unwrap();
 
     let elements = (EXPECTED_ORDER / 2).pow(2) - num_internal_entries;
@@ -1255,23 +1264,23 @@ fn tree_balance() {
 #[cfg(not(target_os = "wasi"))] // TODO remove this line once WASI gets flock
 #[test]
 fn database_lock() {
-    let tmpfile = create_tempfile();
-    let result = Database::create(tmpfile.path());
+    let vfs = MockVfs::new();
+    let result = Database::create(DB_PATH, vfs.config(), vfs.clone());
     assert!(result.is_ok());
-    let result2 = Database::open(tmpfile.path());
+    let result2 = Database::open(DB_PATH, vfs.config(), vfs.clone());
     assert!(
         matches!(result2, Err(DatabaseError::DatabaseAlreadyOpen)),
         "{result2:?}",
     );
     drop(result);
-    let result = Database::open(tmpfile.path());
+    let result = Database::open(DB_PATH, vfs.config(), vfs.clone());
     assert!(result.is_ok());
 }
 
 #[test]
 fn persistent_savepoint() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let definition: TableDefinition<u32, &str> = TableDefinition::new("x");
 
     let txn = db.begin_write().unwrap();
@@ -1290,7 +1299,7 @@ fn persistent_savepoint() {
     txn.commit().unwrap();
 
     drop(db);
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     // Make sure running the GC doesn't invalidate the savepoint
     let txn = db.begin_write().unwrap();
     txn.commit().unwrap();
@@ -1308,10 +1317,72 @@ fn persistent_savepoint() {
     assert_eq!(table.get(&0).unwrap().unwrap().value(), "hello");
 }
 
+#[test]
+fn read_at_savepoint() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let definition: TableDefinition<u32, &str> = TableDefinition::new("x");
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(definition).unwrap();
+        table.insert(&0, "hello").unwrap();
+    }
+    txn.commit().unwrap();
+
+    let txn = db.begin_write().unwrap();
+    let savepoint_id = txn.persistent_savepoint().unwrap();
+    {
+        let mut table = txn.open_table(definition).unwrap();
+        table.insert(&0, "world").unwrap();
+        table.insert(&1, "new").unwrap();
+    }
+    txn.commit().unwrap();
+
+    drop(db);
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    assert!(matches!(
+        db.begin_read_at(savepoint_id + 1).err().unwrap(),
+        SavepointError::InvalidSavepoint
+    ));
+
+    let old = db.begin_read_at(savepoint_id).unwrap();
+    let txn = db.begin_write().unwrap();
+    assert!(txn.delete_persistent_savepoint(savepoint_id).unwrap());
+    {
+        let mut table = txn.open_table(definition).unwrap();
+        for i in 2..100 {
+            table.insert(&i, "value").unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    // Make sure running the GC doesn't free the pages of the open transaction
+    let txn = db.begin_write().unwrap();
+    txn.commit().unwrap();
+    let txn = db.begin_write().unwrap();
+    txn.commit().unwrap();
+
+    let table = old.open_table(definition).unwrap();
+    assert_eq!(table.len().unwrap(), 1);
+    assert_eq!(table.get(&0).unwrap().unwrap().value(), "hello");
+    drop(table);
+    drop(old);
+
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(definition).unwrap();
+    assert_eq!(table.get(&0).unwrap().unwrap().value(), "world");
+    assert_eq!(table.len().unwrap(), 100);
+
+    assert!(matches!(
+        db.begin_read_at(savepoint_id).err().unwrap(),
+        SavepointError::InvalidSavepoint
+    ));
+}
+
 #[test]
 fn savepoint() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let definition: TableDefinition<u32, &str> = TableDefinition::new("x");
 
     let txn = db.begin_write().unwrap();
@@ -1352,8 +1423,8 @@ fn savepoint() {
 
 #[test]
 fn compaction() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let definition: TableDefinition<u32, &[u8]> = TableDefinition::new("x");
 
     let big_value = vec![0u8; 100 * 1024];
@@ -1384,22 +1455,115 @@ fn compaction() {
     // The values are > 1 page, so shouldn't get relocated. Therefore there should be a bunch of fragmented space,
     // since we left the last 100 values in the db.
     drop(db);
-    let file_size = tmpfile.as_file().metadata().unwrap().len();
-    let mut db = Database::open(tmpfile.path()).unwrap();
+    let file_size = vfs.file(DB_PATH).unwrap().len() as u64;
+    let mut db = Database::open(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     assert!(db.compact().unwrap());
     drop(db);
-    let file_size2 = tmpfile.as_file().metadata().unwrap().len();
+    let file_size2 = vfs.file(DB_PATH).unwrap().len() as u64;
     assert!(file_size2 < file_size);
 }
 
+#[test]
+fn compaction_step() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let definition: TableDefinition<u32, &[u8]> = TableDefinition::new("x");
+
+    let big_value = vec![0u8; 100 * 1024];
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(definition).unwrap();
+        for i in 0..100 {
+            table.insert(&i, big_value.as_slice()).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(definition).unwrap();
+        for i in 0..90 {
+            table.remove(&i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    let txn = db.begin_write().unwrap();
+    txn.commit().unwrap();
+    let file_size = vfs.file(DB_PATH).unwrap().len() as u64;
+
+    // Neither readers nor savepoints prevent compaction
+    let read_txn = db.begin_read().unwrap();
+    let savepoint = db.begin_write().unwrap().ephemeral_savepoint().unwrap();
+    let mut steps = 0;
+    while db.compact_step(1).unwrap() {
+        steps += 1;
+        let table = read_txn.open_table(definition).unwrap();
+        assert_eq!(table.len().unwrap(), 10);
+    }
+    // Each step relocates a single page
+    assert!(steps > 1);
+    let txn = db.begin_read().unwrap();
+    assert_eq!(txn.open_table(definition).unwrap().len().unwrap(), 10);
+    drop(txn);
+
+    // The relocated pages are freed once nothing references them
+    drop(read_txn);
+    drop(savepoint);
+    while db.compact_step(usize::MAX).unwrap() {}
+    let file_size2 = vfs.file(DB_PATH).unwrap().len() as u64;
+    assert!(file_size2 < file_size, "{file_size2} >= {file_size}");
+
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(definition).unwrap();
+    for i in 90..100 {
+        assert_eq!(
+            table.get(&i).unwrap().unwrap().value(),
+            big_value.as_slice()
+        );
+    }
+}
+
+#[test]
+fn compaction_step_is_bounded() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let definition: TableDefinition<u64, u64> = TableDefinition::new("x");
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(definition).unwrap();
+        for i in 0..10_000 {
+            table.insert(&i, &i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    while db.compact_step(usize::MAX).unwrap() {}
+
+    // Each step visits a single page, so a pass over the database takes a step per page
+    let txn = db.begin_write().unwrap();
+    let stats = txn.stats().unwrap();
+    txn.abort().unwrap();
+    let mut steps = 0;
+    while db.compact_step(1).unwrap() {
+        steps += 1;
+    }
+    assert!(steps >= stats.leaf_pages(), "{steps}");
+
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(definition).unwrap();
+    assert_eq!(table.len().unwrap(), 10_000);
+    assert_eq!(table.get(&1234).unwrap().unwrap().value(), 1234);
+}
+
 fn require_send<T: Send>(_: &T) {}
 fn require_sync<T: Sync + Send>(_: &T) {}
 
 #[test]
 fn is_send() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let definition: TableDefinition<u32, &[u8]> = TableDefinition::new("x");
 
     let txn = db.begin_write().unwrap();
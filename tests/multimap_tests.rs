@@ -1,18 +1,12 @@
-use redb::{Database, MultimapTableDefinition, ReadableMultimapTable, TableError};
+use redb::{Database, MockVfs, MultimapTableDefinition, ReadableMultimapTable, TableError};
+
+const DB_PATH: &str = "/test.redb";
 
 const STR_TABLE: MultimapTableDefinition<&str, &str> = MultimapTableDefinition::new("str_to_str");
 const SLICE_U64_TABLE: MultimapTableDefinition<&[u8], u64> =
     MultimapTableDefinition::new("slice_to_u64");
 const U64_TABLE: MultimapTableDefinition<u64, u64> = MultimapTableDefinition::new("u64");
 
-fn create_tempfile() -> tempfile::NamedTempFile {
-    if cfg!(target_os = "wasi") {
-        tempfile::NamedTempFile::new_in("/").unwrap()
-    } else {
-        tempfile::NamedTempFile::new().unwrap()
-    }
-}
-
 fn get_vec(
     table: &impl ReadableMultimapTable<&'static str, &'static str>,
     key: &str,
@@ -31,8 +25,8 @@ fn get_vec(
 
 #[test]
 fn len() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_multimap_table(STR_TABLE).unwrap();
@@ -47,10 +41,57 @@ fn len() {
     assert_eq!(table.len().unwrap(), 3);
 }
 
+#[test]
+fn len_maintained() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_multimap_table(U64_TABLE).unwrap();
+        // Enough values for key 0 to be stored in a subtree
+        for i in 0..1000 {
+            table.insert(0, i).unwrap();
+        }
+        for i in 1..10 {
+            table.insert(i, i).unwrap();
+            table.insert(i, i + 1).unwrap();
+        }
+        table.insert(1, 1).unwrap();
+        assert_eq!(table.len().unwrap(), 1018);
+        assert!(table.remove(1, 1).unwrap());
+        assert!(!table.remove(1, 1).unwrap());
+        assert_eq!(table.len().unwrap(), 1017);
+    }
+    write_txn.commit().unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_multimap_table(U64_TABLE).unwrap();
+        assert_eq!(table.remove_all(0).unwrap().count(), 1000);
+        assert_eq!(table.len().unwrap(), 17);
+    }
+    write_txn.abort().unwrap();
+
+    let write_txn = db.begin_write().unwrap();
+    {
+        let mut table = write_txn.open_multimap_table(U64_TABLE).unwrap();
+        assert_eq!(table.len().unwrap(), 1017);
+        table.remove_all(2).unwrap();
+        assert_eq!(table.len().unwrap(), 1015);
+    }
+    write_txn.commit().unwrap();
+    drop(db);
+
+    let db = Database::open(DB_PATH, vfs.config(), vfs).unwrap();
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_multimap_table(U64_TABLE).unwrap();
+    assert_eq!(table.len().unwrap(), 1015);
+}
+
 #[test]
 fn is_empty() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let write_txn = db.begin_write().unwrap();
     {
@@ -66,8 +107,8 @@ fn is_empty() {
 
 #[test]
 fn insert() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_multimap_table(STR_TABLE).unwrap();
@@ -88,8 +129,8 @@ fn insert() {
 
 #[test]
 fn range_query() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_multimap_table(SLICE_U64_TABLE).unwrap();
@@ -139,8 +180,8 @@ fn range_query() {
 
 #[test]
 fn range_lifetime() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let definition: MultimapTableDefinition<&str, &str> = MultimapTableDefinition::new("x");
 
@@ -174,8 +215,8 @@ fn range_lifetime() {
 
 #[test]
 fn delete() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_multimap_table(STR_TABLE).unwrap();
@@ -231,8 +272,8 @@ fn delete() {
 
 #[test]
 fn wrong_types() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let definition: MultimapTableDefinition<u32, u32> = MultimapTableDefinition::new("x");
     let wrong_definition: MultimapTableDefinition<u64, u64> = MultimapTableDefinition::new("x");
@@ -258,12 +299,12 @@ fn wrong_types() {
 
 #[test]
 fn efficient_storage() {
-    let tmpfile = create_tempfile();
+    let vfs = MockVfs::new();
     let expected_max_size = 1024 * 1024;
     // Write enough values that big_key.len() * entries > db_size to check that duplicate key data is not stored
     // and entries * sizeof(u32) > page_size to validate that large numbers of values can be stored per key
     let entries = 10000;
-    let db = Database::create(tmpfile.path()).unwrap();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let table_def: MultimapTableDefinition<&[u8], u32> = MultimapTableDefinition::new("x");
     let write_txn = db.begin_write().unwrap();
     {
@@ -283,8 +324,8 @@ fn efficient_storage() {
 
 #[test]
 fn reopen_table() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_multimap_table(STR_TABLE).unwrap();
@@ -299,8 +340,8 @@ fn reopen_table() {
 
 #[test]
 fn iter() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
     let write_txn = db.begin_write().unwrap();
     {
         let mut table = write_txn.open_multimap_table(U64_TABLE).unwrap();
@@ -326,8 +367,8 @@ fn iter() {
 
 #[test]
 fn multimap_signature_lifetimes() {
-    let tmpfile = create_tempfile();
-    let db = Database::create(tmpfile.path()).unwrap();
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
     let def: MultimapTableDefinition<&str, u64> = MultimapTableDefinition::new("x");
 
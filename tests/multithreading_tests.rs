@@ -1,22 +1,16 @@
 #[cfg(not(target_os = "wasi"))]
 mod multithreading_test {
-    use redb::{Database, ReadableTable, TableDefinition};
+    use redb::{Database, MockVfs, ReadableTable, TableDefinition};
     use std::sync::Arc;
     use std::thread;
 
-    fn create_tempfile() -> tempfile::NamedTempFile {
-        if cfg!(target_os = "wasi") {
-            tempfile::NamedTempFile::new_in("/").unwrap()
-        } else {
-            tempfile::NamedTempFile::new().unwrap()
-        }
-    }
+    const DB_PATH: &str = "/test.redb";
 
     const TABLE: TableDefinition<&str, &str> = TableDefinition::new("x");
     #[test]
     fn len() {
-        let tmpfile = create_tempfile();
-        let db = Database::create(tmpfile.path()).unwrap();
+        let vfs = MockVfs::new();
+        let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
         let db = Arc::new(db);
         let write_txn = db.begin_write().unwrap();
         {
@@ -42,8 +36,8 @@ mod multithreading_test {
 
     #[test]
     fn multithreaded_insert() {
-        let tmpfile = create_tempfile();
-        let db = Database::create(tmpfile.path()).unwrap();
+        let vfs = MockVfs::new();
+        let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
 
         const DEF1: TableDefinition<&str, &str> = TableDefinition::new("x");
         const DEF2: TableDefinition<&str, &str> = TableDefinition::new("y");
@@ -0,0 +1,238 @@
+use redb::{
+    Bincode, Database, Json, MockVfs, ReadableTable, RedbValue, SerdeKey, StableName,
+    TableDefinition, TableError,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const DB_PATH: &str = "/test.redb";
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+enum Kind {
+    Empty,
+    Named(String),
+    Point { x: i32, y: i32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct Key {
+    kind: Kind,
+    id: i64,
+    tags: Vec<String>,
+    parent: Option<u16>,
+}
+
+impl StableName for Key {
+    fn stable_name() -> String {
+        "Key".to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct User {
+    name: String,
+    age: u8,
+    scores: BTreeMap<String, f64>,
+}
+
+impl StableName for User {
+    fn stable_name() -> String {
+        "User".to_string()
+    }
+}
+
+fn create() -> Database {
+    let vfs = MockVfs::new();
+    Database::create(DB_PATH, vfs.config(), vfs).unwrap()
+}
+
+fn user(name: &str) -> User {
+    let mut scores = BTreeMap::new();
+    scores.insert("math".to_string(), 1.5);
+    User {
+        name: name.to_string(),
+        age: 30,
+        scores,
+    }
+}
+
+#[test]
+fn json_and_bincode_values() {
+    let json: TableDefinition<u64, Json<User>> = TableDefinition::new("json");
+    let bincode: TableDefinition<&str, Bincode<User>> = TableDefinition::new("bincode");
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(json).unwrap();
+        table.insert(0, user("alice")).unwrap();
+        table.insert(1, &user("bob")).unwrap();
+        let mut table = txn.open_table(bincode).unwrap();
+        table.insert("carol", user("carol")).unwrap();
+    }
+    txn.commit().unwrap();
+
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(json).unwrap();
+    assert_eq!(table.get(0).unwrap().unwrap().value(), user("alice"));
+    assert_eq!(table.get(1).unwrap().unwrap().value(), user("bob"));
+    let table = txn.open_table(bincode).unwrap();
+    assert_eq!(table.get("carol").unwrap().unwrap().value(), user("carol"));
+}
+
+#[test]
+fn ordered_keys() {
+    let mut keys = vec![];
+    for kind in [
+        Kind::Empty,
+        Kind::Named("".to_string()),
+        Kind::Named("a".to_string()),
+        Kind::Named("a\0".to_string()),
+        Kind::Named("ab".to_string()),
+        Kind::Point { x: -5, y: 3 },
+        Kind::Point { x: 2, y: -1 },
+    ] {
+        for id in [i64::MIN, -1, 0, 1, i64::MAX] {
+            for tags in [
+                vec![],
+                vec!["x".to_string()],
+                vec!["x".to_string(), "".to_string()],
+            ] {
+                for parent in [None, Some(0), Some(u16::MAX)] {
+                    keys.push(Key {
+                        kind: kind.clone(),
+                        id,
+                        tags: tags.clone(),
+                        parent,
+                    });
+                }
+            }
+        }
+    }
+    let mut shuffled = keys.clone();
+    shuffled.reverse();
+    shuffled.rotate_left(keys.len() / 3);
+
+    let definition: TableDefinition<SerdeKey<Key>, u64> = TableDefinition::new("keys");
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(definition).unwrap();
+        for (i, key) in shuffled.iter().enumerate() {
+            table.insert(key, i as u64).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    keys.sort();
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(definition).unwrap();
+    let stored: Vec<Key> = table
+        .iter()
+        .unwrap()
+        .map(|entry| entry.unwrap().0.value())
+        .collect();
+    assert_eq!(stored, keys);
+
+    let start = Key {
+        kind: Kind::Named("a".to_string()),
+        id: 0,
+        tags: vec![],
+        parent: None,
+    };
+    let end = Key {
+        kind: Kind::Point { x: -5, y: 3 },
+        id: i64::MIN,
+        tags: vec![],
+        parent: None,
+    };
+    let range: Vec<Key> = table
+        .range::<&Key>(&start..&end)
+        .unwrap()
+        .map(|entry| entry.unwrap().0.value())
+        .collect();
+    let expected: Vec<Key> = keys
+        .iter()
+        .filter(|key| **key >= start && **key < end)
+        .cloned()
+        .collect();
+    assert_eq!(range, expected);
+}
+
+#[test]
+fn ordered_floats() {
+    let definition: TableDefinition<SerdeKey<(f64, char)>, ()> = TableDefinition::new("floats");
+    let mut keys = vec![
+        (f64::NEG_INFINITY, 'a'),
+        (-1.5, 'b'),
+        (-0.0, 'c'),
+        (0.0, 'd'),
+        (1e-300, 'e'),
+        (2.0, 'f'),
+        (f64::INFINITY, 'g'),
+    ];
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(definition).unwrap();
+        for key in keys.iter().rev() {
+            table.insert(key, ()).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(definition).unwrap();
+    let stored: Vec<(f64, char)> = table
+        .iter()
+        .unwrap()
+        .map(|entry| entry.unwrap().0.value())
+        .collect();
+    keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+    assert_eq!(stored, keys);
+}
+
+#[test]
+fn type_mismatch() {
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Other {
+        name: String,
+    }
+
+    impl StableName for Other {
+        fn stable_name() -> String {
+            "Other".to_string()
+        }
+    }
+
+    let definition: TableDefinition<u64, Json<User>> = TableDefinition::new("users");
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    txn.open_table(definition).unwrap();
+    txn.commit().unwrap();
+
+    let txn = db.begin_write().unwrap();
+    let other: TableDefinition<u64, Json<Other>> = TableDefinition::new("users");
+    assert!(matches!(
+        txn.open_table(other),
+        Err(TableError::TableTypeMismatch { .. })
+    ));
+    let bincode: TableDefinition<u64, Bincode<User>> = TableDefinition::new("users");
+    assert!(matches!(
+        txn.open_table(bincode),
+        Err(TableError::TableTypeMismatch { .. })
+    ));
+}
+
+#[test]
+fn stable_type_names() {
+    assert_eq!(Json::<User>::type_name().name(), "Json<User>");
+    assert_eq!(
+        Bincode::<Vec<User>>::type_name().name(),
+        "Bincode<Vec<User>>"
+    );
+    assert_eq!(
+        SerdeKey::<(f64, Option<String>)>::type_name().name(),
+        "SerdeKey<(f64, Option<String>)>"
+    );
+    assert_eq!(SerdeKey::<(u8,)>::type_name().name(), "SerdeKey<(u8,)>");
+}
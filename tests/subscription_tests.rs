@@ -0,0 +1,206 @@
+use redb::{
+    ChangeEvent, ChangeKind, Database, MockVfs, MultimapTableDefinition, ReadableTable,
+    TableDefinition,
+};
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+const DB_PATH: &str = "/test.redb";
+
+const U64_TABLE: TableDefinition<u64, u64> = TableDefinition::new("u64");
+const STR_TABLE: TableDefinition<&str, &str> = TableDefinition::new("str");
+const MULTIMAP_TABLE: MultimapTableDefinition<u64, u64> = MultimapTableDefinition::new("multimap");
+
+fn create() -> Database {
+    let vfs = MockVfs::new();
+    Database::create(DB_PATH, vfs.config(), vfs).unwrap()
+}
+
+fn changes(event: &ChangeEvent) -> Vec<(ChangeKind, u64)> {
+    event
+        .changes()
+        .iter()
+        .map(|change| (change.kind(), change.key::<u64>()))
+        .collect()
+}
+
+fn next(receiver: &mut UnboundedReceiver<ChangeEvent>) -> Vec<(ChangeKind, u64)> {
+    changes(&receiver.try_recv().unwrap())
+}
+
+#[test]
+fn insert_update_remove() {
+    let db = create();
+    let mut receiver = db.subscribe(U64_TABLE, 0..10);
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        for i in 0..20 {
+            table.insert(i, i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    let event = receiver.try_recv().unwrap();
+    let expected: Vec<(ChangeKind, u64)> = (0..10).map(|i| (ChangeKind::Inserted, i)).collect();
+    assert_eq!(changes(&event), expected);
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        table.insert(1, 100).unwrap();
+        table.remove(2).unwrap();
+        // Neither present before nor after the transaction
+        table.insert(50, 50).unwrap();
+        table.insert(5, 5).unwrap();
+        table.remove(5).unwrap();
+        table.pop_first().unwrap();
+    }
+    txn.commit().unwrap();
+    let second = receiver.try_recv().unwrap();
+    assert!(second.transaction_id() > event.transaction_id());
+    assert_eq!(
+        changes(&second),
+        vec![
+            (ChangeKind::Removed, 0),
+            (ChangeKind::Updated, 1),
+            (ChangeKind::Removed, 2),
+            (ChangeKind::Removed, 5),
+        ]
+    );
+
+    // Changes outside of the range are not reported
+    let txn = db.begin_write().unwrap();
+    txn.open_table(U64_TABLE).unwrap().insert(10, 10).unwrap();
+    txn.commit().unwrap();
+    assert_eq!(receiver.try_recv().unwrap_err(), TryRecvError::Empty);
+}
+
+#[test]
+fn aborted() {
+    let db = create();
+    let mut receiver = db.subscribe(U64_TABLE, 0..);
+
+    let txn = db.begin_write().unwrap();
+    txn.open_table(U64_TABLE).unwrap().insert(0, 0).unwrap();
+    txn.abort().unwrap();
+
+    let txn = db.begin_write().unwrap();
+    txn.open_table(U64_TABLE).unwrap().insert(1, 1).unwrap();
+    drop(txn);
+
+    let txn = db.begin_write().unwrap();
+    txn.open_table(U64_TABLE).unwrap().insert(2, 2).unwrap();
+    txn.commit().unwrap();
+
+    assert_eq!(next(&mut receiver), vec![(ChangeKind::Inserted, 2)]);
+    assert_eq!(receiver.try_recv().unwrap_err(), TryRecvError::Empty);
+}
+
+#[test]
+fn drain() {
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        for i in 0..10 {
+            table.insert(i, i).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+
+    let mut receiver = db.subscribe(U64_TABLE, 0..);
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(U64_TABLE).unwrap();
+        assert_eq!(table.drain(0..3).unwrap().count(), 3);
+        let removed = table.drain_filter(4.., |key, _| key % 2 == 0).unwrap();
+        assert_eq!(removed.count(), 3);
+    }
+    txn.commit().unwrap();
+
+    let removed: Vec<(ChangeKind, u64)> = [0, 1, 2, 4, 6, 8]
+        .into_iter()
+        .map(|i| (ChangeKind::Removed, i))
+        .collect();
+    assert_eq!(next(&mut receiver), removed);
+
+    let txn = db.begin_read().unwrap();
+    assert_eq!(txn.open_table(U64_TABLE).unwrap().len().unwrap(), 4);
+}
+
+#[test]
+fn multimap() {
+    let db = create();
+    let mut receiver = db.subscribe_multimap(MULTIMAP_TABLE, 0..);
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_multimap_table(MULTIMAP_TABLE).unwrap();
+        table.insert(0, 0).unwrap();
+        table.insert(0, 1).unwrap();
+        table.insert(1, 0).unwrap();
+        table.insert(2, 0).unwrap();
+    }
+    txn.commit().unwrap();
+    assert_eq!(
+        next(&mut receiver),
+        vec![
+            (ChangeKind::Inserted, 0),
+            (ChangeKind::Inserted, 1),
+            (ChangeKind::Inserted, 2)
+        ]
+    );
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_multimap_table(MULTIMAP_TABLE).unwrap();
+        // Already present
+        table.insert(2, 0).unwrap();
+        table.remove(0, 0).unwrap();
+        table.remove(1, 0).unwrap();
+        table.remove_all(2).unwrap();
+    }
+    txn.commit().unwrap();
+    assert_eq!(
+        next(&mut receiver),
+        vec![
+            (ChangeKind::Updated, 0),
+            (ChangeKind::Removed, 1),
+            (ChangeKind::Removed, 2)
+        ]
+    );
+}
+
+#[test]
+fn multiple_subscribers() {
+    let db = create();
+    let mut all = db.subscribe(STR_TABLE, ""..);
+    let mut prefixed = db.subscribe(STR_TABLE, "b".."c");
+    let dropped = db.subscribe(STR_TABLE, ""..);
+    drop(dropped);
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_table(STR_TABLE).unwrap();
+        table.insert("apple", "").unwrap();
+        table.insert("banana", "").unwrap();
+    }
+    {
+        // Other tables are not reported
+        txn.open_table(U64_TABLE).unwrap().insert(0, 0).unwrap();
+    }
+    txn.commit().unwrap();
+
+    let event = all.try_recv().unwrap();
+    let keys: Vec<&str> = event
+        .changes()
+        .iter()
+        .map(|change| std::str::from_utf8(change.key_bytes()).unwrap())
+        .collect();
+    assert_eq!(keys, vec!["apple", "banana"]);
+    let event = prefixed.try_recv().unwrap();
+    assert_eq!(event.changes().len(), 1);
+    assert_eq!(event.changes()[0].key::<&str>(), "banana");
+    assert_eq!(all.try_recv().unwrap_err(), TryRecvError::Empty);
+}
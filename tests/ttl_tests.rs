@@ -0,0 +1,147 @@
+use redb::{
+    Database, MockVfs, MultimapTableDefinition, ReadableMultimapTable, ReadableTtlTable,
+    TtlTableDefinition,
+};
+use std::time::{Duration, SystemTime};
+
+const DB_PATH: &str = "/test.redb";
+
+const SESSIONS: TtlTableDefinition<u64, &str> = TtlTableDefinition::new("sessions");
+const EXPIRY: MultimapTableDefinition<u64, u64> = MultimapTableDefinition::new("sessions$expiry");
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+fn create() -> Database {
+    let vfs = MockVfs::new();
+    Database::create(DB_PATH, vfs.config(), vfs).unwrap()
+}
+
+fn indexed_entries(db: &Database) -> u64 {
+    let txn = db.begin_read().unwrap();
+    let expiry = txn.open_multimap_table(EXPIRY).unwrap();
+    expiry.len().unwrap()
+}
+
+#[test]
+fn expired_entries_are_hidden() {
+    let db = create();
+    let past = SystemTime::now() - HOUR;
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_ttl_table(SESSIONS).unwrap();
+        table.insert(1, "live", HOUR).unwrap();
+        table.insert(2, "expired", Duration::ZERO).unwrap();
+        table.insert_until(3, "expired", past).unwrap();
+        table.insert(4, "live", HOUR).unwrap();
+        assert_eq!(table.get(1).unwrap().unwrap().value(), "live");
+        assert!(table.get(2).unwrap().is_none());
+        let keys: Vec<u64> = table
+            .iter()
+            .unwrap()
+            .map(|entry| entry.unwrap().0.value())
+            .collect();
+        assert_eq!(keys, [1, 4]);
+    }
+    txn.commit().unwrap();
+
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_ttl_table(SESSIONS).unwrap();
+    assert_eq!(table.get(4).unwrap().unwrap().value(), "live");
+    assert!(table.get(3).unwrap().is_none());
+    let keys: Vec<u64> = table
+        .range(2..)
+        .unwrap()
+        .rev()
+        .map(|entry| entry.unwrap().0.value())
+        .collect();
+    assert_eq!(keys, [4]);
+    let expires_at = table.expires_at(1).unwrap().unwrap();
+    assert!(expires_at > SystemTime::now() + HOUR - Duration::from_secs(60));
+    assert!(table.expires_at(2).unwrap().is_none());
+}
+
+#[test]
+fn entries_expire() {
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_ttl_table(SESSIONS).unwrap();
+        table.insert(1, "short", Duration::from_millis(50)).unwrap();
+        table.insert(2, "long", HOUR).unwrap();
+        assert_eq!(table.get(1).unwrap().unwrap().value(), "short");
+    }
+    txn.commit().unwrap();
+
+    std::thread::sleep(Duration::from_millis(100));
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_ttl_table(SESSIONS).unwrap();
+    assert!(table.get(1).unwrap().is_none());
+    assert_eq!(table.iter().unwrap().count(), 1);
+}
+
+#[test]
+fn overwrite_and_remove() {
+    let db = create();
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_ttl_table(SESSIONS).unwrap();
+        assert!(table.insert(1, "a", Duration::ZERO).unwrap().is_none());
+        // The expired value is not returned
+        assert!(table.insert(1, "b", HOUR).unwrap().is_none());
+        let old = table.insert(1, "c", HOUR * 2).unwrap().unwrap();
+        assert_eq!(old.value(), "b");
+        drop(old);
+        assert_eq!(table.get(1).unwrap().unwrap().value(), "c");
+
+        table.insert(2, "d", HOUR).unwrap();
+        assert_eq!(table.remove(2).unwrap().unwrap().value(), "d");
+        assert!(table.remove(2).unwrap().is_none());
+    }
+    txn.commit().unwrap();
+    assert_eq!(indexed_entries(&db), 1);
+}
+
+#[test]
+fn purge() {
+    let db = create();
+    let expires_at = SystemTime::now() + Duration::from_millis(500);
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_ttl_table(SESSIONS).unwrap();
+        for i in 0..100 {
+            table.insert_until(i, "expiring", expires_at).unwrap();
+        }
+        for i in 100..110 {
+            table.insert(i, "live", HOUR).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+    assert_eq!(indexed_entries(&db), 110);
+    while SystemTime::now() <= expires_at {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_ttl_table(SESSIONS).unwrap();
+        assert_eq!(table.purge_expired(0).unwrap(), 0);
+        assert_eq!(table.purge_expired(10).unwrap(), 10);
+        assert_eq!(table.purge_expired(u64::MAX).unwrap(), 90);
+        assert_eq!(table.purge_expired(u64::MAX).unwrap(), 0);
+        assert_eq!(table.iter().unwrap().count(), 10);
+    }
+    txn.commit().unwrap();
+    assert_eq!(indexed_entries(&db), 10);
+
+    // Writes purge expired entries lazily
+    let txn = db.begin_write().unwrap();
+    {
+        let mut table = txn.open_ttl_table(SESSIONS).unwrap();
+        for i in 0..5 {
+            table.insert(i, "expired", Duration::ZERO).unwrap();
+        }
+        table.insert(200, "live", HOUR).unwrap();
+    }
+    txn.commit().unwrap();
+    assert_eq!(indexed_entries(&db), 11);
+}
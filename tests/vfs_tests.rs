@@ -0,0 +1,305 @@
+use redb::{
+    Database, DatabaseError, Error, MockVfs, ReadableTable, StorageError, TableDefinition,
+    VfsConfig, VfsTransport,
+};
+use std::io;
+use std::time::{Duration, Instant};
+
+const DB_PATH: &str = "/test.redb";
+const LOCK_PATH: &str = "/test.redb.lock";
+
+const U64_TABLE: TableDefinition<u64, u64> = TableDefinition::new("u64");
+
+// Rejects every request, as the VFS does when the caller lacks the capability for the drive
+struct NoCap;
+
+impl VfsTransport for NoCap {
+    fn send_and_await_response(
+        &self,
+        _node: &str,
+        _process: &str,
+        _ipc: Vec<u8>,
+        _payload: Option<Vec<u8>>,
+        _timeout: Duration,
+    ) -> io::Result<(Vec<u8>, Option<Vec<u8>>)> {
+        Ok((br#"{"Err":"NoCap"}"#.to_vec(), None))
+    }
+}
+
+#[test]
+fn vfs_error_response() {
+    let result = Database::create("/db.redb", VfsConfig::new("our", "drive"), NoCap);
+    match result {
+        Err(DatabaseError::Storage(StorageError::Vfs { kind, action })) => {
+            assert_eq!(kind, "NoCap");
+            assert_eq!(action, "GetEntry");
+        }
+        other => panic!("Unexpected result: {other:?}"),
+    }
+}
+
+#[test]
+fn vfs_open_error_response() {
+    let result = Database::open("/db.redb", VfsConfig::new("our", "drive"), NoCap);
+    match result {
+        Err(DatabaseError::Storage(StorageError::Vfs { kind, action })) => {
+            assert_eq!(kind, "NoCap");
+            assert_eq!(action, "GetEntry");
+        }
+        other => panic!("Unexpected result: {other:?}"),
+    }
+}
+
+fn insert(db: &Database, count: u64) -> Result<(), Error> {
+    let txn = db.begin_write()?;
+    {
+        let mut table = txn.open_table(U64_TABLE)?;
+        for i in 0..count {
+            table.insert(i, i)?;
+        }
+    }
+    txn.commit()?;
+    Ok(())
+}
+
+#[test]
+fn batched_writes() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let writes_before = vfs.request_count("WriteOffset") + vfs.request_count("WriteOffsets");
+    let syncs_before = vfs.request_count("SyncData");
+
+    insert(&db, 10_000).unwrap();
+
+    let writes =
+        vfs.request_count("WriteOffset") + vfs.request_count("WriteOffsets") - writes_before;
+    assert!(writes <= 2, "{writes} writes");
+    assert!(vfs.request_count("SyncData") > syncs_before);
+    drop(db);
+
+    let db = Database::open(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(U64_TABLE).unwrap();
+    assert_eq!(table.len().unwrap(), 10_000);
+}
+
+#[test]
+fn injected_write_failure() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    vfs.fail_next("WriteOffset", 1);
+    vfs.fail_next("WriteOffsets", 1);
+
+    match insert(&db, 1000) {
+        Err(Error::Vfs { kind, action }) => {
+            assert_eq!(kind, "NoCap");
+            assert!(action.starts_with("WriteOffset"), "{action}");
+        }
+        other => panic!("Unexpected result: {other:?}"),
+    }
+}
+
+#[test]
+fn dropped_response() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    vfs.drop_next("SyncData", 1);
+
+    match insert(&db, 1) {
+        Err(Error::Io(err)) => {
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        }
+        other => panic!("Unexpected result: {other:?}"),
+    }
+}
+
+#[test]
+fn latency() {
+    let vfs = MockVfs::new();
+    vfs.set_latency(Duration::from_millis(1));
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    insert(&db, 100).unwrap();
+    drop(db);
+
+    let mut config = vfs.config();
+    config.set_timeout(Duration::from_millis(1));
+    vfs.set_latency(Duration::from_millis(10));
+    match Database::open(DB_PATH, config, vfs.clone()) {
+        Err(DatabaseError::Storage(StorageError::Io(err))) => {
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        }
+        other => panic!("Unexpected result: {other:?}"),
+    }
+}
+
+fn short_lease(vfs: &MockVfs) -> VfsConfig {
+    let mut config = vfs.config();
+    config.set_lease_duration(Duration::from_millis(200));
+    config
+}
+
+#[test]
+fn stale_lease() {
+    let vfs = MockVfs::new();
+    // Simulate a process which crashed without closing the database, by restoring the lock file
+    // it held
+    let db = Database::create(DB_PATH, short_lease(&vfs), vfs.clone()).unwrap();
+    let lock_file = vfs.file(LOCK_PATH).unwrap();
+    drop(db);
+    vfs.insert_file(LOCK_PATH, lock_file);
+
+    let result = Database::open(DB_PATH, vfs.config(), vfs.clone());
+    assert!(matches!(result, Err(DatabaseError::DatabaseAlreadyOpen)));
+
+    std::thread::sleep(Duration::from_millis(300));
+    Database::open(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+}
+
+#[test]
+fn break_lease() {
+    let vfs = MockVfs::new();
+    std::mem::forget(Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap());
+
+    let result = Database::open(DB_PATH, vfs.config(), vfs.clone());
+    assert!(matches!(result, Err(DatabaseError::DatabaseAlreadyOpen)));
+
+    Database::break_lease(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    Database::open(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+}
+
+#[test]
+fn lease_lost() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, short_lease(&vfs), vfs.clone()).unwrap();
+    insert(&db, 10).unwrap();
+
+    Database::break_lease(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    let db2 = Database::open(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+
+    match insert(&db, 10) {
+        Err(Error::Vfs { kind, .. }) => {
+            assert_eq!(kind, "LeaseLost");
+        }
+        other => panic!("Unexpected result: {other:?}"),
+    }
+    drop(db);
+    insert(&db2, 10).unwrap();
+}
+
+#[test]
+fn lease_renewed() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, short_lease(&vfs), vfs.clone()).unwrap();
+    for _ in 0..5 {
+        std::thread::sleep(Duration::from_millis(80));
+        insert(&db, 10).unwrap();
+    }
+
+    let result = Database::open(DB_PATH, vfs.config(), vfs.clone());
+    assert!(matches!(result, Err(DatabaseError::DatabaseAlreadyOpen)));
+}
+
+#[test]
+fn lease_kept_while_idle() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, short_lease(&vfs), vfs.clone()).unwrap();
+    insert(&db, 10).unwrap();
+
+    // Nothing renews the lease in the background, so an idle database must renew it itself
+    for _ in 0..8 {
+        std::thread::sleep(Duration::from_millis(80));
+        db.renew_lease().unwrap();
+    }
+    let result = Database::open(DB_PATH, vfs.config(), vfs.clone());
+    assert!(matches!(result, Err(DatabaseError::DatabaseAlreadyOpen)));
+    insert(&db, 10).unwrap();
+
+    drop(db);
+    Database::open(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+}
+
+#[test]
+fn lease_expires_while_idle() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, short_lease(&vfs), vfs.clone()).unwrap();
+    insert(&db, 10).unwrap();
+
+    std::thread::sleep(Duration::from_millis(300));
+    let db2 = Database::open(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    assert!(db.renew_lease().is_err());
+    match insert(&db, 10) {
+        Err(Error::Vfs { kind, .. }) => {
+            assert_eq!(kind, "LeaseLost");
+        }
+        other => panic!("Unexpected result: {other:?}"),
+    }
+    insert(&db2, 10).unwrap();
+}
+
+#[test]
+fn lease_written_in_place() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, short_lease(&vfs), vfs.clone()).unwrap();
+    let lease = vfs.file(LOCK_PATH).unwrap();
+    std::thread::sleep(Duration::from_millis(120));
+    let set_size = vfs.request_count("SetSize");
+    db.renew_lease().unwrap();
+
+    // The lease is replaced by a single write of the same length, without truncating the file
+    let renewed = vfs.file(LOCK_PATH).unwrap();
+    assert_ne!(renewed, lease);
+    assert_eq!(renewed.len(), lease.len());
+    assert_eq!(vfs.request_count("SetSize"), set_size);
+}
+
+#[test]
+fn unparseable_lease() {
+    let vfs = MockVfs::new();
+    drop(Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap());
+    // Such as a lease which is still being written by another process
+    vfs.insert_file(LOCK_PATH, br#"{"holder":1"#.to_vec());
+
+    // The lock file is considered held until it has been left unchanged for a whole lease
+    let start = Instant::now();
+    Database::open(DB_PATH, short_lease(&vfs), vfs.clone()).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
+
+fn scan_chunk_requests(vfs: &MockVfs, read_ahead: usize) -> u64 {
+    let db = Database::builder()
+        .set_read_ahead(read_ahead)
+        .open(DB_PATH, vfs.config(), vfs.clone())
+        .unwrap();
+    let txn = db.begin_read().unwrap();
+    let table = txn.open_table(U64_TABLE).unwrap();
+
+    let before = vfs.request_count("GetFileChunk");
+    let mut expected = 0;
+    for entry in table.range::<u64>(..).unwrap() {
+        assert_eq!(entry.unwrap().0.value(), expected);
+        expected += 1;
+    }
+    assert_eq!(expected, 10_000);
+    let mut expected = 10_000;
+    for entry in table.range::<u64>(..).unwrap().rev() {
+        expected -= 1;
+        assert_eq!(entry.unwrap().0.value(), expected);
+    }
+    vfs.request_count("GetFileChunk") - before
+}
+
+#[test]
+fn read_ahead() {
+    let vfs = MockVfs::new();
+    let db = Database::create(DB_PATH, vfs.config(), vfs.clone()).unwrap();
+    insert(&db, 10_000).unwrap();
+    drop(db);
+
+    let without = scan_chunk_requests(&vfs, 0);
+    let with = scan_chunk_requests(&vfs, 16);
+    assert!(
+        with * 4 < without,
+        "{with} requests, {without} without read-ahead"
+    );
+}